@@ -0,0 +1,30 @@
+// Same inserts and queries with every hasher, run with
+// cargo bench --bench hasher_bench --features xxhash,wyhash
+use bloomf::{BloomFilter, Sha256BuildHasher, WyBuildHasher, Xxh3BuildHasher};
+use criterion::{criterion_group, criterion_main, Criterion};
+use std::hash::BuildHasher;
+
+fn bench_hasher<S: BuildHasher>(c: &mut Criterion, name: &str, hasher: S) {
+    let items: Vec<String> = (0..10_000).map(|i| format!("item_{}", i)).collect();
+    let mut bloom = BloomFilter::with_hasher(1_000_000, 7, hasher);
+
+    c.bench_function(&format!("{}_set_10000_items", name), |b| {
+        b.iter(|| {
+            for item in &items {
+                bloom.set(item);
+            }
+        });
+    });
+    c.bench_function(&format!("{}_test_10000_items", name), |b| {
+        b.iter(|| items.iter().filter(|item| bloom.test(item)).count());
+    });
+}
+
+fn bench_hashers(c: &mut Criterion) {
+    bench_hasher(c, "sha256", Sha256BuildHasher);
+    bench_hasher(c, "xxh3", Xxh3BuildHasher);
+    bench_hasher(c, "wyhash", WyBuildHasher);
+}
+
+criterion_group!(benches, bench_hashers);
+criterion_main!(benches);
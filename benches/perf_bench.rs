@@ -101,5 +101,65 @@ fn bench_bloom_filter(c: &mut Criterion) {
     }
 }
 
-criterion_group!(benches, bench_bloom_filter);
+fn bench_test_many(c: &mut Criterion) {
+    let mut bloom = BloomFilter::new(1_000_000, 7);
+    let items: Vec<String> = (0..10_000).map(|i| format!("item_{}", i)).collect();
+    for item in items.iter().step_by(2) {
+        bloom.set(item);
+    }
+
+    c.bench_function("test_loop_10000_items", |b| {
+        b.iter(|| items.iter().filter(|item| bloom.test(item)).count());
+    });
+    c.bench_function("test_many_10000_items", |b| {
+        b.iter(|| bloom.test_many(&items));
+    });
+}
+
+fn bench_insert_all(c: &mut Criterion) {
+    let items: Vec<String> = (0..10_000).map(|i| format!("item_{}", i)).collect();
+
+    c.bench_function("set_loop_10000_items", |b| {
+        let mut bloom = BloomFilter::new(1_000_000, 7);
+        b.iter(|| {
+            for item in &items {
+                bloom.set(item);
+            }
+        });
+    });
+    c.bench_function("insert_all_10000_items", |b| {
+        let mut bloom = BloomFilter::new(1_000_000, 7);
+        b.iter(|| bloom.insert_all(&items));
+    });
+}
+
+// Integer keys through `Hash` and SHA256 vs the splitmix64 fast path
+fn bench_u64_keys(c: &mut Criterion) {
+    c.bench_function("set_test_10000_u64_hashed", |b| {
+        let mut bloom = BloomFilter::new(1_000_000, 7);
+        b.iter(|| {
+            for key in 0..10_000u64 {
+                bloom.set(&key);
+                bloom.test(&key);
+            }
+        });
+    });
+    c.bench_function("insert_contains_10000_u64", |b| {
+        let mut bloom = BloomFilter::new(1_000_000, 7);
+        b.iter(|| {
+            for key in 0..10_000u64 {
+                bloom.insert_u64(key);
+                bloom.contains_u64(key);
+            }
+        });
+    });
+}
+
+criterion_group!(
+    benches,
+    bench_bloom_filter,
+    bench_test_many,
+    bench_insert_all,
+    bench_u64_keys
+);
 criterion_main!(benches);
@@ -0,0 +1,3 @@
+// Links bloomf into the shared library, the exported C, wasm-bindgen and Python symbols all
+// come from the bloomf crate and its `ffi`, `wasm` and `python` features.
+pub use bloomf_lib::*;
@@ -0,0 +1,48 @@
+/* C API of bloomf, built with `cargo build --release -p bloomf-bindings --features ffi`
+ * (target/release/libbloomf.so / .dylib / .dll).
+ *
+ * Filters use the same encoding as the Rust `BloomFilter::to_bytes`, items are byte strings
+ * hashed like `BloomFilter::set_bytes`. A filter must not be used from two threads while one
+ * of them inserts. */
+
+#ifndef BLOOMF_H
+#define BLOOMF_H
+
+#include <stdbool.h>
+#include <stddef.h>
+#include <stdint.h>
+
+#ifdef __cplusplus
+extern "C" {
+#endif
+
+typedef struct BloomFilter BloomFilter;
+
+/* NULL if size or num_hashes is 0, or num_hashes is above 64 */
+BloomFilter *bloomf_new(size_t size, size_t num_hashes);
+
+/* Same as bloomf_new, the seed is stored with the filter. Filters with the same size,
+ * num_hashes and seed set the same bits in every process and in Rust. */
+BloomFilter *bloomf_new_seeded(size_t size, size_t num_hashes, uint64_t seed);
+
+void bloomf_free(BloomFilter *bloom);
+
+void bloomf_insert(BloomFilter *bloom, const uint8_t *data, size_t len);
+
+bool bloomf_contains(const BloomFilter *bloom, const uint8_t *data, size_t len);
+
+void bloomf_clear(BloomFilter *bloom);
+
+/* Returns *out_len bytes, release them with bloomf_bytes_free */
+uint8_t *bloomf_serialize(const BloomFilter *bloom, size_t *out_len);
+
+void bloomf_bytes_free(uint8_t *data, size_t len);
+
+/* NULL if the bytes are not a valid encoded filter */
+BloomFilter *bloomf_deserialize(const uint8_t *data, size_t len);
+
+#ifdef __cplusplus
+}
+#endif
+
+#endif /* BLOOMF_H */
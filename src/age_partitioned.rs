@@ -0,0 +1,189 @@
+// Age-partitioned Bloom filter (Shtul, Baquero & Almeida 2020) for "last N items" windows.
+// k + l slices of m bits with one hash function each. Items are inserted into the k newest
+// slices and every g inserts the oldest slice is cleared and becomes the newest one. An item is
+// reported when k consecutive slices contain it, so it is remembered for at least l * g inserts
+// and forgotten for good after (l + 1) * g, when the oldest of its slices retires.
+//
+// Slices are sized so each one is about half full when it retires, which keeps the false
+// positive rate close to that of a regular filter with k hashes (e.g. ~0.1% for k = 10, l = 7).
+
+use std::hash::{BuildHasher, Hash};
+
+use crate::bitset::BitVec;
+use crate::hash::{self, Sha256BuildHasher};
+use crate::stats::FilterStats;
+
+pub struct AgePartitionedBloomFilter<S = Sha256BuildHasher> {
+    bit_array: BitVec,
+    k: usize,
+    l: usize,
+    slice_size: usize,
+    // physical slice of the newest (logical 0) slice, logical i is at (base + i) % (k + l)
+    base: usize,
+    generation_size: usize,
+    // inserts into the current generation
+    count: usize,
+    hasher: S,
+}
+
+impl AgePartitionedBloomFilter {
+    // window: number of most recent items that are always remembered
+    pub fn new(k: usize, l: usize, window: usize) -> Self {
+        Self::with_hasher(k, l, window, Sha256BuildHasher)
+    }
+}
+
+impl<S: BuildHasher> AgePartitionedBloomFilter<S> {
+    pub fn with_hasher(k: usize, l: usize, window: usize, hasher: S) -> Self {
+        assert!(k > 0 && l > 0, "k and l must not be 0");
+        let generation_size = window.div_ceil(l).max(1);
+        // every slice receives k generations of inserts, fill it up to 1/2
+        let slice_size = ((k * generation_size) as f64 / std::f64::consts::LN_2).ceil() as usize;
+        AgePartitionedBloomFilter {
+            bit_array: BitVec::new((k + l) * slice_size),
+            k,
+            l,
+            slice_size,
+            base: 0,
+            generation_size,
+            count: 0,
+            hasher,
+        }
+    }
+
+    fn num_slices(&self) -> usize {
+        self.k + self.l
+    }
+
+    pub fn insert<T: Hash + ?Sized>(&mut self, item: &T) {
+        if self.count >= self.generation_size {
+            self.shift();
+        }
+        let num_slices = self.num_slices();
+        // one hash per physical slice, so bits stay valid while slices age
+        let indices: Vec<usize> =
+            hash::indices(&self.hasher, item, num_slices, self.slice_size).collect();
+        for logical in 0..self.k {
+            let slice = (self.base + logical) % num_slices;
+            self.bit_array.set(slice * self.slice_size + indices[slice]);
+        }
+        self.count += 1;
+    }
+
+    pub fn contains<T: Hash + ?Sized>(&self, item: &T) -> bool {
+        let num_slices = self.num_slices();
+        let indices: Vec<usize> =
+            hash::indices(&self.hasher, item, num_slices, self.slice_size).collect();
+        let mut run = 0;
+        for logical in 0..num_slices {
+            let slice = (self.base + logical) % num_slices;
+            if self.bit_array.get(slice * self.slice_size + indices[slice]) {
+                run += 1;
+                if run == self.k {
+                    return true;
+                }
+            } else if num_slices - logical - 1 < self.k {
+                // not enough slices left for a run of k
+                return false;
+            } else {
+                run = 0;
+            }
+        }
+        false
+    }
+
+    // Retires the oldest slice and starts a new generation. Called automatically every
+    // `generation_size` inserts, can be called manually for time based windows.
+    pub fn shift(&mut self) {
+        let num_slices = self.num_slices();
+        self.base = (self.base + num_slices - 1) % num_slices;
+        let start = self.base * self.slice_size;
+        for idx in start..start + self.slice_size {
+            self.bit_array.unset(idx);
+        }
+        self.count = 0;
+    }
+
+    // Items that are guaranteed to be remembered
+    pub fn window(&self) -> usize {
+        self.l * self.generation_size
+    }
+
+    pub fn generation_size(&self) -> usize {
+        self.generation_size
+    }
+
+    // estimated_items are the items in the k newest slices or older, the false positive
+    // probability that of a run of k slices with the bits set, from each slice's fill ratio
+    pub fn stats(&self) -> FilterStats {
+        let num_slices = self.num_slices();
+        let mut stats = FilterStats::new(
+            num_slices * self.slice_size,
+            self.k,
+            self.bit_array.count_ones(),
+            self.memory_usage(),
+        );
+        // runs[r]: chance that the last r slices are set and no run of k was found before
+        let mut runs = vec![0.0; self.k];
+        runs[0] = 1.0;
+        let mut found = 0.0;
+        for logical in 0..num_slices {
+            let start = (self.base + logical) % num_slices * self.slice_size;
+            let ones = (start..start + self.slice_size)
+                .filter(|&idx| self.bit_array.get(idx))
+                .count();
+            let p = crate::fill_ratio(ones, self.slice_size);
+            let mut next = vec![0.0; self.k];
+            for (run, &chance) in runs.iter().enumerate() {
+                next[0] += chance * (1.0 - p);
+                if run + 1 == self.k {
+                    found += chance * p;
+                } else {
+                    next[run + 1] += chance * p;
+                }
+            }
+            runs = next;
+        }
+        stats.estimated_fpp = found;
+        stats
+    }
+
+    pub fn memory_usage(&self) -> usize {
+        size_of::<Self>() + self.bit_array.heap_bytes()
+    }
+
+    pub fn clear(&mut self) {
+        self.bit_array.clear();
+        self.base = 0;
+        self.count = 0;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sliding_window() {
+        let mut bloom = AgePartitionedBloomFilter::new(10, 7, 700);
+        assert_eq!(bloom.window(), 700);
+
+        for i in 0..5_000u32 {
+            bloom.insert(&i);
+            if i % 1000 == 999 {
+                // the last `window` items are always there
+                for j in i.saturating_sub(699)..=i {
+                    assert!(bloom.contains(&j));
+                }
+            }
+        }
+        // items older than (l + 1) * g are gone, up to false positives
+        let old = (0..3_000u32).filter(|i| bloom.contains(i)).count();
+        assert!(old < 10, "{} old items", old);
+        let false_positives = (100_000..110_000u32).filter(|i| bloom.contains(i)).count();
+        assert!(false_positives < 30, "{} false positives", false_positives);
+
+        bloom.clear();
+        assert!(!bloom.contains(&4_999u32));
+    }
+}
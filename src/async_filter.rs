@@ -0,0 +1,98 @@
+// Bloom filter for async code (feature `tokio`). Same as `ThreadSafeBF` but behind a
+// `tokio::sync::RwLock`: a task waiting for the lock yields to the executor instead of
+// blocking its worker thread. tokio locks don't get poisoned, so nothing here fails.
+
+use std::hash::{BuildHasher, Hash};
+
+use tokio::sync::RwLock;
+
+use crate::hash::Sha256BuildHasher;
+use crate::BloomFilter;
+
+pub struct AsyncBloomFilter<S = Sha256BuildHasher> {
+    bf: RwLock<BloomFilter<S>>,
+}
+
+impl AsyncBloomFilter {
+    pub fn new(size: usize, num_hashes: usize) -> Self {
+        Self::with_hasher(size, num_hashes, Sha256BuildHasher)
+    }
+}
+
+impl<S: BuildHasher> AsyncBloomFilter<S> {
+    pub fn with_hasher(size: usize, num_hashes: usize, hasher: S) -> Self {
+        Self::from_filter(BloomFilter::with_hasher(size, num_hashes, hasher))
+    }
+
+    pub fn from_filter(bloom: BloomFilter<S>) -> Self {
+        AsyncBloomFilter {
+            bf: RwLock::new(bloom),
+        }
+    }
+
+    pub async fn set<T: Hash + ?Sized>(&self, item: &T) {
+        self.bf.write().await.set(item);
+    }
+
+    // See `BloomFilter::insert`
+    pub async fn insert<T: Hash + ?Sized>(&self, item: &T) -> bool {
+        self.bf.write().await.insert(item)
+    }
+
+    // One write lock for the whole batch
+    pub async fn insert_all<I>(&self, items: I)
+    where
+        I: IntoIterator,
+        I::Item: Hash,
+    {
+        self.bf.write().await.insert_all(items);
+    }
+
+    pub async fn test<T: Hash + ?Sized>(&self, item: &T) -> bool {
+        self.bf.read().await.test(item)
+    }
+
+    pub async fn test_many<T: Hash>(&self, items: &[T]) -> Vec<bool> {
+        self.bf.read().await.test_many(items)
+    }
+
+    pub async fn memory_usage(&self) -> usize {
+        size_of::<Self>() + self.bf.read().await.heap_bytes()
+    }
+
+    pub fn into_inner(self) -> BloomFilter<S> {
+        self.bf.into_inner()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Arc;
+
+    #[tokio::test]
+    async fn test_async_set_and_test() {
+        let bloom = Arc::new(AsyncBloomFilter::new(10_000, 4));
+        let tasks: Vec<_> = (0..4u32)
+            .map(|t| {
+                let bloom = Arc::clone(&bloom);
+                tokio::spawn(async move {
+                    for i in 0..100u32 {
+                        bloom.set(&(t * 100 + i)).await;
+                    }
+                })
+            })
+            .collect();
+        for task in tasks {
+            task.await.unwrap();
+        }
+        assert!(bloom.test(&0u32).await);
+        assert!(bloom.insert(&399u32).await);
+        assert!(!bloom.insert(&400u32).await);
+        let items: Vec<u32> = (0..400).collect();
+        assert!(bloom.test_many(&items).await.into_iter().all(|hit| hit));
+
+        let bloom = Arc::try_unwrap(bloom).ok().unwrap().into_inner();
+        assert!(bloom.test(&123u32));
+    }
+}
@@ -0,0 +1,124 @@
+// Attenuated Bloom filter: an array of filters by hop distance, used for resource discovery
+// in P2P networks. Level 0 holds what a node has itself, level i what is reachable i hops
+// away through the neighbor the filter describes. A node advertises its filter and every
+// receiver folds it in one level further out with `shift_and_merge`:
+//
+//   route.shift_and_merge(&neighbor_advert)?;
+//   route.distance(&resource) // Some(hops) -> forward the query to that neighbor
+//
+// Information more than depth - 1 hops away falls off the last level.
+
+use std::hash::{BuildHasher, Hash};
+
+use crate::error::MergeError;
+use crate::hash::Sha256BuildHasher;
+use crate::BloomFilter;
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct AttenuatedBloomFilter<S = Sha256BuildHasher> {
+    levels: Vec<BloomFilter<S>>,
+}
+
+impl AttenuatedBloomFilter {
+    pub fn new(depth: usize, size: usize, num_hashes: usize) -> Self {
+        Self::with_hasher(depth, size, num_hashes, Sha256BuildHasher)
+    }
+}
+
+impl<S: BuildHasher + Clone> AttenuatedBloomFilter<S> {
+    // `depth` levels of `size` bits each
+    pub fn with_hasher(depth: usize, size: usize, num_hashes: usize, hasher: S) -> Self {
+        assert!(depth > 0, "an attenuated filter needs at least one level");
+        AttenuatedBloomFilter {
+            levels: (0..depth)
+                .map(|_| BloomFilter::with_hasher(size, num_hashes, hasher.clone()))
+                .collect(),
+        }
+    }
+
+    // Panics if level >= depth
+    pub fn set<T: Hash + ?Sized>(&mut self, level: usize, item: &T) {
+        self.levels[level].set(item);
+    }
+
+    pub fn test<T: Hash + ?Sized>(&self, level: usize, item: &T) -> bool {
+        self.levels[level].test(item)
+    }
+
+    // Lowest level the item (possibly) is at, the number of hops to it
+    pub fn distance<T: Hash + ?Sized>(&self, item: &T) -> Option<usize> {
+        self.levels.iter().position(|level| level.test(item))
+    }
+
+    // Level i + 1 gets the neighbor's level i, the neighbor's last level is dropped.
+    // Both must have the same parameters (see `BloomFilter::union`), the depth may differ.
+    pub fn shift_and_merge(
+        &mut self,
+        neighbor: &AttenuatedBloomFilter<S>,
+    ) -> Result<(), MergeError> {
+        for (level, theirs) in self.levels[1..].iter_mut().zip(&neighbor.levels) {
+            level.union_with(theirs)?;
+        }
+        Ok(())
+    }
+
+    // Empties the levels learned from neighbors, before merging fresh advertisements
+    pub fn clear_remote(&mut self) {
+        for level in &mut self.levels[1..] {
+            level.reset();
+        }
+    }
+
+    pub fn depth(&self) -> usize {
+        self.levels.len()
+    }
+
+    pub fn level(&self, level: usize) -> &BloomFilter<S> {
+        &self.levels[level]
+    }
+
+    pub fn memory_usage(&self) -> usize {
+        size_of::<Self>()
+            + self
+                .levels
+                .iter()
+                .map(|level| level.memory_usage())
+                .sum::<usize>()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_shift_and_merge() {
+        // a - b - c, resources "x" on c and "y" on b
+        let mut c = AttenuatedBloomFilter::new(3, 1024, 3);
+        c.set(0, "x");
+        let mut b = AttenuatedBloomFilter::new(3, 1024, 3);
+        b.set(0, "y");
+        b.shift_and_merge(&c).unwrap();
+        assert_eq!(b.distance("x"), Some(1));
+        assert_eq!(b.distance("y"), Some(0));
+
+        let mut a = AttenuatedBloomFilter::new(3, 1024, 3);
+        a.shift_and_merge(&b).unwrap();
+        assert_eq!(a.distance("y"), Some(1));
+        assert_eq!(a.distance("x"), Some(2));
+        assert!(a.test(2, "x") && !a.test(1, "x"));
+        assert_eq!(a.distance("z"), None);
+
+        // one hop further falls off
+        let mut far = AttenuatedBloomFilter::new(3, 1024, 3);
+        far.shift_and_merge(&a).unwrap();
+        assert_eq!(far.distance("x"), None);
+        assert_eq!(far.distance("y"), Some(2));
+
+        a.clear_remote();
+        assert_eq!(a.distance("y"), None);
+        assert!(a
+            .shift_and_merge(&AttenuatedBloomFilter::new(3, 2048, 3))
+            .is_err());
+    }
+}
@@ -0,0 +1,214 @@
+// Command line tool for filter files in the `BloomFilter::save` format.
+//
+//   bloomf build [--fp 0.01] [--items N] --out keys.bf < keys.txt
+//   bloomf query keys.bf item...
+//   bloomf merge a.bf b.bf... --out merged.bf
+//   bloomf info keys.bf
+//
+// Every input line is one item, hashed like a Rust `&str` (without the line ending), so
+// `bloom.test("item")` in Rust answers the same as `bloomf query`. Without `--items` the
+// filter is sized for the number of lines read. `query` prints "maybe" or "no" per item.
+
+use std::env;
+use std::io::{self, BufRead, Write};
+use std::process::ExitCode;
+
+use bloomf::BloomFilter;
+
+const USAGE: &str = "usage:
+  bloomf build [--fp 0.01] [--items N] --out FILE < items.txt
+  bloomf query FILE ITEM...
+  bloomf merge FILE FILE... --out FILE
+  bloomf info FILE";
+
+fn main() -> ExitCode {
+    let args: Vec<String> = env::args().skip(1).collect();
+    let stdin = io::stdin();
+    let stdout = io::stdout();
+    match run(&args, stdin.lock(), &mut stdout.lock()) {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(err) => {
+            eprintln!("bloomf: {}", err);
+            ExitCode::FAILURE
+        }
+    }
+}
+
+fn run(args: &[String], input: impl BufRead, out: &mut impl Write) -> Result<(), String> {
+    let (command, args) = args.split_first().ok_or(USAGE)?;
+    let mut options = Options::parse(args)?;
+    match command.as_str() {
+        "build" => {
+            let path = options.out.take().ok_or("build needs --out FILE.")?;
+            let fp = options.fp.unwrap_or(0.01);
+            if !(fp > 0.0 && fp < 1.0) {
+                return Err("--fp must be between 0 and 1.".into());
+            }
+            let lines = input
+                .lines()
+                .collect::<io::Result<Vec<String>>>()
+                .map_err(|err| format!("Can't read items: {}.", err))?;
+            let builder = BloomFilter::builder()
+                .expected_items(options.items.unwrap_or(lines.len()))
+                .false_positive_rate(fp);
+            // rates far below 2^-64 need more hashes than `load` takes
+            let mut bloom = BloomFilter::try_new(builder.size(), builder.num_hashes())
+                .map_err(|err| err.to_string())?;
+            for line in &lines {
+                bloom.set(line.as_str());
+            }
+            save(&bloom, &path)
+        }
+        "query" => {
+            let (path, items) = options.files.split_first().ok_or(USAGE)?;
+            let bloom = load(path)?;
+            for item in items {
+                let answer = if bloom.test(item.as_str()) {
+                    "maybe"
+                } else {
+                    "no"
+                };
+                writeln!(out, "{}\t{}", item, answer).map_err(|err| err.to_string())?;
+            }
+            Ok(())
+        }
+        "merge" => {
+            let path = options.out.take().ok_or("merge needs --out FILE.")?;
+            let (first, rest) = options.files.split_first().ok_or(USAGE)?;
+            let mut merged = load(first)?;
+            for file in rest {
+                merged
+                    .union_with(&load(file)?)
+                    .map_err(|err| format!("{}: {}", file, err))?;
+            }
+            save(&merged, &path)
+        }
+        "info" => {
+            let [path] = options.files.as_slice() else {
+                return Err(USAGE.into());
+            };
+            let bloom = load(path)?;
+            writeln!(
+                out,
+                "bits\t{}\nhashes\t{}\nfill ratio\t{:.4}\nestimated items\t{}\ncurrent fpp\t{:.6}",
+                bloom.size(),
+                bloom.num_hashes(),
+                bloom.fill_ratio(),
+                bloom.estimated_len(),
+                bloom.current_fpp()
+            )
+            .map_err(|err| err.to_string())
+        }
+        _ => Err(USAGE.into()),
+    }
+}
+
+#[derive(Default)]
+struct Options {
+    fp: Option<f64>,
+    items: Option<usize>,
+    out: Option<String>,
+    files: Vec<String>,
+}
+
+impl Options {
+    fn parse(args: &[String]) -> Result<Self, String> {
+        let mut options = Options::default();
+        let mut args = args.iter();
+        while let Some(arg) = args.next() {
+            let mut value = |name: &str| {
+                args.next()
+                    .cloned()
+                    .ok_or_else(|| format!("{} needs a value.", name))
+            };
+            match arg.as_str() {
+                "--fp" => {
+                    let fp = value("--fp")?;
+                    options.fp = Some(fp.parse().map_err(|_| format!("Bad --fp {}.", fp))?);
+                }
+                "--items" => {
+                    let items = value("--items")?;
+                    options.items = Some(
+                        items
+                            .parse()
+                            .map_err(|_| format!("Bad --items {}.", items))?,
+                    );
+                }
+                "--out" => options.out = Some(value("--out")?),
+                _ => options.files.push(arg.clone()),
+            }
+        }
+        Ok(options)
+    }
+}
+
+fn load(path: &str) -> Result<BloomFilter, String> {
+    BloomFilter::load(path).map_err(|err| format!("{}: {}", path, err))
+}
+
+fn save(bloom: &BloomFilter, path: &str) -> Result<(), String> {
+    bloom.save(path).map_err(|err| format!("{}: {}", path, err))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(line: &str) -> Vec<String> {
+        line.split_whitespace().map(String::from).collect()
+    }
+
+    #[test]
+    fn test_build_query_merge() {
+        let dir = env::temp_dir().join(format!("bloomf_cli_{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let a = dir.join("a.bf").display().to_string();
+        let b = dir.join("b.bf").display().to_string();
+        let merged = dir.join("merged.bf").display().to_string();
+
+        let build = |path: &str, input: &str| {
+            let line = format!("build --fp 0.001 --items 100 --out {}", path);
+            run(&args(&line), input.as_bytes(), &mut Vec::new())
+        };
+        build(&a, "foo\nbar\r\n").unwrap();
+        build(&b, "baz\n").unwrap();
+        assert!(BloomFilter::load(&a).unwrap().test("bar"));
+
+        run(
+            &args(&format!("merge {} {} --out {}", a, b, merged)),
+            &b""[..],
+            &mut Vec::new(),
+        )
+        .unwrap();
+        let mut out = Vec::new();
+        run(
+            &args(&format!("query {} foo baz qux", merged)),
+            &b""[..],
+            &mut out,
+        )
+        .unwrap();
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "foo\tmaybe\nbaz\tmaybe\nqux\tno\n"
+        );
+
+        let mut out = Vec::new();
+        run(&args(&format!("info {}", a)), &b""[..], &mut out).unwrap();
+        assert!(String::from_utf8(out)
+            .unwrap()
+            .starts_with("bits\t1438\nhashes\t10\n"));
+
+        assert!(run(&args("build --out"), &b""[..], &mut Vec::new()).is_err());
+        let tiny = dir.join("tiny.bf");
+        let line = format!("build --fp 1e-300 --out {}", tiny.display());
+        assert!(run(&args(&line), &b"foo\n"[..], &mut Vec::new()).is_err());
+        assert!(!tiny.exists());
+        assert!(run(
+            &args(&format!("query {}.missing x", a)),
+            &b""[..],
+            &mut Vec::new()
+        )
+        .is_err());
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
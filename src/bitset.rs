@@ -0,0 +1,305 @@
+// Packed bit storage: 64 bits per word instead of one byte per `bool`.
+// Bit `idx` lives in word `idx / 64` at position `idx % 64`.
+
+use std::sync::atomic::{AtomicU64, Ordering};
+
+#[derive(Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub(crate) struct BitVec {
+    words: Vec<u64>,
+}
+
+pub(crate) fn word_count(len: usize) -> usize {
+    len.div_ceil(64)
+}
+
+impl BitVec {
+    pub(crate) fn new(len: usize) -> Self {
+        BitVec {
+            words: vec![0; word_count(len)],
+        }
+    }
+
+    pub(crate) fn from_words(words: Vec<u64>) -> Self {
+        BitVec { words }
+    }
+
+    pub(crate) fn words(&self) -> &[u64] {
+        &self.words
+    }
+
+    pub(crate) fn words_mut(&mut self) -> &mut [u64] {
+        &mut self.words
+    }
+
+    #[cfg(feature = "rayon")]
+    pub(crate) fn into_words(self) -> Vec<u64> {
+        self.words
+    }
+
+    pub(crate) fn heap_bytes(&self) -> usize {
+        self.words.capacity() * size_of::<u64>()
+    }
+
+    pub(crate) fn count_ones(&self) -> usize {
+        self.words
+            .iter()
+            .map(|word| word.count_ones() as usize)
+            .sum()
+    }
+
+    // Both must have the same length
+    pub(crate) fn union_with(&mut self, other: &BitVec) {
+        for (word, other) in self.words.iter_mut().zip(&other.words) {
+            *word |= other;
+        }
+    }
+
+    pub(crate) fn intersect_with(&mut self, other: &BitVec) {
+        for (word, other) in self.words.iter_mut().zip(&other.words) {
+            *word &= other;
+        }
+    }
+
+    // Indices of the set bits in increasing order
+    pub(crate) fn ones(&self) -> impl Iterator<Item = usize> + '_ {
+        self.words
+            .iter()
+            .enumerate()
+            .flat_map(|(i, &word)| word_ones(i, word))
+    }
+
+    pub(crate) fn get(&self, idx: usize) -> bool {
+        self.words[idx / 64] & (1 << (idx % 64)) != 0
+    }
+
+    pub(crate) fn set(&mut self, idx: usize) {
+        self.words[idx / 64] |= 1 << (idx % 64);
+    }
+
+    // Sets the bit and returns whether it was already set
+    pub(crate) fn test_and_set(&mut self, idx: usize) -> bool {
+        let was_set = self.get(idx);
+        self.set(idx);
+        was_set
+    }
+
+    pub(crate) fn unset(&mut self, idx: usize) {
+        self.words[idx / 64] &= !(1 << (idx % 64));
+    }
+
+    pub(crate) fn clear(&mut self) {
+        self.words.fill(0);
+    }
+
+    // `indices` holds `k` indices per item, returns whether all bits of every item are set
+    pub(crate) fn test_many(&self, indices: &[usize], k: usize) -> Vec<bool> {
+        #[cfg(all(feature = "simd", target_arch = "x86_64"))]
+        if is_x86_feature_detected!("avx2") {
+            return indices
+                .chunks(k)
+                // SAFETY: avx2 is available and every index is within the bit vector
+                .map(|item| unsafe { all_set_avx2(&self.words, item) })
+                .collect();
+        }
+        indices
+            .chunks(k)
+            .map(|item| item.iter().all(|&idx| self.get(idx)))
+            .collect()
+    }
+}
+
+// Gathers the words of 4 indices at once and checks their bits in one go
+#[cfg(all(feature = "simd", target_arch = "x86_64"))]
+#[target_feature(enable = "avx2")]
+unsafe fn all_set_avx2(words: &[u64], indices: &[usize]) -> bool {
+    use std::arch::x86_64::*;
+
+    let mut chunks = indices.chunks_exact(4);
+    for chunk in &mut chunks {
+        let idx = _mm256_set_epi64x(
+            chunk[3] as i64,
+            chunk[2] as i64,
+            chunk[1] as i64,
+            chunk[0] as i64,
+        );
+        let word_idx = _mm256_srli_epi64::<6>(idx);
+        let gathered = _mm256_i64gather_epi64::<8>(words.as_ptr() as *const i64, word_idx);
+        let masks = _mm256_sllv_epi64(
+            _mm256_set1_epi64x(1),
+            _mm256_and_si256(idx, _mm256_set1_epi64x(63)),
+        );
+        let unset = _mm256_cmpeq_epi64(_mm256_and_si256(gathered, masks), _mm256_setzero_si256());
+        if _mm256_movemask_epi8(unset) != 0 {
+            return false;
+        }
+    }
+    chunks
+        .remainder()
+        .iter()
+        .all(|&idx| words[idx / 64] & (1 << (idx % 64)) != 0)
+}
+
+// Indices of the set bits of the i-th word
+fn word_ones(i: usize, mut word: u64) -> impl Iterator<Item = usize> {
+    std::iter::from_fn(move || {
+        if word == 0 {
+            return None;
+        }
+        let bit = word.trailing_zeros() as usize;
+        word &= word - 1;
+        Some(i * 64 + bit)
+    })
+}
+
+// Same layout as `BitVec` but shareable between threads, bits are set with `fetch_or`
+pub(crate) struct AtomicBitVec {
+    words: Vec<AtomicU64>,
+}
+
+impl AtomicBitVec {
+    pub(crate) fn new(len: usize) -> Self {
+        AtomicBitVec {
+            words: (0..word_count(len)).map(|_| AtomicU64::new(0)).collect(),
+        }
+    }
+
+    pub(crate) fn from_words(words: Vec<u64>) -> Self {
+        AtomicBitVec {
+            words: words.into_iter().map(AtomicU64::new).collect(),
+        }
+    }
+
+    pub(crate) fn heap_bytes(&self) -> usize {
+        self.words.capacity() * size_of::<AtomicU64>()
+    }
+
+    pub(crate) fn count_ones(&self) -> usize {
+        self.words
+            .iter()
+            .map(|word| word.load(Ordering::Relaxed).count_ones() as usize)
+            .sum()
+    }
+
+    // Snapshot of the words, bits set concurrently may or may not be included
+    pub(crate) fn to_words(&self) -> Vec<u64> {
+        self.words
+            .iter()
+            .map(|word| word.load(Ordering::Relaxed))
+            .collect()
+    }
+
+    // Same as `BitVec::ones`, every word is read when the iterator gets to it
+    pub(crate) fn ones(&self) -> impl Iterator<Item = usize> + '_ {
+        self.words
+            .iter()
+            .enumerate()
+            .flat_map(|(i, word)| word_ones(i, word.load(Ordering::Relaxed)))
+    }
+
+    pub(crate) fn into_words(self) -> Vec<u64> {
+        self.words.into_iter().map(AtomicU64::into_inner).collect()
+    }
+
+    pub(crate) fn get(&self, idx: usize) -> bool {
+        self.words[idx / 64].load(Ordering::Relaxed) & (1 << (idx % 64)) != 0
+    }
+
+    pub(crate) fn set(&self, idx: usize) {
+        self.words[idx / 64].fetch_or(1 << (idx % 64), Ordering::Relaxed);
+    }
+
+    pub(crate) fn test_and_set(&self, idx: usize) -> bool {
+        let mask = 1 << (idx % 64);
+        self.words[idx / 64].fetch_or(mask, Ordering::Relaxed) & mask != 0
+    }
+
+    pub(crate) fn clear(&self) {
+        for word in &self.words {
+            word.store(0, Ordering::Relaxed);
+        }
+    }
+
+    // Zeroes every word and returns what it held, each word in one atomic swap
+    pub(crate) fn take_words(&self) -> Vec<u64> {
+        self.words
+            .iter()
+            .map(|word| word.swap(0, Ordering::Relaxed))
+            .collect()
+    }
+}
+
+// Serialized as a snapshot of the words, same as `BitVec`
+#[cfg(feature = "serde")]
+impl serde::Serialize for AtomicBitVec {
+    fn serialize<Ser: serde::Serializer>(&self, serializer: Ser) -> Result<Ser::Ok, Ser::Error> {
+        use serde::ser::SerializeStruct;
+
+        let words = self.to_words();
+        let mut state = serializer.serialize_struct("BitVec", 1)?;
+        state.serialize_field("words", &words)?;
+        state.end()
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<'de> serde::Deserialize<'de> for AtomicBitVec {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let bits = BitVec::deserialize(deserializer)?;
+        Ok(AtomicBitVec::from_words(bits.words))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_many_matches_get() {
+        let mut bits = BitVec::new(1000);
+        for idx in (0..1000).step_by(3) {
+            bits.set(idx);
+        }
+        let indices: Vec<usize> = (0..1000).collect();
+        for k in [1, 3, 4, 5, 8] {
+            let expected: Vec<bool> = indices
+                .chunks(k)
+                .map(|item| item.iter().all(|&idx| bits.get(idx)))
+                .collect();
+            assert_eq!(bits.test_many(&indices, k), expected);
+        }
+        let all_set: Vec<usize> = (0..999).step_by(3).collect();
+        assert!(bits.test_many(&all_set, 7).iter().all(|&hit| hit));
+    }
+
+    #[test]
+    fn test_get_set_across_words() {
+        let mut bits = BitVec::new(130);
+        assert_eq!(bits.words.len(), 3);
+
+        for idx in [0, 63, 64, 129] {
+            assert!(!bits.get(idx));
+            bits.set(idx);
+            assert!(bits.get(idx));
+        }
+        assert!(!bits.get(1));
+        assert!(!bits.get(65));
+
+        bits.clear();
+        assert!(!bits.get(129));
+    }
+
+    #[test]
+    fn test_atomic_get_set_across_words() {
+        let bits = AtomicBitVec::new(130);
+        assert_eq!(bits.words.len(), 3);
+
+        for idx in [0, 63, 64, 129] {
+            assert!(!bits.get(idx));
+            bits.set(idx);
+            assert!(bits.get(idx));
+        }
+        assert!(!bits.get(1));
+        assert!(!bits.get(65));
+    }
+}
@@ -0,0 +1,167 @@
+// Blocked Bloom filter (Putze, Sanders & Singler 2007): the bit array is split into 512 bit
+// blocks, one cache line each, and all k bits of an item are set in the same block. Queries touch
+// a single cache line instead of k random ones, at the cost of a slightly higher false positive
+// rate than a standard filter with the same number of bits (blocks fill unevenly).
+//
+// The item hash picks the block, a remix of it gives the k positions inside the block.
+
+use std::hash::{BuildHasher, Hash};
+
+use crate::hash::{self, mix, Sha256BuildHasher};
+use crate::heap_bytes;
+use crate::stats::FilterStats;
+
+const BLOCK_BITS: usize = 512;
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+#[repr(C, align(64))]
+struct Block([u64; 8]);
+
+pub struct BlockedBloomFilter<S = Sha256BuildHasher> {
+    blocks: Vec<Block>,
+    num_hashes: usize,
+    hasher: S,
+    seed: u64,
+}
+
+impl BlockedBloomFilter {
+    // size is rounded up to a multiple of 512 bits
+    pub fn new(size: usize, num_hashes: usize) -> Self {
+        Self::with_hasher(size, num_hashes, Sha256BuildHasher)
+    }
+}
+
+impl<S: BuildHasher> BlockedBloomFilter<S> {
+    pub fn with_hasher(size: usize, num_hashes: usize, hasher: S) -> Self {
+        assert!(num_hashes > 0, "number of hashes must not be 0");
+        BlockedBloomFilter {
+            blocks: vec![Block([0; 8]); size.div_ceil(BLOCK_BITS).max(1)],
+            num_hashes,
+            hasher,
+            seed: 0,
+        }
+    }
+
+    // Same as `BloomFilter::with_seed`
+    pub fn with_seed(mut self, seed: u64) -> Self {
+        self.seed = seed;
+        self
+    }
+
+    pub fn seed(&self) -> u64 {
+        self.seed
+    }
+
+    // Block index and the double hashing seeds for the bits inside it
+    fn locate<T: Hash + ?Sized>(&self, item: &T) -> (usize, u32, u32) {
+        let seeded = hash::Seeded {
+            build_hasher: &self.hasher,
+            seed: self.seed,
+        };
+        let hash = seeded.hash_one(item);
+        let block = ((hash as u128 * self.blocks.len() as u128) >> 64) as usize;
+        let bits = mix(hash, 0);
+        (block, bits as u32, (bits >> 32) as u32 | 1)
+    }
+
+    pub fn insert<T: Hash + ?Sized>(&mut self, item: &T) {
+        let (block, h1, h2) = self.locate(item);
+        let block = &mut self.blocks[block].0;
+        for i in 0..self.num_hashes as u32 {
+            let bit = h1.wrapping_add(i.wrapping_mul(h2)) as usize % BLOCK_BITS;
+            block[bit / 64] |= 1 << (bit % 64);
+        }
+    }
+
+    pub fn contains<T: Hash + ?Sized>(&self, item: &T) -> bool {
+        let (block, h1, h2) = self.locate(item);
+        self.block_contains(block, h1, h2)
+    }
+
+    // Same as calling `contains` for every item, but hashes all of them before touching the
+    // blocks so the cache misses of different items can overlap
+    pub fn test_many<T: Hash>(&self, items: &[T]) -> Vec<bool> {
+        let located: Vec<_> = items.iter().map(|item| self.locate(item)).collect();
+        located
+            .into_iter()
+            .map(|(block, h1, h2)| self.block_contains(block, h1, h2))
+            .collect()
+    }
+
+    fn block_contains(&self, block: usize, h1: u32, h2: u32) -> bool {
+        let block = &self.blocks[block].0;
+        (0..self.num_hashes as u32).all(|i| {
+            let bit = h1.wrapping_add(i.wrapping_mul(h2)) as usize % BLOCK_BITS;
+            block[bit / 64] & (1 << (bit % 64)) != 0
+        })
+    }
+
+    pub fn num_blocks(&self) -> usize {
+        self.blocks.len()
+    }
+
+    pub fn bit_len(&self) -> usize {
+        self.blocks.len() * BLOCK_BITS
+    }
+
+    pub fn num_hashes(&self) -> usize {
+        self.num_hashes
+    }
+
+    pub fn memory_usage(&self) -> usize {
+        size_of::<Self>() + heap_bytes(&self.blocks)
+    }
+
+    // Estimates per block, the false positive probability is the average of the blocks'
+    // fill^k, higher than the one of a standard filter with the same fill ratio
+    pub fn stats(&self) -> FilterStats {
+        let set_bits = self.blocks.iter().map(|block| {
+            block
+                .0
+                .iter()
+                .map(|word| word.count_ones() as usize)
+                .sum::<usize>()
+        });
+        FilterStats::from_parts(BLOCK_BITS, self.num_hashes, set_bits, self.memory_usage())
+    }
+
+    pub fn clear(&mut self) {
+        self.blocks.fill(Block([0; 8]));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_blocked_false_positive_rate() {
+        let mut bloom = crate::BloomFilter::builder()
+            .expected_items(10_000)
+            .false_positive_rate(0.01)
+            .build_blocked();
+        assert_eq!(std::mem::align_of::<Block>(), 64);
+        assert_eq!(bloom.bit_len() % 512, 0);
+
+        for i in 0..10_000u32 {
+            bloom.insert(&i);
+        }
+        for i in 0..10_000u32 {
+            assert!(bloom.contains(&i));
+        }
+        let false_positives = (10_000..110_000u32).filter(|i| bloom.contains(i)).count();
+        // a bit worse than the 1% of a standard filter
+        assert!(
+            false_positives < 1_500,
+            "{} false positives",
+            false_positives
+        );
+
+        let queries: Vec<u32> = (5_000..15_000).collect();
+        let expected: Vec<bool> = queries.iter().map(|i| bloom.contains(i)).collect();
+        assert_eq!(bloom.test_many(&queries), expected);
+
+        bloom.clear();
+        assert!(!bloom.contains(&1u32));
+    }
+}
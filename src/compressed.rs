@@ -0,0 +1,287 @@
+// Compressed transmission format for `BloomFilter` (Mitzenmacher 2002), ex. for sharing cache
+// summaries between proxies. `compress` arithmetic codes the bits with the filter's own fill
+// ratio as the probability of a set bit, so a filter with fill f costs about m * H(f) bits on
+// the wire instead of m. The optimal filter is half full and gains nothing, build filters meant
+// for sending with `params::compressed_params` (or `BloomFilterBuilder::build_for_compression`):
+// fewer hashes over more bits send fewer bytes for the same false positive rate, the receiver
+// pays with the larger m in memory.
+//
+// offset  size  field (little-endian)
+// 0       4     magic b"BLMZ"
+// 4       2     format version, currently 1
+// 6       2     flags, 0
+// 8       8     size of the bit array in bits (m)
+// 16      4     number of hashes (k)
+// 20      4     layout version (`hash::LAYOUT_VERSION`)
+// 24      8     seed, 0 for unseeded filters
+// 32      2     probability of a 0 bit in 1/4096ths
+// 34      ..    range coded bits, bit 0 first
+
+use std::hash::BuildHasher;
+
+use crate::bitset::word_count;
+use crate::error::BloomError;
+use crate::hash::{Sha256BuildHasher, LAYOUT_VERSION};
+use crate::BloomFilter;
+
+const MAGIC: [u8; 4] = *b"BLMZ";
+const VERSION: u16 = 1;
+const HEADER_LEN: usize = 34;
+const PROB_BITS: u32 = 12;
+const PROB_ONE: u32 = 1 << PROB_BITS;
+const TOP: u32 = 1 << 24;
+
+impl BloomFilter {
+    pub fn decompress(bytes: &[u8]) -> Result<Self, BloomError> {
+        Self::decompress_with_hasher(bytes, Sha256BuildHasher)
+    }
+}
+
+impl<S: BuildHasher> BloomFilter<S> {
+    // Like `to_bytes`, the hasher and custom hash functions are not included
+    pub fn compress(&self) -> Vec<u8> {
+        let words = self.bit_array.words();
+        let ones = self.bit_array.ones().count();
+        let zeros = self.size - ones;
+        // an empty bit array has no ratio, any probability encodes it
+        let prob_zero = (zeros as u64 * PROB_ONE as u64 + self.size as u64 / 2)
+            .checked_div(self.size as u64)
+            .unwrap_or(PROB_ONE as u64 / 2)
+            .clamp(1, PROB_ONE as u64 - 1) as u16;
+
+        let mut encoder = RangeEncoder::new(HEADER_LEN + self.size / 64);
+        encoder.out.extend_from_slice(&MAGIC);
+        encoder.out.extend_from_slice(&VERSION.to_le_bytes());
+        encoder.out.extend_from_slice(&0u16.to_le_bytes());
+        encoder
+            .out
+            .extend_from_slice(&(self.size as u64).to_le_bytes());
+        encoder
+            .out
+            .extend_from_slice(&(self.num_hashes as u32).to_le_bytes());
+        encoder.out.extend_from_slice(&LAYOUT_VERSION.to_le_bytes());
+        encoder.out.extend_from_slice(&self.seed.to_le_bytes());
+        encoder.out.extend_from_slice(&prob_zero.to_le_bytes());
+        for idx in 0..self.size {
+            encoder.encode(words[idx / 64] >> (idx % 64) & 1 == 1, prob_zero as u32);
+        }
+        encoder.finish()
+    }
+
+    // The hasher must be the one the filter was built with
+    pub fn decompress_with_hasher(bytes: &[u8], hasher: S) -> Result<Self, BloomError> {
+        let (size, num_hashes, seed, words) =
+            decode(bytes).map_err(BloomError::SerializationError)?;
+        Ok(Self::from_parts(size, num_hashes, words, hasher).with_seed(seed))
+    }
+}
+
+fn decode(bytes: &[u8]) -> Result<(usize, usize, u64, Vec<u64>), String> {
+    if bytes.len() < HEADER_LEN {
+        return Err("Compressed filter is too short.".into());
+    }
+    if bytes[0..4] != MAGIC {
+        return Err("Not a compressed filter (bad magic).".into());
+    }
+    let version = u16::from_le_bytes([bytes[4], bytes[5]]);
+    if version != VERSION {
+        return Err(format!(
+            "Unsupported compressed format version {}.",
+            version
+        ));
+    }
+    let flags = u16::from_le_bytes([bytes[6], bytes[7]]);
+    if flags != 0 {
+        return Err(format!("Unsupported format flags {:#x}.", flags));
+    }
+    let size = u64::from_le_bytes(bytes[8..16].try_into().unwrap());
+    let num_hashes = u32::from_le_bytes(bytes[16..20].try_into().unwrap()) as usize;
+    let layout = u32::from_le_bytes(bytes[20..24].try_into().unwrap());
+    if layout != LAYOUT_VERSION {
+        return Err(format!(
+            "Filter was encoded with layout version {}, this build uses {}. Rebuild it.",
+            layout, LAYOUT_VERSION
+        ));
+    }
+    let seed = u64::from_le_bytes(bytes[24..32].try_into().unwrap());
+    let prob_zero = u16::from_le_bytes([bytes[32], bytes[33]]) as u32;
+    if size == 0 || num_hashes == 0 || prob_zero == 0 || prob_zero >= PROB_ONE {
+        return Err("Invalid compressed filter parameters.".into());
+    }
+    // every bit costs at least log2(4096 / 4095) bits, checked before allocating
+    let data = &bytes[HEADER_LEN..];
+    let max_bits = (data.len() as f64 * 8.0) / (PROB_ONE as f64 / (PROB_ONE - 1) as f64).log2();
+    if size as f64 > max_bits {
+        return Err("Compressed filter is truncated.".into());
+    }
+    let size = size as usize;
+
+    let mut decoder = RangeDecoder::new(data)?;
+    let mut words = vec![0u64; word_count(size)];
+    for idx in 0..size {
+        if decoder.decode(prob_zero)? {
+            words[idx / 64] |= 1 << (idx % 64);
+        }
+    }
+    Ok((size, num_hashes, seed, words))
+}
+
+// Binary range coder with a fixed probability, the carry propagating one of LZMA
+struct RangeEncoder {
+    out: Vec<u8>,
+    low: u64,
+    range: u32,
+    cache: u8,
+    cache_size: u64,
+}
+
+impl RangeEncoder {
+    fn new(capacity: usize) -> Self {
+        RangeEncoder {
+            out: Vec::with_capacity(capacity),
+            low: 0,
+            range: u32::MAX,
+            cache: 0,
+            cache_size: 1,
+        }
+    }
+
+    // `prob_zero` out of PROB_ONE
+    fn encode(&mut self, bit: bool, prob_zero: u32) {
+        let bound = (self.range >> PROB_BITS) * prob_zero;
+        if bit {
+            self.low += bound as u64;
+            self.range -= bound;
+        } else {
+            self.range = bound;
+        }
+        while self.range < TOP {
+            self.range <<= 8;
+            self.shift_low();
+        }
+    }
+
+    fn shift_low(&mut self) {
+        if self.low < 0xff00_0000 || self.low > u32::MAX as u64 {
+            let carry = (self.low >> 32) as u8;
+            let mut byte = self.cache;
+            loop {
+                self.out.push(byte.wrapping_add(carry));
+                byte = 0xff;
+                self.cache_size -= 1;
+                if self.cache_size == 0 {
+                    break;
+                }
+            }
+            self.cache = (self.low >> 24) as u8;
+        }
+        self.cache_size += 1;
+        self.low = (self.low & 0x00ff_ffff) << 8;
+    }
+
+    fn finish(mut self) -> Vec<u8> {
+        for _ in 0..5 {
+            self.shift_low();
+        }
+        self.out
+    }
+}
+
+struct RangeDecoder<'a> {
+    data: &'a [u8],
+    position: usize,
+    code: u32,
+    range: u32,
+}
+
+impl<'a> RangeDecoder<'a> {
+    fn new(data: &'a [u8]) -> Result<Self, String> {
+        let mut decoder = RangeDecoder {
+            data,
+            position: 0,
+            code: 0,
+            range: u32::MAX,
+        };
+        for _ in 0..5 {
+            decoder.code = decoder.code << 8 | decoder.next_byte()? as u32;
+        }
+        Ok(decoder)
+    }
+
+    fn next_byte(&mut self) -> Result<u8, String> {
+        let byte = *self
+            .data
+            .get(self.position)
+            .ok_or("Compressed filter is truncated.")?;
+        self.position += 1;
+        Ok(byte)
+    }
+
+    fn decode(&mut self, prob_zero: u32) -> Result<bool, String> {
+        let bound = (self.range >> PROB_BITS) * prob_zero;
+        let bit = if self.code < bound {
+            self.range = bound;
+            false
+        } else {
+            self.code -= bound;
+            self.range -= bound;
+            true
+        };
+        while self.range < TOP {
+            self.range <<= 8;
+            self.code = self.code << 8 | self.next_byte()? as u32;
+        }
+        Ok(bit)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_compress() {
+        let n = 10_000;
+        let mut sparse = BloomFilter::builder()
+            .expected_items(n)
+            .seed(5)
+            .build_for_compression(20.0);
+        let mut optimal = BloomFilter::builder().expected_items(n).build();
+        for i in 0..n as u32 {
+            sparse.set(&i);
+            optimal.set(&i);
+        }
+        let compressed = sparse.compress();
+        let decompressed = BloomFilter::decompress(&compressed).unwrap();
+        assert_eq!(decompressed, sparse);
+        assert_eq!(decompressed.seed(), 5);
+        let false_positives = (n as u32..n as u32 + 100_000)
+            .filter(|i| decompressed.test(i))
+            .count();
+        assert!(
+            false_positives < 1300,
+            "{} false positives",
+            false_positives
+        );
+
+        // same false positive rate, fewer bytes than either encoding of the optimal filter
+        let optimal_compressed = optimal.compress();
+        assert!(compressed.len() < optimal_compressed.len());
+        assert!(compressed.len() < optimal.to_bytes().len() * 95 / 100);
+        assert_eq!(
+            BloomFilter::decompress(&optimal_compressed).unwrap(),
+            optimal
+        );
+
+        // nothing to decompress into, but compressing doesn't panic
+        let empty = BloomFilter::new(0, 3).compress();
+        assert!(BloomFilter::decompress(&empty).is_err());
+
+        let empty = BloomFilter::new(100_000, 3);
+        assert!(empty.compress().len() < 100);
+        assert_eq!(BloomFilter::decompress(&empty.compress()).unwrap(), empty);
+
+        assert!(BloomFilter::decompress(&compressed[..compressed.len() / 2]).is_err());
+        assert!(BloomFilter::decompress(&sparse.to_bytes()).is_err());
+    }
+}
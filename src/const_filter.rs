@@ -0,0 +1,106 @@
+// Bloom filter with its size and number of hashes fixed at compile time, stored inline in a
+// `[u64; WORDS]` (64 * WORDS bits) so it never allocates: it can live on the stack, in a static
+// or inside another struct. Const generics can't compute `BITS / 64` on stable Rust, so the
+// size is given in words.
+//
+// Items land on the same bits as in `BloomFilter::new(64 * WORDS, K)` with the same hasher.
+
+use std::hash::{BuildHasher, Hash};
+
+use crate::hash::{self, Sha256BuildHasher};
+use crate::stats::FilterStats;
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub struct ConstBloomFilter<const WORDS: usize, const K: usize, S = Sha256BuildHasher> {
+    words: [u64; WORDS],
+    hasher: S,
+}
+
+impl<const WORDS: usize, const K: usize> ConstBloomFilter<WORDS, K> {
+    // Ex. static SEEN: Mutex<ConstBloomFilter<16, 4>> = Mutex::new(ConstBloomFilter::new());
+    pub const fn new() -> Self {
+        Self::with_hasher(Sha256BuildHasher)
+    }
+}
+
+impl<const WORDS: usize, const K: usize> Default for ConstBloomFilter<WORDS, K> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<const WORDS: usize, const K: usize, S> ConstBloomFilter<WORDS, K, S> {
+    pub const fn with_hasher(hasher: S) -> Self {
+        const {
+            assert!(WORDS > 0, "ConstBloomFilter needs at least one word");
+            assert!(K > 0, "ConstBloomFilter needs at least one hash");
+        }
+        ConstBloomFilter {
+            words: [0; WORDS],
+            hasher,
+        }
+    }
+
+    pub const fn bit_len(&self) -> usize {
+        WORDS * 64
+    }
+
+    pub const fn num_hashes(&self) -> usize {
+        K
+    }
+
+    // Everything is inline, no heap allocations
+    pub const fn memory_usage(&self) -> usize {
+        size_of::<Self>()
+    }
+
+    pub fn stats(&self) -> FilterStats {
+        let set_bits = self
+            .words
+            .iter()
+            .map(|word| word.count_ones() as usize)
+            .sum();
+        FilterStats::new(WORDS * 64, K, set_bits, self.memory_usage())
+    }
+
+    pub fn clear(&mut self) {
+        self.words = [0; WORDS];
+    }
+}
+
+impl<const WORDS: usize, const K: usize, S: BuildHasher> ConstBloomFilter<WORDS, K, S> {
+    pub fn insert<T: Hash + ?Sized>(&mut self, item: &T) {
+        for idx in hash::indices(&self.hasher, item, K, WORDS * 64) {
+            self.words[idx / 64] |= 1 << (idx % 64);
+        }
+    }
+
+    pub fn contains<T: Hash + ?Sized>(&self, item: &T) -> bool {
+        hash::indices(&self.hasher, item, K, WORDS * 64)
+            .all(|idx| self.words[idx / 64] & (1 << (idx % 64)) != 0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::BloomFilter;
+
+    #[test]
+    fn test_const_filter() {
+        let mut filter = ConstBloomFilter::<16, 4>::new();
+        assert_eq!(filter.bit_len(), 1024);
+        assert_eq!(std::mem::size_of_val(&filter), 16 * 8);
+
+        let mut bloom = BloomFilter::new(1024, 4);
+        for i in 0..100u32 {
+            filter.insert(&i);
+            bloom.set(&i);
+        }
+        assert!((0..100u32).all(|i| filter.contains(&i)));
+        assert!((0..10_000u32).all(|i| filter.contains(&i) == bloom.test(&i)));
+
+        filter.clear();
+        assert!(!filter.contains(&1u32));
+    }
+}
@@ -0,0 +1,152 @@
+// Count-Min sketch (Cormode & Muthukrishnan 2005) for frequency estimation over streams.
+// depth rows of width counters, every item increments one counter per row and its estimate is
+// the minimum of those counters. With width = e / epsilon and depth = ln(1 / delta) the estimate
+// overcounts by more than epsilon * total count with probability at most delta.
+
+use std::hash::{BuildHasher, Hash};
+
+use crate::error::MergeError;
+use crate::hash::{self, Sha256BuildHasher};
+use crate::heap_bytes;
+
+pub struct CountMinSketch<S = Sha256BuildHasher> {
+    counters: Vec<u64>,
+    width: usize,
+    depth: usize,
+    total: u64,
+    hasher: S,
+}
+
+impl CountMinSketch {
+    pub fn new(width: usize, depth: usize) -> Self {
+        Self::with_hasher(width, depth, Sha256BuildHasher)
+    }
+
+    // epsilon: error bound relative to the total count, delta: probability of exceeding it
+    pub fn with_error(epsilon: f64, delta: f64) -> Self {
+        let (width, depth) = Self::dimensions(epsilon, delta);
+        Self::new(width, depth)
+    }
+
+    // (width, depth) for the given error bounds
+    pub fn dimensions(epsilon: f64, delta: f64) -> (usize, usize) {
+        assert!(epsilon > 0.0 && epsilon < 1.0, "epsilon must be in (0, 1)");
+        assert!(delta > 0.0 && delta < 1.0, "delta must be in (0, 1)");
+        let width = (std::f64::consts::E / epsilon).ceil() as usize;
+        let depth = (1.0 / delta).ln().ceil().max(1.0) as usize;
+        (width, depth)
+    }
+}
+
+impl<S: BuildHasher> CountMinSketch<S> {
+    pub fn with_hasher(width: usize, depth: usize, hasher: S) -> Self {
+        assert!(width > 0 && depth > 0, "width and depth must not be 0");
+        CountMinSketch {
+            counters: vec![0; width * depth],
+            width,
+            depth,
+            total: 0,
+            hasher,
+        }
+    }
+
+    pub fn increment<T: Hash + ?Sized>(&mut self, item: &T) {
+        self.increment_by(item, 1);
+    }
+
+    pub fn increment_by<T: Hash + ?Sized>(&mut self, item: &T, count: u64) {
+        // one column per row, derived with the same double hashing as the filters
+        for (row, column) in hash::indices(&self.hasher, item, self.depth, self.width).enumerate() {
+            let counter = &mut self.counters[row * self.width + column];
+            *counter = counter.saturating_add(count);
+        }
+        self.total = self.total.saturating_add(count);
+    }
+
+    pub fn estimate<T: Hash + ?Sized>(&self, item: &T) -> u64 {
+        hash::indices(&self.hasher, item, self.depth, self.width)
+            .enumerate()
+            .map(|(row, column)| self.counters[row * self.width + column])
+            .min()
+            .unwrap_or(0)
+    }
+
+    // Adds the counts of a sketch built with the same dimensions and hasher
+    pub fn merge(&mut self, other: &CountMinSketch<S>) -> Result<(), MergeError> {
+        let (left, right) = (self.params_fingerprint(), other.params_fingerprint());
+        if left != right {
+            return Err(MergeError::FingerprintMismatch { left, right });
+        }
+        for (counter, other) in self.counters.iter_mut().zip(&other.counters) {
+            *counter = counter.saturating_add(*other);
+        }
+        self.total = self.total.saturating_add(other.total);
+        Ok(())
+    }
+
+    // Hash of width, depth, hasher and layout version, sketches can only be merged when it's equal
+    pub fn params_fingerprint(&self) -> u64 {
+        hash::params_fingerprint(&self.hasher, &[self.width as u64, self.depth as u64])
+    }
+
+    // Sum of all increments
+    pub fn total(&self) -> u64 {
+        self.total
+    }
+
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    pub fn depth(&self) -> usize {
+        self.depth
+    }
+
+    pub fn memory_usage(&self) -> usize {
+        size_of::<Self>() + heap_bytes(&self.counters)
+    }
+
+    pub fn clear(&mut self) {
+        self.counters.fill(0);
+        self.total = 0;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_estimates_within_bounds() {
+        assert_eq!(CountMinSketch::dimensions(0.001, 0.01), (2719, 5));
+
+        let mut sketch = CountMinSketch::with_error(0.01, 0.01);
+        for i in 0..10_000u32 {
+            sketch.increment(&(i % 100));
+        }
+        sketch.increment_by("heavy", 5000);
+        assert_eq!(sketch.total(), 15_000);
+
+        assert!(sketch.estimate("heavy") >= 5000);
+        assert!(sketch.estimate("heavy") <= 5000 + 150);
+        for i in 0..100u32 {
+            assert!(sketch.estimate(&i) >= 100);
+            assert!(sketch.estimate(&i) <= 100 + 150);
+        }
+    }
+
+    #[test]
+    fn test_merge() {
+        let mut a = CountMinSketch::new(100, 4);
+        let mut b = CountMinSketch::new(100, 4);
+        a.increment_by("foo", 3);
+        b.increment_by("foo", 4);
+        b.increment("bar");
+        a.merge(&b).unwrap();
+        assert_eq!(a.estimate("foo"), 7);
+        assert_eq!(a.estimate("bar"), 1);
+        assert_eq!(a.total(), 8);
+
+        assert!(a.merge(&CountMinSketch::new(50, 4)).is_err());
+    }
+}
@@ -0,0 +1,363 @@
+// Counting Bloom filter: every slot is a small counter instead of a bit so items can be
+// removed again. Uses the same double hashing scheme as `BloomFilter`.
+//
+// `ConcurrentCountingBloomFilter` is the same filter with atomic counters, updated with
+// compare-and-swap loops so inserts, removes and queries can run from many threads without a lock.
+
+use std::hash::{BuildHasher, Hash};
+use std::sync::atomic::{AtomicU8, Ordering};
+
+use crate::bitset::word_count;
+use crate::hash::{self, Sha256BuildHasher};
+use crate::stats::FilterStats;
+use crate::{heap_bytes, BloomFilter};
+
+pub struct CountingBloomFilter<S = Sha256BuildHasher> {
+    counters: Vec<u8>,
+    num_hashes: usize,
+    size: usize,
+    hasher: S,
+    seed: u64,
+}
+
+// Serde form: the fields plus the layout version, checked on the way in like `BloomFilter`'s
+#[cfg(feature = "serde")]
+#[derive(serde::Serialize, serde::Deserialize)]
+struct CountingRepr<C, S> {
+    counters: C,
+    num_hashes: usize,
+    size: usize,
+    hasher: S,
+    #[serde(default)]
+    seed: u64,
+    #[serde(default)]
+    layout_version: Option<u32>,
+}
+
+#[cfg(feature = "serde")]
+impl<S: serde::Serialize> serde::Serialize for CountingBloomFilter<S> {
+    fn serialize<Ser: serde::Serializer>(&self, serializer: Ser) -> Result<Ser::Ok, Ser::Error> {
+        CountingRepr {
+            counters: &self.counters,
+            num_hashes: self.num_hashes,
+            size: self.size,
+            hasher: &self.hasher,
+            seed: self.seed,
+            layout_version: Some(hash::LAYOUT_VERSION),
+        }
+        .serialize(serializer)
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<'de, S: serde::Deserialize<'de>> serde::Deserialize<'de> for CountingBloomFilter<S> {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        use serde::de::Error;
+
+        let repr = CountingRepr::<Vec<u8>, S>::deserialize(deserializer)?;
+        let layout = repr
+            .layout_version
+            .ok_or_else(|| D::Error::custom("Filter has no layout version. Rebuild it."))?;
+        crate::encoding::check_layout(layout).map_err(D::Error::custom)?;
+        crate::check_params(repr.size, repr.num_hashes).map_err(D::Error::custom)?;
+        if repr.counters.len() != repr.size {
+            return Err(D::Error::custom(format!(
+                "Expected {} counters, got {}.",
+                repr.size,
+                repr.counters.len()
+            )));
+        }
+        Ok(CountingBloomFilter {
+            counters: repr.counters,
+            num_hashes: repr.num_hashes,
+            size: repr.size,
+            hasher: repr.hasher,
+            seed: repr.seed,
+        })
+    }
+}
+
+impl CountingBloomFilter {
+    pub fn new(size: usize, num_hashes: usize) -> Self {
+        Self::with_hasher(size, num_hashes, Sha256BuildHasher)
+    }
+}
+
+impl<S: BuildHasher> CountingBloomFilter<S> {
+    pub fn with_hasher(size: usize, num_hashes: usize, hasher: S) -> Self {
+        CountingBloomFilter {
+            counters: vec![0; size],
+            num_hashes,
+            size,
+            hasher,
+            seed: 0,
+        }
+    }
+
+    // Same as `BloomFilter::with_seed`
+    pub fn with_seed(mut self, seed: u64) -> Self {
+        self.seed = seed;
+        self
+    }
+
+    pub fn seed(&self) -> u64 {
+        self.seed
+    }
+
+    fn seeded_hasher(&self) -> hash::Seeded<'_, S> {
+        hash::Seeded {
+            build_hasher: &self.hasher,
+            seed: self.seed,
+        }
+    }
+
+    // Counters saturate at 255 and are never decremented once saturated,
+    // otherwise removing could create false negatives.
+    pub fn insert<T: Hash + ?Sized>(&mut self, item: &T) {
+        for idx in hash::indices(&self.seeded_hasher(), item, self.num_hashes, self.size) {
+            self.counters[idx] = self.counters[idx].saturating_add(1);
+        }
+    }
+
+    // Returns false (and changes nothing) if the item is definitely not in the filter.
+    // Removing an item that was never inserted but tests positive corrupts the filter,
+    // same as with any counting Bloom filter.
+    pub fn remove<T: Hash + ?Sized>(&mut self, item: &T) -> bool {
+        if !self.contains(item) {
+            return false;
+        }
+        for idx in hash::indices(&self.seeded_hasher(), item, self.num_hashes, self.size) {
+            if self.counters[idx] != u8::MAX {
+                self.counters[idx] -= 1;
+            }
+        }
+        true
+    }
+
+    pub fn contains<T: Hash + ?Sized>(&self, item: &T) -> bool {
+        hash::indices(&self.seeded_hasher(), item, self.num_hashes, self.size)
+            .all(|idx| self.counters[idx] > 0)
+    }
+
+    pub fn memory_usage(&self) -> usize {
+        size_of::<Self>() + heap_bytes(&self.counters)
+    }
+
+    // size_bits and set_bits count counters
+    pub fn stats(&self) -> FilterStats {
+        let set = self.counters.iter().filter(|&&counter| counter > 0).count();
+        FilterStats::new(self.size, self.num_hashes, set, self.memory_usage())
+    }
+
+    pub fn clear(&mut self) {
+        self.counters.fill(0);
+    }
+
+    // Plain filter with a bit set for every nonzero counter, answers `test` exactly like this
+    // filter answers `contains` in 1/8 of the memory, but items can't be removed anymore
+    pub fn compact(self) -> BloomFilter<S> {
+        let mut words = vec![0u64; word_count(self.size)];
+        for (idx, _) in self.counters.iter().enumerate().filter(|(_, &c)| c > 0) {
+            words[idx / 64] |= 1 << (idx % 64);
+        }
+        BloomFilter::from_parts(self.size, self.num_hashes, words, self.hasher).with_seed(self.seed)
+    }
+}
+
+pub struct ConcurrentCountingBloomFilter<S = Sha256BuildHasher> {
+    counters: Vec<AtomicU8>,
+    num_hashes: usize,
+    size: usize,
+    hasher: S,
+}
+
+impl ConcurrentCountingBloomFilter {
+    pub fn new(size: usize, num_hashes: usize) -> Self {
+        Self::with_hasher(size, num_hashes, Sha256BuildHasher)
+    }
+}
+
+impl<S: BuildHasher> ConcurrentCountingBloomFilter<S> {
+    pub fn with_hasher(size: usize, num_hashes: usize, hasher: S) -> Self {
+        ConcurrentCountingBloomFilter {
+            counters: (0..size).map(|_| AtomicU8::new(0)).collect(),
+            num_hashes,
+            size,
+            hasher,
+        }
+    }
+
+    // Same saturation rule as `CountingBloomFilter::insert`
+    pub fn insert<T: Hash + ?Sized>(&self, item: &T) {
+        for idx in hash::indices(&self.hasher, item, self.num_hashes, self.size) {
+            let _ = self.counters[idx]
+                .fetch_update(Ordering::Relaxed, Ordering::Relaxed, |c| c.checked_add(1));
+        }
+    }
+
+    // Same as `CountingBloomFilter::remove`. A remove racing with a remove of the same item
+    // can both pass the membership check, counters never go below 0 but the item is then
+    // removed twice, which is the same misuse as removing an item that was never inserted.
+    pub fn remove<T: Hash + ?Sized>(&self, item: &T) -> bool {
+        if !self.contains(item) {
+            return false;
+        }
+        for idx in hash::indices(&self.hasher, item, self.num_hashes, self.size) {
+            let _ = self.counters[idx].fetch_update(Ordering::Relaxed, Ordering::Relaxed, |c| {
+                if c == 0 || c == u8::MAX {
+                    None
+                } else {
+                    Some(c - 1)
+                }
+            });
+        }
+        true
+    }
+
+    pub fn contains<T: Hash + ?Sized>(&self, item: &T) -> bool {
+        hash::indices(&self.hasher, item, self.num_hashes, self.size)
+            .all(|idx| self.counters[idx].load(Ordering::Relaxed) > 0)
+    }
+
+    pub fn memory_usage(&self) -> usize {
+        size_of::<Self>() + heap_bytes(&self.counters)
+    }
+
+    // Same as `CountingBloomFilter::stats`, of a snapshot of the counters
+    pub fn stats(&self) -> FilterStats {
+        let set = self
+            .counters
+            .iter()
+            .filter(|counter| counter.load(Ordering::Relaxed) > 0)
+            .count();
+        FilterStats::new(self.size, self.num_hashes, set, self.memory_usage())
+    }
+
+    // Not atomic as a whole, concurrent inserts may or may not survive
+    pub fn clear(&self) {
+        for counter in &self.counters {
+            counter.store(0, Ordering::Relaxed);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_insert_remove() {
+        let mut bloom = CountingBloomFilter::new(1000, 3);
+
+        bloom.insert("foo");
+        bloom.insert("bar");
+        bloom.insert("bar");
+        assert!(bloom.contains("foo"));
+        assert!(bloom.contains("bar"));
+        assert!(!bloom.contains("baz"));
+
+        assert!(bloom.remove("foo"));
+        assert!(!bloom.contains("foo"));
+        assert!(!bloom.remove("foo"));
+
+        // inserted twice, so it survives one removal
+        assert!(bloom.remove("bar"));
+        assert!(bloom.contains("bar"));
+        assert!(bloom.remove("bar"));
+        assert!(!bloom.contains("bar"));
+    }
+
+    #[test]
+    fn test_compact() {
+        let mut counting = CountingBloomFilter::new(10_000, 4);
+        for i in 0..1000u32 {
+            counting.insert(&i);
+        }
+        for i in 0..500u32 {
+            counting.remove(&i);
+        }
+        let answers: Vec<bool> = (0..5000u32).map(|i| counting.contains(&i)).collect();
+        let bloom = counting.compact();
+        assert!((500..1000u32).all(|i| bloom.test(&i)));
+        assert_eq!(
+            (0..5000u32).map(|i| bloom.test(&i)).collect::<Vec<_>>(),
+            answers
+        );
+        assert_eq!(bloom.size(), 10_000);
+        assert!(bloom.memory_usage() < 10_000 / 8 + 200);
+    }
+
+    #[test]
+    fn test_saturated_counters_stick() {
+        let mut bloom = CountingBloomFilter::new(100, 2);
+        for _ in 0..300 {
+            bloom.insert(&1u8);
+        }
+        for _ in 0..300 {
+            bloom.remove(&1u8);
+        }
+        assert!(bloom.contains(&1u8));
+
+        bloom.clear();
+        assert!(!bloom.contains(&1u8));
+    }
+
+    #[test]
+    fn test_concurrent_insert_remove() {
+        use std::sync::Arc;
+        use std::thread;
+
+        let bloom = Arc::new(ConcurrentCountingBloomFilter::new(100_000, 4));
+        let handles: Vec<_> = (0..4u32)
+            .map(|t| {
+                let bloom = Arc::clone(&bloom);
+                thread::spawn(move || {
+                    for i in 0..5_000u32 {
+                        bloom.insert(&(t, i));
+                    }
+                    // every thread removes the odd items it inserted
+                    for i in (1..5_000u32).step_by(2) {
+                        assert!(bloom.remove(&(t, i)));
+                    }
+                })
+            })
+            .collect();
+        for handle in handles {
+            handle.join().unwrap();
+        }
+        for t in 0..4u32 {
+            assert!((0..5_000u32).step_by(2).all(|i| bloom.contains(&(t, i))));
+        }
+        let remaining = (0..4u32)
+            .flat_map(|t| (1..5_000u32).step_by(2).map(move |i| (t, i)))
+            .filter(|item| bloom.contains(item))
+            .count();
+        // false positives against the 10_000 items left, ~1.2%
+        assert!(remaining < 300, "{} removed items still present", remaining);
+
+        bloom.clear();
+        assert!(!bloom.contains(&(0u32, 0u32)));
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn test_serde_round_trip() {
+        let mut bloom = CountingBloomFilter::new(1000, 4);
+        bloom.insert("foo");
+        bloom.insert("foo");
+        let json = serde_json::to_string(&bloom).unwrap();
+        let mut bloom: CountingBloomFilter = serde_json::from_str(&json).unwrap();
+        assert!(bloom.remove("foo"));
+        assert!(bloom.contains("foo"));
+
+        let value = serde_json::to_value(&bloom).unwrap();
+        for (field, bad) in [
+            ("size", serde_json::json!(10)),
+            ("layout_version", serde_json::Value::Null),
+        ] {
+            let mut value = value.clone();
+            value[field] = bad;
+            assert!(serde_json::from_value::<CountingBloomFilter>(value).is_err());
+        }
+    }
+}
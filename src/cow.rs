@@ -0,0 +1,273 @@
+// Copy-on-write Bloom filter for read-mostly workloads (feature `arc-swap`). The bits are split
+// into blocks of 64 words (4096 bits) behind `Arc`s, and the table of blocks is an immutable
+// snapshot readers load without locking. A write copies only the blocks it changes plus the
+// table of pointers and swaps the new table in, so unlike `SnapshotBloomFilter` inserts are
+// visible right away without copying the whole bit array. Items that are already present
+// cost writers a lookup and no copy.
+//
+// A query is one snapshot load and plain reads of the bits, `snapshot` takes the load out of
+// the loop for batches of queries.
+
+use std::hash::{BuildHasher, Hash};
+use std::sync::{Arc, Mutex};
+
+use arc_swap::ArcSwap;
+
+use crate::bitset::word_count;
+use crate::error::BloomError;
+use crate::hash::{self, Sha256BuildHasher};
+use crate::stats::FilterStats;
+use crate::{heap_bytes, BloomFilter, INSERT_BATCH};
+
+const BLOCK_WORDS: usize = 64;
+const BLOCK_BITS: usize = BLOCK_WORDS * 64;
+
+type Blocks = Vec<Arc<Vec<u64>>>;
+
+pub struct CowBloomFilter<S = Sha256BuildHasher> {
+    blocks: ArcSwap<Blocks>,
+    // serializes writers, readers never take it
+    writer: Mutex<()>,
+    num_hashes: usize,
+    size: usize,
+    seed: u64,
+    hasher: S,
+}
+
+impl CowBloomFilter {
+    pub fn new(size: usize, num_hashes: usize) -> Self {
+        Self::with_hasher(size, num_hashes, Sha256BuildHasher)
+    }
+}
+
+impl<S: BuildHasher> CowBloomFilter<S> {
+    pub fn with_hasher(size: usize, num_hashes: usize, hasher: S) -> Self {
+        Self::from_bits(BloomFilter::with_hasher(size, num_hashes, hasher))
+    }
+
+    // Keeps the bits and the seed of `filter`. Fails if it has custom hash functions, they
+    // can't be carried over.
+    pub fn from_filter(filter: BloomFilter<S>) -> Result<Self, BloomError> {
+        if !filter.hash_fns.is_empty() {
+            return Err(BloomError::InvalidParams(
+                "Filters with custom hash functions can't be made copy-on-write.".into(),
+            ));
+        }
+        Ok(Self::from_bits(filter))
+    }
+
+    fn from_bits(filter: BloomFilter<S>) -> Self {
+        let blocks = filter
+            .bit_array
+            .words()
+            .chunks(BLOCK_WORDS)
+            .map(|block| Arc::new(block.to_vec()))
+            .collect();
+        CowBloomFilter {
+            blocks: ArcSwap::from_pointee(blocks),
+            writer: Mutex::new(()),
+            num_hashes: filter.num_hashes,
+            size: filter.size,
+            seed: filter.seed,
+            hasher: filter.hasher,
+        }
+    }
+
+    pub fn set<T: Hash + ?Sized>(&self, item: &T) -> Result<(), BloomError> {
+        self.insert(item).map(|_| ())
+    }
+
+    // Like `BloomFilter::insert`, returns whether the item was (possibly) already present
+    pub fn insert<T: Hash + ?Sized>(&self, item: &T) -> Result<bool, BloomError> {
+        let indices: Vec<usize> = self.indices(item).collect();
+        self.write(&indices)
+    }
+
+    // One copy of every changed block per batch instead of per item
+    pub fn insert_all<I>(&self, items: I) -> Result<(), BloomError>
+    where
+        I: IntoIterator,
+        I::Item: Hash,
+    {
+        let mut indices = Vec::with_capacity(INSERT_BATCH * self.num_hashes);
+        for item in items {
+            indices.extend(self.indices(&item));
+            if indices.len() >= INSERT_BATCH * self.num_hashes {
+                self.write(&indices)?;
+                indices.clear();
+            }
+        }
+        self.write(&indices).map(|_| ())
+    }
+
+    // Sets the bits, true if they all were set already
+    fn write(&self, indices: &[usize]) -> Result<bool, BloomError> {
+        let _writer = self.writer.lock().map_err(|_| BloomError::PoisonedLock)?;
+        let current = self.blocks.load_full();
+        if indices.iter().all(|&idx| get(&current, idx)) {
+            return Ok(true);
+        }
+        let mut blocks = Blocks::clone(&current);
+        for &idx in indices {
+            if !get(&blocks, idx) {
+                // copies the block the first time, it's shared with `current`
+                let block = Arc::make_mut(&mut blocks[idx / BLOCK_BITS]);
+                block[idx % BLOCK_BITS / 64] |= 1 << (idx % 64);
+            }
+        }
+        self.blocks.store(Arc::new(blocks));
+        Ok(false)
+    }
+
+    pub fn test<T: Hash + ?Sized>(&self, item: &T) -> bool {
+        let blocks = self.blocks.load();
+        self.indices(item).all(|idx| get(&blocks, idx))
+    }
+
+    pub fn test_many<T: Hash>(&self, items: &[T]) -> Vec<bool> {
+        let snapshot = self.snapshot();
+        items.iter().map(|item| snapshot.test(item)).collect()
+    }
+
+    // The current bits, unaffected by later writes
+    pub fn snapshot(&self) -> CowSnapshot<'_, S> {
+        CowSnapshot {
+            blocks: self.blocks.load_full(),
+            filter: self,
+        }
+    }
+
+    // Copy of the current bits as a plain filter
+    pub fn to_filter(&self) -> BloomFilter<S>
+    where
+        S: Clone,
+    {
+        let mut words = Vec::with_capacity(word_count(self.size));
+        for block in self.blocks.load().iter() {
+            words.extend_from_slice(block);
+        }
+        BloomFilter::from_parts(self.size, self.num_hashes, words, self.hasher.clone())
+            .with_seed(self.seed)
+    }
+
+    // The current table and its blocks, older snapshots still held by readers are not counted
+    pub fn memory_usage(&self) -> usize {
+        let blocks = self.blocks.load();
+        let block_bytes: usize = blocks
+            .iter()
+            .map(|block| 2 * size_of::<usize>() + size_of::<Vec<u64>>() + heap_bytes(block))
+            .sum();
+        size_of::<Self>()
+            + 2 * size_of::<usize>()
+            + size_of::<Blocks>()
+            + heap_bytes(&blocks)
+            + block_bytes
+    }
+
+    // Of the current bits
+    pub fn stats(&self) -> FilterStats {
+        let set_bits = self
+            .blocks
+            .load()
+            .iter()
+            .flat_map(|block| block.iter())
+            .map(|word| word.count_ones() as usize)
+            .sum();
+        FilterStats::new(self.size, self.num_hashes, set_bits, self.memory_usage())
+    }
+
+    pub fn size(&self) -> usize {
+        self.size
+    }
+
+    pub fn num_hashes(&self) -> usize {
+        self.num_hashes
+    }
+
+    fn indices<T: Hash + ?Sized>(&self, item: &T) -> hash::Indices {
+        let hasher = hash::Seeded {
+            build_hasher: &self.hasher,
+            seed: self.seed,
+        };
+        hash::indices(&hasher, item, self.num_hashes, self.size)
+    }
+}
+
+fn get(blocks: &Blocks, idx: usize) -> bool {
+    blocks[idx / BLOCK_BITS][idx % BLOCK_BITS / 64] & (1 << (idx % 64)) != 0
+}
+
+// Bits of a `CowBloomFilter` at one point in time, queries on it don't touch the swap
+pub struct CowSnapshot<'a, S = Sha256BuildHasher> {
+    blocks: Arc<Blocks>,
+    filter: &'a CowBloomFilter<S>,
+}
+
+impl<S: BuildHasher> CowSnapshot<'_, S> {
+    pub fn test<T: Hash + ?Sized>(&self, item: &T) -> bool {
+        self.filter.indices(item).all(|idx| get(&self.blocks, idx))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::thread;
+
+    #[test]
+    fn test_copy_on_write() {
+        let bloom = Arc::new(CowBloomFilter::new(100_000, 4));
+        assert!(!bloom.insert("foo").unwrap());
+        assert!(bloom.insert("foo").unwrap());
+        assert!(bloom.test("foo"));
+
+        let before = bloom.snapshot();
+        let table = bloom.blocks.load_full();
+        bloom.set("bar").unwrap();
+        assert!(bloom.test("bar"));
+        assert!(!before.test("bar"));
+        // at most the 4 blocks of "bar" were copied
+        let after = bloom.blocks.load_full();
+        let copied = table
+            .iter()
+            .zip(after.iter())
+            .filter(|(old, new)| !Arc::ptr_eq(old, new))
+            .count();
+        assert!((1..=4).contains(&copied));
+
+        let writers: Vec<_> = (0..4u32)
+            .map(|t| {
+                let bloom = Arc::clone(&bloom);
+                thread::spawn(move || bloom.insert_all(t * 1000..(t + 1) * 1000).unwrap())
+            })
+            .collect();
+        for writer in writers {
+            writer.join().unwrap();
+        }
+        assert!((0..4000u32).all(|i| bloom.test(&i)));
+
+        let mut plain = BloomFilter::new(100_000, 4);
+        plain.set("foo");
+        plain.set("bar");
+        plain.insert_all(0..4000u32);
+        assert_eq!(bloom.to_filter(), plain);
+        assert_eq!(bloom.stats().set_bits, plain.stats().set_bits);
+        assert_eq!(
+            bloom.test_many(&[1u32, 5000]),
+            plain.test_many(&[1u32, 5000])
+        );
+
+        let seeded = BloomFilter::new(1000, 1).with_seed(3);
+        assert_eq!(
+            CowBloomFilter::from_filter(seeded.clone())
+                .unwrap()
+                .to_filter(),
+            seeded
+        );
+        let mut custom = BloomFilter::new(1000, 1);
+        custom
+            .set_hash_fn(vec![Box::new(|bytes| bytes.len() as u64)])
+            .unwrap();
+        assert!(CowBloomFilter::from_filter(custom).is_err());
+    }
+}
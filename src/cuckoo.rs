@@ -0,0 +1,227 @@
+// Cuckoo filter (Fan et al. 2014): stores a 16 bit fingerprint of every item in one of two
+// candidate buckets, the second bucket is derived from the first one and the fingerprint
+// (partial-key cuckoo hashing), so entries can be moved around and deleted without the key.
+
+use std::hash::{BuildHasher, Hash};
+
+use crate::error::BloomError;
+use crate::hash::Sha256BuildHasher;
+use crate::heap_bytes;
+use crate::rng::Rng;
+
+const BUCKET_SIZE: usize = 4;
+const MAX_KICKS: usize = 500;
+// 0 marks an empty slot
+const EMPTY: u16 = 0;
+
+pub struct CuckooFilter<S = Sha256BuildHasher> {
+    buckets: Vec<[u16; BUCKET_SIZE]>,
+    // power of two minus one, buckets.len() - 1
+    mask: usize,
+    len: usize,
+    // Fingerprint that could not be placed after MAX_KICKS, kept so no item is lost.
+    // While it's occupied the filter is full.
+    victim: Option<(usize, u16)>,
+    hasher: S,
+    rng: Rng,
+}
+
+// Fingerprints are mixed before deriving the alternate bucket so nearby fingerprints
+// don't end up in nearby buckets (MurmurHash3 finalizer)
+fn mix(fingerprint: u16) -> usize {
+    let mut h = fingerprint as u64;
+    h ^= h >> 33;
+    h = h.wrapping_mul(0xff51_afd7_ed55_8ccd);
+    h ^= h >> 33;
+    h = h.wrapping_mul(0xc4ce_b9fe_1a85_ec53);
+    h ^= h >> 33;
+    h as usize
+}
+
+impl CuckooFilter {
+    pub fn new(capacity: usize) -> Self {
+        Self::with_hasher(capacity, Sha256BuildHasher)
+    }
+}
+
+impl<S: BuildHasher> CuckooFilter<S> {
+    pub fn with_hasher(capacity: usize, hasher: S) -> Self {
+        // Buckets of 4 reach ~95% occupancy before inserts start failing
+        let num_buckets = ((capacity as f64 / BUCKET_SIZE as f64 / 0.95).ceil() as usize)
+            .max(1)
+            .next_power_of_two();
+        CuckooFilter {
+            buckets: vec![[EMPTY; BUCKET_SIZE]; num_buckets],
+            mask: num_buckets - 1,
+            len: 0,
+            victim: None,
+            hasher,
+            rng: Rng::from_entropy(),
+        }
+    }
+
+    fn index_and_fingerprint<T: Hash + ?Sized>(&self, item: &T) -> (usize, u16) {
+        let hash = self.hasher.hash_one(item);
+        let fingerprint = match (hash >> 48) as u16 {
+            EMPTY => 1,
+            fingerprint => fingerprint,
+        };
+        (hash as usize & self.mask, fingerprint)
+    }
+
+    fn alt_index(&self, index: usize, fingerprint: u16) -> usize {
+        (index ^ mix(fingerprint)) & self.mask
+    }
+
+    fn insert_into(&mut self, index: usize, fingerprint: u16) -> bool {
+        match self.buckets[index].iter_mut().find(|slot| **slot == EMPTY) {
+            Some(slot) => {
+                *slot = fingerprint;
+                true
+            }
+            None => false,
+        }
+    }
+
+    // Fails once the filter is full, the item is not inserted in that case.
+    pub fn insert<T: Hash + ?Sized>(&mut self, item: &T) -> Result<(), BloomError> {
+        if self.victim.is_some() {
+            return Err(BloomError::CapacityExceeded);
+        }
+        let (i1, fingerprint) = self.index_and_fingerprint(item);
+        let i2 = self.alt_index(i1, fingerprint);
+        if self.insert_into(i1, fingerprint) || self.insert_into(i2, fingerprint) {
+            self.len += 1;
+            return Ok(());
+        }
+
+        // Both buckets are full, kick a random entry to its other bucket
+        let mut index = if self.rng.below(2) == 0 { i1 } else { i2 };
+        let mut fingerprint = fingerprint;
+        for _ in 0..MAX_KICKS {
+            let slot = self.rng.below(BUCKET_SIZE);
+            std::mem::swap(&mut fingerprint, &mut self.buckets[index][slot]);
+            index = self.alt_index(index, fingerprint);
+            if self.insert_into(index, fingerprint) {
+                self.len += 1;
+                return Ok(());
+            }
+        }
+        // The new item is in the table, the last kicked out one waits in the victim slot
+        self.victim = Some((index, fingerprint));
+        self.len += 1;
+        Ok(())
+    }
+
+    pub fn contains<T: Hash + ?Sized>(&self, item: &T) -> bool {
+        let (i1, fingerprint) = self.index_and_fingerprint(item);
+        let i2 = self.alt_index(i1, fingerprint);
+        if let Some((index, victim)) = self.victim {
+            if victim == fingerprint && (index == i1 || index == i2) {
+                return true;
+            }
+        }
+        self.buckets[i1].contains(&fingerprint) || self.buckets[i2].contains(&fingerprint)
+    }
+
+    // Only remove items that were inserted, removing a false positive removes another item.
+    pub fn remove<T: Hash + ?Sized>(&mut self, item: &T) -> bool {
+        let (i1, fingerprint) = self.index_and_fingerprint(item);
+        let i2 = self.alt_index(i1, fingerprint);
+        for index in [i1, i2] {
+            if let Some(slot) = self.buckets[index].iter_mut().find(|s| **s == fingerprint) {
+                *slot = EMPTY;
+                self.len -= 1;
+                // Room was made, try to put the victim back in the table
+                if let Some((index, victim)) = self.victim.take() {
+                    let alt = self.alt_index(index, victim);
+                    if !self.insert_into(index, victim) && !self.insert_into(alt, victim) {
+                        self.victim = Some((index, victim));
+                    }
+                }
+                return true;
+            }
+        }
+        if let Some((index, victim)) = self.victim {
+            if victim == fingerprint && (index == i1 || index == i2) {
+                self.victim = None;
+                self.len -= 1;
+                return true;
+            }
+        }
+        false
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    // Number of fingerprint slots
+    pub fn capacity(&self) -> usize {
+        self.buckets.len() * BUCKET_SIZE
+    }
+
+    pub fn memory_usage(&self) -> usize {
+        size_of::<Self>() + heap_bytes(&self.buckets)
+    }
+
+    pub fn clear(&mut self) {
+        self.buckets.fill([EMPTY; BUCKET_SIZE]);
+        self.victim = None;
+        self.len = 0;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_insert_contains_remove() {
+        let mut cuckoo = CuckooFilter::new(1000);
+        for i in 0..900 {
+            cuckoo.insert(&i).unwrap();
+        }
+        assert_eq!(cuckoo.len(), 900);
+        for i in 0..900 {
+            assert!(cuckoo.contains(&i));
+        }
+        let false_positives = (900..10_900).filter(|i| cuckoo.contains(i)).count();
+        assert!(false_positives < 10, "{} false positives", false_positives);
+
+        for i in 0..450 {
+            assert!(cuckoo.remove(&i));
+        }
+        assert_eq!(cuckoo.len(), 450);
+        for i in 450..900 {
+            assert!(cuckoo.contains(&i));
+        }
+        assert!(!cuckoo.remove("never inserted"));
+
+        cuckoo.clear();
+        assert!(cuckoo.is_empty());
+        assert!(!cuckoo.contains(&500));
+    }
+
+    #[test]
+    fn test_full_filter_keeps_items() {
+        let mut cuckoo = CuckooFilter::new(8);
+        let capacity = cuckoo.capacity();
+        let mut inserted = Vec::new();
+        for i in 0..capacity * 2 {
+            if cuckoo.insert(&i).is_err() {
+                break;
+            }
+            inserted.push(i);
+        }
+        assert!(inserted.len() <= capacity + 1);
+        assert!(cuckoo.insert("one more").is_err());
+        for i in &inserted {
+            assert!(cuckoo.contains(i));
+        }
+    }
+}
@@ -0,0 +1,101 @@
+// Probabilistic dedup for any iterator: `.bloom_dedup(capacity, false_positive_rate)` yields
+// the items not seen before, remembered in a Bloom filter instead of a HashSet:
+//
+//   use bloomf::BloomDedupExt;
+//   for record in records.bloom_dedup(10_000_000, 0.001) { ... }
+//
+// Memory stays at ~1.2 bytes per item at 0.1% whatever the items are. The price are false
+// positives: about false_positive_rate of the new items (more once capacity is exceeded) are
+// taken for duplicates and dropped. Duplicates are never let through.
+
+use std::hash::{BuildHasher, Hash};
+use std::iter::FusedIterator;
+
+use crate::hash::Sha256BuildHasher;
+use crate::BloomFilter;
+
+pub trait BloomDedupExt: Iterator + Sized {
+    // Filter sized for `capacity` distinct items at `false_positive_rate`
+    fn bloom_dedup(self, capacity: usize, false_positive_rate: f64) -> BloomDedup<Self>
+    where
+        Self::Item: Hash,
+    {
+        let filter = BloomFilter::builder()
+            .expected_items(capacity)
+            .false_positive_rate(false_positive_rate)
+            .build();
+        self.bloom_dedup_with(filter)
+    }
+
+    // Dedups against an existing filter, ex. one loaded from the previous run
+    fn bloom_dedup_with<S: BuildHasher>(self, filter: BloomFilter<S>) -> BloomDedup<Self, S>
+    where
+        Self::Item: Hash,
+    {
+        BloomDedup { iter: self, filter }
+    }
+}
+
+impl<I: Iterator> BloomDedupExt for I {}
+
+pub struct BloomDedup<I, S = Sha256BuildHasher> {
+    iter: I,
+    filter: BloomFilter<S>,
+}
+
+impl<I, S> BloomDedup<I, S> {
+    pub fn filter(&self) -> &BloomFilter<S> {
+        &self.filter
+    }
+
+    // The filter with every item seen so far
+    pub fn into_filter(self) -> BloomFilter<S> {
+        self.filter
+    }
+}
+
+impl<I, S> Iterator for BloomDedup<I, S>
+where
+    I: Iterator,
+    I::Item: Hash,
+    S: BuildHasher,
+{
+    type Item = I::Item;
+
+    fn next(&mut self) -> Option<I::Item> {
+        let filter = &mut self.filter;
+        self.iter.find(|item| !filter.insert(item))
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (0, self.iter.size_hint().1)
+    }
+}
+
+impl<I, S> FusedIterator for BloomDedup<I, S>
+where
+    I: FusedIterator,
+    I::Item: Hash,
+    S: BuildHasher,
+{
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_bloom_dedup() {
+        let items = (0..1000u32).chain(500..1500).chain(0..1500);
+        let unique: Vec<u32> = items.bloom_dedup(1500, 0.001).collect();
+        // first-seen order, false positives would only drop new items
+        assert!(unique.len() > 1490 && unique.len() <= 1500);
+        assert!(unique.windows(2).all(|pair| pair[0] < pair[1]));
+
+        let mut words = ["a", "b", "a"].into_iter().bloom_dedup(10, 0.01);
+        assert_eq!(words.by_ref().collect::<Vec<_>>(), ["a", "b"]);
+        let filter = words.into_filter();
+        let rest: Vec<_> = ["b", "c"].into_iter().bloom_dedup_with(filter).collect();
+        assert_eq!(rest, ["c"]);
+    }
+}
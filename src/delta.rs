@@ -0,0 +1,376 @@
+// Incremental replication. `DeltaBloomFilter` remembers which chunks of 64 words (4096 bits)
+// changed when, `delta_since(version)` exports only the chunks written after `version` and
+// `BloomFilter::apply_delta` copies them into a replica:
+//
+//   let delta = primary.delta_since(replica_version);
+//   replica.apply_delta(&Delta::from_bytes(&delta.to_bytes())?)?;
+//   replica_version = delta.version();
+//
+// Every export starts a new version, writes after it show up in the next delta. A new replica
+// starts from an empty filter with the same parameters and `delta_since(0)`. Versions only
+// live as long as the primary: after it restarts replicas have to resync from version 0.
+//
+// Encoding, little-endian:
+//
+// offset  size  field
+// 0       4     magic b"BLMD"
+// 4       2     format version, currently 2
+// 6       2     reserved, 0
+// 8       8     version of the delta
+// 16      8     size of the filter in bits (m)
+// 24      4     number of hashes (k)
+// 28      4     number of chunks n
+// 32      8     hash seed
+// 40      ...   n times a u32 chunk index and the words of the chunk (64, fewer for the last
+//               chunk of the filter)
+
+use std::hash::{BuildHasher, Hash};
+
+use crate::bitset::word_count;
+use crate::error::{BloomError, MergeError};
+use crate::hash::{self, Sha256BuildHasher};
+use crate::heap_bytes;
+use crate::stats::FilterStats;
+use crate::BloomFilter;
+
+const CHUNK_WORDS: usize = 64;
+const CHUNK_BITS: usize = CHUNK_WORDS * 64;
+const MAGIC: [u8; 4] = *b"BLMD";
+const VERSION: u16 = 2;
+const HEADER_LEN: usize = 40;
+
+pub struct DeltaBloomFilter<S = Sha256BuildHasher> {
+    filter: BloomFilter<S>,
+    // version of the last write to every chunk, 0 if it was never written
+    chunk_versions: Vec<u64>,
+    version: u64,
+}
+
+impl DeltaBloomFilter {
+    pub fn new(size: usize, num_hashes: usize) -> Self {
+        Self::from_filter(BloomFilter::new(size, num_hashes))
+    }
+}
+
+impl<S: BuildHasher> DeltaBloomFilter<S> {
+    pub fn with_hasher(size: usize, num_hashes: usize, hasher: S) -> Self {
+        Self::from_filter(BloomFilter::with_hasher(size, num_hashes, hasher))
+    }
+
+    // Chunks with bits set already count as written in the first version
+    pub fn from_filter(filter: BloomFilter<S>) -> Self {
+        let chunk_versions = filter
+            .bit_array
+            .words()
+            .chunks(CHUNK_WORDS)
+            .map(|chunk| chunk.iter().any(|&word| word != 0) as u64)
+            .collect();
+        DeltaBloomFilter {
+            filter,
+            chunk_versions,
+            version: 1,
+        }
+    }
+
+    pub fn set<T: Hash + ?Sized>(&mut self, item: &T) {
+        self.insert(item);
+    }
+
+    // Same as `BloomFilter::insert`
+    pub fn insert<T: Hash + ?Sized>(&mut self, item: &T) -> bool {
+        let filter = &mut self.filter;
+        let mut present = true;
+        for idx in hash::probe(
+            &filter.seeded_hasher(),
+            &filter.hash_fns,
+            item,
+            filter.num_hashes,
+            filter.size,
+        ) {
+            if !filter.bit_array.test_and_set(idx) {
+                present = false;
+                self.chunk_versions[idx / CHUNK_BITS] = self.version;
+            }
+        }
+        present
+    }
+
+    pub fn test<T: Hash + ?Sized>(&self, item: &T) -> bool {
+        self.filter.test(item)
+    }
+
+    // Replicas get the cleared chunks with the next delta
+    pub fn reset(&mut self) {
+        self.filter.reset();
+        self.chunk_versions.fill(self.version);
+    }
+
+    // Version the next writes belong to
+    pub fn version(&self) -> u64 {
+        self.version
+    }
+
+    // The chunks written after `version`, and starts a new version
+    pub fn delta_since(&mut self, version: u64) -> Delta {
+        let words = self.filter.bit_array.words();
+        let chunks = self
+            .chunk_versions
+            .iter()
+            .enumerate()
+            .filter(|&(_, &written)| written > version)
+            .map(|(chunk, _)| {
+                let start = chunk * CHUNK_WORDS;
+                let end = (start + CHUNK_WORDS).min(words.len());
+                (chunk, words[start..end].to_vec())
+            })
+            .collect();
+        let delta = Delta {
+            version: self.version,
+            size: self.filter.size,
+            num_hashes: self.filter.num_hashes,
+            seed: self.filter.seed,
+            chunks,
+        };
+        self.version += 1;
+        delta
+    }
+
+    pub fn memory_usage(&self) -> usize {
+        size_of::<Self>() + self.filter.heap_bytes() + heap_bytes(&self.chunk_versions)
+    }
+
+    pub fn stats(&self) -> FilterStats {
+        FilterStats {
+            memory_bytes: self.memory_usage(),
+            ..self.filter.stats()
+        }
+    }
+
+    pub fn filter(&self) -> &BloomFilter<S> {
+        &self.filter
+    }
+
+    pub fn into_filter(self) -> BloomFilter<S> {
+        self.filter
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Delta {
+    version: u64,
+    size: usize,
+    num_hashes: usize,
+    seed: u64,
+    // (chunk index, words)
+    chunks: Vec<(usize, Vec<u64>)>,
+}
+
+impl Delta {
+    // Pass this to the next `delta_since`
+    pub fn version(&self) -> u64 {
+        self.version
+    }
+
+    // Number of changed chunks
+    pub fn len(&self) -> usize {
+        self.chunks.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.chunks.is_empty()
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let words: usize = self.chunks.iter().map(|(_, words)| words.len()).sum();
+        let mut bytes = Vec::with_capacity(HEADER_LEN + self.chunks.len() * 4 + words * 8);
+        bytes.extend_from_slice(&MAGIC);
+        bytes.extend_from_slice(&VERSION.to_le_bytes());
+        bytes.extend_from_slice(&0u16.to_le_bytes());
+        bytes.extend_from_slice(&self.version.to_le_bytes());
+        bytes.extend_from_slice(&(self.size as u64).to_le_bytes());
+        bytes.extend_from_slice(&(self.num_hashes as u32).to_le_bytes());
+        bytes.extend_from_slice(&(self.chunks.len() as u32).to_le_bytes());
+        bytes.extend_from_slice(&self.seed.to_le_bytes());
+        for (chunk, words) in &self.chunks {
+            bytes.extend_from_slice(&(*chunk as u32).to_le_bytes());
+            for word in words {
+                bytes.extend_from_slice(&word.to_le_bytes());
+            }
+        }
+        bytes
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, BloomError> {
+        Self::decode(bytes).map_err(BloomError::SerializationError)
+    }
+
+    fn decode(bytes: &[u8]) -> Result<Self, String> {
+        if bytes.len() < HEADER_LEN {
+            return Err("Encoded delta is too short.".into());
+        }
+        if bytes[..4] != MAGIC {
+            return Err("Not an encoded delta (bad magic).".into());
+        }
+        let format = u16::from_le_bytes(bytes[4..6].try_into().unwrap());
+        if format != VERSION {
+            return Err(format!("Unsupported delta format version {}.", format));
+        }
+        let version = u64::from_le_bytes(bytes[8..16].try_into().unwrap());
+        let size = usize::try_from(u64::from_le_bytes(bytes[16..24].try_into().unwrap()))
+            .map_err(|_| "Filter size doesn't fit in memory.".to_string())?;
+        let num_hashes = u32::from_le_bytes(bytes[24..28].try_into().unwrap()) as usize;
+        let count = u32::from_le_bytes(bytes[28..32].try_into().unwrap()) as usize;
+        let seed = u64::from_le_bytes(bytes[32..40].try_into().unwrap());
+
+        let total_words = word_count(size);
+        let num_chunks = total_words.div_ceil(CHUNK_WORDS);
+        let mut rest = &bytes[HEADER_LEN..];
+        let mut chunks = Vec::with_capacity(count.min(num_chunks));
+        for _ in 0..count {
+            if rest.len() < 4 {
+                return Err("Encoded delta is truncated.".into());
+            }
+            let chunk = u32::from_le_bytes(rest[..4].try_into().unwrap()) as usize;
+            if chunk >= num_chunks {
+                return Err(format!("Chunk {} is out of range.", chunk));
+            }
+            let len = (total_words - chunk * CHUNK_WORDS).min(CHUNK_WORDS);
+            let words = rest
+                .get(4..4 + len * 8)
+                .ok_or("Encoded delta is truncated.")?;
+            chunks.push((
+                chunk,
+                words
+                    .chunks_exact(8)
+                    .map(|word| u64::from_le_bytes(word.try_into().unwrap()))
+                    .collect(),
+            ));
+            rest = &rest[4 + len * 8..];
+        }
+        if !rest.is_empty() {
+            return Err("Trailing bytes after the encoded delta.".into());
+        }
+        Ok(Delta {
+            version,
+            size,
+            num_hashes,
+            seed,
+            chunks,
+        })
+    }
+}
+
+impl<S: BuildHasher> BloomFilter<S> {
+    // Overwrites the chunks in `delta` with the primary's bits. The filter must have the
+    // primary's size, number of hashes and seed (and hasher, to answer the same).
+    pub fn apply_delta(&mut self, delta: &Delta) -> Result<(), BloomError> {
+        if self.size != delta.size {
+            return Err(BloomError::IncompatibleParams(MergeError::SizeMismatch {
+                left: self.size,
+                right: delta.size,
+            }));
+        }
+        if self.num_hashes != delta.num_hashes {
+            return Err(BloomError::IncompatibleParams(
+                MergeError::HashCountMismatch {
+                    left: self.num_hashes,
+                    right: delta.num_hashes,
+                },
+            ));
+        }
+        if self.seed != delta.seed {
+            // the primary's fingerprint, as far as the delta tells
+            let primary = hash::Seeded {
+                build_hasher: &self.hasher,
+                seed: delta.seed,
+            };
+            return Err(BloomError::IncompatibleParams(
+                MergeError::FingerprintMismatch {
+                    left: self.params_fingerprint(),
+                    right: hash::params_fingerprint(
+                        &primary,
+                        &[delta.size as u64, delta.num_hashes as u64],
+                    ),
+                },
+            ));
+        }
+        let words = self.bit_array.words_mut();
+        for (chunk, chunk_words) in &delta.chunks {
+            let start = chunk * CHUNK_WORDS;
+            words[start..start + chunk_words.len()].copy_from_slice(chunk_words);
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_replicate_with_deltas() {
+        let mut primary = DeltaBloomFilter::new(1_000_000, 3);
+        let mut replica = BloomFilter::new(1_000_000, 3);
+
+        let delta = primary.delta_since(0);
+        assert!(delta.is_empty());
+        replica.apply_delta(&delta).unwrap();
+        let mut version = delta.version();
+
+        for i in 0..10u32 {
+            primary.set(&i);
+        }
+        let delta = primary.delta_since(version);
+        // at most 30 of the 245 chunks
+        assert!(!delta.is_empty() && delta.len() <= 30);
+        assert!(delta.to_bytes().len() < 30 * (4 + 512) + HEADER_LEN);
+        replica
+            .apply_delta(&Delta::from_bytes(&delta.to_bytes()).unwrap())
+            .unwrap();
+        version = delta.version();
+        assert_eq!(&replica, primary.filter());
+
+        // nothing new
+        let delta = primary.delta_since(version);
+        assert!(delta.is_empty());
+        version = delta.version();
+        primary.set(&"new");
+        let delta = primary.delta_since(version);
+        assert!((1..=3).contains(&delta.len()));
+        replica.apply_delta(&delta).unwrap();
+        version = delta.version();
+        assert_eq!(&replica, primary.filter());
+
+        // a fresh replica catches up from 0
+        let mut fresh = BloomFilter::new(1_000_000, 3);
+        fresh.apply_delta(&primary.delta_since(0)).unwrap();
+        assert_eq!(&fresh, primary.filter());
+
+        primary.reset();
+        replica.apply_delta(&primary.delta_since(version)).unwrap();
+        assert_eq!(&replica, primary.filter());
+
+        assert!(BloomFilter::new(1000, 3).apply_delta(&delta).is_err());
+        assert!(Delta::from_bytes(&delta.to_bytes()[..40]).is_err());
+    }
+
+    #[test]
+    fn test_seed_mismatch() {
+        let mut primary = DeltaBloomFilter::from_filter(BloomFilter::new(10_000, 3).with_seed(5));
+        primary.set(&"item");
+        let delta = Delta::from_bytes(&primary.delta_since(0).to_bytes()).unwrap();
+        for seed in [0, 7] {
+            let mut replica = BloomFilter::new(10_000, 3).with_seed(seed);
+            assert!(matches!(
+                replica.apply_delta(&delta),
+                Err(BloomError::IncompatibleParams(
+                    MergeError::FingerprintMismatch { .. }
+                ))
+            ));
+            assert!(!replica.test(&"item"));
+        }
+        let mut replica = BloomFilter::new(10_000, 3).with_seed(5);
+        replica.apply_delta(&delta).unwrap();
+        assert!(replica.test(&"item"));
+    }
+}
@@ -0,0 +1,223 @@
+// d-left counting Bloom filter (Bonomi et al. 2006): the operations of `CountingBloomFilter`
+// (insert, remove, contains) in about half the memory or less. Instead of k counters per item
+// it stores one cell per item, a 12 bit remainder of the item's fingerprint and a 4 bit
+// counter, in the least loaded of d = 4 candidate buckets (one per subtable, the leftmost one
+// on ties), which keeps the buckets evenly filled.
+//
+// The d candidate (bucket, remainder) pairs are d different permutations of one fingerprint,
+// so two items share a cell only if their whole fingerprints are equal, and then they behave
+// as one item with a count of 2. That's what makes removing safe without the key: a cell
+// always belongs to exactly one fingerprint. A false positive needs an item whose
+// fingerprint equals a stored one, about d * 8 * load / 4096 per query.
+//
+// Counters saturate at 15 and then never go down, like in `CountingBloomFilter`.
+
+use std::hash::{BuildHasher, Hash};
+
+use crate::error::BloomError;
+use crate::hash::Sha256BuildHasher;
+use crate::heap_bytes;
+
+const SUBTABLES: usize = 4;
+const BUCKET_CELLS: usize = 8;
+const REMAINDER_BITS: u32 = 12;
+const COUNTER_BITS: u32 = 4;
+const MAX_COUNT: u16 = (1 << COUNTER_BITS) - 1;
+// Fill at which inserts into a filter sized with `new` are unlikely to fail
+const TARGET_LOAD: f64 = 0.75;
+// Odd multipliers of the permutations, one per subtable
+const MULTIPLIERS: [u64; SUBTABLES] = [
+    0x9e37_79b9_7f4a_7c15,
+    0xc2b2_ae3d_27d4_eb4f,
+    0x1656_67b1_9e37_79f9,
+    0xd6e8_feb8_6659_fd93,
+];
+
+pub struct DLeftCountingBloomFilter<S = Sha256BuildHasher> {
+    // SUBTABLES * buckets of BUCKET_CELLS cells, cell = remainder << COUNTER_BITS | counter,
+    // empty while the counter is 0
+    cells: Vec<u16>,
+    // log2 of the buckets per subtable
+    bucket_bits: u32,
+    // occupied cells
+    len: usize,
+    hasher: S,
+}
+
+impl DLeftCountingBloomFilter {
+    pub fn new(capacity: usize) -> Self {
+        Self::with_hasher(capacity, Sha256BuildHasher)
+    }
+}
+
+impl<S: BuildHasher> DLeftCountingBloomFilter<S> {
+    // Room for about `capacity` distinct items, the buckets per subtable are rounded up to a
+    // power of two
+    pub fn with_hasher(capacity: usize, hasher: S) -> Self {
+        let buckets = ((capacity as f64 / (SUBTABLES * BUCKET_CELLS) as f64 / TARGET_LOAD).ceil()
+            as usize)
+            .max(1)
+            .next_power_of_two();
+        DLeftCountingBloomFilter {
+            cells: vec![0; SUBTABLES * buckets * BUCKET_CELLS],
+            bucket_bits: buckets.trailing_zeros(),
+            len: 0,
+            hasher,
+        }
+    }
+
+    // Offset of the first cell and remainder of the item's bucket in every subtable
+    fn locate<T: Hash + ?Sized>(&self, item: &T) -> [(usize, u16); SUBTABLES] {
+        let bits = self.bucket_bits + REMAINDER_BITS;
+        let mask = (1u64 << bits) - 1;
+        let fingerprint = self.hasher.hash_one(item) & mask;
+        let buckets = 1usize << self.bucket_bits;
+        let mut locations = [(0, 0); SUBTABLES];
+        for (table, location) in locations.iter_mut().enumerate() {
+            // multiplication by an odd number and xorshift are both bijections of `bits` bits
+            let mut x = fingerprint.wrapping_mul(MULTIPLIERS[table]) & mask;
+            x ^= x >> bits.div_ceil(2);
+            x = x.wrapping_mul(MULTIPLIERS[SUBTABLES - 1 - table]) & mask;
+            let bucket = (x >> REMAINDER_BITS) as usize;
+            let remainder = (x & ((1 << REMAINDER_BITS) - 1)) as u16;
+            *location = ((table * buckets + bucket) * BUCKET_CELLS, remainder);
+        }
+        locations
+    }
+
+    // Index of the occupied cell holding the item's fingerprint
+    fn find(&self, locations: &[(usize, u16); SUBTABLES]) -> Option<usize> {
+        locations.iter().find_map(|&(start, remainder)| {
+            (start..start + BUCKET_CELLS).find(|&cell| {
+                let cell_value = self.cells[cell];
+                cell_value & MAX_COUNT != 0 && cell_value >> COUNTER_BITS == remainder
+            })
+        })
+    }
+
+    // Fails once all d buckets of the item are full, the item is not inserted in that case
+    pub fn insert<T: Hash + ?Sized>(&mut self, item: &T) -> Result<(), BloomError> {
+        let locations = self.locate(item);
+        if let Some(cell) = self.find(&locations) {
+            if self.cells[cell] & MAX_COUNT != MAX_COUNT {
+                self.cells[cell] += 1;
+            }
+            return Ok(());
+        }
+        let (start, remainder) = locations
+            .iter()
+            .copied()
+            .min_by_key(|&(start, _)| self.occupied(start))
+            .unwrap();
+        let cell = (start..start + BUCKET_CELLS)
+            .find(|&cell| self.cells[cell] & MAX_COUNT == 0)
+            .ok_or(BloomError::CapacityExceeded)?;
+        self.cells[cell] = remainder << COUNTER_BITS | 1;
+        self.len += 1;
+        Ok(())
+    }
+
+    fn occupied(&self, start: usize) -> usize {
+        self.cells[start..start + BUCKET_CELLS]
+            .iter()
+            .filter(|&&cell| cell & MAX_COUNT != 0)
+            .count()
+    }
+
+    // Same contract as `CountingBloomFilter::remove`
+    pub fn remove<T: Hash + ?Sized>(&mut self, item: &T) -> bool {
+        let Some(cell) = self.find(&self.locate(item)) else {
+            return false;
+        };
+        let count = self.cells[cell] & MAX_COUNT;
+        if count != MAX_COUNT {
+            self.cells[cell] -= 1;
+            if count == 1 {
+                self.len -= 1;
+            }
+        }
+        true
+    }
+
+    pub fn contains<T: Hash + ?Sized>(&self, item: &T) -> bool {
+        self.find(&self.locate(item)).is_some()
+    }
+
+    // How many times the item was inserted (minus removed), 15 once saturated.
+    // Items with the same fingerprint add up.
+    pub fn count<T: Hash + ?Sized>(&self, item: &T) -> usize {
+        self.find(&self.locate(item))
+            .map_or(0, |cell| (self.cells[cell] & MAX_COUNT) as usize)
+    }
+
+    // Number of distinct fingerprints stored
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    // Number of cells
+    pub fn capacity(&self) -> usize {
+        self.cells.len()
+    }
+
+    pub fn memory_usage(&self) -> usize {
+        size_of::<Self>() + heap_bytes(&self.cells)
+    }
+
+    pub fn clear(&mut self) {
+        self.cells.fill(0);
+        self.len = 0;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{params, CountingBloomFilter};
+
+    #[test]
+    fn test_insert_contains_remove() {
+        let mut dleft = DLeftCountingBloomFilter::new(10_000);
+        for i in 0..10_000u32 {
+            dleft.insert(&i).unwrap();
+        }
+        assert!((0..10_000u32).all(|i| dleft.contains(&i)));
+        let false_positives = (10_000..110_000u32).filter(|i| dleft.contains(i)).count();
+        assert!(
+            false_positives < 1000,
+            "{} false positives",
+            false_positives
+        );
+
+        for i in 0..5000u32 {
+            assert!(dleft.remove(&i));
+        }
+        assert!((5000..10_000u32).all(|i| dleft.contains(&i)));
+        assert!((0..5000u32).filter(|i| dleft.contains(i)).count() < 50);
+
+        dleft.insert(&7u32).unwrap();
+        dleft.insert(&7u32).unwrap();
+        assert_eq!(dleft.count(&7u32), 2);
+        assert!(dleft.remove(&7u32));
+        assert_eq!(dleft.count(&7u32), 1);
+
+        // same operations as a counting filter with a similar false positive rate
+        let size = params::optimal_bit_count(10_000, 0.01);
+        let counting = CountingBloomFilter::new(size, params::optimal_num_hashes(size, 10_000));
+        assert!(dleft.memory_usage() * 2 < counting.memory_usage());
+    }
+
+    #[test]
+    fn test_full() {
+        let mut dleft = DLeftCountingBloomFilter::new(1);
+        let inserted = (0..100u32).take_while(|i| dleft.insert(i).is_ok()).count();
+        assert!((SUBTABLES * BUCKET_CELLS..100).contains(&inserted));
+        assert!(dleft.insert(&1000u32).is_err());
+        dleft.clear();
+        assert!(dleft.is_empty());
+    }
+}
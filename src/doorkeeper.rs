@@ -0,0 +1,93 @@
+// Doorkeeper of TinyLFU cache admission (Einziger, Friedman & Manes 2017): a Bloom filter in
+// front of the frequency sketch that lets an item through on its second occurrence only, so the
+// long tail of one-hit wonders never reaches the counters. It is cleared every `reset_period`
+// calls to `admit`, the sample size of TinyLFU, so "seen before" means within the current
+// period.
+//
+//   let mut doorkeeper = Doorkeeper::new(100_000, 0.01);
+//   if doorkeeper.admit(&key) { sketch.increment(&key) }
+
+use std::hash::{BuildHasher, Hash};
+
+use crate::hash::Sha256BuildHasher;
+use crate::{params, BloomFilter};
+
+pub struct Doorkeeper<S = Sha256BuildHasher> {
+    filter: BloomFilter<S>,
+    reset_period: usize,
+    // calls to admit since the last reset
+    count: usize,
+}
+
+impl Doorkeeper {
+    pub fn new(reset_period: usize, false_positive_rate: f64) -> Self {
+        Self::with_hasher(reset_period, false_positive_rate, Sha256BuildHasher)
+    }
+}
+
+impl<S: BuildHasher> Doorkeeper<S> {
+    // Sized for `reset_period` distinct items, the most one period can see
+    pub fn with_hasher(reset_period: usize, false_positive_rate: f64, hasher: S) -> Self {
+        assert!(reset_period > 0, "reset period must not be 0");
+        assert!(
+            false_positive_rate > 0.0 && false_positive_rate < 1.0,
+            "false positive rate must be in (0, 1)"
+        );
+        let size = params::optimal_bit_count(reset_period, false_positive_rate);
+        let num_hashes = params::optimal_num_hashes(size, reset_period);
+        Doorkeeper {
+            filter: BloomFilter::with_hasher(size, num_hashes, hasher),
+            reset_period,
+            count: 0,
+        }
+    }
+
+    // Records the item and returns true if it was already seen in this period. A false
+    // positive admits an item on its first occurrence.
+    pub fn admit<T: Hash + ?Sized>(&mut self, item: &T) -> bool {
+        if self.count >= self.reset_period {
+            self.reset();
+        }
+        self.count += 1;
+        self.filter.insert(item)
+    }
+
+    // Whether the item was seen in this period, without recording it
+    pub fn contains<T: Hash + ?Sized>(&self, item: &T) -> bool {
+        self.filter.test(item)
+    }
+
+    // Starts a new period
+    pub fn reset(&mut self) {
+        self.filter.reset();
+        self.count = 0;
+    }
+
+    pub fn reset_period(&self) -> usize {
+        self.reset_period
+    }
+
+    pub fn memory_usage(&self) -> usize {
+        size_of::<Self>() + self.filter.heap_bytes()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_doorkeeper() {
+        let mut doorkeeper = Doorkeeper::new(1000, 0.01);
+        assert!(!doorkeeper.admit("a"));
+        assert!(doorkeeper.contains("a"));
+        assert!(doorkeeper.admit("a"));
+        let one_hit = (0..998u32).filter(|i| doorkeeper.admit(i)).count();
+        assert!(one_hit < 20, "{} admitted on first occurrence", one_hit);
+
+        // the 1001st call starts a new period
+        assert!(!doorkeeper.admit("a"));
+        assert!(!doorkeeper.contains(&1u32));
+        assert!(doorkeeper.admit("a"));
+    }
+}
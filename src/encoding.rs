@@ -0,0 +1,228 @@
+// Binary encoding of `BloomFilter` and `AtomicBloomFilter`, independent of serde.
+// All integers are little-endian.
+//
+// offset  size  field
+// 0       4     magic b"BLMF"
+// 4       2     format version, currently 2
+// 6       2     flags, bit 0 (FLAG_SEED) set for seeded filters, the others 0
+// 8       8     size of the bit array in bits (m)
+// 16      4     number of hashes (k)
+// 20      4     layout version (`hash::LAYOUT_VERSION`) the bits were set with
+// 24      8     seed, only with FLAG_SEED
+// 24/32   8*w   bit array as w = ceil(m / 64) words, bit i is bit i % 64 of word i / 64
+//
+// Unseeded filters are written exactly as before seeds existed, readers that don't know
+// FLAG_SEED reject seeded ones instead of answering with the wrong bits.
+// The hasher is not part of the encoding, decoding with a different hasher than the one
+// the filter was built with gives a filter that answers garbage.
+// Readers reject versions they don't know instead of guessing, and filters of another layout:
+// their items sit on other bits. Format version 1 had no layout field, its filters used
+// layout 1.
+
+use std::fs::{self, File};
+use std::io::{self, Write};
+use std::path::Path;
+
+use crate::bitset::word_count;
+use crate::error::BloomError;
+use crate::hash::LAYOUT_VERSION;
+
+pub(crate) const MAGIC: [u8; 4] = *b"BLMF";
+pub(crate) const VERSION: u16 = 2;
+pub(crate) const HEADER_LEN: usize = 24;
+pub(crate) const FLAG_SEED: u16 = 1;
+
+// Offset of the bit array
+pub(crate) fn header_len(seed: u64) -> usize {
+    if seed == 0 {
+        HEADER_LEN
+    } else {
+        HEADER_LEN + 8
+    }
+}
+
+pub(crate) fn encode(size: usize, num_hashes: usize, seed: u64, words: &[u64]) -> Vec<u8> {
+    let flags = if seed == 0 { 0 } else { FLAG_SEED };
+    let mut bytes = Vec::with_capacity(header_len(seed) + words.len() * 8);
+    bytes.extend_from_slice(&MAGIC);
+    bytes.extend_from_slice(&VERSION.to_le_bytes());
+    bytes.extend_from_slice(&flags.to_le_bytes());
+    bytes.extend_from_slice(&(size as u64).to_le_bytes());
+    bytes.extend_from_slice(&(num_hashes as u32).to_le_bytes());
+    bytes.extend_from_slice(&LAYOUT_VERSION.to_le_bytes());
+    if seed != 0 {
+        bytes.extend_from_slice(&seed.to_le_bytes());
+    }
+    for word in words {
+        bytes.extend_from_slice(&word.to_le_bytes());
+    }
+    bytes
+}
+
+// Returns (size, num_hashes, seed, words)
+pub(crate) fn decode(bytes: &[u8]) -> Result<(usize, usize, u64, Vec<u64>), BloomError> {
+    let (size, num_hashes, seed) = decode_header(bytes)?;
+    let words = bytes[header_len(seed)..]
+        .chunks_exact(8)
+        .map(|word| u64::from_le_bytes(word.try_into().unwrap()))
+        .collect();
+    Ok((size, num_hashes, seed, words))
+}
+
+// Checks the header and that the bit array is complete, returns (size, num_hashes, seed)
+pub(crate) fn decode_header(bytes: &[u8]) -> Result<(usize, usize, u64), BloomError> {
+    check_header(bytes).map_err(BloomError::SerializationError)
+}
+
+fn check_header(bytes: &[u8]) -> Result<(usize, usize, u64), String> {
+    if bytes.len() < HEADER_LEN {
+        return Err("Encoded filter is too short.".into());
+    }
+    if bytes[0..4] != MAGIC {
+        return Err("Not an encoded filter (bad magic).".into());
+    }
+    let version = u16::from_le_bytes([bytes[4], bytes[5]]);
+    if version == 1 {
+        return Err(format!(
+            "Filter was encoded with layout version 1, this build uses {}. Rebuild it.",
+            LAYOUT_VERSION
+        ));
+    }
+    if version != VERSION {
+        return Err(format!("Unsupported format version {}.", version));
+    }
+    let flags = u16::from_le_bytes([bytes[6], bytes[7]]);
+    if flags & !FLAG_SEED != 0 {
+        return Err(format!("Unsupported format flags {:#x}.", flags));
+    }
+    let size = u64::from_le_bytes(bytes[8..16].try_into().unwrap());
+    let size = usize::try_from(size).map_err(|_| "Filter size doesn't fit in memory.")?;
+    let num_hashes = u32::from_le_bytes(bytes[16..20].try_into().unwrap()) as usize;
+    let layout = u32::from_le_bytes(bytes[20..24].try_into().unwrap());
+    check_layout(layout)?;
+    // same limits as `try_new`, a filter of 0 bits would panic on the first query
+    crate::check_params(size, num_hashes).map_err(|err| err.to_string())?;
+
+    let seed = if flags & FLAG_SEED != 0 {
+        let seed = bytes
+            .get(HEADER_LEN..HEADER_LEN + 8)
+            .ok_or("Encoded filter is too short.")?;
+        let seed = u64::from_le_bytes(seed.try_into().unwrap());
+        if seed == 0 {
+            return Err("Seeded filter with seed 0.".into());
+        }
+        seed
+    } else {
+        0
+    };
+
+    let body = &bytes[header_len(seed)..];
+    if body.len() != word_count(size) * 8 {
+        return Err(format!(
+            "Expected {} bytes of bit array, got {}.",
+            word_count(size) * 8,
+            body.len()
+        ));
+    }
+    // set padding bits would count in count_ones, fill_ratio and equality
+    let last = u64::from_le_bytes(body[body.len() - 8..].try_into().unwrap());
+    if size % 64 != 0 && last >> (size % 64) != 0 {
+        return Err("Bits past the size of the filter are set.".into());
+    }
+    Ok((size, num_hashes, seed))
+}
+
+// Filters set with another mapping of items to bits answer garbage, shared with the serde impls
+pub(crate) fn check_layout(layout: u32) -> Result<(), String> {
+    if layout != LAYOUT_VERSION {
+        return Err(format!(
+            "Filter was encoded with layout version {}, this build uses {}. Rebuild it.",
+            layout, LAYOUT_VERSION
+        ));
+    }
+    Ok(())
+}
+
+// Writes to a temporary file next to `path` and renames it over `path`, so readers (and a
+// crash halfway through) see either the old file or the complete new one
+pub(crate) fn write_atomic(path: &Path, bytes: &[u8]) -> io::Result<()> {
+    let file_name = path
+        .file_name()
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "Path has no file name."))?;
+    let mut tmp_name = file_name.to_os_string();
+    tmp_name.push(format!(".tmp{}", std::process::id()));
+    let tmp_path = path.with_file_name(tmp_name);
+
+    let result = (|| {
+        let mut file = File::create(&tmp_path)?;
+        file.write_all(bytes)?;
+        file.sync_all()?;
+        fs::rename(&tmp_path, path)
+    })();
+    if result.is_err() {
+        let _ = fs::remove_file(&tmp_path);
+        return result;
+    }
+    // persist the rename itself, not possible on every platform
+    if let Some(dir) = path.parent() {
+        let dir = if dir.as_os_str().is_empty() {
+            Path::new(".")
+        } else {
+            dir
+        };
+        if let Ok(dir) = File::open(dir) {
+            let _ = dir.sync_all();
+        }
+    }
+    Ok(())
+}
+
+pub(crate) fn read(path: &Path) -> io::Result<(usize, usize, u64, Vec<u64>)> {
+    let bytes = fs::read(path)?;
+    decode(&bytes).map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_layout() {
+        let bytes = encode(100, 3, 0, &[1, 1 << 35]);
+        assert_eq!(bytes.len(), HEADER_LEN + 16);
+        assert_eq!(&bytes[..8], b"BLMF\x02\x00\x00\x00");
+        assert_eq!(bytes[8], 100);
+        assert_eq!(bytes[16], 3);
+        assert_eq!(bytes[20], LAYOUT_VERSION as u8);
+        assert_eq!(bytes[24], 1);
+        assert_eq!(bytes[36], 0x08);
+        assert_eq!(decode(&bytes).unwrap(), (100, 3, 0, vec![1, 1 << 35]));
+        assert!(decode(&encode(100, 3, 0, &[1, 1 << 36])).is_err());
+        assert!(decode(&encode(128, 3, 0, &[1, 1 << 63])).is_ok());
+
+        let mut future = bytes.clone();
+        future[4] = 3;
+        assert!(decode(&future).is_err());
+        let mut other_layout = bytes.clone();
+        other_layout[20] += 1;
+        assert!(decode(&other_layout).is_err());
+        let mut old = bytes.clone();
+        old[4] = 1;
+        old[20] = 0;
+        assert!(decode(&old).is_err());
+        assert!(decode(&bytes[..30]).is_err());
+        assert!(decode(b"not a filter at all, no no no").is_err());
+
+        let seeded = encode(100, 3, 42, &[1, 1 << 35]);
+        assert_eq!(seeded.len(), HEADER_LEN + 8 + 16);
+        assert_eq!(&seeded[4..8], b"\x02\x00\x01\x00");
+        assert_eq!(seeded[24], 42);
+        assert_eq!(seeded[32], 1);
+        assert_eq!(decode(&seeded).unwrap(), (100, 3, 42, vec![1, 1 << 35]));
+        assert!(decode(&seeded[..seeded.len() - 8]).is_err());
+
+        assert!(decode(&encode(0, 3, 0, &[])).is_err());
+        assert!(decode(&encode(100, 0, 0, &[1, 1 << 35])).is_err());
+        assert!(decode(&encode(100, 65, 0, &[1, 1 << 35])).is_err());
+    }
+}
@@ -0,0 +1,95 @@
+// Typed errors: `BloomError` for everything that can fail, `MergeError` for the APIs that
+// combine filters and sketches (wrapped in `BloomError::IncompatibleParams` where both meet).
+
+use std::fmt;
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum MergeError {
+    // Number of bits differs
+    SizeMismatch { left: usize, right: usize },
+    // Number of hash functions differs
+    HashCountMismatch { left: usize, right: usize },
+    // Filters with custom hash functions (`set_hash_fn`) can't be combined,
+    // there is no way to tell whether the closures are the same
+    CustomHashFns,
+    // `params_fingerprint` differs: other parameters, hasher (seed) or layout version
+    FingerprintMismatch { left: u64, right: u64 },
+    // The combined items don't fit in the result
+    CapacityExceeded,
+}
+
+impl fmt::Display for MergeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            MergeError::SizeMismatch { left, right } => {
+                write!(f, "Can't merge filters of {} and {} bits.", left, right)
+            }
+            MergeError::HashCountMismatch { left, right } => write!(
+                f,
+                "Can't merge filters with {} and {} hash functions.",
+                left, right
+            ),
+            MergeError::CustomHashFns => {
+                write!(f, "Can't merge filters with custom hash functions.")
+            }
+            MergeError::FingerprintMismatch { left, right } => write!(
+                f,
+                "Can't merge filters with different parameters (fingerprints {:016x} and {:016x}).",
+                left, right
+            ),
+            MergeError::CapacityExceeded => write!(f, "Merged filters don't fit in the result."),
+        }
+    }
+}
+
+impl std::error::Error for MergeError {}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum BloomError {
+    // A thread panicked while holding the lock of a `ThreadSafeBF`
+    PoisonedLock,
+    // Filters that can't be combined
+    IncompatibleParams(MergeError),
+    // The filter is full (cuckoo, quotient and d-left filters) and the item was not inserted,
+    // or an IBLT holds more entries than it can list
+    CapacityExceeded,
+    // Bytes that are not a valid encoded filter, with the reason
+    SerializationError(String),
+    // Parameters the filter can't work with, with the reason
+    InvalidParams(String),
+    // A static filter (fuse, ribbon) couldn't be built from the items
+    ConstructionFailed(String),
+}
+
+impl fmt::Display for BloomError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            BloomError::PoisonedLock => {
+                write!(
+                    f,
+                    "Failed to acquire lock on BloomFilter. Lock is poisoned."
+                )
+            }
+            BloomError::IncompatibleParams(err) => err.fmt(f),
+            BloomError::CapacityExceeded => write!(f, "Filter is full."),
+            BloomError::SerializationError(reason)
+            | BloomError::InvalidParams(reason)
+            | BloomError::ConstructionFailed(reason) => f.write_str(reason),
+        }
+    }
+}
+
+impl std::error::Error for BloomError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            BloomError::IncompatibleParams(err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+impl From<MergeError> for BloomError {
+    fn from(err: MergeError) -> Self {
+        BloomError::IncompatibleParams(err)
+    }
+}
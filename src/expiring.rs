@@ -0,0 +1,208 @@
+// Bloom filter whose items age out after a TTL. Time is split into generations of ttl / n,
+// every generation is a plain Bloom filter and new items always go into the newest one.
+// A generation is dropped once its newest possible item is older than the TTL, so an item is
+// reported for at least `ttl` and at most `ttl + ttl / n` after its last insert.
+
+use std::collections::VecDeque;
+use std::hash::{BuildHasher, Hash};
+use std::time::{Duration, Instant};
+
+use crate::hash::Sha256BuildHasher;
+use crate::stats::FilterStats;
+use crate::{params, BloomFilter};
+
+const DEFAULT_GENERATIONS: u32 = 4;
+
+struct Generation<S> {
+    bloom: BloomFilter<S>,
+    started: Instant,
+}
+
+pub struct ExpiringBloomFilter<S = Sha256BuildHasher> {
+    generations: VecDeque<Generation<S>>,
+    ttl: Duration,
+    span: Duration,
+    size: usize,
+    num_hashes: usize,
+    hasher: S,
+}
+
+impl ExpiringBloomFilter {
+    // capacity: number of items expected to be inserted within one TTL
+    pub fn new(ttl: Duration, capacity: usize, false_positive_rate: f64) -> Self {
+        Self::with_hasher(ttl, capacity, false_positive_rate, Sha256BuildHasher)
+    }
+}
+
+impl<S: BuildHasher + Clone> ExpiringBloomFilter<S> {
+    pub fn with_hasher(
+        ttl: Duration,
+        capacity: usize,
+        false_positive_rate: f64,
+        hasher: S,
+    ) -> Self {
+        Self::with_options(
+            ttl,
+            DEFAULT_GENERATIONS,
+            capacity,
+            false_positive_rate,
+            hasher,
+        )
+    }
+
+    // More generations expire items closer to the TTL but make queries slower
+    pub fn with_options(
+        ttl: Duration,
+        num_generations: u32,
+        capacity: usize,
+        false_positive_rate: f64,
+        hasher: S,
+    ) -> Self {
+        assert!(!ttl.is_zero(), "ttl must not be 0");
+        assert!(num_generations > 0, "number of generations must not be 0");
+        assert!(
+            false_positive_rate > 0.0 && false_positive_rate < 1.0,
+            "false positive rate must be in (0, 1)"
+        );
+        // up to n + 1 generations are live at once, each one is sized for the whole capacity
+        // so the union stays below the target rate
+        let fpp = false_positive_rate / (num_generations + 1) as f64;
+        let size = params::optimal_bit_count(capacity.max(1), fpp);
+        let num_hashes = params::optimal_num_hashes(size, capacity.max(1));
+        ExpiringBloomFilter {
+            generations: VecDeque::new(),
+            ttl,
+            span: ttl / num_generations,
+            size,
+            num_hashes,
+            hasher,
+        }
+    }
+
+    pub fn insert<T: Hash + ?Sized>(&mut self, item: &T) {
+        self.insert_at(item, Instant::now());
+    }
+
+    // `now` must not go backwards between calls
+    pub fn insert_at<T: Hash + ?Sized>(&mut self, item: &T, now: Instant) {
+        self.expire_at(now);
+        let rotate = match self.generations.back() {
+            Some(current) => now.saturating_duration_since(current.started) >= self.span,
+            None => true,
+        };
+        if rotate {
+            self.generations.push_back(Generation {
+                bloom: BloomFilter::with_hasher(self.size, self.num_hashes, self.hasher.clone()),
+                started: now,
+            });
+        }
+        self.generations.back_mut().unwrap().bloom.set(item);
+    }
+
+    pub fn contains<T: Hash + ?Sized>(&self, item: &T) -> bool {
+        self.contains_at(item, Instant::now())
+    }
+
+    pub fn contains_at<T: Hash + ?Sized>(&self, item: &T, now: Instant) -> bool {
+        self.generations
+            .iter()
+            .rev()
+            .take_while(|generation| !self.is_expired(generation, now))
+            .any(|generation| generation.bloom.test(item))
+    }
+
+    // Inserts the item and returns whether it was seen within the TTL
+    pub fn check_and_insert<T: Hash + ?Sized>(&mut self, item: &T) -> bool {
+        let now = Instant::now();
+        let seen = self.contains_at(item, now);
+        self.insert_at(item, now);
+        seen
+    }
+
+    // Drops the generations that only hold expired items
+    pub fn expire_at(&mut self, now: Instant) {
+        while let Some(oldest) = self.generations.front() {
+            if !self.is_expired(oldest, now) {
+                break;
+            }
+            self.generations.pop_front();
+        }
+    }
+
+    fn is_expired(&self, generation: &Generation<S>, now: Instant) -> bool {
+        // the newest item of a generation was inserted before started + span
+        now.saturating_duration_since(generation.started) >= self.span + self.ttl
+    }
+
+    pub fn ttl(&self) -> Duration {
+        self.ttl
+    }
+
+    pub fn num_generations(&self) -> usize {
+        self.generations.len()
+    }
+
+    // Sums over the generations still held, including expired ones `expire_at` hasn't dropped
+    pub fn stats(&self) -> FilterStats {
+        FilterStats {
+            num_hashes: self.num_hashes,
+            ..FilterStats::from_chain(
+                self.generations.iter().map(|g| g.bloom.stats()),
+                self.memory_usage(),
+            )
+        }
+    }
+
+    pub fn memory_usage(&self) -> usize {
+        let generations: usize = self.generations.iter().map(|g| g.bloom.heap_bytes()).sum();
+        size_of::<Self>() + self.generations.capacity() * size_of::<Generation<S>>() + generations
+    }
+
+    pub fn clear(&mut self) {
+        self.generations.clear();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_items_expire() {
+        let ttl = Duration::from_secs(60);
+        let mut bloom = ExpiringBloomFilter::new(ttl, 1000, 0.01);
+        let start = Instant::now();
+
+        bloom.insert_at("foo", start);
+        bloom.insert_at("bar", start + Duration::from_secs(30));
+        assert!(bloom.contains_at("foo", start + Duration::from_secs(59)));
+        assert!(bloom.contains_at("foo", start + ttl));
+        assert!(!bloom.contains_at("foo", start + Duration::from_secs(76)));
+        assert!(bloom.contains_at("bar", start + Duration::from_secs(76)));
+
+        bloom.insert_at("baz", start + Duration::from_secs(120));
+        assert!(!bloom.contains_at("bar", start + Duration::from_secs(120)));
+        assert!(bloom.contains_at("baz", start + Duration::from_secs(120)));
+        assert_eq!(bloom.num_generations(), 1);
+    }
+
+    #[test]
+    fn test_generations_are_rotated() {
+        let ttl = Duration::from_secs(40);
+        let mut bloom = ExpiringBloomFilter::new(ttl, 1000, 0.01);
+        let start = Instant::now();
+        for second in 0..200u64 {
+            bloom.insert_at(&second, start + Duration::from_secs(second));
+        }
+        assert!(bloom.num_generations() <= 6);
+        let now = start + Duration::from_secs(199);
+        for second in 160..200u64 {
+            assert!(bloom.contains_at(&second, now));
+        }
+        let false_positives = (0..150u64).filter(|s| bloom.contains_at(s, now)).count();
+        assert!(false_positives < 3);
+
+        assert!(!bloom.check_and_insert("new"));
+        assert!(bloom.check_and_insert("new"));
+    }
+}
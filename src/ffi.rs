@@ -0,0 +1,136 @@
+// C API (feature `ffi`), declared in include/bloomf.h and built from bindings/. Filters are opaque pointers to a
+// `BloomFilter` with the default hasher, items are byte strings hashed like `set_bytes`, and
+// `bloomf_serialize` writes the `BloomFilter::to_bytes` encoding, so C and Rust services can
+// exchange filters.
+//
+// Every function taking a filter pointer needs one returned by `bloomf_new` or
+// `bloomf_deserialize` and not yet passed to `bloomf_free`. `data` must point to `len` readable
+// bytes (it may be null when len is 0). Nothing here is thread safe, a filter must not be
+// used from two threads while one of them inserts.
+#![allow(clippy::missing_safety_doc)]
+
+use std::slice;
+
+use crate::BloomFilter;
+
+unsafe fn bytes<'a>(data: *const u8, len: usize) -> &'a [u8] {
+    if len == 0 {
+        &[]
+    } else {
+        slice::from_raw_parts(data, len)
+    }
+}
+
+// Null if size or num_hashes is invalid, see `BloomFilter::try_new`
+#[no_mangle]
+pub extern "C" fn bloomf_new(size: usize, num_hashes: usize) -> *mut BloomFilter {
+    match BloomFilter::try_new(size, num_hashes) {
+        Ok(bloom) => Box::into_raw(Box::new(bloom)),
+        Err(_) => std::ptr::null_mut(),
+    }
+}
+
+// Same as `bloomf_new` with `BloomFilter::with_seed`: filters with the same size, number of
+// hashes and seed set the same bits here and in Rust
+#[no_mangle]
+pub extern "C" fn bloomf_new_seeded(size: usize, num_hashes: usize, seed: u64) -> *mut BloomFilter {
+    match BloomFilter::try_new(size, num_hashes) {
+        Ok(bloom) => Box::into_raw(Box::new(bloom.with_seed(seed))),
+        Err(_) => std::ptr::null_mut(),
+    }
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn bloomf_free(bloom: *mut BloomFilter) {
+    if !bloom.is_null() {
+        drop(Box::from_raw(bloom));
+    }
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn bloomf_insert(bloom: *mut BloomFilter, data: *const u8, len: usize) {
+    (*bloom).set_bytes(bytes(data, len));
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn bloomf_contains(
+    bloom: *const BloomFilter,
+    data: *const u8,
+    len: usize,
+) -> bool {
+    (*bloom).test_bytes(bytes(data, len))
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn bloomf_clear(bloom: *mut BloomFilter) {
+    (*bloom).reset();
+}
+
+// Returns a buffer of *out_len bytes, to be released with `bloomf_bytes_free`
+#[no_mangle]
+pub unsafe extern "C" fn bloomf_serialize(
+    bloom: *const BloomFilter,
+    out_len: *mut usize,
+) -> *mut u8 {
+    let encoded = (*bloom).to_bytes().into_boxed_slice();
+    *out_len = encoded.len();
+    Box::into_raw(encoded) as *mut u8
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn bloomf_bytes_free(data: *mut u8, len: usize) {
+    if !data.is_null() {
+        drop(Box::from_raw(std::ptr::slice_from_raw_parts_mut(data, len)));
+    }
+}
+
+// Null if the bytes are not a valid encoded filter
+#[no_mangle]
+pub unsafe extern "C" fn bloomf_deserialize(data: *const u8, len: usize) -> *mut BloomFilter {
+    match BloomFilter::from_bytes(bytes(data, len)) {
+        Ok(bloom) => Box::into_raw(Box::new(bloom)),
+        Err(_) => std::ptr::null_mut(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_c_api_round_trip() {
+        unsafe {
+            assert!(bloomf_new(0, 3).is_null());
+            let bloom = bloomf_new(1000, 3);
+            bloomf_insert(bloom, b"foo".as_ptr(), 3);
+            assert!(bloomf_contains(bloom, b"foo".as_ptr(), 3));
+            assert!(!bloomf_contains(bloom, b"bar".as_ptr(), 3));
+
+            let mut len = 0;
+            let encoded = bloomf_serialize(bloom, &mut len);
+            let copy = bloomf_deserialize(encoded, len);
+            assert!(!copy.is_null());
+            assert!(bloomf_contains(copy, b"foo".as_ptr(), 3));
+            assert!(bloomf_deserialize(encoded, len - 1).is_null());
+            bloomf_bytes_free(encoded, len);
+
+            bloomf_clear(bloom);
+            assert!(!bloomf_contains(bloom, b"foo".as_ptr(), 3));
+            bloomf_free(bloom);
+            bloomf_free(copy);
+
+            let seeded = bloomf_new_seeded(1000, 3, 42);
+            bloomf_insert(seeded, b"foo".as_ptr(), 3);
+            let mut rust = BloomFilter::new(1000, 3).with_seed(42);
+            rust.set_bytes(b"foo");
+            assert_eq!(*seeded, rust);
+            let encoded = bloomf_serialize(seeded, &mut len);
+            let copy = bloomf_deserialize(encoded, len);
+            assert_eq!((*copy).seed(), 42);
+            assert!(bloomf_contains(copy, b"foo".as_ptr(), 3));
+            bloomf_bytes_free(encoded, len);
+            bloomf_free(seeded);
+            bloomf_free(copy);
+        }
+    }
+}
@@ -0,0 +1,126 @@
+// Read-only filter for the build once, query forever case. `freeze` turns a filter into a
+// `FrozenBloomFilter` that has no way to change its bits: queries are plain loads, no locks
+// and no atomics, and it's `Sync` (for a `Sync` hasher) so one `Arc<FrozenBloomFilter>` can be
+// queried from every thread. `thaw` gives the filter back to add more items.
+
+use std::hash::{BuildHasher, Hash};
+
+use crate::error::BloomError;
+use crate::hash::Sha256BuildHasher;
+use crate::stats::FilterStats;
+use crate::view::BloomFilterRef;
+use crate::{AtomicBloomFilter, BloomFilter};
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct FrozenBloomFilter<S = Sha256BuildHasher>(BloomFilter<S>);
+
+impl<S: BuildHasher> FrozenBloomFilter<S> {
+    pub fn test<T: Hash + ?Sized>(&self, item: &T) -> bool {
+        self.0.test(item)
+    }
+
+    pub fn test_bytes(&self, bytes: impl AsRef<[u8]>) -> bool {
+        self.0.test_bytes(bytes)
+    }
+
+    pub fn test_many<T: Hash>(&self, items: &[T]) -> Vec<bool> {
+        self.0.test_many(items)
+    }
+
+    pub fn size(&self) -> usize {
+        self.0.size()
+    }
+
+    pub fn num_hashes(&self) -> usize {
+        self.0.num_hashes()
+    }
+
+    pub fn seed(&self) -> u64 {
+        self.0.seed()
+    }
+
+    pub fn estimated_len(&self) -> usize {
+        self.0.estimated_len()
+    }
+
+    pub fn fill_ratio(&self) -> f64 {
+        self.0.fill_ratio()
+    }
+
+    pub fn memory_usage(&self) -> usize {
+        self.0.memory_usage()
+    }
+
+    pub fn stats(&self) -> FilterStats {
+        self.0.stats()
+    }
+
+    pub fn as_raw_words(&self) -> &[u64] {
+        self.0.as_raw_words()
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        self.0.to_bytes()
+    }
+
+    // The filter it was frozen from, writable again
+    pub fn thaw(self) -> BloomFilter<S> {
+        self.0
+    }
+}
+
+impl<S: BuildHasher + Clone> FrozenBloomFilter<S> {
+    pub fn as_filter_ref(&self) -> Result<BloomFilterRef<'_, S>, BloomError> {
+        self.0.as_filter_ref()
+    }
+}
+
+impl<S> BloomFilter<S> {
+    pub fn freeze(self) -> FrozenBloomFilter<S> {
+        FrozenBloomFilter(self)
+    }
+}
+
+impl<S: BuildHasher> AtomicBloomFilter<S> {
+    // Takes the filter by value, so every insert has finished
+    pub fn freeze(self) -> FrozenBloomFilter<S> {
+        let words = self.bit_array.into_words();
+        FrozenBloomFilter(
+            BloomFilter::from_parts(self.size, self.num_hashes, words, self.hasher)
+                .with_seed(self.seed),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Arc;
+    use std::thread;
+
+    #[test]
+    fn test_freeze() {
+        let mut bloom = BloomFilter::new(10_000, 4);
+        bloom.insert_all(0..500u32);
+        let frozen = Arc::new(bloom.clone().freeze());
+
+        let handles: Vec<_> = (0..4)
+            .map(|_| {
+                let frozen = Arc::clone(&frozen);
+                thread::spawn(move || (0..500u32).all(|i| frozen.test(&i)))
+            })
+            .collect();
+        assert!(handles.into_iter().all(|handle| handle.join().unwrap()));
+        assert_eq!(frozen.as_raw_words(), bloom.as_raw_words());
+
+        let atomic = AtomicBloomFilter::new(10_000, 4).with_seed(3);
+        atomic.set(&1u32);
+        let frozen_atomic = atomic.freeze();
+        assert!(frozen_atomic.test(&1u32));
+        assert_eq!(frozen_atomic.seed(), 3);
+
+        let mut thawed = Arc::try_unwrap(frozen).unwrap().thaw();
+        thawed.set(&1000u32);
+        assert!(thawed.test(&1000u32));
+    }
+}
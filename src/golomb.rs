@@ -0,0 +1,368 @@
+// Golomb-coded set: a sorted set of hash values in [0, range) stored as Golomb-Rice coded gaps.
+// Built from items with one hash over range = n / p it needs about log2(1 / p) + 1.5 bits per
+// item, less than the 1.44 * log2(1 / p) of a Bloom filter, but queries have to decode gaps.
+// A sampled index (every 64 values) keeps that to a short scan instead of the whole set.
+//
+// `BloomFilter::to_gcs` stores the positions of the set bits the same way and answers exactly
+// like the filter. That only pays off for sparse filters, a filter around half full doesn't
+// compress.
+
+use std::hash::{BuildHasher, Hash};
+
+use crate::error::BloomError;
+use crate::hash::{self, Sha256BuildHasher};
+use crate::heap_bytes;
+
+const INDEX_INTERVAL: usize = 64;
+const HEADER_LEN: usize = 21;
+
+pub struct GolombSet<S = Sha256BuildHasher> {
+    data: Vec<u8>,
+    // number of values
+    len: usize,
+    range: u64,
+    num_hashes: usize,
+    rice_bits: u8,
+    // (value, bit position of the next gap) of every INDEX_INTERVAL-th value
+    index: Vec<(u64, usize)>,
+    hasher: S,
+}
+
+impl GolombSet {
+    pub fn from_items<T: Hash>(items: &[T], false_positive_rate: f64) -> Self {
+        Self::from_items_with_hasher(items, false_positive_rate, Sha256BuildHasher)
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, BloomError> {
+        Self::from_bytes_with_hasher(bytes, Sha256BuildHasher)
+    }
+}
+
+impl<S: BuildHasher> GolombSet<S> {
+    pub fn from_items_with_hasher<T: Hash>(
+        items: &[T],
+        false_positive_rate: f64,
+        hasher: S,
+    ) -> Self {
+        assert!(
+            false_positive_rate > 0.0 && false_positive_rate < 1.0,
+            "false positive rate must be in (0, 1)"
+        );
+        let m = (1.0 / false_positive_rate).round().max(2.0) as u64;
+        let range = (items.len().max(1) as u64).saturating_mul(m);
+        let mut values: Vec<u64> = items
+            .iter()
+            .flat_map(|item| hash::indices(&hasher, item, 1, range as usize))
+            .map(|value| value as u64)
+            .collect();
+        values.sort_unstable();
+        values.dedup();
+        Self::from_sorted(&values, range, 1, hasher)
+    }
+
+    // `values` must be sorted, unique and below `range`
+    pub(crate) fn from_sorted(values: &[u64], range: u64, num_hashes: usize, hasher: S) -> Self {
+        let rice_bits = rice_parameter(range, values.len());
+        let mut writer = BitWriter::default();
+        let mut previous = 0;
+        for &value in values {
+            let gap = value - previous;
+            writer.write_unary(gap >> rice_bits);
+            writer.write_bits(gap, rice_bits);
+            previous = value;
+        }
+        let mut set = GolombSet {
+            data: writer.bytes,
+            len: values.len(),
+            range,
+            num_hashes,
+            rice_bits,
+            index: Vec::new(),
+            hasher,
+        };
+        set.build_index();
+        set
+    }
+
+    fn build_index(&mut self) {
+        let mut reader = BitReader::new(&self.data, 0);
+        let mut value = 0;
+        let mut index = Vec::with_capacity(self.len / INDEX_INTERVAL + 1);
+        for i in 0..self.len {
+            if i % INDEX_INTERVAL == 0 {
+                index.push((value, reader.position));
+            }
+            value += reader.read_gap(self.rice_bits);
+        }
+        self.index = index;
+    }
+
+    fn contains_value(&self, target: u64) -> bool {
+        if self.len == 0 {
+            return false;
+        }
+        // last sample below the target, the value it starts from is the one before its block
+        let sample = self
+            .index
+            .partition_point(|&(value, _)| value < target)
+            .max(1);
+        let (mut value, position) = self.index[sample - 1];
+        let mut reader = BitReader::new(&self.data, position);
+        let remaining = (self.len - (sample - 1) * INDEX_INTERVAL).min(INDEX_INTERVAL);
+        for _ in 0..remaining {
+            value += reader.read_gap(self.rice_bits);
+            if value >= target {
+                return value == target;
+            }
+        }
+        false
+    }
+
+    pub fn contains<T: Hash + ?Sized>(&self, item: &T) -> bool {
+        hash::indices(&self.hasher, item, self.num_hashes, self.range as usize)
+            .all(|value| self.contains_value(value as u64))
+    }
+
+    // Number of stored values: distinct item hashes, or set bits for `BloomFilter::to_gcs`
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    pub fn memory_usage(&self) -> usize {
+        size_of::<Self>() + heap_bytes(&self.data) + heap_bytes(&self.index)
+    }
+
+    // Size of the encoded set in bytes
+    pub fn encoded_len(&self) -> usize {
+        HEADER_LEN + self.data.len()
+    }
+
+    // u64 range, u32 number of hashes, u8 rice parameter, u64 number of values (all
+    // little-endian), then the Golomb-Rice coded gaps, most significant bit first
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(self.encoded_len());
+        bytes.extend_from_slice(&self.range.to_le_bytes());
+        bytes.extend_from_slice(&(self.num_hashes as u32).to_le_bytes());
+        bytes.push(self.rice_bits);
+        bytes.extend_from_slice(&(self.len as u64).to_le_bytes());
+        bytes.extend_from_slice(&self.data);
+        bytes
+    }
+
+    // The hasher must be the one the set was built with
+    pub fn from_bytes_with_hasher(bytes: &[u8], hasher: S) -> Result<Self, BloomError> {
+        Self::decode(bytes, hasher).map_err(BloomError::SerializationError)
+    }
+
+    fn decode(bytes: &[u8], hasher: S) -> Result<Self, String> {
+        if bytes.len() < HEADER_LEN {
+            return Err("Encoded GolombSet is too short.".into());
+        }
+        let range = u64::from_le_bytes(bytes[0..8].try_into().unwrap());
+        let num_hashes = u32::from_le_bytes(bytes[8..12].try_into().unwrap()) as usize;
+        let rice_bits = bytes[12];
+        let len = u64::from_le_bytes(bytes[13..21].try_into().unwrap()) as usize;
+        if rice_bits > 63 || range == 0 {
+            return Err("Invalid GolombSet parameters.".into());
+        }
+        // with 0 hashes every item would be contained
+        if num_hashes == 0 || num_hashes > crate::MAX_HASHES {
+            return Err(format!(
+                "Number of hashes must be between 1 and {}, got {}.",
+                crate::MAX_HASHES,
+                num_hashes
+            ));
+        }
+        let data = bytes[HEADER_LEN..].to_vec();
+        // every value takes at least rice_bits + 1 bits
+        if (len as u128) * (rice_bits as u128 + 1) > data.len() as u128 * 8 {
+            return Err("Encoded GolombSet is truncated.".into());
+        }
+        let mut set = GolombSet {
+            data,
+            len,
+            range,
+            num_hashes,
+            rice_bits,
+            index: Vec::new(),
+            hasher,
+        };
+        set.check_and_build_index()?;
+        Ok(set)
+    }
+
+    // Like `build_index`, but for untrusted data: unary runs must not run off the end
+    fn check_and_build_index(&mut self) -> Result<(), String> {
+        let total_bits = self.data.len() * 8;
+        let mut reader = BitReader::new(&self.data, 0);
+        let mut value = 0u64;
+        let mut index = Vec::with_capacity(self.len / INDEX_INTERVAL + 1);
+        for i in 0..self.len {
+            if i % INDEX_INTERVAL == 0 {
+                index.push((value, reader.position));
+            }
+            let gap = reader
+                .try_read_gap(self.rice_bits, total_bits)
+                .ok_or("Encoded GolombSet is truncated.")?;
+            value = value
+                .checked_add(gap)
+                .filter(|&value| value < self.range)
+                .ok_or("GolombSet value is out of range.")?;
+        }
+        self.index = index;
+        Ok(())
+    }
+}
+
+// Rice parameter for `count` values spread over `range`, about log2 of the mean gap * ln(2)
+fn rice_parameter(range: u64, count: usize) -> u8 {
+    if count == 0 {
+        return 0;
+    }
+    let mean_gap = range as f64 / count as f64;
+    (mean_gap * std::f64::consts::LN_2)
+        .log2()
+        .floor()
+        .clamp(0.0, 63.0) as u8
+}
+
+#[derive(Default)]
+struct BitWriter {
+    bytes: Vec<u8>,
+    bit_len: usize,
+}
+
+impl BitWriter {
+    fn push(&mut self, bit: bool) {
+        if self.bit_len.is_multiple_of(8) {
+            self.bytes.push(0);
+        }
+        if bit {
+            *self.bytes.last_mut().unwrap() |= 0x80 >> (self.bit_len % 8);
+        }
+        self.bit_len += 1;
+    }
+
+    fn write_unary(&mut self, quotient: u64) {
+        for _ in 0..quotient {
+            self.push(true);
+        }
+        self.push(false);
+    }
+
+    fn write_bits(&mut self, value: u64, bits: u8) {
+        for i in (0..bits).rev() {
+            self.push(value >> i & 1 == 1);
+        }
+    }
+}
+
+struct BitReader<'a> {
+    bytes: &'a [u8],
+    position: usize,
+}
+
+impl<'a> BitReader<'a> {
+    fn new(bytes: &'a [u8], position: usize) -> Self {
+        BitReader { bytes, position }
+    }
+
+    fn read(&mut self) -> bool {
+        let bit = self.bytes[self.position / 8] & (0x80 >> (self.position % 8)) != 0;
+        self.position += 1;
+        bit
+    }
+
+    fn read_gap(&mut self, rice_bits: u8) -> u64 {
+        let mut quotient = 0;
+        while self.read() {
+            quotient += 1;
+        }
+        let mut remainder = 0;
+        for _ in 0..rice_bits {
+            remainder = remainder << 1 | self.read() as u64;
+        }
+        quotient << rice_bits | remainder
+    }
+
+    fn try_read_gap(&mut self, rice_bits: u8, total_bits: usize) -> Option<u64> {
+        let mut quotient = 0u64;
+        loop {
+            if self.position >= total_bits {
+                return None;
+            }
+            if !self.read() {
+                break;
+            }
+            quotient += 1;
+        }
+        if self.position + rice_bits as usize > total_bits {
+            return None;
+        }
+        let mut remainder = 0;
+        for _ in 0..rice_bits {
+            remainder = remainder << 1 | self.read() as u64;
+        }
+        quotient
+            .checked_mul(1 << rice_bits)
+            .map(|high| high | remainder)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::BloomFilter;
+
+    #[test]
+    fn test_golomb_set() {
+        let items: Vec<u32> = (0..10_000).collect();
+        let set = GolombSet::from_items(&items, 0.01);
+        assert!(set.len() > 9_900);
+        assert!(items.iter().all(|item| set.contains(item)));
+        let false_positives = (10_000..110_000u32).filter(|i| set.contains(i)).count();
+        assert!(
+            false_positives < 1_300,
+            "{} false positives",
+            false_positives
+        );
+
+        // ~8.2 bits per item vs ~9.6 for a Bloom filter with the same error
+        assert!(set.encoded_len() * 8 < 10_000 * 9);
+
+        let decoded = GolombSet::from_bytes(&set.to_bytes()).unwrap();
+        assert!(items.iter().all(|item| decoded.contains(item)));
+        assert!(GolombSet::from_bytes(&set.to_bytes()[..500]).is_err());
+        // range 0, 0 hashes
+        for field in [0..8, 8..12] {
+            let mut bytes = set.to_bytes();
+            bytes[field].fill(0);
+            assert!(GolombSet::from_bytes(&bytes).is_err());
+        }
+    }
+
+    #[test]
+    fn test_bloom_filter_to_gcs() {
+        let mut bloom = BloomFilter::new(100_000, 3);
+        for i in 0..1000u32 {
+            bloom.set(&i);
+        }
+        let gcs = bloom.to_gcs().unwrap();
+        assert!(gcs.len() <= 3000);
+        for i in 0..5000u32 {
+            assert_eq!(gcs.contains(&i), bloom.test(&i));
+        }
+        assert!(gcs.encoded_len() < 100_000 / 8 / 2);
+
+        let empty = BloomFilter::new(1000, 3).to_gcs().unwrap();
+        assert!(empty.is_empty());
+        assert!(!empty.contains("foo"));
+
+        let seeded = BloomFilter::new(1000, 3).with_seed(42);
+        assert!(seeded.to_gcs().is_err());
+    }
+}
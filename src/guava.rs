@@ -0,0 +1,302 @@
+// Interop with Guava's `com.google.common.hash.BloomFilter` (`writeTo` / `readFrom`).
+//
+// Serialized form, big-endian (Java `DataOutputStream`):
+//   u8 strategy ordinal (0 = MURMUR128_MITZ_32, 1 = MURMUR128_MITZ_64, the default)
+//   u8 number of hash functions
+//   i32 number of words, then the words as i64
+// Bit i is bit i % 64 of word i / 64, the filter has words * 64 bits.
+//
+// Guava hashes what the `Funnel` writes with murmur3_x64_128 (seed 0), so items here are those
+// bytes: UTF-8 for `Funnels.stringFunnel(UTF_8)`, `value.to_le_bytes()` for
+// `Funnels.longFunnel()` / `integerFunnel()`, the array itself for `byteArrayFunnel()`.
+
+use crate::error::BloomError;
+use crate::heap_bytes;
+use crate::stats::FilterStats;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum GuavaStrategy {
+    Murmur128Mitz32,
+    Murmur128Mitz64,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct GuavaBloomFilter {
+    strategy: GuavaStrategy,
+    num_hashes: u8,
+    words: Vec<u64>,
+}
+
+impl GuavaBloomFilter {
+    // Same sizing as `BloomFilter.create(funnel, expectedInsertions, fpp)`
+    pub fn new(expected_insertions: usize, false_positive_rate: f64) -> Self {
+        assert!(
+            false_positive_rate > 0.0 && false_positive_rate < 1.0,
+            "false positive rate must be in (0, 1)"
+        );
+        let expected_insertions = expected_insertions.max(1);
+        // Guava truncates where `params::optimal_bit_count` rounds up
+        let ln2 = std::f64::consts::LN_2;
+        let num_bits = ((-(expected_insertions as f64) * false_positive_rate.ln() / (ln2 * ln2))
+            as usize)
+            .max(1);
+        let num_hashes = ((num_bits as f64 / expected_insertions as f64) * ln2)
+            .round()
+            .clamp(1.0, 255.0) as u8;
+        GuavaBloomFilter {
+            strategy: GuavaStrategy::Murmur128Mitz64,
+            num_hashes,
+            words: vec![0; num_bits.div_ceil(64).max(1)],
+        }
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, BloomError> {
+        Self::decode(bytes).map_err(BloomError::SerializationError)
+    }
+
+    fn decode(bytes: &[u8]) -> Result<Self, String> {
+        if bytes.len() < 6 {
+            return Err("Guava BloomFilter is too short.".into());
+        }
+        let strategy = match bytes[0] {
+            0 => GuavaStrategy::Murmur128Mitz32,
+            1 => GuavaStrategy::Murmur128Mitz64,
+            other => return Err(format!("Unknown Guava BloomFilter strategy {}.", other)),
+        };
+        let num_hashes = bytes[1];
+        let num_words = i32::from_be_bytes(bytes[2..6].try_into().unwrap());
+        let body = &bytes[6..];
+        if num_words <= 0 || body.len() != num_words as usize * 8 {
+            return Err(format!(
+                "Expected {} words of Guava BloomFilter data, got {} bytes.",
+                num_words,
+                body.len()
+            ));
+        }
+        let words = body
+            .chunks_exact(8)
+            .map(|word| u64::from_be_bytes(word.try_into().unwrap()))
+            .collect();
+        Ok(GuavaBloomFilter {
+            strategy,
+            num_hashes,
+            words,
+        })
+    }
+
+    // Readable with `BloomFilter.readFrom(in, funnel)`
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(6 + self.words.len() * 8);
+        bytes.push(match self.strategy {
+            GuavaStrategy::Murmur128Mitz32 => 0,
+            GuavaStrategy::Murmur128Mitz64 => 1,
+        });
+        bytes.push(self.num_hashes);
+        bytes.extend_from_slice(&(self.words.len() as i32).to_be_bytes());
+        for word in &self.words {
+            bytes.extend_from_slice(&word.to_be_bytes());
+        }
+        bytes
+    }
+
+    fn indices(&self, item: &[u8]) -> Vec<u64> {
+        let bit_size = self.bit_len();
+        let (h1, h2) = murmur3_x64_128(item, 0);
+        match self.strategy {
+            GuavaStrategy::Murmur128Mitz64 => {
+                let mut combined = h1;
+                (0..self.num_hashes)
+                    .map(|_| {
+                        let idx = (combined & i64::MAX as u64) % bit_size;
+                        combined = combined.wrapping_add(h2);
+                        idx
+                    })
+                    .collect()
+            }
+            GuavaStrategy::Murmur128Mitz32 => {
+                let hash1 = h1 as i32;
+                let hash2 = (h1 >> 32) as i32;
+                (1..=self.num_hashes as i32)
+                    .map(|i| {
+                        let mut combined = hash1.wrapping_add(i.wrapping_mul(hash2));
+                        if combined < 0 {
+                            combined = !combined;
+                        }
+                        combined as u64 % bit_size
+                    })
+                    .collect()
+            }
+        }
+    }
+
+    // `put`, returns true if any bit changed
+    pub fn insert(&mut self, item: impl AsRef<[u8]>) -> bool {
+        let mut changed = false;
+        for idx in self.indices(item.as_ref()) {
+            let word = &mut self.words[(idx / 64) as usize];
+            changed |= *word & (1 << (idx % 64)) == 0;
+            *word |= 1 << (idx % 64);
+        }
+        changed
+    }
+
+    // `mightContain`
+    pub fn contains(&self, item: impl AsRef<[u8]>) -> bool {
+        self.indices(item.as_ref())
+            .into_iter()
+            .all(|idx| self.words[(idx / 64) as usize] & (1 << (idx % 64)) != 0)
+    }
+
+    pub fn memory_usage(&self) -> usize {
+        size_of::<Self>() + heap_bytes(&self.words)
+    }
+
+    pub fn stats(&self) -> FilterStats {
+        let set_bits = self
+            .words
+            .iter()
+            .map(|word| word.count_ones() as usize)
+            .sum();
+        FilterStats::new(
+            self.bit_len() as usize,
+            self.num_hashes(),
+            set_bits,
+            self.memory_usage(),
+        )
+    }
+
+    pub fn strategy(&self) -> GuavaStrategy {
+        self.strategy
+    }
+
+    pub fn num_hashes(&self) -> usize {
+        self.num_hashes as usize
+    }
+
+    pub fn bit_len(&self) -> u64 {
+        self.words.len() as u64 * 64
+    }
+}
+
+// MurmurHash3 x64 128 by Austin Appleby, returns (h1, h2)
+// which Guava writes as the 16 little-endian hash bytes.
+pub(crate) fn murmur3_x64_128(data: &[u8], seed: u64) -> (u64, u64) {
+    const C1: u64 = 0x87c3_7b91_1142_53d5;
+    const C2: u64 = 0x4cf5_ad43_2745_937f;
+
+    fn fmix64(mut k: u64) -> u64 {
+        k ^= k >> 33;
+        k = k.wrapping_mul(0xff51_afd7_ed55_8ccd);
+        k ^= k >> 33;
+        k = k.wrapping_mul(0xc4ce_b9fe_1a85_ec53);
+        k ^ (k >> 33)
+    }
+
+    let mut h1 = seed;
+    let mut h2 = seed;
+    let mut blocks = data.chunks_exact(16);
+    for block in &mut blocks {
+        let k1 = u64::from_le_bytes(block[..8].try_into().unwrap());
+        let k2 = u64::from_le_bytes(block[8..].try_into().unwrap());
+        h1 ^= k1.wrapping_mul(C1).rotate_left(31).wrapping_mul(C2);
+        h1 = h1
+            .rotate_left(27)
+            .wrapping_add(h2)
+            .wrapping_mul(5)
+            .wrapping_add(0x52dc_e729);
+        h2 ^= k2.wrapping_mul(C2).rotate_left(33).wrapping_mul(C1);
+        h2 = h2
+            .rotate_left(31)
+            .wrapping_add(h1)
+            .wrapping_mul(5)
+            .wrapping_add(0x3849_5ab5);
+    }
+
+    let tail = blocks.remainder();
+    let mut k1 = 0u64;
+    let mut k2 = 0u64;
+    for (i, &byte) in tail.iter().enumerate() {
+        if i < 8 {
+            k1 ^= (byte as u64) << (8 * i);
+        } else {
+            k2 ^= (byte as u64) << (8 * (i - 8));
+        }
+    }
+    if tail.len() > 8 {
+        h2 ^= k2.wrapping_mul(C2).rotate_left(33).wrapping_mul(C1);
+    }
+    if !tail.is_empty() {
+        h1 ^= k1.wrapping_mul(C1).rotate_left(31).wrapping_mul(C2);
+    }
+
+    h1 ^= data.len() as u64;
+    h2 ^= data.len() as u64;
+    h1 = h1.wrapping_add(h2);
+    h2 = h2.wrapping_add(h1);
+    h1 = fmix64(h1);
+    h2 = fmix64(h2);
+    h1 = h1.wrapping_add(h2);
+    h2 = h2.wrapping_add(h1);
+    (h1, h2)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_murmur3_x64_128() {
+        assert_eq!(murmur3_x64_128(b"", 0), (0, 0));
+        let (h1, h2) = murmur3_x64_128(b"Hello, world!", 0);
+        assert_eq!(
+            h1.to_le_bytes(),
+            [0xdf, 0x65, 0xd6, 0xd2, 0xd1, 0x2d, 0x51, 0xf1]
+        );
+        assert_eq!(
+            h2.to_le_bytes(),
+            [0x64, 0xc5, 0xf3, 0xa8, 0x50, 0x66, 0x32, 0x2c]
+        );
+        let (h1, _) = murmur3_x64_128(
+            b"Lorem ipsum dolor sit amet, consectetur adipisicing elit",
+            0,
+        );
+        assert_eq!(
+            h1.to_le_bytes(),
+            [0x6f, 0x5c, 0xb0, 0x2c, 0xfd, 0x5e, 0xdc, 0x6f]
+        );
+    }
+
+    #[test]
+    fn test_round_trip() {
+        // BloomFilter.create(Funnels.stringFunnel(UTF_8), 1000, 0.01)
+        let mut bloom = GuavaBloomFilter::new(1000, 0.01);
+        assert_eq!(bloom.num_hashes(), 7);
+        assert_eq!(bloom.bit_len(), 9600);
+
+        assert!(bloom.insert("foo"));
+        assert!(!bloom.insert("foo"));
+        bloom.insert(42i64.to_le_bytes());
+
+        let bytes = bloom.to_bytes();
+        assert_eq!(&bytes[..6], &[1, 7, 0, 0, 0, 150]);
+        let decoded = GuavaBloomFilter::from_bytes(&bytes).unwrap();
+        assert_eq!(decoded, bloom);
+        assert!(decoded.contains("foo"));
+        assert!(decoded.contains(42i64.to_le_bytes()));
+        assert!(!decoded.contains("bar"));
+
+        assert!(GuavaBloomFilter::from_bytes(&bytes[..100]).is_err());
+    }
+
+    #[test]
+    fn test_mitz32_strategy() {
+        let mut bytes = GuavaBloomFilter::new(100, 0.01).to_bytes();
+        bytes[0] = 0;
+        let mut bloom = GuavaBloomFilter::from_bytes(&bytes).unwrap();
+        assert_eq!(bloom.strategy(), GuavaStrategy::Murmur128Mitz32);
+        for i in 0..100i32 {
+            bloom.insert(i.to_le_bytes());
+        }
+        assert!((0..100i32).all(|i| bloom.contains(i.to_le_bytes())));
+    }
+}
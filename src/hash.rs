@@ -0,0 +1,591 @@
+// Hashing used by the filters: the default SHA256 `BuildHasher` and the derivation of the
+// k bit indices of an item from any `BuildHasher`.
+
+use std::hash::{BuildHasher, Hash, Hasher};
+
+use sha2::{Digest, Sha256};
+
+// Integer writes of the hashers here, always little-endian so bit positions don't depend on
+// the platform
+macro_rules! le_integer_writes {
+    () => {
+        fn write_u16(&mut self, i: u16) {
+            self.write(&i.to_le_bytes());
+        }
+
+        fn write_u32(&mut self, i: u32) {
+            self.write(&i.to_le_bytes());
+        }
+
+        fn write_u64(&mut self, i: u64) {
+            self.write(&i.to_le_bytes());
+        }
+
+        fn write_u128(&mut self, i: u128) {
+            self.write(&i.to_le_bytes());
+        }
+
+        fn write_usize(&mut self, i: usize) {
+            self.write_u64(i as u64);
+        }
+    };
+}
+
+// `Hasher` adapter so any `T: Hash` can be fed into SHA256.
+// Integers are always written little-endian so bit positions don't depend on the platform.
+#[derive(Clone, Default)]
+pub struct Sha256Hasher(Sha256);
+
+impl Sha256Hasher {
+    pub fn new() -> Self {
+        Sha256Hasher(Sha256::new())
+    }
+}
+
+impl Hasher for Sha256Hasher {
+    fn write(&mut self, bytes: &[u8]) {
+        self.0.update(bytes);
+    }
+
+    le_integer_writes!();
+
+    fn finish(&self) -> u64 {
+        let hash_res = self.0.clone().finalize();
+
+        let mut hash_val = [0u8; 8];
+        hash_val.copy_from_slice(&hash_res[0..8]); // Take the first 8 bytes of the hash
+        u64::from_le_bytes(hash_val)
+    }
+}
+
+// User supplied hash function, see `BloomFilter::set_hash_fn`.
+pub type HashFn = Box<dyn Fn(&[u8]) -> u64 + Send + Sync>;
+
+// Collects the bytes a `Hash` impl writes so they can be passed to a `HashFn`.
+// Integers are written little-endian, same as `Sha256Hasher`.
+#[derive(Default)]
+pub(crate) struct ByteCollector(Vec<u8>);
+
+impl Hasher for ByteCollector {
+    fn write(&mut self, bytes: &[u8]) {
+        self.0.extend_from_slice(bytes);
+    }
+
+    le_integer_writes!();
+
+    // Only used to collect bytes, the hashing is done by the `HashFn`s
+    fn finish(&self) -> u64 {
+        0
+    }
+}
+
+pub(crate) fn item_bytes<T: Hash + ?Sized>(item: &T) -> Vec<u8> {
+    let mut collector = ByteCollector::default();
+    item.hash(&mut collector);
+    collector.0
+}
+
+// Default `BuildHasher` of the filters, every hasher it builds starts from the same state
+// so bit positions are the same across runs and processes.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Sha256BuildHasher;
+
+impl BuildHasher for Sha256BuildHasher {
+    type Hasher = Sha256Hasher;
+
+    fn build_hasher(&self) -> Sha256Hasher {
+        Sha256Hasher::new()
+    }
+}
+
+// Non-cryptographic alternatives to SHA256, several times faster per probe (see the benches)
+// and just as good at spreading items over the bits. Like `Sha256BuildHasher` they always start
+// from the same state, so the positions are stable across runs and platforms. Pick one per
+// filter, ex. BloomFilter::with_hasher(1000, 3, Xxh3BuildHasher). SHA256 stays the default:
+// filters built with it keep loading, and it's the one to use when items may be chosen by an
+// adversary.
+
+// XXH3 64 bits, feature `xxhash`
+#[cfg(feature = "xxhash")]
+#[derive(Clone, Default)]
+pub struct Xxh3Hasher(xxhash_rust::xxh3::Xxh3Default);
+
+#[cfg(feature = "xxhash")]
+impl Hasher for Xxh3Hasher {
+    fn write(&mut self, bytes: &[u8]) {
+        self.0.update(bytes);
+    }
+
+    le_integer_writes!();
+
+    fn finish(&self) -> u64 {
+        self.0.digest()
+    }
+}
+
+#[cfg(feature = "xxhash")]
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Xxh3BuildHasher;
+
+#[cfg(feature = "xxhash")]
+impl BuildHasher for Xxh3BuildHasher {
+    type Hasher = Xxh3Hasher;
+
+    fn build_hasher(&self) -> Xxh3Hasher {
+        Xxh3Hasher::default()
+    }
+}
+
+// wyhash, feature `wyhash`
+#[cfg(feature = "wyhash")]
+#[derive(Clone, Default)]
+pub struct WyHasher(wyhash::WyHash);
+
+#[cfg(feature = "wyhash")]
+impl Hasher for WyHasher {
+    fn write(&mut self, bytes: &[u8]) {
+        self.0.write(bytes);
+    }
+
+    le_integer_writes!();
+
+    fn finish(&self) -> u64 {
+        self.0.finish()
+    }
+}
+
+#[cfg(feature = "wyhash")]
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct WyBuildHasher;
+
+#[cfg(feature = "wyhash")]
+impl BuildHasher for WyBuildHasher {
+    type Hasher = WyHasher;
+
+    fn build_hasher(&self) -> WyHasher {
+        WyHasher::default()
+    }
+}
+
+// Keyed SipHash-2-4 (feature `siphash`) for filters gating untrusted input. With a public
+// hasher anyone can search offline for items that land on already set bits and get them
+// through as false positives, with a secret 128-bit key they can't tell where anything lands.
+// The key is never written out: `to_bytes` doesn't include the hasher, the type has no serde
+// impls and `Debug` hides it. Keep it yourself (`key`) to load the filter again, ex.
+// BloomFilter::from_bytes_with_hasher(&bytes, SipBuildHasher::new(key)).
+#[cfg(feature = "siphash")]
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub struct SipBuildHasher {
+    key: [u8; 16],
+}
+
+#[cfg(feature = "siphash")]
+impl SipBuildHasher {
+    pub fn new(key: [u8; 16]) -> Self {
+        SipBuildHasher { key }
+    }
+
+    // Key from the OS randomness behind `RandomState`, lost with the process unless saved
+    pub fn random() -> Self {
+        let state = std::collections::hash_map::RandomState::new();
+        let mut key = [0u8; 16];
+        key[..8].copy_from_slice(&state.hash_one(0u8).to_le_bytes());
+        key[8..].copy_from_slice(&state.hash_one(1u8).to_le_bytes());
+        SipBuildHasher { key }
+    }
+
+    pub fn key(&self) -> [u8; 16] {
+        self.key
+    }
+}
+
+#[cfg(feature = "siphash")]
+impl std::fmt::Debug for SipBuildHasher {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("SipBuildHasher").finish_non_exhaustive()
+    }
+}
+
+#[cfg(feature = "siphash")]
+impl BuildHasher for SipBuildHasher {
+    type Hasher = SipHasher;
+
+    fn build_hasher(&self) -> SipHasher {
+        SipHasher(siphasher::sip::SipHasher24::new_with_key(&self.key))
+    }
+}
+
+#[cfg(feature = "siphash")]
+#[derive(Clone)]
+pub struct SipHasher(siphasher::sip::SipHasher24);
+
+#[cfg(feature = "siphash")]
+impl Hasher for SipHasher {
+    fn write(&mut self, bytes: &[u8]) {
+        self.0.write(bytes);
+    }
+
+    le_integer_writes!();
+
+    fn finish(&self) -> u64 {
+        self.0.finish()
+    }
+}
+
+// Hashers of a seeded filter start with the seed, so filters with different seeds put the
+// same item on unrelated bits. Seed 0 writes nothing, unseeded filters keep their positions.
+// Custom `HashFn`s don't see the seed.
+pub(crate) struct Seeded<'a, S> {
+    pub(crate) build_hasher: &'a S,
+    pub(crate) seed: u64,
+}
+
+impl<S: BuildHasher> BuildHasher for Seeded<'_, S> {
+    type Hasher = S::Hasher;
+
+    fn build_hasher(&self) -> S::Hasher {
+        let mut hasher = self.build_hasher.build_hasher();
+        if self.seed != 0 {
+            hasher.write_u64(self.seed);
+        }
+        hasher
+    }
+}
+
+// Version of the way items are mapped to bit positions. Bumped whenever the same
+// parameters would put an item on different bits, so old and new filters don't get merged
+// (and encoded filters of another layout are rejected, see `encoding.rs`).
+// 1: h1 and h2 from two digests
+// 2: h2 mixed from the digest giving h1
+// 3: indices scaled to the size with `fastrange` instead of taken mod the size
+pub const LAYOUT_VERSION: u32 = 3;
+
+// Identifies everything that decides where items land: the layout version, the structure's
+// parameters and the hasher (through the hash of a fixed probe, which differs between
+// hashers and seeds). Structures with equal fingerprints can be merged.
+pub(crate) fn params_fingerprint<S: BuildHasher>(build_hasher: &S, params: &[u64]) -> u64 {
+    let mut fingerprint = Sha256Hasher::new();
+    fingerprint.write_u32(LAYOUT_VERSION);
+    for &param in params {
+        fingerprint.write_u64(param);
+    }
+    fingerprint.write_u64(build_hasher.hash_one(b"bloomf params fingerprint"));
+    fingerprint.finish()
+}
+
+// All k indices from a single digest (Kirsch–Mitzenmacher double hashing)
+// g_i(x) = h1(x) + i * h2(x) mod size behaves like k independent hashes for Bloom filters
+// Ex. for "foo" with the default SHA256 hasher
+// 1. "foo" is fed through its `Hash` impl -> b"foo" followed by the str terminator 0xff
+// 2. h1 = first 8 bytes (little-endian) of SHA256(b"foo" + [0xff])
+// 3. h2 = MurmurHash3 finalizer of h1 + H2_SALT, distinct items with the same h1 mod size
+//    still get different sequences
+// 4. the i-th index is fastrange(h1 + i * h2, size), so k only costs an addition and a
+//    multiplication per index past the one digest
+// Lemire's multiply-shift: maps a hash uniformly to [0, size) as floor(hash * size / 2^64).
+// Unlike hash % size it has no bias towards the low indices when size doesn't divide 2^64,
+// and it's a multiplication instead of a division. Uses the high bits of the hash.
+pub(crate) fn fastrange(hash: u64, size: u64) -> u64 {
+    ((hash as u128 * size as u128) >> 64) as u64
+}
+
+// MurmurHash3 finalizer, re-mixes a hash with a seed (ex. the construction seed of the
+// XOR, fuse and ribbon filters)
+pub(crate) fn mix(key: u64, seed: u64) -> u64 {
+    let mut h = key.wrapping_add(seed);
+    h ^= h >> 33;
+    h = h.wrapping_mul(0xff51_afd7_ed55_8ccd);
+    h ^= h >> 33;
+    h = h.wrapping_mul(0xc4ce_b9fe_1a85_ec53);
+    h ^ (h >> 33)
+}
+
+const H2_SALT: u64 = 0x9e37_79b9_7f4a_7c15;
+
+pub(crate) struct Indices {
+    h1: u64,
+    h2: u64,
+    i: usize,
+    num_hashes: usize,
+    size: u64,
+}
+
+impl Indices {
+    // `hasher` already holds the item, the item is only hashed once
+    fn new<H: Hasher>(hasher: H, num_hashes: usize, size: usize) -> Self {
+        Self::from_hash(hasher.finish(), num_hashes, size)
+    }
+
+    fn from_hash(h1: u64, num_hashes: usize, size: usize) -> Self {
+        let h2 = mix(h1, H2_SALT);
+        Indices {
+            h1,
+            h2,
+            i: 0,
+            num_hashes,
+            size: size as u64,
+        }
+    }
+}
+
+impl Iterator for Indices {
+    type Item = usize;
+
+    fn next(&mut self) -> Option<usize> {
+        if self.i == self.num_hashes {
+            return None;
+        }
+        let hash = self.h1.wrapping_add((self.i as u64).wrapping_mul(self.h2));
+        self.i += 1;
+        Some(fastrange(hash, self.size) as usize)
+    }
+}
+
+// Bit indices of an item: either double hashing over the filter's `BuildHasher` or one index
+// per user supplied `HashFn`.
+pub(crate) enum Probe<'a> {
+    Double(Indices),
+    Custom {
+        bytes: Vec<u8>,
+        hash_fns: &'a [HashFn],
+        i: usize,
+        size: u64,
+    },
+}
+
+impl<'a> Probe<'a> {
+    fn custom(bytes: Vec<u8>, hash_fns: &'a [HashFn], size: usize) -> Self {
+        Probe::Custom {
+            bytes,
+            hash_fns,
+            i: 0,
+            size: size as u64,
+        }
+    }
+}
+
+pub(crate) fn probe<'a, S: BuildHasher, T: Hash + ?Sized>(
+    build_hasher: &S,
+    hash_fns: &'a [HashFn],
+    item: &T,
+    num_hashes: usize,
+    size: usize,
+) -> Probe<'a> {
+    if hash_fns.is_empty() {
+        Probe::Double(indices(build_hasher, item, num_hashes, size))
+    } else {
+        Probe::custom(item_bytes(item), hash_fns, size)
+    }
+}
+
+pub(crate) fn probe_bytes<'a, S: BuildHasher>(
+    build_hasher: &S,
+    hash_fns: &'a [HashFn],
+    bytes: &[u8],
+    num_hashes: usize,
+    size: usize,
+) -> Probe<'a> {
+    if hash_fns.is_empty() {
+        Probe::Double(bytes_indices(build_hasher, bytes, num_hashes, size))
+    } else {
+        Probe::custom(bytes.to_vec(), hash_fns, size)
+    }
+}
+
+impl Iterator for Probe<'_> {
+    type Item = usize;
+
+    fn next(&mut self) -> Option<usize> {
+        match self {
+            Probe::Double(indices) => indices.next(),
+            Probe::Custom {
+                bytes,
+                hash_fns,
+                i,
+                size,
+            } => {
+                let hash_fn = hash_fns.get(*i)?;
+                *i += 1;
+                // mod, not `fastrange`: user functions may well return small values
+                Some((hash_fn(bytes) % *size) as usize)
+            }
+        }
+    }
+}
+
+pub(crate) fn indices<S: BuildHasher, T: Hash + ?Sized>(
+    build_hasher: &S,
+    item: &T,
+    num_hashes: usize,
+    size: usize,
+) -> Indices {
+    let mut hasher = build_hasher.build_hasher();
+    item.hash(&mut hasher);
+    Indices::new(hasher, num_hashes, size)
+}
+
+// Same as `indices` but for raw keys: the bytes are hashed as-is, without the
+// length prefix/terminator a `Hash` impl would add.
+pub(crate) fn bytes_indices<S: BuildHasher>(
+    build_hasher: &S,
+    bytes: &[u8],
+    num_hashes: usize,
+    size: usize,
+) -> Indices {
+    let mut hasher = build_hasher.build_hasher();
+    hasher.write(bytes);
+    Indices::new(hasher, num_hashes, size)
+}
+
+// Indices of an integer key without a hasher: h1 is splitmix64 of the key (xor the seed), a
+// bijection, so distinct keys never share h1. A few multiplications instead of a digest.
+// These are other bits than the `Hash` impl of the integer gets.
+pub(crate) fn u64_indices(key: u64, seed: u64, num_hashes: usize, size: usize) -> Indices {
+    Indices::from_hash(splitmix64(key ^ seed), num_hashes, size)
+}
+
+fn splitmix64(x: u64) -> u64 {
+    let mut z = x.wrapping_add(0x9e37_79b9_7f4a_7c15);
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    z ^ (z >> 31)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_double_hashing_indices() {
+        let indices: Vec<usize> = indices(&Sha256BuildHasher, "foo", 10, 1000).collect();
+        assert_eq!(indices.len(), 10);
+        assert!(indices.iter().all(|&idx| idx < 1000));
+        assert_eq!(
+            indices,
+            super::indices(&Sha256BuildHasher, "foo", 10, 1000).collect::<Vec<_>>()
+        );
+        assert_ne!(
+            indices,
+            super::indices(&Sha256BuildHasher, "bar", 10, 1000).collect::<Vec<_>>()
+        );
+    }
+
+    #[test]
+    fn test_golden_indices() {
+        // Serialized filters depend on these, changing them needs a LAYOUT_VERSION bump
+        let unseeded: Vec<usize> = indices(&Sha256BuildHasher, "foo", 5, 1000).collect();
+        let seeded = Seeded {
+            build_hasher: &Sha256BuildHasher,
+            seed: 42,
+        };
+        let seeded: Vec<usize> = indices(&seeded, "foo", 5, 1000).collect();
+        assert_eq!(unseeded, [432, 912, 392, 872, 353]);
+        assert_eq!(seeded, [16, 8, 0, 992, 984]);
+        assert_eq!(LAYOUT_VERSION, 3);
+    }
+
+    #[test]
+    fn test_custom_probe() {
+        let hash_fns: Vec<HashFn> = vec![
+            Box::new(|bytes| bytes.len() as u64),
+            Box::new(|bytes| bytes.iter().map(|&b| b as u64).sum()),
+        ];
+        let probe = Probe::custom(b"ab".to_vec(), &hash_fns, 100);
+        assert_eq!(probe.collect::<Vec<_>>(), vec![2, 95]);
+
+        // `Hash` framing is kept: str adds a 0xff terminator
+        assert_eq!(item_bytes("ab"), vec![b'a', b'b', 0xff]);
+        assert_eq!(item_bytes(&1u32), vec![1, 0, 0, 0]);
+    }
+
+    #[test]
+    fn test_sha256_hasher_is_platform_independent() {
+        let mut a = Sha256Hasher::new();
+        a.write_usize(7);
+        let mut b = Sha256Hasher::new();
+        b.write(&7u64.to_le_bytes());
+        assert_eq!(a.finish(), b.finish());
+    }
+
+    #[cfg(feature = "xxhash")]
+    #[test]
+    fn test_xxh3_hasher() {
+        let mut hasher = Xxh3BuildHasher.build_hasher();
+        hasher.write_u32(7);
+        hasher.write(b"foo");
+        assert_eq!(
+            hasher.finish(),
+            xxhash_rust::xxh3::xxh3_64(b"\x07\x00\x00\x00foo")
+        );
+
+        let mut bloom = crate::BloomFilter::with_hasher(1000, 4, Xxh3BuildHasher);
+        bloom.set("foo");
+        assert!(bloom.test("foo"));
+        assert!(!bloom.test("bar"));
+        assert_ne!(
+            indices(&Xxh3BuildHasher, "foo", 4, 1000).collect::<Vec<_>>(),
+            indices(&Sha256BuildHasher, "foo", 4, 1000).collect::<Vec<_>>()
+        );
+    }
+
+    #[cfg(feature = "wyhash")]
+    #[test]
+    fn test_wyhash_hasher() {
+        let mut a = WyBuildHasher.build_hasher();
+        a.write_usize(7);
+        let mut b = WyBuildHasher.build_hasher();
+        b.write(&7u64.to_le_bytes());
+        assert_eq!(a.finish(), b.finish());
+
+        let mut bloom = crate::BloomFilter::with_hasher(1000, 4, WyBuildHasher);
+        bloom.set("foo");
+        assert!(bloom.test("foo"));
+        assert!(!bloom.test("bar"));
+    }
+
+    #[cfg(feature = "siphash")]
+    #[test]
+    fn test_keyed_siphash() {
+        let key = *b"0123456789abcdef";
+        let mut bloom = crate::BloomFilter::with_hasher(1000, 4, SipBuildHasher::new(key));
+        bloom.set("foo");
+        let positions =
+            |hasher: &SipBuildHasher| indices(hasher, "foo", 4, 1000).collect::<Vec<_>>();
+        assert_eq!(
+            positions(&SipBuildHasher::new(key)),
+            positions(&SipBuildHasher::new(key))
+        );
+        assert_ne!(
+            positions(&SipBuildHasher::new(key)),
+            positions(&SipBuildHasher::random())
+        );
+        assert_ne!(
+            SipBuildHasher::random().key(),
+            SipBuildHasher::random().key()
+        );
+
+        let bytes = bloom.to_bytes();
+        assert!(!bytes.windows(8).any(|window| window == &key[..8]));
+        assert!(!format!("{:?}", bloom.hasher).contains("0123"));
+        let loaded =
+            crate::BloomFilter::from_bytes_with_hasher(&bytes, SipBuildHasher::new(key)).unwrap();
+        assert!(loaded.test("foo"));
+    }
+
+    #[test]
+    fn test_fastrange() {
+        assert_eq!(fastrange(0, 1000), 0);
+        assert_eq!(fastrange(u64::MAX, 1000), 999);
+        assert_eq!(fastrange(1 << 63, 1000), 500);
+        assert_eq!(fastrange(u64::MAX, 1), 0);
+        // every index gets a share of the hashes within one of the others
+        let mut counts = [0u32; 3];
+        for i in 0..3000u64 {
+            counts[fastrange(i.wrapping_mul(0x9e37_79b9_7f4a_7c15), 3) as usize] += 1;
+        }
+        assert!(counts.iter().all(|&count| (900..=1100).contains(&count)));
+    }
+}
@@ -0,0 +1,149 @@
+// HyperLogLog (Flajolet et al. 2007) for estimating the number of distinct items.
+// 2^precision registers of one byte, the first `precision` bits of an item's 64 bit hash pick the
+// register and the register keeps the maximum rank (leading zeros + 1) of the remaining bits.
+// Standard error is about 1.04 / sqrt(2^precision). Small cardinalities fall back to linear counting,
+// with 64 bit hashes no large range correction is needed.
+
+use std::hash::{BuildHasher, Hash};
+
+use crate::error::MergeError;
+use crate::hash::{self, Sha256BuildHasher};
+use crate::heap_bytes;
+
+pub const MIN_PRECISION: u8 = 4;
+pub const MAX_PRECISION: u8 = 18;
+
+pub struct HyperLogLog<S = Sha256BuildHasher> {
+    registers: Vec<u8>,
+    precision: u8,
+    hasher: S,
+}
+
+impl HyperLogLog {
+    pub fn new(precision: u8) -> Self {
+        Self::with_hasher(precision, Sha256BuildHasher)
+    }
+}
+
+impl<S: BuildHasher> HyperLogLog<S> {
+    pub fn with_hasher(precision: u8, hasher: S) -> Self {
+        assert!(
+            (MIN_PRECISION..=MAX_PRECISION).contains(&precision),
+            "precision must be between {} and {}",
+            MIN_PRECISION,
+            MAX_PRECISION
+        );
+        HyperLogLog {
+            registers: vec![0; 1 << precision],
+            precision,
+            hasher,
+        }
+    }
+
+    pub fn insert<T: Hash + ?Sized>(&mut self, item: &T) {
+        let hash = self.hasher.hash_one(item);
+        let index = (hash >> (64 - self.precision)) as usize;
+        // rank of the remaining 64 - p bits, capped for an all zero remainder
+        let rank = ((hash << self.precision).leading_zeros() + 1).min(65 - self.precision as u32);
+        let register = &mut self.registers[index];
+        *register = (*register).max(rank as u8);
+    }
+
+    pub fn estimate(&self) -> u64 {
+        let m = self.registers.len() as f64;
+        let alpha = match self.registers.len() {
+            16 => 0.673,
+            32 => 0.697,
+            64 => 0.709,
+            _ => 0.7213 / (1.0 + 1.079 / m),
+        };
+        let sum: f64 = self.registers.iter().map(|&r| 2f64.powi(-(r as i32))).sum();
+        let raw = alpha * m * m / sum;
+
+        let zeros = self.registers.iter().filter(|&&r| r == 0).count();
+        if raw <= 2.5 * m && zeros > 0 {
+            (m * (m / zeros as f64).ln()).round() as u64
+        } else {
+            raw.round() as u64
+        }
+    }
+
+    // Union of the two sets, both must use the same precision and hasher
+    pub fn merge(&mut self, other: &HyperLogLog<S>) -> Result<(), MergeError> {
+        let (left, right) = (self.params_fingerprint(), other.params_fingerprint());
+        if left != right {
+            return Err(MergeError::FingerprintMismatch { left, right });
+        }
+        for (register, other) in self.registers.iter_mut().zip(&other.registers) {
+            *register = (*register).max(*other);
+        }
+        Ok(())
+    }
+
+    // Hash of precision, hasher and layout version, HyperLogLogs can only be merged when it's equal
+    pub fn params_fingerprint(&self) -> u64 {
+        hash::params_fingerprint(&self.hasher, &[self.precision as u64])
+    }
+
+    pub fn precision(&self) -> u8 {
+        self.precision
+    }
+
+    // Expected relative standard error of `estimate`
+    pub fn standard_error(&self) -> f64 {
+        1.04 / (self.registers.len() as f64).sqrt()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.registers.iter().all(|&r| r == 0)
+    }
+
+    pub fn memory_usage(&self) -> usize {
+        size_of::<Self>() + heap_bytes(&self.registers)
+    }
+
+    pub fn clear(&mut self) {
+        self.registers.fill(0);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_estimate() {
+        let mut hll = HyperLogLog::new(12);
+        assert!(hll.is_empty());
+        assert_eq!(hll.estimate(), 0);
+
+        for i in 0..100u32 {
+            hll.insert(&i);
+            hll.insert(&i);
+        }
+        let small = hll.estimate() as f64;
+        assert!((small - 100.0).abs() < 5.0, "estimate {}", small);
+
+        for i in 0..50_000u32 {
+            hll.insert(&i);
+        }
+        let large = hll.estimate() as f64;
+        let bound = 4.0 * hll.standard_error() * 50_000.0;
+        assert!((large - 50_000.0).abs() < bound, "estimate {}", large);
+    }
+
+    #[test]
+    fn test_merge() {
+        let mut a = HyperLogLog::new(10);
+        let mut b = HyperLogLog::new(10);
+        for i in 0..2000u32 {
+            a.insert(&i);
+            b.insert(&(i + 1000));
+        }
+        a.merge(&b).unwrap();
+        let merged = a.estimate() as f64;
+        assert!((merged - 3000.0).abs() < 3000.0 * 4.0 * a.standard_error());
+
+        assert!(a.merge(&HyperLogLog::new(11)).is_err());
+    }
+}
@@ -0,0 +1,350 @@
+// Invertible Bloom lookup table (Goodrich & Mitzenmacher 2011). Like a counting Bloom filter
+// every key lands in k cells, but a cell keeps the XOR of the keys and values in it and of a
+// check hash next to the count. A cell holding one entry gives it back whole, removing that
+// entry from its other cells frees more of them, and so on: `list_entries` recovers every
+// entry as long as there are not more than about cells / 1.3 of them (k = 3 or 4), however
+// many were inserted and deleted on the way there.
+//
+// Keys and values have a fixed size in bytes. Deleting an entry that was never inserted is
+// allowed and shows up as a negative entry, which is what makes subtracting two tables give
+// the difference of their sets.
+
+use std::hash::BuildHasher;
+
+use crate::error::{BloomError, MergeError};
+use crate::hash::{self, fastrange, mix, Sha256BuildHasher};
+use crate::heap_bytes;
+
+// u64 cells, u32 number of hashes, u32 key size, u32 value size
+const HEADER_LEN: usize = 20;
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Iblt<S = Sha256BuildHasher> {
+    counts: Vec<i64>,
+    // XOR of the check hashes of the keys in every cell
+    hash_sums: Vec<u64>,
+    // cells * key_size and cells * value_size bytes
+    key_sums: Vec<u8>,
+    value_sums: Vec<u8>,
+    key_size: usize,
+    value_size: usize,
+    num_hashes: usize,
+    hasher: S,
+}
+
+// Result of `Iblt::list_entries`, (key, value) pairs
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct IbltEntries {
+    pub inserted: Vec<(Vec<u8>, Vec<u8>)>,
+    // deleted without being inserted
+    pub deleted: Vec<(Vec<u8>, Vec<u8>)>,
+}
+
+impl Iblt {
+    pub fn new(cells: usize, num_hashes: usize, key_size: usize, value_size: usize) -> Self {
+        Self::with_hasher(cells, num_hashes, key_size, value_size, Sha256BuildHasher)
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, BloomError> {
+        Self::from_bytes_with_hasher(bytes, Sha256BuildHasher)
+    }
+}
+
+impl<S: BuildHasher> Iblt<S> {
+    // Every key gets one cell in each of num_hashes equal parts of the table, so cells is
+    // rounded up to a multiple of num_hashes
+    pub fn with_hasher(
+        cells: usize,
+        num_hashes: usize,
+        key_size: usize,
+        value_size: usize,
+        hasher: S,
+    ) -> Self {
+        assert!(num_hashes > 0, "an IBLT needs at least one hash");
+        assert!(key_size > 0, "keys must be at least one byte");
+        let cells = cells.max(1).div_ceil(num_hashes) * num_hashes;
+        Iblt {
+            counts: vec![0; cells],
+            hash_sums: vec![0; cells],
+            key_sums: vec![0; cells * key_size],
+            value_sums: vec![0; cells * value_size],
+            key_size,
+            value_size,
+            num_hashes,
+            hasher,
+        }
+    }
+
+    // Panics if the key or value has the wrong size
+    pub fn insert(&mut self, key: &[u8], value: &[u8]) {
+        self.update(key, value, 1);
+    }
+
+    // Removes an inserted entry, the value must be the inserted one
+    pub fn delete(&mut self, key: &[u8], value: &[u8]) {
+        self.update(key, value, -1);
+    }
+
+    fn update(&mut self, key: &[u8], value: &[u8], count: i64) {
+        assert_eq!(key.len(), self.key_size, "wrong key size");
+        assert_eq!(value.len(), self.value_size, "wrong value size");
+        let check = self.check_hash(key);
+        for cell in self.cells(key) {
+            self.apply(cell, key, value, check, count);
+        }
+    }
+
+    fn apply(&mut self, cell: usize, key: &[u8], value: &[u8], check: u64, count: i64) {
+        self.counts[cell] += count;
+        self.hash_sums[cell] ^= check;
+        xor_into(
+            &mut self.key_sums[cell * self.key_size..][..self.key_size],
+            key,
+        );
+        xor_into(
+            &mut self.value_sums[cell * self.value_size..][..self.value_size],
+            value,
+        );
+    }
+
+    fn cells(&self, key: &[u8]) -> impl Iterator<Item = usize> {
+        let hash = self.hasher.hash_one(key);
+        let part = (self.counts.len() / self.num_hashes) as u64;
+        (0..self.num_hashes)
+            .map(move |i| i * part as usize + fastrange(mix(hash, i as u64 + 1), part) as usize)
+    }
+
+    fn check_hash(&self, key: &[u8]) -> u64 {
+        mix(self.hasher.hash_one(key), 0)
+    }
+
+    fn key(&self, cell: usize) -> &[u8] {
+        &self.key_sums[cell * self.key_size..][..self.key_size]
+    }
+
+    fn value(&self, cell: usize) -> &[u8] {
+        &self.value_sums[cell * self.value_size..][..self.value_size]
+    }
+
+    // A cell with a single entry, inserted or deleted
+    fn is_pure(&self, cell: usize) -> bool {
+        self.counts[cell].abs() == 1 && self.hash_sums[cell] == self.check_hash(self.key(cell))
+    }
+
+    fn is_empty_cell(&self, cell: usize) -> bool {
+        self.counts[cell] == 0
+            && self.hash_sums[cell] == 0
+            && self.key(cell).iter().all(|&byte| byte == 0)
+            && self.value(cell).iter().all(|&byte| byte == 0)
+    }
+
+    // Every entry, or `CapacityExceeded` if the table holds too many of them to list
+    pub fn list_entries(&self) -> Result<IbltEntries, BloomError>
+    where
+        S: Clone,
+    {
+        let mut table = self.clone();
+        let mut entries = IbltEntries::default();
+        let mut pure: Vec<usize> = (0..table.counts.len())
+            .filter(|&cell| table.is_pure(cell))
+            .collect();
+        while let Some(cell) = pure.pop() {
+            // emptied since it was queued
+            if !table.is_pure(cell) {
+                continue;
+            }
+            let key = table.key(cell).to_vec();
+            let value = table.value(cell).to_vec();
+            let count = table.counts[cell];
+            let check = table.check_hash(&key);
+            let cells: Vec<usize> = table.cells(&key).collect();
+            for &other in &cells {
+                table.apply(other, &key, &value, check, -count);
+            }
+            pure.extend(cells.into_iter().filter(|&other| table.is_pure(other)));
+            if count == 1 {
+                entries.inserted.push((key, value));
+            } else {
+                entries.deleted.push((key, value));
+            }
+        }
+        if (0..table.counts.len()).all(|cell| table.is_empty_cell(cell)) {
+            Ok(entries)
+        } else {
+            Err(BloomError::CapacityExceeded)
+        }
+    }
+
+    // Table of the entries of self minus those of other: entries only in self are inserted
+    // ones, entries only in other deleted ones. Both need the same parameters and hasher.
+    pub fn subtract(&self, other: &Iblt<S>) -> Result<Iblt<S>, MergeError>
+    where
+        S: Clone,
+    {
+        let (left, right) = (self.params_fingerprint(), other.params_fingerprint());
+        if left != right {
+            return Err(MergeError::FingerprintMismatch { left, right });
+        }
+        let mut difference = self.clone();
+        for (count, other) in difference.counts.iter_mut().zip(&other.counts) {
+            *count -= other;
+        }
+        for (sum, other) in difference.hash_sums.iter_mut().zip(&other.hash_sums) {
+            *sum ^= other;
+        }
+        xor_into(&mut difference.key_sums, &other.key_sums);
+        xor_into(&mut difference.value_sums, &other.value_sums);
+        Ok(difference)
+    }
+
+    // Hash of the cell count, number of hashes, key and value sizes, hasher and layout
+    // version, tables can only be subtracted when it's equal
+    pub fn params_fingerprint(&self) -> u64 {
+        hash::params_fingerprint(
+            &self.hasher,
+            &[
+                self.counts.len() as u64,
+                self.num_hashes as u64,
+                self.key_size as u64,
+                self.value_size as u64,
+            ],
+        )
+    }
+
+    pub fn num_cells(&self) -> usize {
+        self.counts.len()
+    }
+
+    pub fn num_hashes(&self) -> usize {
+        self.num_hashes
+    }
+
+    pub fn key_size(&self) -> usize {
+        self.key_size
+    }
+
+    pub fn value_size(&self) -> usize {
+        self.value_size
+    }
+
+    pub fn memory_usage(&self) -> usize {
+        size_of::<Self>()
+            + heap_bytes(&self.counts)
+            + heap_bytes(&self.hash_sums)
+            + heap_bytes(&self.key_sums)
+            + heap_bytes(&self.value_sums)
+    }
+
+    // Size of the encoded table in bytes
+    pub fn encoded_len(&self) -> usize {
+        HEADER_LEN + self.counts.len() * 16 + self.key_sums.len() + self.value_sums.len()
+    }
+
+    // u64 number of cells, u32 number of hashes, u32 key size, u32 value size (all
+    // little-endian), then every cell: i64 count, u64 check hash sum, key sum, value sum
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(self.encoded_len());
+        bytes.extend_from_slice(&(self.counts.len() as u64).to_le_bytes());
+        bytes.extend_from_slice(&(self.num_hashes as u32).to_le_bytes());
+        bytes.extend_from_slice(&(self.key_size as u32).to_le_bytes());
+        bytes.extend_from_slice(&(self.value_size as u32).to_le_bytes());
+        for cell in 0..self.counts.len() {
+            bytes.extend_from_slice(&self.counts[cell].to_le_bytes());
+            bytes.extend_from_slice(&self.hash_sums[cell].to_le_bytes());
+            bytes.extend_from_slice(self.key(cell));
+            bytes.extend_from_slice(self.value(cell));
+        }
+        bytes
+    }
+
+    // The hasher must be the one the table was built with
+    pub fn from_bytes_with_hasher(bytes: &[u8], hasher: S) -> Result<Self, BloomError> {
+        Self::decode(bytes, hasher).map_err(BloomError::SerializationError)
+    }
+
+    fn decode(bytes: &[u8], hasher: S) -> Result<Self, String> {
+        if bytes.len() < HEADER_LEN {
+            return Err("Encoded IBLT is too short.".into());
+        }
+        let cells = u64::from_le_bytes(bytes[0..8].try_into().unwrap());
+        let num_hashes = u32::from_le_bytes(bytes[8..12].try_into().unwrap()) as usize;
+        let key_size = u32::from_le_bytes(bytes[12..16].try_into().unwrap()) as usize;
+        let value_size = u32::from_le_bytes(bytes[16..20].try_into().unwrap()) as usize;
+        if num_hashes == 0 || key_size == 0 || cells == 0 || cells % num_hashes as u64 != 0 {
+            return Err("Invalid IBLT parameters.".into());
+        }
+        let cell_len = 16 + key_size + value_size;
+        let body = &bytes[HEADER_LEN..];
+        if cells.checked_mul(cell_len as u64) != Some(body.len() as u64) {
+            return Err(format!(
+                "Expected {} cells of {} bytes, got {} bytes.",
+                cells,
+                cell_len,
+                body.len()
+            ));
+        }
+        let mut iblt = Iblt::with_hasher(cells as usize, num_hashes, key_size, value_size, hasher);
+        for (cell, bytes) in body.chunks_exact(cell_len).enumerate() {
+            iblt.counts[cell] = i64::from_le_bytes(bytes[0..8].try_into().unwrap());
+            iblt.hash_sums[cell] = u64::from_le_bytes(bytes[8..16].try_into().unwrap());
+            iblt.key_sums[cell * key_size..][..key_size].copy_from_slice(&bytes[16..16 + key_size]);
+            iblt.value_sums[cell * value_size..][..value_size]
+                .copy_from_slice(&bytes[16 + key_size..]);
+        }
+        Ok(iblt)
+    }
+
+    pub fn clear(&mut self) {
+        self.counts.fill(0);
+        self.hash_sums.fill(0);
+        self.key_sums.fill(0);
+        self.value_sums.fill(0);
+    }
+}
+
+fn xor_into(sum: &mut [u8], bytes: &[u8]) {
+    for (sum, byte) in sum.iter_mut().zip(bytes) {
+        *sum ^= byte;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_list_entries() {
+        let mut iblt = Iblt::new(150, 3, 4, 8);
+        for i in 0..1000u32 {
+            iblt.insert(&i.to_le_bytes(), &(i as u64 * 7).to_le_bytes());
+        }
+        // too many entries left to peel
+        assert!(iblt.list_entries().is_err());
+        for i in 0..950u32 {
+            iblt.delete(&i.to_le_bytes(), &(i as u64 * 7).to_le_bytes());
+        }
+        iblt.delete(b"gone", &[0; 8]);
+
+        let mut entries = iblt.list_entries().unwrap();
+        entries.inserted.sort();
+        let expected: Vec<_> = (950..1000u32)
+            .map(|i| {
+                (
+                    i.to_le_bytes().to_vec(),
+                    (i as u64 * 7).to_le_bytes().to_vec(),
+                )
+            })
+            .collect();
+        assert_eq!(entries.inserted, expected);
+        assert_eq!(entries.deleted, vec![(b"gone".to_vec(), vec![0; 8])]);
+
+        let decoded = Iblt::from_bytes(&iblt.to_bytes()).unwrap();
+        assert_eq!(decoded, iblt);
+        assert_eq!(iblt.to_bytes().len(), iblt.encoded_len());
+        assert!(Iblt::from_bytes(&iblt.to_bytes()[..100]).is_err());
+
+        iblt.clear();
+        assert_eq!(iblt.list_entries().unwrap(), IbltEntries::default());
+        assert_eq!(iblt.num_cells(), 150);
+    }
+}
@@ -0,0 +1,204 @@
+// Tower middleware that short-circuits repeated requests (feature `tower`). `key` extracts the
+// idempotency key of a request, ex. the Idempotency-Key header, and a request whose key was
+// seen within the TTL doesn't reach the inner service: `duplicate` builds its response instead,
+// ex. 409 Conflict. Requests without a key always pass.
+//
+//   let layer = IdempotencyLayer::new(
+//       Duration::from_secs(24 * 3600), 1_000_000, 1e-6,
+//       |req: &Request<Body>| req.headers().get("idempotency-key").map(|v| v.as_bytes().to_vec()),
+//       |_: &Request<Body>| StatusCode::CONFLICT.into_response(),
+//   );
+//
+// Keys are recorded when the request arrives, so concurrent retries are caught too, but a
+// request that failed stays recorded: clients must retry with a new key. The keys live in an
+// `ExpiringBloomFilter`, so about false_positive_rate of the new keys are rejected as
+// duplicates, size it accordingly. All services of a layer share one filter.
+
+use std::future::Future;
+use std::hash::Hash;
+use std::pin::Pin;
+use std::sync::{Arc, Mutex, PoisonError};
+use std::task::{Context, Poll};
+use std::time::Duration;
+
+use pin_project_lite::pin_project;
+use tower_layer::Layer;
+use tower_service::Service;
+
+use crate::ExpiringBloomFilter;
+
+pub struct IdempotencyLayer<K, D> {
+    seen: Arc<Mutex<ExpiringBloomFilter>>,
+    key: K,
+    duplicate: D,
+}
+
+impl<K, D> IdempotencyLayer<K, D> {
+    // capacity: number of keyed requests expected within one TTL
+    pub fn new(
+        ttl: Duration,
+        capacity: usize,
+        false_positive_rate: f64,
+        key: K,
+        duplicate: D,
+    ) -> Self {
+        Self::from_filter(
+            ExpiringBloomFilter::new(ttl, capacity, false_positive_rate),
+            key,
+            duplicate,
+        )
+    }
+
+    pub fn from_filter(seen: ExpiringBloomFilter, key: K, duplicate: D) -> Self {
+        IdempotencyLayer {
+            seen: Arc::new(Mutex::new(seen)),
+            key,
+            duplicate,
+        }
+    }
+}
+
+impl<K: Clone, D: Clone> Clone for IdempotencyLayer<K, D> {
+    fn clone(&self) -> Self {
+        IdempotencyLayer {
+            seen: self.seen.clone(),
+            key: self.key.clone(),
+            duplicate: self.duplicate.clone(),
+        }
+    }
+}
+
+impl<Svc, K: Clone, D: Clone> Layer<Svc> for IdempotencyLayer<K, D> {
+    type Service = Idempotency<Svc, K, D>;
+
+    fn layer(&self, inner: Svc) -> Self::Service {
+        Idempotency {
+            inner,
+            seen: self.seen.clone(),
+            key: self.key.clone(),
+            duplicate: self.duplicate.clone(),
+        }
+    }
+}
+
+pub struct Idempotency<Svc, K, D> {
+    inner: Svc,
+    seen: Arc<Mutex<ExpiringBloomFilter>>,
+    key: K,
+    duplicate: D,
+}
+
+impl<Svc: Clone, K: Clone, D: Clone> Clone for Idempotency<Svc, K, D> {
+    fn clone(&self) -> Self {
+        Idempotency {
+            inner: self.inner.clone(),
+            seen: self.seen.clone(),
+            key: self.key.clone(),
+            duplicate: self.duplicate.clone(),
+        }
+    }
+}
+
+impl<Svc, K, D, Req, Key> Service<Req> for Idempotency<Svc, K, D>
+where
+    Svc: Service<Req>,
+    K: Fn(&Req) -> Option<Key>,
+    Key: Hash,
+    D: Fn(&Req) -> Svc::Response,
+{
+    type Response = Svc::Response;
+    type Error = Svc::Error;
+    type Future = IdempotencyFuture<Svc::Future, Svc::Response>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, req: Req) -> Self::Future {
+        if let Some(key) = (self.key)(&req) {
+            // a panic while holding the lock leaves at most some extra bits set
+            let seen = self
+                .seen
+                .lock()
+                .unwrap_or_else(PoisonError::into_inner)
+                .check_and_insert(&key);
+            if seen {
+                return IdempotencyFuture::Duplicate {
+                    response: Some((self.duplicate)(&req)),
+                };
+            }
+        }
+        IdempotencyFuture::Inner {
+            future: self.inner.call(req),
+        }
+    }
+}
+
+pin_project! {
+    #[project = IdempotencyFutureProj]
+    pub enum IdempotencyFuture<F, R> {
+        Inner { #[pin] future: F },
+        Duplicate { response: Option<R> },
+    }
+}
+
+impl<F, R, E> Future for IdempotencyFuture<F, R>
+where
+    F: Future<Output = Result<R, E>>,
+{
+    type Output = Result<R, E>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        match self.project() {
+            IdempotencyFutureProj::Inner { future } => future.poll(cx),
+            IdempotencyFutureProj::Duplicate { response } => Poll::Ready(Ok(response
+                .take()
+                .expect("IdempotencyFuture polled after completion"))),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::convert::Infallible;
+    use std::future::{ready, Ready};
+
+    // echoes the request body
+    struct Echo;
+
+    impl Service<(Option<u64>, &'static str)> for Echo {
+        type Response = String;
+        type Error = Infallible;
+        type Future = Ready<Result<String, Infallible>>;
+
+        fn poll_ready(&mut self, _: &mut Context<'_>) -> Poll<Result<(), Infallible>> {
+            Poll::Ready(Ok(()))
+        }
+
+        fn call(&mut self, (_, body): (Option<u64>, &'static str)) -> Self::Future {
+            ready(Ok(body.to_string()))
+        }
+    }
+
+    #[tokio::test]
+    async fn test_duplicates_short_circuit() {
+        let layer = IdempotencyLayer::new(
+            Duration::from_secs(60),
+            1000,
+            1e-6,
+            |req: &(Option<u64>, &str)| req.0,
+            |_: &(Option<u64>, &str)| "duplicate".to_string(),
+        );
+        let mut service = layer.layer(Echo);
+        // another service of the same layer shares the seen keys
+        let mut other = layer.layer(Echo);
+
+        assert_eq!(service.call((Some(1), "pay")).await.unwrap(), "pay");
+        assert_eq!(service.call((Some(1), "pay")).await.unwrap(), "duplicate");
+        assert_eq!(other.call((Some(1), "pay")).await.unwrap(), "duplicate");
+        assert_eq!(other.call((Some(2), "refund")).await.unwrap(), "refund");
+        assert_eq!(service.call((None, "get")).await.unwrap(), "get");
+        assert_eq!(service.call((None, "get")).await.unwrap(), "get");
+    }
+}
@@ -0,0 +1,194 @@
+// Canonical byte encodings for keys that aren't strings. `Hash` impls feed the hasher
+// platform- and Rust-specific bytes (usize lengths, native endianness, OsStr internals), and
+// `to_string()` formats drift (IPv6 zero compression, UUID case), so the same logical key
+// could land on other bits in another process or language. `set_key` / `test_key` hash the
+// `Key` bytes the way `set_bytes` does, so `set_key(&ip)` in Rust and
+// `set_bytes(ip.packed)` anywhere else set the same bits:
+//
+//   u32, u64, u128       big-endian
+//   Ipv4Addr, Ipv6Addr   the 4 / 16 octets, IpAddr the octets of the address it holds
+//   Uuid                 the 16 bytes in RFC 4122 order (feature `uuid`)
+//   str, [u8]            as is
+//   Path, OsStr          UTF-8 when the name is valid Unicode, otherwise the raw bytes on
+//                        Unix and UTF-16LE on Windows
+
+use std::borrow::Cow;
+use std::ffi::{OsStr, OsString};
+use std::hash::BuildHasher;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+use std::path::{Path, PathBuf};
+
+use crate::{AtomicBloomFilter, BloomFilter};
+
+pub trait Key {
+    fn key_bytes(&self) -> Cow<'_, [u8]>;
+}
+
+impl<K: Key + ?Sized> Key for &K {
+    fn key_bytes(&self) -> Cow<'_, [u8]> {
+        (**self).key_bytes()
+    }
+}
+
+macro_rules! be_integer_keys {
+    ($($ty:ty),*) => {
+        $(
+            impl Key for $ty {
+                fn key_bytes(&self) -> Cow<'_, [u8]> {
+                    Cow::Owned(self.to_be_bytes().to_vec())
+                }
+            }
+        )*
+    };
+}
+
+be_integer_keys!(u32, u64, u128);
+
+impl Key for Ipv4Addr {
+    fn key_bytes(&self) -> Cow<'_, [u8]> {
+        Cow::Owned(self.octets().to_vec())
+    }
+}
+
+impl Key for Ipv6Addr {
+    fn key_bytes(&self) -> Cow<'_, [u8]> {
+        Cow::Owned(self.octets().to_vec())
+    }
+}
+
+impl Key for IpAddr {
+    fn key_bytes(&self) -> Cow<'_, [u8]> {
+        match self {
+            IpAddr::V4(ip) => ip.key_bytes(),
+            IpAddr::V6(ip) => ip.key_bytes(),
+        }
+    }
+}
+
+#[cfg(feature = "uuid")]
+impl Key for uuid::Uuid {
+    fn key_bytes(&self) -> Cow<'_, [u8]> {
+        Cow::Borrowed(self.as_bytes())
+    }
+}
+
+impl Key for str {
+    fn key_bytes(&self) -> Cow<'_, [u8]> {
+        Cow::Borrowed(self.as_bytes())
+    }
+}
+
+impl Key for String {
+    fn key_bytes(&self) -> Cow<'_, [u8]> {
+        Cow::Borrowed(self.as_bytes())
+    }
+}
+
+impl Key for [u8] {
+    fn key_bytes(&self) -> Cow<'_, [u8]> {
+        Cow::Borrowed(self)
+    }
+}
+
+impl Key for Vec<u8> {
+    fn key_bytes(&self) -> Cow<'_, [u8]> {
+        Cow::Borrowed(self)
+    }
+}
+
+impl Key for OsStr {
+    fn key_bytes(&self) -> Cow<'_, [u8]> {
+        if let Some(name) = self.to_str() {
+            return Cow::Borrowed(name.as_bytes());
+        }
+        #[cfg(unix)]
+        {
+            use std::os::unix::ffi::OsStrExt;
+            Cow::Borrowed(self.as_bytes())
+        }
+        #[cfg(windows)]
+        {
+            use std::os::windows::ffi::OsStrExt;
+            Cow::Owned(self.encode_wide().flat_map(u16::to_le_bytes).collect())
+        }
+        #[cfg(not(any(unix, windows)))]
+        {
+            Cow::Owned(self.to_string_lossy().into_owned().into_bytes())
+        }
+    }
+}
+
+impl Key for OsString {
+    fn key_bytes(&self) -> Cow<'_, [u8]> {
+        self.as_os_str().key_bytes()
+    }
+}
+
+impl Key for Path {
+    fn key_bytes(&self) -> Cow<'_, [u8]> {
+        self.as_os_str().key_bytes()
+    }
+}
+
+impl Key for PathBuf {
+    fn key_bytes(&self) -> Cow<'_, [u8]> {
+        self.as_os_str().key_bytes()
+    }
+}
+
+impl<S: BuildHasher> BloomFilter<S> {
+    pub fn set_key<K: Key + ?Sized>(&mut self, key: &K) {
+        self.set_bytes(key.key_bytes());
+    }
+
+    pub fn test_key<K: Key + ?Sized>(&self, key: &K) -> bool {
+        self.test_bytes(key.key_bytes())
+    }
+}
+
+impl<S: BuildHasher> AtomicBloomFilter<S> {
+    pub fn set_key<K: Key + ?Sized>(&self, key: &K) {
+        self.set_bytes(key.key_bytes());
+    }
+
+    pub fn test_key<K: Key + ?Sized>(&self, key: &K) -> bool {
+        self.test_bytes(key.key_bytes())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_canonical_keys() {
+        assert_eq!(&*1u64.key_bytes(), &[0, 0, 0, 0, 0, 0, 0, 1]);
+        let ip: Ipv6Addr = "2001:db8::1".parse().unwrap();
+        // every spelling of the address is the same key
+        let expanded: Ipv6Addr = "2001:0DB8:0000:0000:0000:0000:0000:0001".parse().unwrap();
+        assert_eq!(ip.key_bytes(), expanded.key_bytes());
+        assert_eq!(IpAddr::V6(ip).key_bytes(), ip.key_bytes());
+        assert_eq!(Path::new("a/b").key_bytes(), "a/b".key_bytes());
+
+        let mut bloom = BloomFilter::new(10_000, 4);
+        bloom.set_key(&Ipv4Addr::new(10, 0, 0, 1));
+        bloom.set_key(Path::new("/var/log"));
+        assert!(bloom.test_bytes([10, 0, 0, 1]));
+        assert!(bloom.test_key(&PathBuf::from("/var/log")));
+        assert!(!bloom.test_key(&Ipv4Addr::new(10, 0, 0, 2)));
+
+        let atomic = AtomicBloomFilter::new(10_000, 4);
+        atomic.set_key(&7u128);
+        assert!(atomic.test_key(&7u128));
+        assert!(!atomic.test_key(&7u64));
+    }
+
+    #[cfg(feature = "uuid")]
+    #[test]
+    fn test_uuid_key() {
+        let id = uuid::Uuid::from_u128(0x67e5_5044_10b1_426f_9247_bb68_0e5f_e0c8);
+        let mut bloom = BloomFilter::new(10_000, 4);
+        bloom.set_key(&id);
+        assert!(bloom.test_key(&0x67e5_5044_10b1_426f_9247_bb68_0e5f_e0c8u128));
+    }
+}
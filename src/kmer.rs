@@ -0,0 +1,212 @@
+// DNA k-mers as 2 bit per base integers (A = 0, C = 1, G = 2, T = 3, either case) for k up to
+// 32. A k-mer and its reverse complement are the same sequence read from the other strand, so
+// both map to the canonical code, the smaller of the two. Codes go through the integer fast
+// path (`insert_u64`), no string or digest per k-mer.
+//
+// `kmers` slides a window over a whole sequence, updating both strands' codes with one shift
+// per base. Windows with other characters (N, IUPAC codes) are skipped. One filter should only
+// hold k-mers of one k: "A" and "AA" have the same code.
+
+use std::hash::BuildHasher;
+use std::iter::FusedIterator;
+
+use crate::error::BloomError;
+use crate::{AtomicBloomFilter, BloomFilter};
+
+pub const MAX_K: usize = 32;
+
+fn base_code(base: u8) -> Option<u64> {
+    match base {
+        b'A' | b'a' => Some(0),
+        b'C' | b'c' => Some(1),
+        b'G' | b'g' => Some(2),
+        b'T' | b't' => Some(3),
+        _ => None,
+    }
+}
+
+// Canonical code of one k-mer, None if it has a non-ACGT base or isn't 1 to 32 bases long
+pub fn encode_kmer(kmer: &[u8]) -> Option<u64> {
+    if kmer.is_empty() || kmer.len() > MAX_K {
+        return None;
+    }
+    let mut forward = 0;
+    let mut reverse = 0;
+    for (i, &base) in kmer.iter().enumerate() {
+        let code = base_code(base)?;
+        forward = forward << 2 | code;
+        reverse |= (3 - code) << (2 * i);
+    }
+    Some(forward.min(reverse))
+}
+
+// Canonical codes of every k-mer of the sequence, panics unless 1 <= k <= 32
+pub fn kmers(seq: &[u8], k: usize) -> Kmers<'_> {
+    assert!((1..=MAX_K).contains(&k), "k must be in 1..=32");
+    Kmers {
+        seq,
+        k,
+        pos: 0,
+        forward: 0,
+        reverse: 0,
+        valid: 0,
+        mask: u64::MAX >> (64 - 2 * k),
+    }
+}
+
+pub struct Kmers<'a> {
+    seq: &'a [u8],
+    k: usize,
+    pos: usize,
+    forward: u64,
+    reverse: u64,
+    // valid bases at the end of the window
+    valid: usize,
+    mask: u64,
+}
+
+impl Iterator for Kmers<'_> {
+    type Item = u64;
+
+    fn next(&mut self) -> Option<u64> {
+        while let Some(&base) = self.seq.get(self.pos) {
+            self.pos += 1;
+            let Some(code) = base_code(base) else {
+                self.valid = 0;
+                continue;
+            };
+            self.forward = (self.forward << 2 | code) & self.mask;
+            self.reverse = self.reverse >> 2 | (3 - code) << (2 * (self.k - 1));
+            self.valid += 1;
+            if self.valid >= self.k {
+                return Some(self.forward.min(self.reverse));
+            }
+        }
+        None
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let left = self.seq.len() - self.pos;
+        (
+            0,
+            Some((left + self.valid.min(self.k - 1)).saturating_sub(self.k - 1)),
+        )
+    }
+}
+
+impl FusedIterator for Kmers<'_> {}
+
+fn invalid_kmer(kmer: &[u8]) -> BloomError {
+    BloomError::InvalidParams(format!(
+        "{:?} is not a DNA k-mer of 1 to 32 bases.",
+        String::from_utf8_lossy(kmer)
+    ))
+}
+
+impl<S: BuildHasher> BloomFilter<S> {
+    // Returns whether the k-mer (or its reverse complement) was possibly present
+    pub fn insert_kmer(&mut self, kmer: &[u8]) -> Result<bool, BloomError> {
+        let code = encode_kmer(kmer).ok_or_else(|| invalid_kmer(kmer))?;
+        Ok(self.insert_u64(code))
+    }
+
+    // False for invalid k-mers
+    pub fn contains_kmer(&self, kmer: &[u8]) -> bool {
+        encode_kmer(kmer).is_some_and(|code| self.contains_u64(code))
+    }
+
+    // Inserts every k-mer of the sequence, returns how many there were
+    pub fn insert_kmers(&mut self, seq: &[u8], k: usize) -> usize {
+        let mut count = 0;
+        for code in kmers(seq, k) {
+            self.insert_u64(code);
+            count += 1;
+        }
+        count
+    }
+
+    // Number of k-mers of the sequence in the filter, ex. to classify reads
+    pub fn kmer_hits(&self, seq: &[u8], k: usize) -> usize {
+        kmers(seq, k)
+            .filter(|&code| self.contains_u64(code))
+            .count()
+    }
+}
+
+impl<S: BuildHasher> AtomicBloomFilter<S> {
+    pub fn insert_kmer(&self, kmer: &[u8]) -> Result<bool, BloomError> {
+        let code = encode_kmer(kmer).ok_or_else(|| invalid_kmer(kmer))?;
+        Ok(self.insert_u64(code))
+    }
+
+    pub fn contains_kmer(&self, kmer: &[u8]) -> bool {
+        encode_kmer(kmer).is_some_and(|code| self.contains_u64(code))
+    }
+
+    pub fn insert_kmers(&self, seq: &[u8], k: usize) -> usize {
+        let mut count = 0;
+        for code in kmers(seq, k) {
+            self.insert_u64(code);
+            count += 1;
+        }
+        count
+    }
+
+    pub fn kmer_hits(&self, seq: &[u8], k: usize) -> usize {
+        kmers(seq, k)
+            .filter(|&code| self.contains_u64(code))
+            .count()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_canonical_kmers() {
+        // ACGTT and its reverse complement AACGT
+        assert_eq!(encode_kmer(b"ACGTT"), encode_kmer(b"AACGT"));
+        assert_eq!(encode_kmer(b"aacgt"), Some(0b00_00_01_10_11));
+        assert_eq!(encode_kmer(b"ACNGT"), None);
+        assert_eq!(encode_kmer(&[b'A'; 33]), None);
+
+        let seq = b"GATTACANNCCGGATTACA";
+        let rolled: Vec<u64> = kmers(seq, 4).collect();
+        let windows: Vec<u64> = seq.windows(4).filter_map(encode_kmer).collect();
+        assert_eq!(rolled, windows);
+        assert_eq!(rolled.len(), 4 + 7);
+        assert!(kmers(seq, 4).size_hint().1.unwrap() >= rolled.len());
+        let long = [b'G'; 40];
+        assert_eq!(kmers(&long, 32).collect::<Vec<_>>().len(), 9);
+        assert_eq!(kmers(&long, 32).next(), encode_kmer(&long[..32]));
+    }
+
+    #[test]
+    fn test_kmer_filter() {
+        let genome = b"ATGCGTACGTTAGCCTAGGCTAACGTTGCAGTCGATCGGATCCTAGCTAGGATCGATCGTAGCTAGCTAG";
+        let mut bloom = BloomFilter::new(10_000, 5);
+        assert_eq!(bloom.insert_kmers(genome, 21), genome.len() - 20);
+        // a read from the other strand
+        let read: Vec<u8> = genome[10..50]
+            .iter()
+            .rev()
+            .map(|&base| match base {
+                b'A' => b'T',
+                b'C' => b'G',
+                b'G' => b'C',
+                _ => b'A',
+            })
+            .collect();
+        assert_eq!(bloom.kmer_hits(&read, 21), 20);
+        assert!(bloom.contains_kmer(&genome[5..26]));
+        assert!(!bloom.contains_kmer(b"AAAAAAAAAAAAAAAAAAAAA"));
+        assert!(bloom.insert_kmer(b"ACGTN").is_err());
+
+        let atomic = AtomicBloomFilter::new(10_000, 5);
+        atomic.insert_kmers(genome, 21);
+        assert_eq!(atomic.kmer_hits(&read, 21), 20);
+        assert!(!atomic.insert_kmer(b"ACGT").unwrap());
+        assert!(atomic.contains_kmer(b"acgt"));
+    }
+}
@@ -1,150 +1,1772 @@
-use std::sync::atomic::{AtomicBool, Ordering};
+use std::fmt;
+use std::fs::File;
+use std::hash::{BuildHasher, Hash};
+use std::io::{self, BufRead, BufReader};
+use std::ops::{BitAnd, BitAndAssign, BitOr, BitOrAssign};
+use std::path::Path;
 use std::sync::{Arc, RwLock};
-use std::thread;
 
-use sha2::{Digest, Sha256};
+pub mod age_partitioned;
+#[cfg(feature = "tokio")]
+pub mod async_filter;
+pub mod attenuated;
+mod bitset;
+pub mod blocked;
+mod compressed;
+pub mod const_filter;
+pub mod count_min;
+pub mod counting;
+#[cfg(feature = "arc-swap")]
+pub mod cow;
+pub mod cuckoo;
+pub mod dedup;
+pub mod delta;
+pub mod dleft;
+pub mod doorkeeper;
+mod encoding;
+pub mod error;
+pub mod expiring;
+#[cfg(feature = "ffi")]
+pub mod ffi;
+pub mod frozen;
+pub mod fuse;
+pub mod golomb;
+pub mod guava;
+pub mod hash;
+pub mod hyperloglog;
+pub mod iblt;
+#[cfg(feature = "tower")]
+pub mod idempotency;
+pub mod key;
+pub mod kmer;
+#[cfg(feature = "metrics")]
+pub mod metrics;
+pub mod minhash;
+#[cfg(feature = "memmap2")]
+pub mod mmap;
+#[cfg(feature = "rayon")]
+mod parallel;
+pub mod params;
+#[cfg(feature = "python")]
+pub mod python;
+pub mod quotient;
+pub mod reconcile;
+pub mod redisbloom;
+#[cfg(feature = "resp")]
+pub mod resp;
+pub mod ribbon;
+mod rng;
+pub mod scalable;
+#[cfg(feature = "server")]
+pub mod server;
+pub mod set;
+pub mod sharded;
+pub mod sliding;
+#[cfg(feature = "arc-swap")]
+pub mod snapshot;
+pub mod spectral;
+pub mod stable;
+pub mod stats;
+#[cfg(feature = "futures")]
+pub mod stream;
+pub mod tinylfu;
+pub mod topk;
+pub mod url_dedup;
+pub mod view;
+pub mod wal;
+#[cfg(feature = "wasm")]
+pub mod wasm;
+pub mod xor;
 
-pub struct BloomFilter {
-    bit_array: Vec<bool>,
+pub use age_partitioned::AgePartitionedBloomFilter;
+#[cfg(feature = "tokio")]
+pub use async_filter::AsyncBloomFilter;
+pub use attenuated::AttenuatedBloomFilter;
+use bitset::{AtomicBitVec, BitVec};
+pub use blocked::BlockedBloomFilter;
+pub use const_filter::ConstBloomFilter;
+pub use count_min::CountMinSketch;
+pub use counting::{ConcurrentCountingBloomFilter, CountingBloomFilter};
+#[cfg(feature = "arc-swap")]
+pub use cow::{CowBloomFilter, CowSnapshot};
+pub use cuckoo::CuckooFilter;
+pub use dedup::{BloomDedup, BloomDedupExt};
+pub use delta::{Delta, DeltaBloomFilter};
+pub use dleft::DLeftCountingBloomFilter;
+pub use doorkeeper::Doorkeeper;
+pub use error::{BloomError, MergeError};
+pub use expiring::ExpiringBloomFilter;
+pub use frozen::FrozenBloomFilter;
+pub use fuse::{BinaryFuse16, BinaryFuse4Wise16, BinaryFuse4Wise8, BinaryFuse8, BinaryFuseFilter};
+pub use golomb::GolombSet;
+pub use guava::GuavaBloomFilter;
+pub use hash::{HashFn, Sha256BuildHasher, Sha256Hasher};
+#[cfg(feature = "siphash")]
+pub use hash::{SipBuildHasher, SipHasher};
+#[cfg(feature = "wyhash")]
+pub use hash::{WyBuildHasher, WyHasher};
+#[cfg(feature = "xxhash")]
+pub use hash::{Xxh3BuildHasher, Xxh3Hasher};
+pub use hyperloglog::HyperLogLog;
+pub use iblt::{Iblt, IbltEntries};
+#[cfg(feature = "tower")]
+pub use idempotency::{Idempotency, IdempotencyLayer};
+pub use key::Key;
+#[cfg(feature = "metrics")]
+pub use metrics::MeteredBloomFilter;
+pub use minhash::MinHash;
+#[cfg(feature = "memmap2")]
+pub use mmap::MmapBloomFilter;
+pub use quotient::QuotientFilter;
+pub use reconcile::{SetDiff, SetSketch};
+pub use redisbloom::RedisBloomFilter;
+pub use ribbon::{RibbonFilter, RibbonFilterBuilder};
+pub use scalable::ScalableBloomFilter;
+pub use set::BloomSet;
+pub use sharded::ShardedBloomFilter;
+pub use sliding::SlidingWindowBloomFilter;
+#[cfg(feature = "arc-swap")]
+pub use snapshot::SnapshotBloomFilter;
+pub use spectral::SpectralBloomFilter;
+pub use stable::StableBloomFilter;
+pub use stats::FilterStats;
+#[cfg(feature = "futures")]
+pub use stream::{BloomDedupStream, BloomDedupStreamExt};
+pub use tinylfu::FrequencySketch;
+pub use topk::TopK;
+pub use url_dedup::{UrlDeduper, UrlRules};
+pub use view::BloomFilterRef;
+pub use wal::DurableBloomFilter;
+pub use xor::{Xor16, Xor8, XorFilter};
+
+// `tracing::debug!` with the `tracing` feature, nothing without
+macro_rules! debug_event {
+    ($($arg:tt)*) => {
+        #[cfg(feature = "tracing")]
+        tracing::debug!($($arg)*);
+    };
+}
+
+// Debug span around a batch method with the filter type, its number of hashes and the given
+// fields, nothing without the `tracing` feature
+macro_rules! batch_span {
+    ($filter:literal $(, $field:ident = $value:expr)*; $item:item) => {
+        #[cfg_attr(
+            feature = "tracing",
+            tracing::instrument(
+                level = "debug",
+                skip_all,
+                fields(filter = $filter, k = self.num_hashes $(, $field = $value)*)
+            )
+        )]
+        $item
+    };
+}
+
+// Items hashed per batch by `insert_all` before their bits are set
+const INSERT_BATCH: usize = 256;
+
+// More hashes than this only make sense for false positive rates below 2^-64
+const MAX_HASHES: usize = 64;
+
+// Parameters accepted by the `try_new` constructors
+fn check_params(size: usize, num_hashes: usize) -> Result<(), BloomError> {
+    if size == 0 {
+        return Err(BloomError::InvalidParams(
+            "Filter size must be at least one bit.".into(),
+        ));
+    }
+    if num_hashes == 0 || num_hashes > MAX_HASHES {
+        return Err(BloomError::InvalidParams(format!(
+            "Number of hashes must be between 1 and {}, got {}.",
+            MAX_HASHES, num_hashes
+        )));
+    }
+    Ok(())
+}
+
+// Bit indices past the size would land in the padding of the last word
+fn check_bit_index(idx: usize, size: usize) {
+    assert!(
+        idx < size,
+        "bit index {} out of range for a filter of {} bits",
+        idx,
+        size
+    );
+}
+
+// Heap bytes of the buffer of a Vec, for the `memory_usage` methods
+pub(crate) fn heap_bytes<T>(vec: &Vec<T>) -> usize {
+    vec.capacity() * size_of::<T>()
+}
+
+// The builder panics on rates outside (0, 1), file loaders report them as an error instead
+fn check_keys_file_rate(false_positive_rate: f64) -> io::Result<()> {
+    if false_positive_rate > 0.0 && false_positive_rate < 1.0 {
+        Ok(())
+    } else {
+        Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "False positive rate must be in (0, 1).",
+        ))
+    }
+}
+
+// Calls `f` with every non-empty line without its "\n" or "\r\n", returns the number of calls
+fn for_each_line<R: BufRead>(mut reader: R, mut f: impl FnMut(&[u8])) -> io::Result<u64> {
+    let mut line = Vec::new();
+    let mut count = 0;
+    while reader.read_until(b'\n', &mut line)? > 0 {
+        let mut key = line.strip_suffix(b"\n").unwrap_or(&line);
+        key = key.strip_suffix(b"\r").unwrap_or(key);
+        if !key.is_empty() {
+            f(key);
+            count += 1;
+        }
+        line.clear();
+    }
+    Ok(count)
+}
+
+fn fill_ratio(set_bits: usize, size: usize) -> f64 {
+    if size == 0 {
+        return 1.0;
+    }
+    set_bits as f64 / size as f64
+}
+
+pub struct BloomFilter<S = Sha256BuildHasher> {
+    bit_array: BitVec,
     num_hashes: usize,
     size: usize,
-    //hash_funcs: Vec<Box<dyn Fn(&[u8]) -> u64>>,
+    hasher: S,
+    // Written into the hasher before every item, see `with_seed`
+    seed: u64,
+    // When set, replaces `hasher`: one function per hash, see `set_hash_fn`.
+    // Closures can't be serialized, such filters refuse to be. Clones share them.
+    hash_fns: Arc<Vec<HashFn>>,
+}
+
+// Serde form of `BloomFilter` and `AtomicBloomFilter`: their fields plus the layout version the
+// bits were set with. Deserializing checks it like `from_bytes` does, along with the parameters,
+// so a hand-edited or pre-layout-3 filter is an error instead of a panic or wrong answers.
+#[cfg(feature = "serde")]
+#[derive(serde::Serialize, serde::Deserialize)]
+struct FilterRepr<B, S> {
+    bit_array: B,
+    num_hashes: usize,
+    size: usize,
+    hasher: S,
+    #[serde(default)]
+    seed: u64,
+    #[serde(default)]
+    layout_version: Option<u32>,
+}
+
+#[cfg(feature = "serde")]
+impl<B, S> FilterRepr<B, S> {
+    fn check(&self, words: usize) -> Result<(), String> {
+        let layout = self.layout_version.ok_or_else(|| {
+            format!(
+                "Filter has no layout version, it was serialized before layout {}. Rebuild it.",
+                hash::LAYOUT_VERSION
+            )
+        })?;
+        encoding::check_layout(layout)?;
+        check_params(self.size, self.num_hashes).map_err(|err| err.to_string())?;
+        if words != bitset::word_count(self.size) {
+            return Err(format!(
+                "Expected {} words of bit array, got {}.",
+                bitset::word_count(self.size),
+                words
+            ));
+        }
+        Ok(())
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<S: serde::Serialize> serde::Serialize for BloomFilter<S> {
+    fn serialize<Ser: serde::Serializer>(&self, serializer: Ser) -> Result<Ser::Ok, Ser::Error> {
+        if !self.hash_fns.is_empty() {
+            return Err(serde::ser::Error::custom(
+                "Filters with custom hash functions can't be serialized.",
+            ));
+        }
+        FilterRepr {
+            bit_array: &self.bit_array,
+            num_hashes: self.num_hashes,
+            size: self.size,
+            hasher: &self.hasher,
+            seed: self.seed,
+            layout_version: Some(hash::LAYOUT_VERSION),
+        }
+        .serialize(serializer)
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<'de, S: serde::Deserialize<'de>> serde::Deserialize<'de> for BloomFilter<S> {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let repr = FilterRepr::<BitVec, S>::deserialize(deserializer)?;
+        repr.check(repr.bit_array.words().len())
+            .map_err(serde::de::Error::custom)?;
+        Ok(BloomFilter {
+            bit_array: repr.bit_array,
+            num_hashes: repr.num_hashes,
+            size: repr.size,
+            hasher: repr.hasher,
+            seed: repr.seed,
+            hash_fns: Arc::default(),
+        })
+    }
+}
+
+impl<S: Clone> Clone for BloomFilter<S> {
+    fn clone(&self) -> Self {
+        BloomFilter {
+            bit_array: self.bit_array.clone(),
+            num_hashes: self.num_hashes,
+            size: self.size,
+            hasher: self.hasher.clone(),
+            seed: self.seed,
+            hash_fns: Arc::clone(&self.hash_fns),
+        }
+    }
+}
+
+// Parameters and fill instead of the bits
+impl<S> fmt::Debug for BloomFilter<S> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("BloomFilter")
+            .field("size", &self.size)
+            .field("num_hashes", &self.num_hashes)
+            .field(
+                "fill_ratio",
+                &fill_ratio(self.bit_array.count_ones(), self.size),
+            )
+            .field("custom_hash_fns", &!self.hash_fns.is_empty())
+            .finish()
+    }
+}
+
+// Same parameters, seed and bits. Hashers and custom hash functions can't be compared, filters
+// built with different ones compare equal if their bits happen to match.
+impl<S> PartialEq for BloomFilter<S> {
+    fn eq(&self, other: &Self) -> bool {
+        self.size == other.size
+            && self.num_hashes == other.num_hashes
+            && self.seed == other.seed
+            && self.bit_array == other.bit_array
+    }
 }
 
-pub struct ThreadSafeBF {
-    bf: Arc<RwLock<BloomFilter>>,
+impl<S> Eq for BloomFilter<S> {}
+
+// Sizes filters from the expected number of items and the target false positive rate
+// instead of a raw bit count and number of hashes.
+// Ex. BloomFilter::builder().expected_items(1_000_000).false_positive_rate(0.01).build()
+pub struct BloomFilterBuilder {
+    expected_items: usize,
+    false_positive_rate: f64,
+    seed: u64,
 }
 
-pub struct AtomicBloomFilter {
-    bit_array: Vec<AtomicBool>,
+pub struct ThreadSafeBF<S = Sha256BuildHasher> {
+    bf: Arc<RwLock<BloomFilter<S>>>,
+}
+
+pub struct AtomicBloomFilter<S = Sha256BuildHasher> {
+    bit_array: AtomicBitVec,
     num_hashes: usize,
     size: usize,
+    hasher: S,
+    seed: u64,
+}
+
+// Same form as `BloomFilter`, of a snapshot of the bits
+#[cfg(feature = "serde")]
+impl<S: serde::Serialize> serde::Serialize for AtomicBloomFilter<S> {
+    fn serialize<Ser: serde::Serializer>(&self, serializer: Ser) -> Result<Ser::Ok, Ser::Error> {
+        FilterRepr {
+            bit_array: &self.bit_array,
+            num_hashes: self.num_hashes,
+            size: self.size,
+            hasher: &self.hasher,
+            seed: self.seed,
+            layout_version: Some(hash::LAYOUT_VERSION),
+        }
+        .serialize(serializer)
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<'de, S: serde::Deserialize<'de>> serde::Deserialize<'de> for AtomicBloomFilter<S> {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let repr = FilterRepr::<BitVec, S>::deserialize(deserializer)?;
+        repr.check(repr.bit_array.words().len())
+            .map_err(serde::de::Error::custom)?;
+        Ok(AtomicBloomFilter {
+            bit_array: AtomicBitVec::from_words(repr.bit_array.words().to_vec()),
+            num_hashes: repr.num_hashes,
+            size: repr.size,
+            hasher: repr.hasher,
+            seed: repr.seed,
+        })
+    }
+}
+
+// Deep copy of a snapshot of the bits
+impl<S: Clone> Clone for AtomicBloomFilter<S> {
+    fn clone(&self) -> Self {
+        AtomicBloomFilter {
+            bit_array: AtomicBitVec::from_words(self.bit_array.to_words()),
+            num_hashes: self.num_hashes,
+            size: self.size,
+            hasher: self.hasher.clone(),
+            seed: self.seed,
+        }
+    }
+}
+
+impl<S> fmt::Debug for AtomicBloomFilter<S> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("AtomicBloomFilter")
+            .field("size", &self.size)
+            .field("num_hashes", &self.num_hashes)
+            .field(
+                "fill_ratio",
+                &fill_ratio(self.bit_array.count_ones(), self.size),
+            )
+            .finish()
+    }
+}
+
+// Compares snapshots, same caveats as `BloomFilter`
+impl<S> PartialEq for AtomicBloomFilter<S> {
+    fn eq(&self, other: &Self) -> bool {
+        self.size == other.size
+            && self.num_hashes == other.num_hashes
+            && self.seed == other.seed
+            && self.bit_array.to_words() == other.bit_array.to_words()
+    }
+}
+
+impl AtomicBloomFilter {
+    pub fn new(size: usize, num_hashes: usize) -> Self {
+        Self::with_hasher(size, num_hashes, Sha256BuildHasher)
+    }
+
+    pub fn try_new(size: usize, num_hashes: usize) -> Result<Self, BloomError> {
+        Self::try_with_hasher(size, num_hashes, Sha256BuildHasher)
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, BloomError> {
+        Self::from_bytes_with_hasher(bytes, Sha256BuildHasher)
+    }
+}
+
+impl<S: BuildHasher> AtomicBloomFilter<S> {
+    pub fn with_hasher(size: usize, num_hashes: usize, hasher: S) -> Self {
+        AtomicBloomFilter {
+            bit_array: AtomicBitVec::new(size),
+            num_hashes,
+            size,
+            hasher,
+            seed: 0,
+        }
+    }
+
+    // Same as `BloomFilter::with_seed`
+    pub fn with_seed(mut self, seed: u64) -> Self {
+        self.seed = seed;
+        self
+    }
+
+    // Same as `BloomFilter::try_with_hasher`
+    pub fn try_with_hasher(size: usize, num_hashes: usize, hasher: S) -> Result<Self, BloomError> {
+        check_params(size, num_hashes)?;
+        Ok(Self::with_hasher(size, num_hashes, hasher))
+    }
+
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(
+            level = "trace",
+            skip_all,
+            fields(filter = "AtomicBloomFilter", k = self.num_hashes)
+        )
+    )]
+    pub fn set<T: Hash + ?Sized>(&self, item: &T) {
+        for idx in hash::indices(&self.seeded_hasher(), item, self.num_hashes, self.size) {
+            self.bit_array.set(idx);
+        }
+    }
+
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(
+            level = "trace",
+            skip_all,
+            fields(filter = "AtomicBloomFilter", k = self.num_hashes)
+        )
+    )]
+    pub fn test<T: Hash + ?Sized>(&self, item: &T) -> bool {
+        for idx in hash::indices(&self.seeded_hasher(), item, self.num_hashes, self.size) {
+            if !self.bit_array.get(idx) {
+                return false;
+            }
+        }
+        true
+    }
+
+    // Like `set`, but returns whether the item was (possibly) already present. Of two threads
+    // inserting the same new item at the same time at most one sees true.
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(
+            level = "trace",
+            skip_all,
+            fields(filter = "AtomicBloomFilter", k = self.num_hashes)
+        )
+    )]
+    pub fn insert<T: Hash + ?Sized>(&self, item: &T) -> bool {
+        let mut present = true;
+        for idx in hash::indices(&self.seeded_hasher(), item, self.num_hashes, self.size) {
+            present &= self.bit_array.test_and_set(idx);
+        }
+        present
+    }
+
+    // Sets the item's bits and returns whether all of them were set before, from the results
+    // of the `fetch_or`s that set them (same as `insert`). For "process only if unseen"
+    // across threads: `if !bloom.test_and_set(&item) { process(item) }` skips no new item
+    // (false positives aside), since of the threads racing on a new item at least one finds
+    // a bit unset. The bits live in different words, so two racers can both find one unset
+    // and both process the item, processing should tolerate that.
+    pub fn test_and_set<T: Hash + ?Sized>(&self, item: &T) -> bool {
+        self.insert(item)
+    }
+
+    // Integer keys without the hasher, see `BloomFilter::insert_u64`
+    pub fn insert_u64(&self, key: u64) -> bool {
+        let mut present = true;
+        for idx in hash::u64_indices(key, self.seed, self.num_hashes, self.size) {
+            present &= self.bit_array.test_and_set(idx);
+        }
+        present
+    }
+
+    pub fn contains_u64(&self, key: u64) -> bool {
+        hash::u64_indices(key, self.seed, self.num_hashes, self.size)
+            .all(|idx| self.bit_array.get(idx))
+    }
+
+    // Same as `BloomFilter::test_many`, against bits that may be set concurrently
+    batch_span! {
+        "AtomicBloomFilter", batch_size = items.len();
+        pub fn test_many<T: Hash>(&self, items: &[T]) -> Vec<bool> {
+            if self.num_hashes == 0 {
+                return vec![true; items.len()];
+            }
+            let mut indices = Vec::with_capacity(items.len() * self.num_hashes);
+            for item in items {
+                indices.extend(hash::indices(
+                    &self.seeded_hasher(),
+                    item,
+                    self.num_hashes,
+                    self.size,
+                ));
+            }
+            indices
+                .chunks(self.num_hashes)
+                .map(|item| item.iter().all(|&idx| self.bit_array.get(idx)))
+                .collect()
+        }
+    }
+
+    // True if every item is (possibly) present, stops at the first one that isn't
+    pub fn contains_all<I>(&self, items: I) -> bool
+    where
+        I: IntoIterator,
+        I::Item: Hash,
+    {
+        items.into_iter().all(|item| self.test(&item))
+    }
+
+    // True if some item is (possibly) present, stops at the first one that is
+    pub fn contains_any<I>(&self, items: I) -> bool
+    where
+        I: IntoIterator,
+        I::Item: Hash,
+    {
+        items.into_iter().any(|item| self.test(&item))
+    }
+
+    // Same as `BloomFilter::insert_all`
+    batch_span! {
+        "AtomicBloomFilter";
+        pub fn insert_all<I>(&self, items: I)
+        where
+            I: IntoIterator,
+            I::Item: Hash,
+        {
+            let mut indices = Vec::with_capacity(INSERT_BATCH * self.num_hashes);
+            for item in items {
+                indices.extend(hash::indices(
+                    &self.seeded_hasher(),
+                    &item,
+                    self.num_hashes,
+                    self.size,
+                ));
+                if indices.len() >= INSERT_BATCH * self.num_hashes {
+                    debug_event!(batch_size = INSERT_BATCH, "setting bits of batch");
+                    for &idx in &indices {
+                        self.bit_array.set(idx);
+                    }
+                    indices.clear();
+                }
+            }
+            if !indices.is_empty() {
+                debug_event!(
+                    batch_size = indices.len() / self.num_hashes.max(1),
+                    "setting bits of batch"
+                );
+                for idx in indices {
+                    self.bit_array.set(idx);
+                }
+            }
+        }
+    }
+
+    pub fn set_bytes(&self, bytes: impl AsRef<[u8]>) {
+        for idx in hash::bytes_indices(
+            &self.seeded_hasher(),
+            bytes.as_ref(),
+            self.num_hashes,
+            self.size,
+        ) {
+            self.bit_array.set(idx);
+        }
+    }
+
+    pub fn test_bytes(&self, bytes: impl AsRef<[u8]>) -> bool {
+        for idx in hash::bytes_indices(
+            &self.seeded_hasher(),
+            bytes.as_ref(),
+            self.num_hashes,
+            self.size,
+        ) {
+            if !self.bit_array.get(idx) {
+                return false;
+            }
+        }
+        true
+    }
+
+    // Same as `BloomFilter::estimated_len`, concurrent inserts may or may not be counted
+    pub fn estimated_len(&self) -> usize {
+        params::estimated_items(self.size, self.num_hashes, self.bit_array.count_ones()).round()
+            as usize
+    }
+
+    pub fn size(&self) -> usize {
+        self.size
+    }
+
+    pub fn num_hashes(&self) -> usize {
+        self.num_hashes
+    }
+
+    pub fn seed(&self) -> u64 {
+        self.seed
+    }
+
+    // Same as `BloomFilter::bit_len` / `get_bit` / `set_bit`
+    pub fn bit_len(&self) -> usize {
+        self.size
+    }
+
+    pub fn get_bit(&self, idx: usize) -> bool {
+        check_bit_index(idx, self.size);
+        self.bit_array.get(idx)
+    }
+
+    pub fn set_bit(&self, idx: usize) {
+        check_bit_index(idx, self.size);
+        self.bit_array.set(idx);
+    }
+
+    // Same as `BloomFilter::iter_set_bits`, bits set while iterating may or may not show up
+    pub fn iter_set_bits(&self) -> impl Iterator<Item = usize> + '_ {
+        self.bit_array.ones()
+    }
+
+    // Unsets every bit in place, word by word. Not atomic with respect to concurrent inserts:
+    // an item inserted during the clear can keep some of its bits and lose others, so it
+    // may test negative afterwards. Stop the writers first when that matters.
+    pub fn clear(&self) {
+        self.bit_array.clear();
+    }
+
+    // Like `clear`, but swaps every word with zero and returns the old bits as a filter, ex.
+    // to persist or ship the bits of the period that just ended. No set bit is lost, each one
+    // ends up in the returned filter or stays in this one, but an item inserted concurrently
+    // can end up split between the two.
+    pub fn replace_with_empty(&self) -> AtomicBloomFilter<S>
+    where
+        S: Clone,
+    {
+        AtomicBloomFilter {
+            bit_array: AtomicBitVec::from_words(self.bit_array.take_words()),
+            num_hashes: self.num_hashes,
+            size: self.size,
+            hasher: self.hasher.clone(),
+            seed: self.seed,
+        }
+    }
+
+    // Same as `BloomFilter::memory_usage`
+    pub fn memory_usage(&self) -> usize {
+        size_of::<Self>() + self.bit_array.heap_bytes()
+    }
+
+    // Same as `BloomFilter::fill_ratio`, of a snapshot of the bits
+    pub fn fill_ratio(&self) -> f64 {
+        fill_ratio(self.bit_array.count_ones(), self.size)
+    }
+
+    // Same as `BloomFilter::current_fpp`
+    pub fn current_fpp(&self) -> f64 {
+        self.fill_ratio().powi(self.num_hashes as i32)
+    }
+
+    // Of a snapshot of the bits
+    pub fn stats(&self) -> FilterStats {
+        FilterStats::new(
+            self.size,
+            self.num_hashes,
+            self.bit_array.count_ones(),
+            self.memory_usage(),
+        )
+    }
+
+    // Same encoding as `BloomFilter::to_bytes`, of a snapshot of the bits
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(
+            level = "debug",
+            skip_all,
+            fields(filter = "AtomicBloomFilter", k = self.num_hashes, size = self.size)
+        )
+    )]
+    pub fn to_bytes(&self) -> Vec<u8> {
+        encoding::encode(
+            self.size,
+            self.num_hashes,
+            self.seed,
+            &self.bit_array.to_words(),
+        )
+    }
+
+    // The hasher must be the one the filter was built with
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(
+            level = "debug",
+            skip_all,
+            fields(filter = "AtomicBloomFilter", len = bytes.len()),
+            err
+        )
+    )]
+    pub fn from_bytes_with_hasher(bytes: &[u8], hasher: S) -> Result<Self, BloomError> {
+        let (size, num_hashes, seed, words) = encoding::decode(bytes)?;
+        Ok(AtomicBloomFilter {
+            bit_array: AtomicBitVec::from_words(words),
+            num_hashes,
+            size,
+            hasher,
+            seed,
+        })
+    }
+
+    fn seeded_hasher(&self) -> hash::Seeded<'_, S> {
+        hash::Seeded {
+            build_hasher: &self.hasher,
+            seed: self.seed,
+        }
+    }
+}
+
+impl BloomFilter {
+    pub fn new(size: usize, num_hashes: usize) -> Self {
+        Self::with_hasher(size, num_hashes, Sha256BuildHasher)
+    }
+
+    // Like `new`, but rejects parameters that make a useless filter instead of failing on the
+    // first insert (size 0) or answering true for everything (0 hashes)
+    pub fn try_new(size: usize, num_hashes: usize) -> Result<Self, BloomError> {
+        Self::try_with_hasher(size, num_hashes, Sha256BuildHasher)
+    }
+
+    pub fn builder() -> BloomFilterBuilder {
+        BloomFilterBuilder::new()
+    }
+
+    // Decodes the output of `to_bytes`, see `encoding.rs` for the format
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, BloomError> {
+        Self::from_bytes_with_hasher(bytes, Sha256BuildHasher)
+    }
+
+    pub fn load(path: impl AsRef<Path>) -> io::Result<Self> {
+        Self::load_with_hasher(path, Sha256BuildHasher)
+    }
+
+    // Filter of the keys of a file with one key per line (see `insert_lines`), sized for the
+    // number of keys at the given false positive rate. Reads the file twice, once to count.
+    pub fn from_keys_file(path: impl AsRef<Path>, false_positive_rate: f64) -> io::Result<Self> {
+        check_keys_file_rate(false_positive_rate)?;
+        let path = path.as_ref();
+        let keys = for_each_line(BufReader::new(File::open(path)?), |_| {})?;
+        Self::from_keys_file_with_hint(path, false_positive_rate, keys as usize)
+    }
+
+    // One pass over the file, sized for expected_items keys
+    pub fn from_keys_file_with_hint(
+        path: impl AsRef<Path>,
+        false_positive_rate: f64,
+        expected_items: usize,
+    ) -> io::Result<Self> {
+        check_keys_file_rate(false_positive_rate)?;
+        let mut bloom = BloomFilter::builder()
+            .expected_items(expected_items)
+            .false_positive_rate(false_positive_rate)
+            .build();
+        bloom.insert_lines(BufReader::new(File::open(path)?))?;
+        Ok(bloom)
+    }
 }
 
-impl AtomicBloomFilter {
-    pub fn new(
-        size: usize,
-        num_hashes: usize, //hash_funcs: Vec<Box<dyn Fn(&[u8]) -> u64>>
-    ) -> Self {
-        AtomicBloomFilter {
-            bit_array: (0..size).map(|_| AtomicBool::new(false)).collect(),
-            num_hashes,
-            size,
-            //       hash_funcs,
+impl<S: BuildHasher> BloomFilter<S> {
+    // Ex. BloomFilter::with_hasher(1000, 3, std::collections::hash_map::RandomState::new())
+    pub fn with_hasher(size: usize, num_hashes: usize, hasher: S) -> Self {
+        BloomFilter {
+            bit_array: BitVec::new(size),
+            num_hashes,
+            size,
+            hasher,
+            seed: 0,
+            hash_fns: Arc::default(),
+        }
+    }
+
+    // size must be at least 1 and num_hashes between 1 and 64
+    pub fn try_with_hasher(size: usize, num_hashes: usize, hasher: S) -> Result<Self, BloomError> {
+        check_params(size, num_hashes)?;
+        Ok(Self::with_hasher(size, num_hashes, hasher))
+    }
+
+    // Seeds the hasher: filters with the same parameters, hasher and seed put items on the same
+    // bits in every process and through the C API, different seeds give unrelated positions.
+    // The seed is stored in `to_bytes`. Call it on a new filter, bits already set don't move.
+    // Ex. BloomFilter::new(1000, 3).with_seed(42)
+    pub fn with_seed(mut self, seed: u64) -> Self {
+        self.seed = seed;
+        self
+    }
+
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(
+            level = "trace",
+            skip_all,
+            fields(filter = "BloomFilter", k = self.num_hashes)
+        )
+    )]
+    pub fn set<T: Hash + ?Sized>(&mut self, item: &T) {
+        for idx in hash::probe(
+            &self.seeded_hasher(),
+            &self.hash_fns,
+            item,
+            self.num_hashes,
+            self.size,
+        ) {
+            self.bit_array.set(idx);
+        }
+    }
+
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(
+            level = "trace",
+            skip_all,
+            fields(filter = "BloomFilter", k = self.num_hashes)
+        )
+    )]
+    pub fn test<T: Hash + ?Sized>(&self, item: &T) -> bool {
+        for idx in hash::probe(
+            &self.seeded_hasher(),
+            &self.hash_fns,
+            item,
+            self.num_hashes,
+            self.size,
+        ) {
+            if !self.bit_array.get(idx) {
+                return false;
+            }
+        }
+        true
+    }
+
+    // Like `set`, but returns whether the item was (possibly) already present, i.e. all of its
+    // bits were set before. `if !bloom.insert(&item) { ... }` handles every item at most once,
+    // new items that are false positives are skipped.
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(
+            level = "trace",
+            skip_all,
+            fields(filter = "BloomFilter", k = self.num_hashes)
+        )
+    )]
+    pub fn insert<T: Hash + ?Sized>(&mut self, item: &T) -> bool {
+        let mut present = true;
+        for idx in hash::probe(
+            &self.seeded_hasher(),
+            &self.hash_fns,
+            item,
+            self.num_hashes,
+            self.size,
+        ) {
+            present &= self.bit_array.test_and_set(idx);
+        }
+        present
+    }
+
+    // Fast path for integer keys (ids, u32s widened to u64): the bits come from mixing the key
+    // with splitmix64 instead of hashing it, many times faster than a SHA256 digest. Neither
+    // the hasher nor custom hash functions are used, and the bits differ from the ones of
+    // `insert(&key)`, so keys inserted this way have to be queried with `contains_u64`.
+    // Keys chosen by an adversary can aim at bits, keep the hasher for untrusted input.
+    pub fn insert_u64(&mut self, key: u64) -> bool {
+        let mut present = true;
+        for idx in hash::u64_indices(key, self.seed, self.num_hashes, self.size) {
+            present &= self.bit_array.test_and_set(idx);
+        }
+        present
+    }
+
+    pub fn contains_u64(&self, key: u64) -> bool {
+        hash::u64_indices(key, self.seed, self.num_hashes, self.size)
+            .all(|idx| self.bit_array.get(idx))
+    }
+
+    // Same as calling `test` for every item, but hashes everything first and probes the bits in
+    // bulk (4 bits per instruction with the `simd` feature on x86_64 with AVX2)
+    batch_span! {
+        "BloomFilter", batch_size = items.len();
+        pub fn test_many<T: Hash>(&self, items: &[T]) -> Vec<bool> {
+            if self.num_hashes == 0 {
+                return vec![true; items.len()];
+            }
+            let mut indices = Vec::with_capacity(items.len() * self.num_hashes);
+            for item in items {
+                indices.extend(hash::probe(
+                    &self.seeded_hasher(),
+                    &self.hash_fns,
+                    item,
+                    self.num_hashes,
+                    self.size,
+                ));
+            }
+            self.bit_array.test_many(&indices, self.num_hashes)
+        }
+    }
+
+    // True if every item is (possibly) present, stops at the first one that isn't
+    pub fn contains_all<I>(&self, items: I) -> bool
+    where
+        I: IntoIterator,
+        I::Item: Hash,
+    {
+        items.into_iter().all(|item| self.test(&item))
+    }
+
+    // True if some item is (possibly) present, stops at the first one that is
+    pub fn contains_any<I>(&self, items: I) -> bool
+    where
+        I: IntoIterator,
+        I::Item: Hash,
+    {
+        items.into_iter().any(|item| self.test(&item))
+    }
+
+    // Same as calling `set` for every item, but hashes a batch of items before touching the
+    // bit array and keeps the index buffer across batches
+    batch_span! {
+        "BloomFilter";
+        pub fn insert_all<I>(&mut self, items: I)
+        where
+            I: IntoIterator,
+            I::Item: Hash,
+        {
+            let mut indices = Vec::with_capacity(INSERT_BATCH * self.num_hashes);
+            for item in items {
+                indices.extend(hash::probe(
+                    &self.seeded_hasher(),
+                    &self.hash_fns,
+                    &item,
+                    self.num_hashes,
+                    self.size,
+                ));
+                if indices.len() >= INSERT_BATCH * self.num_hashes {
+                    debug_event!(batch_size = INSERT_BATCH, "setting bits of batch");
+                    for &idx in &indices {
+                        self.bit_array.set(idx);
+                    }
+                    indices.clear();
+                }
+            }
+            if !indices.is_empty() {
+                debug_event!(
+                    batch_size = indices.len() / self.num_hashes.max(1),
+                    "setting bits of batch"
+                );
+                for idx in indices {
+                    self.bit_array.set(idx);
+                }
+            }
+        }
+    }
+
+    // Raw byte keys (digests, encoded ids, ...) hashed without going through `Hash`
+    pub fn set_bytes(&mut self, bytes: impl AsRef<[u8]>) {
+        for idx in hash::probe_bytes(
+            &self.seeded_hasher(),
+            &self.hash_fns,
+            bytes.as_ref(),
+            self.num_hashes,
+            self.size,
+        ) {
+            self.bit_array.set(idx);
+        }
+    }
+
+    pub fn test_bytes(&self, bytes: impl AsRef<[u8]>) -> bool {
+        for idx in hash::probe_bytes(
+            &self.seeded_hasher(),
+            &self.hash_fns,
+            bytes.as_ref(),
+            self.num_hashes,
+            self.size,
+        ) {
+            if !self.bit_array.get(idx) {
+                return false;
+            }
+        }
+        true
+    }
+
+    // Inserts every line of the reader as a raw key (`set_bytes`, without the "\n" or "\r\n"),
+    // query them with `test_bytes` or `test_key`. Empty lines are skipped. Returns the number
+    // of lines inserted, the ones before an I/O error stay inserted.
+    pub fn insert_lines<R: BufRead>(&mut self, reader: R) -> io::Result<u64> {
+        for_each_line(reader, |key| self.set_bytes(key))
+    }
+
+    //For setting hash functions beside SHA256 by user
+    //Each function gives one of the k indices (its result % size), so exactly
+    //num_hashes functions are needed. Items are passed as the bytes their `Hash` impl writes.
+    //The bit array is not rehashed, call it before inserting anything.
+    pub fn set_hash_fn(&mut self, hash_fns: Vec<HashFn>) -> Result<(), BloomError> {
+        if hash_fns.len() != self.num_hashes {
+            return Err(BloomError::InvalidParams(format!(
+                "Expected {} hash functions, got {}.",
+                self.num_hashes,
+                hash_fns.len()
+            )));
+        }
+        self.hash_fns = Arc::new(hash_fns);
+        Ok(())
+    }
+
+    pub fn reset(&mut self) {
+        self.bit_array.clear();
+    }
+
+    // Versioned binary encoding of the parameters and bits. Custom hash functions
+    // and the hasher are not included, the reader has to use the same ones.
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(
+            level = "debug",
+            skip_all,
+            fields(filter = "BloomFilter", k = self.num_hashes, size = self.size)
+        )
+    )]
+    pub fn to_bytes(&self) -> Vec<u8> {
+        encoding::encode(
+            self.size,
+            self.num_hashes,
+            self.seed,
+            self.bit_array.words(),
+        )
+    }
+
+    // The hasher must be the one the filter was built with
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(
+            level = "debug",
+            skip_all,
+            fields(filter = "BloomFilter", len = bytes.len()),
+            err
+        )
+    )]
+    pub fn from_bytes_with_hasher(bytes: &[u8], hasher: S) -> Result<Self, BloomError> {
+        let (size, num_hashes, seed, words) = encoding::decode(bytes)?;
+        Ok(Self::from_parts(size, num_hashes, words, hasher).with_seed(seed))
+    }
+
+    fn from_parts(size: usize, num_hashes: usize, words: Vec<u64>, hasher: S) -> Self {
+        BloomFilter {
+            bit_array: BitVec::from_words(words),
+            num_hashes,
+            size,
+            hasher,
+            seed: 0,
+            hash_fns: Arc::default(),
+        }
+    }
+
+    // Writes `to_bytes` to a temporary file and renames it over `path`,
+    // a crash during the save leaves the previous file intact
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(
+            level = "debug",
+            skip_all,
+            fields(filter = "BloomFilter", k = self.num_hashes, path = ?path.as_ref()),
+            err
+        )
+    )]
+    pub fn save(&self, path: impl AsRef<Path>) -> io::Result<()> {
+        encoding::write_atomic(path.as_ref(), &self.to_bytes())
+    }
+
+    // Corrupt or unknown files fail with `io::ErrorKind::InvalidData`
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(
+            level = "debug",
+            skip_all,
+            fields(filter = "BloomFilter", path = ?path.as_ref()),
+            err
+        )
+    )]
+    pub fn load_with_hasher(path: impl AsRef<Path>, hasher: S) -> io::Result<Self> {
+        let (size, num_hashes, seed, words) = encoding::read(path.as_ref())?;
+        Ok(Self::from_parts(size, num_hashes, words, hasher).with_seed(seed))
+    }
+}
+
+impl<S: BuildHasher + Clone> BloomFilter<S> {
+    // Golomb-coded copy of the set bits that answers like this filter, smaller than the bit
+    // array only when the filter is sparse. The set hashes with the plain hasher, so seeded
+    // filters and ones with custom hash functions are refused: it would miss their items.
+    pub fn to_gcs(&self) -> Result<GolombSet<S>, BloomError> {
+        if self.seed != 0 || !self.hash_fns.is_empty() {
+            return Err(BloomError::InvalidParams(
+                "Only unseeded filters without custom hash functions convert to a GolombSet."
+                    .into(),
+            ));
+        }
+        let ones: Vec<u64> = self.bit_array.ones().map(|idx| idx as u64).collect();
+        Ok(GolombSet::from_sorted(
+            &ones,
+            self.size as u64,
+            self.num_hashes,
+            self.hasher.clone(),
+        ))
+    }
+
+    // Filter of the items of both filters (bitwise OR), answers exactly like a filter that got
+    // all inserts of both. Both must have the same size, number of hashes and hasher.
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(
+            level = "debug",
+            skip_all,
+            fields(filter = "BloomFilter", k = self.num_hashes, size = self.size),
+            err
+        )
+    )]
+    pub fn union(&self, other: &BloomFilter<S>) -> Result<BloomFilter<S>, MergeError> {
+        self.check_compatible(other)?;
+        let mut bit_array = self.bit_array.clone();
+        bit_array.union_with(&other.bit_array);
+        Ok(BloomFilter {
+            bit_array,
+            num_hashes: self.num_hashes,
+            size: self.size,
+            hasher: self.hasher.clone(),
+            seed: self.seed,
+            hash_fns: Arc::default(),
+        })
+    }
+
+    // Bitwise AND of both filters. Every item inserted in both tests positive, but so can items
+    // that were only in one of them (their bits may be set in the other by different items), so
+    // the result is an over-approximation of the intersection, even beyond the usual false
+    // positive rate. That's why it comes wrapped in `Intersection`.
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(
+            level = "debug",
+            skip_all,
+            fields(filter = "BloomFilter", k = self.num_hashes, size = self.size),
+            err
+        )
+    )]
+    pub fn intersect(&self, other: &BloomFilter<S>) -> Result<Intersection<S>, MergeError> {
+        self.check_compatible(other)?;
+        let mut bit_array = self.bit_array.clone();
+        bit_array.intersect_with(&other.bit_array);
+        Ok(Intersection(BloomFilter {
+            bit_array,
+            num_hashes: self.num_hashes,
+            size: self.size,
+            hasher: self.hasher.clone(),
+            seed: self.seed,
+            hash_fns: Arc::default(),
+        }))
+    }
+
+    // Shrinks the filter to size / factor bits by OR-ing every run of `factor` bits into one:
+    // bit idx goes to idx / factor, where every item's bits land since indices are scaled to
+    // the size (idx % (size / factor) with custom hash functions, whose results are taken mod
+    // the size). The result answers for every inserted item, no keys needed, at a higher false
+    // positive rate: fold a sparse filter, ex. one sized for far more items than it got, before
+    // shipping it.
+    // The size must be divisible by `factor`.
+    pub fn fold(&self, factor: usize) -> Result<BloomFilter<S>, BloomError> {
+        if factor == 0 || !self.size.is_multiple_of(factor) {
+            return Err(BloomError::InvalidParams(format!(
+                "Can't fold a filter of {} bits by {}.",
+                self.size, factor
+            )));
+        }
+        let size = self.size / factor;
+        let mut bit_array = BitVec::new(size);
+        for idx in self.bit_array.ones() {
+            if self.hash_fns.is_empty() {
+                bit_array.set(idx / factor);
+            } else {
+                bit_array.set(idx % size);
+            }
+        }
+        Ok(BloomFilter {
+            bit_array,
+            num_hashes: self.num_hashes,
+            size,
+            hasher: self.hasher.clone(),
+            seed: self.seed,
+            hash_fns: Arc::clone(&self.hash_fns),
+        })
+    }
+
+    // `fold(2)`
+    pub fn halve(&self) -> Result<BloomFilter<S>, BloomError> {
+        self.fold(2)
+    }
+}
+
+impl<S: BuildHasher> BloomFilter<S> {
+    // In place `union`, `self |= other` panics instead of returning the error
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(
+            level = "debug",
+            skip_all,
+            fields(filter = "BloomFilter", k = self.num_hashes, size = self.size),
+            err
+        )
+    )]
+    pub fn union_with(&mut self, other: &BloomFilter<S>) -> Result<(), MergeError> {
+        self.check_compatible(other)?;
+        self.bit_array.union_with(&other.bit_array);
+        Ok(())
+    }
+
+    // In place `intersect`, `self &= other` panics instead of returning the error.
+    // Same caveat: afterwards the filter over-approximates the intersection.
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(
+            level = "debug",
+            skip_all,
+            fields(filter = "BloomFilter", k = self.num_hashes, size = self.size),
+            err
+        )
+    )]
+    pub fn intersect_with(&mut self, other: &BloomFilter<S>) -> Result<(), MergeError> {
+        self.check_compatible(other)?;
+        self.bit_array.intersect_with(&other.bit_array);
+        Ok(())
+    }
+
+    // Estimated Jaccard index |A ∩ B| / |A ∪ B| of the two sets, from the estimated sizes of
+    // A, B and their union (the OR of the bits): |A ∩ B| = |A| + |B| - |A ∪ B|. Needs filters
+    // that are not close to full, NaN once the union sets every bit. Two empty sets are
+    // identical.
+    pub fn similarity(&self, other: &BloomFilter<S>) -> Result<f64, MergeError> {
+        self.check_compatible(other)?;
+        let union_ones = self
+            .bit_array
+            .words()
+            .iter()
+            .zip(other.bit_array.words())
+            .map(|(left, right)| (left | right).count_ones() as usize)
+            .sum();
+        let estimate = |ones| params::estimated_items(self.size, self.num_hashes, ones);
+        let union = estimate(union_ones);
+        if union == 0.0 {
+            return Ok(1.0);
+        }
+        let intersection =
+            estimate(self.bit_array.count_ones()) + estimate(other.bit_array.count_ones()) - union;
+        Ok((intersection / union).clamp(0.0, 1.0))
+    }
+
+    fn check_compatible(&self, other: &BloomFilter<S>) -> Result<(), MergeError> {
+        if self.size != other.size {
+            return Err(MergeError::SizeMismatch {
+                left: self.size,
+                right: other.size,
+            });
+        }
+        if self.num_hashes != other.num_hashes {
+            return Err(MergeError::HashCountMismatch {
+                left: self.num_hashes,
+                right: other.num_hashes,
+            });
+        }
+        if !self.hash_fns.is_empty() || !other.hash_fns.is_empty() {
+            return Err(MergeError::CustomHashFns);
+        }
+        let (left, right) = (self.params_fingerprint(), other.params_fingerprint());
+        if left != right {
+            return Err(MergeError::FingerprintMismatch { left, right });
+        }
+        Ok(())
+    }
+
+    // Estimated number of distinct items inserted, from the number of set bits.
+    // usize::MAX once every bit is set.
+    pub fn estimated_len(&self) -> usize {
+        params::estimated_items(self.size, self.num_hashes, self.bit_array.count_ones()).round()
+            as usize
+    }
+
+    // Number of bits
+    pub fn size(&self) -> usize {
+        self.size
+    }
+
+    pub fn num_hashes(&self) -> usize {
+        self.num_hashes
+    }
+
+    // 0 for unseeded filters
+    pub fn seed(&self) -> u64 {
+        self.seed
+    }
+
+    // Bit level access for tools working on the bit array itself, ex. custom merges. Bits are
+    // numbered like in the encoding (see `encoding.rs`), idx must be below `bit_len`.
+    // Setting bits by hand can only add false positives, never lose an item.
+    pub fn bit_len(&self) -> usize {
+        self.size
+    }
+
+    pub fn get_bit(&self, idx: usize) -> bool {
+        check_bit_index(idx, self.size);
+        self.bit_array.get(idx)
+    }
+
+    pub fn set_bit(&mut self, idx: usize) {
+        check_bit_index(idx, self.size);
+        self.bit_array.set(idx);
+    }
+
+    // The bit array without copying it, ex. to hand it to a GPU, shared memory or a network
+    // frame. Bit i is bit i % 64 of word i / 64, the bits past `bit_len` in the last word are 0.
+    // With `size`, `num_hashes`, `seed` and the hasher it's everything needed to answer queries
+    // elsewhere, see `BloomFilterRef`.
+    pub fn as_raw_words(&self) -> &[u64] {
+        self.bit_array.words()
+    }
+
+    pub fn hasher(&self) -> &S {
+        &self.hasher
+    }
+
+    // Positions of the set bits in increasing order, ex. to look at their distribution or feed
+    // them to an external encoding (`to_gcs` is one)
+    pub fn iter_set_bits(&self) -> impl Iterator<Item = usize> + '_ {
+        self.bit_array.ones()
+    }
+
+    // Bytes used by the filter: the struct itself and its heap allocations, the bit array and
+    // the custom hash functions (counted by every clone sharing them). Heap memory owned by
+    // the hasher or captured by custom hash functions is not counted.
+    pub fn memory_usage(&self) -> usize {
+        size_of::<Self>() + self.heap_bytes()
+    }
+
+    pub(crate) fn heap_bytes(&self) -> usize {
+        // Arc counters, the Vec and its buffer
+        let hash_fns =
+            2 * size_of::<usize>() + size_of::<Vec<HashFn>>() + heap_bytes(&self.hash_fns);
+        self.bit_array.heap_bytes() + hash_fns
+    }
+
+    // Fraction of bits that are set, about 0.5 when a filter with the optimal k is at capacity
+    pub fn fill_ratio(&self) -> f64 {
+        fill_ratio(self.bit_array.count_ones(), self.size)
+    }
+
+    // False positive probability right now: the chance that k random bits are all set,
+    // fill_ratio^k. Grows past the design rate once more items than planned are inserted.
+    pub fn current_fpp(&self) -> f64 {
+        self.fill_ratio().powi(self.num_hashes as i32)
+    }
+
+    pub fn stats(&self) -> FilterStats {
+        FilterStats::new(
+            self.size,
+            self.num_hashes,
+            self.bit_array.count_ones(),
+            self.memory_usage(),
+        )
+    }
+
+    // Hash of size, number of hashes, hasher, seed and layout version, filters can only be merged
+    // when it's equal. Custom hash functions are not covered.
+    pub fn params_fingerprint(&self) -> u64 {
+        hash::params_fingerprint(
+            &self.seeded_hasher(),
+            &[self.size as u64, self.num_hashes as u64],
+        )
+    }
+
+    pub(crate) fn seeded_hasher(&self) -> hash::Seeded<'_, S> {
+        hash::Seeded {
+            build_hasher: &self.hasher,
+            seed: self.seed,
+        }
+    }
+}
+
+// The operators panic on incompatible filters (see `MergeError`),
+// use `union` / `intersect` / `union_with` / `intersect_with` to handle that instead.
+impl<S: BuildHasher + Clone> BitOr<&BloomFilter<S>> for &BloomFilter<S> {
+    type Output = BloomFilter<S>;
+
+    fn bitor(self, other: &BloomFilter<S>) -> BloomFilter<S> {
+        self.union(other).unwrap_or_else(|err| panic!("{}", err))
+    }
+}
+
+impl<S: BuildHasher + Clone> BitAnd<&BloomFilter<S>> for &BloomFilter<S> {
+    type Output = Intersection<S>;
+
+    fn bitand(self, other: &BloomFilter<S>) -> Intersection<S> {
+        self.intersect(other)
+            .unwrap_or_else(|err| panic!("{}", err))
+    }
+}
+
+impl<S: BuildHasher> BitOrAssign<&BloomFilter<S>> for BloomFilter<S> {
+    fn bitor_assign(&mut self, other: &BloomFilter<S>) {
+        self.union_with(other)
+            .unwrap_or_else(|err| panic!("{}", err))
+    }
+}
+
+impl<S: BuildHasher> BitAndAssign<&BloomFilter<S>> for BloomFilter<S> {
+    fn bitand_assign(&mut self, other: &BloomFilter<S>) {
+        self.intersect_with(other)
+            .unwrap_or_else(|err| panic!("{}", err))
+    }
+}
+
+// Result of `BloomFilter::intersect`: a superset of the items in both filters.
+// Use it to narrow down candidates that still need an exact check.
+pub struct Intersection<S = Sha256BuildHasher>(BloomFilter<S>);
+
+impl<S: BuildHasher> Intersection<S> {
+    // True for every item of both filters and for some items of only one
+    pub fn test<T: Hash + ?Sized>(&self, item: &T) -> bool {
+        self.0.test(item)
+    }
+
+    // The underlying filter, for when the over-approximation is fine
+    pub fn into_filter(self) -> BloomFilter<S> {
+        self.0
+    }
+}
+
+impl Default for BloomFilterBuilder {
+    fn default() -> Self {
+        BloomFilterBuilder {
+            expected_items: 1000,
+            false_positive_rate: 0.01,
+            seed: 0,
+        }
+    }
+}
+
+impl BloomFilterBuilder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn expected_items(mut self, expected_items: usize) -> Self {
+        self.expected_items = expected_items;
+        self
+    }
+
+    pub fn false_positive_rate(mut self, false_positive_rate: f64) -> Self {
+        assert!(
+            false_positive_rate > 0.0 && false_positive_rate < 1.0,
+            "false positive rate must be in (0, 1)"
+        );
+        self.false_positive_rate = false_positive_rate;
+        self
+    }
+
+    // Applies to every filter the builder builds, see `BloomFilter::with_seed`
+    pub fn seed(mut self, seed: u64) -> Self {
+        self.seed = seed;
+        self
+    }
+
+    pub fn size(&self) -> usize {
+        params::optimal_bit_count(self.expected_items, self.false_positive_rate)
+    }
+
+    pub fn num_hashes(&self) -> usize {
+        params::optimal_num_hashes(self.size(), self.expected_items)
+    }
+
+    pub fn build(&self) -> BloomFilter {
+        BloomFilter::new(self.size(), self.num_hashes()).with_seed(self.seed)
+    }
+
+    pub fn build_atomic(&self) -> AtomicBloomFilter {
+        AtomicBloomFilter::new(self.size(), self.num_hashes()).with_seed(self.seed)
+    }
+
+    // Same false positive rate in fewer bytes once compressed (`BloomFilter::compress`) with up
+    // to max_bits_per_item bits in memory, see `params::compressed_params`
+    pub fn build_for_compression(&self, max_bits_per_item: f64) -> BloomFilter {
+        let (size, num_hashes) = params::compressed_params(
+            self.expected_items,
+            self.false_positive_rate,
+            max_bits_per_item,
+        );
+        BloomFilter::new(size, num_hashes).with_seed(self.seed)
+    }
+
+    pub fn build_thread_safe(&self) -> ThreadSafeBF {
+        ThreadSafeBF {
+            bf: Arc::new(RwLock::new(self.build())),
+        }
+    }
+
+    // Blocked filters need ~10-20% more bits than this for the same false positive rate
+    pub fn build_blocked(&self) -> BlockedBloomFilter {
+        BlockedBloomFilter::new(self.size(), self.num_hashes()).with_seed(self.seed)
+    }
+
+    pub fn build_counting(&self) -> CountingBloomFilter {
+        CountingBloomFilter::new(self.size(), self.num_hashes()).with_seed(self.seed)
+    }
+
+    // expected_items is the capacity of the first filter of the chain
+    pub fn build_scalable(&self) -> ScalableBloomFilter {
+        ScalableBloomFilter::new(self.expected_items, self.false_positive_rate).with_seed(self.seed)
+    }
+}
+
+impl ThreadSafeBF {
+    pub fn new(size: usize, num_hashes: usize) -> Self {
+        Self::with_hasher(size, num_hashes, Sha256BuildHasher)
+    }
+
+    pub fn try_new(size: usize, num_hashes: usize) -> Result<Self, BloomError> {
+        Ok(Self {
+            bf: Arc::new(RwLock::new(BloomFilter::try_new(size, num_hashes)?)),
+        })
+    }
+}
+
+impl<S: BuildHasher> ThreadSafeBF<S> {
+    pub fn with_hasher(size: usize, num_hashes: usize, hasher: S) -> Self {
+        Self {
+            bf: Arc::new(RwLock::new(BloomFilter::with_hasher(
+                size, num_hashes, hasher,
+            ))),
+        }
+    }
+    pub fn set<T: Hash + ?Sized>(&self, item: &T) -> Result<(), BloomError> {
+        match self.bf.write() {
+            Ok(mut blooom) => {
+                blooom.set(item);
+                Ok(())
+            }
+            Err(_) => Err(BloomError::PoisonedLock),
+        }
+    }
+
+    // Test and set under one write lock
+    pub fn insert<T: Hash + ?Sized>(&self, item: &T) -> Result<bool, BloomError> {
+        match self.bf.write() {
+            Ok(mut bloom) => Ok(bloom.insert(item)),
+            Err(_) => Err(BloomError::PoisonedLock),
         }
     }
-    fn hash(&self, item: &str, i: usize) -> usize {
-        let mut hasher = Sha256::new();
-        hasher.update(item.as_bytes());
-        hasher.update(i.to_le_bytes());
-        let hash_res = hasher.finalize();
 
-        let mut hash_val = [0u8; 8];
-        hash_val.copy_from_slice(&hash_res[0..8]); // Take the first 8 bytes of the hash
-        usize::from_le_bytes(hash_val) % self.size
+    pub fn test<T: Hash + ?Sized>(&self, item: &T) -> bool {
+        let bloom = self.bf.read().unwrap();
+        bloom.test(item)
     }
 
-    pub fn set(&self, item: &str) {
-        for i in 0..self.num_hashes {
-            let idx: usize = self.hash(&item, i);
-            self.bit_array[idx].store(true, Ordering::Relaxed);
-        }
+    // One read lock for the whole batch
+    pub fn test_many<T: Hash>(&self, items: &[T]) -> Vec<bool> {
+        let bloom = self.bf.read().unwrap();
+        bloom.test_many(items)
     }
 
-    pub fn test(&self, item: &str) -> bool {
-        for i in 0..self.num_hashes {
-            let idx: usize = self.hash(item, i);
-            if !self.bit_array[idx].load(Ordering::Relaxed) {
-                return false;
-            }
-        }
-        true
+    // One read lock for all items, see `BloomFilter::contains_all`
+    pub fn contains_all<I>(&self, items: I) -> bool
+    where
+        I: IntoIterator,
+        I::Item: Hash,
+    {
+        let bloom = self.bf.read().unwrap();
+        bloom.contains_all(items)
     }
-}
 
-impl BloomFilter {
-    pub fn new(
-        size: usize,
-        num_hashes: usize, //hash_funcs: Vec<Box<dyn Fn(&[u8]) -> u64>>
-    ) -> Self {
-        BloomFilter {
-            bit_array: vec![false; size],
-            num_hashes,
-            size,
-            //       hash_funcs,
-        }
+    pub fn contains_any<I>(&self, items: I) -> bool
+    where
+        I: IntoIterator,
+        I::Item: Hash,
+    {
+        let bloom = self.bf.read().unwrap();
+        bloom.contains_any(items)
     }
 
-    // Creating Multiple Hashes with one hash function
-    fn hash(&self, item: &str, i: usize) -> usize {
-        // Convert the first 8 bytes of the hash to a usize and modulo it by the bit array size
-        // Ex. for "foo"
-        // 1. SHA256("foo") = X
-        // 2. i = 0 as byte -> [0,0,0,0,0,0,0,0]
-        // 3. SHA256("foo" + [0,0,0,0,0,0,0,0]) = e02aa5a0b4e8a3644f8e9c10459dfb64609c95c91fe49328d228f3f10636c2ec
-        // 4. Take first 8 bytes: e02aa5a0b4e8a364 as byte -> [224, 42, 165, 160, 180, 232, 163, 100]
-        // 5. usize::from_le_bytes([224, 42, 165, 160, 180, 232, 163, 100]) = 7235236067926870112
-        // 6. return 7235236067926870112 % 1000 = 112
+    // Runs `f` under one read lock, ex. several queries plus `fill_ratio` on the same bits
+    // without paying for the lock every call
+    pub fn with_read<R>(&self, f: impl FnOnce(&BloomFilter<S>) -> R) -> Result<R, BloomError> {
+        match self.bf.read() {
+            Ok(bloom) => Ok(f(&bloom)),
+            Err(_) => Err(BloomError::PoisonedLock),
+        }
+    }
 
-        let mut hasher = Sha256::new();
-        hasher.update(item.as_bytes());
-        hasher.update(i.to_le_bytes());
-        let hash_res = hasher.finalize();
+    // Runs `f` under one write lock, no reader sees the filter halfway through it
+    pub fn with_write<R>(&self, f: impl FnOnce(&mut BloomFilter<S>) -> R) -> Result<R, BloomError> {
+        match self.bf.write() {
+            Ok(mut bloom) => Ok(f(&mut bloom)),
+            Err(_) => Err(BloomError::PoisonedLock),
+        }
+    }
 
-        let mut hash_val = [0u8; 8];
-        hash_val.copy_from_slice(&hash_res[0..8]); // Take the first 8 bytes of the hash
-        usize::from_le_bytes(hash_val) % self.size
+    // One write lock for the whole batch
+    pub fn insert_all<I>(&self, items: I) -> Result<(), BloomError>
+    where
+        I: IntoIterator,
+        I::Item: Hash,
+    {
+        match self.bf.write() {
+            Ok(mut bloom) => {
+                bloom.insert_all(items);
+                Ok(())
+            }
+            Err(_) => Err(BloomError::PoisonedLock),
+        }
     }
 
-    pub fn set(&mut self, item: &str) {
-        for i in 0..self.num_hashes {
-            let idx: usize = self.hash(&item, i);
-            self.bit_array[idx] = true;
+    pub fn set_hash_fn(&self, hash_fns: Vec<HashFn>) -> Result<(), BloomError> {
+        match self.bf.write() {
+            Ok(mut bloom) => bloom.set_hash_fn(hash_fns),
+            Err(_) => Err(BloomError::PoisonedLock),
         }
     }
 
-    pub fn test(&self, item: &str) -> bool {
-        for i in 0..self.num_hashes {
-            let idx: usize = self.hash(item, i);
-            if !self.bit_array[idx] {
-                return false;
+    pub fn set_bytes(&self, bytes: impl AsRef<[u8]>) -> Result<(), BloomError> {
+        match self.bf.write() {
+            Ok(mut bloom) => {
+                bloom.set_bytes(bytes);
+                Ok(())
             }
+            Err(_) => Err(BloomError::PoisonedLock),
         }
-        true
     }
 
-    //For setting hash functions beside SHA256 by user
-    pub fn set_hash_fn(&mut self, hashFn: Vec<Box<dyn Fn(&[u8]) -> u64>>) {}
-    pub fn reset(&mut self) {
-        self.bit_array.fill(false);
+    pub fn test_bytes(&self, bytes: impl AsRef<[u8]>) -> bool {
+        let bloom = self.bf.read().unwrap();
+        bloom.test_bytes(bytes)
     }
-}
 
-impl ThreadSafeBF {
-    pub fn new(size: usize, num_hashes: usize) -> Self {
-        Self {
-            bf: Arc::new(RwLock::new(BloomFilter::new(size, num_hashes))),
-        }
+    // Same as `BloomFilter::bit_len` / `get_bit` / `set_bit`
+    pub fn bit_len(&self) -> usize {
+        self.bf.read().unwrap().bit_len()
     }
-    pub fn set(&self, item: &str) -> Result<(), String> {
+
+    pub fn get_bit(&self, idx: usize) -> bool {
+        self.bf.read().unwrap().get_bit(idx)
+    }
+
+    pub fn set_bit(&self, idx: usize) -> Result<(), BloomError> {
         match self.bf.write() {
-            Ok(mut blooom) => {
-                blooom.set(item);
+            Ok(mut bloom) => {
+                bloom.set_bit(idx);
                 Ok(())
             }
-            Err(_) => Err("Failed to acquire write lock on BloomFilter. Lock is poisoned.".into()),
+            Err(_) => Err(BloomError::PoisonedLock),
         }
     }
 
-    pub fn test(&self, item: &str) -> bool {
-        let bloom = self.bf.read().unwrap();
-        bloom.test(item)
+    // Same as `BloomFilter::memory_usage`, including the shared lock and filter clones point to
+    pub fn memory_usage(&self) -> Result<usize, BloomError> {
+        match self.bf.read() {
+            Ok(bloom) => Ok(size_of::<Self>()
+                + 2 * size_of::<usize>()
+                + size_of::<RwLock<BloomFilter<S>>>()
+                + bloom.heap_bytes()),
+            Err(_) => Err(BloomError::PoisonedLock),
+        }
+    }
+
+    // Under one read lock, memory as in `memory_usage`
+    pub fn stats(&self) -> Result<FilterStats, BloomError> {
+        let memory_bytes = self.memory_usage()?;
+        let bloom = self.bf.read().map_err(|_| BloomError::PoisonedLock)?;
+        Ok(FilterStats {
+            memory_bytes,
+            ..bloom.stats()
+        })
     }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use std::thread;
 
     #[test]
     fn test_set_and_test() {
@@ -171,6 +1793,848 @@ mod tests {
         assert!(!bloom.test("grape"));
     }
 
+    #[test]
+    fn test_non_string_items() {
+        #[derive(Hash)]
+        struct Point {
+            x: i32,
+            y: i32,
+        }
+
+        let mut bloom = BloomFilter::new(1000, 3);
+
+        bloom.set(&42u64);
+        bloom.set(&Point { x: 1, y: 2 });
+
+        assert!(bloom.test(&42u64));
+        assert!(bloom.test(&Point { x: 1, y: 2 }));
+        assert!(!bloom.test(&43u64));
+        assert!(!bloom.test(&Point { x: 2, y: 1 }));
+    }
+
+    #[test]
+    fn test_byte_keys() {
+        let digest: [u8; 4] = [0xde, 0xad, 0xbe, 0xef];
+        let encoded_id: Vec<u8> = vec![0x08, 0x96, 0x01];
+
+        let mut bloom = BloomFilter::new(1000, 3);
+        bloom.set_bytes(digest);
+        bloom.set_bytes(&encoded_id);
+        assert!(bloom.test_bytes(digest));
+        assert!(bloom.test_bytes(&encoded_id));
+        assert!(!bloom.test_bytes([0xca, 0xfe]));
+
+        let atomic = AtomicBloomFilter::new(1000, 3);
+        atomic.set_bytes(digest);
+        assert!(atomic.test_bytes(digest));
+        assert!(!atomic.test_bytes(&encoded_id));
+
+        let thread_safe = ThreadSafeBF::new(1000, 3);
+        thread_safe.set_bytes(b"foo").unwrap();
+        assert!(thread_safe.test_bytes("foo"));
+        assert!(!thread_safe.test_bytes(b"bar"));
+    }
+
+    #[test]
+    fn test_test_many() {
+        let mut bloom = BloomFilter::new(10_000, 5);
+        for i in 0..500u32 {
+            bloom.set(&i);
+        }
+        let items: Vec<u32> = (0..2000).collect();
+        let expected: Vec<bool> = items.iter().map(|i| bloom.test(i)).collect();
+        assert_eq!(bloom.test_many(&items), expected);
+        assert!(bloom.test_many(&items[..500]).iter().all(|&hit| hit));
+
+        let atomic = AtomicBloomFilter::new(10_000, 5);
+        atomic.insert_all(0..500u32);
+        assert_eq!(atomic.test_many(&items), expected);
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn test_serde_round_trip() {
+        let mut bloom = BloomFilter::new(1000, 4);
+        let atomic = AtomicBloomFilter::new(1000, 4);
+        for i in 0..100 {
+            bloom.set(&i);
+            atomic.set(&i);
+        }
+
+        let json = serde_json::to_string(&bloom).unwrap();
+        let bloom: BloomFilter = serde_json::from_str(&json).unwrap();
+        let json = serde_json::to_string(&atomic).unwrap();
+        let atomic: AtomicBloomFilter = serde_json::from_str(&json).unwrap();
+        assert!((0..100).all(|i| bloom.test(&i) && atomic.test(&i)));
+        for i in 100..1000 {
+            assert_eq!(bloom.test(&i), atomic.test(&i));
+        }
+
+        // hand-edited or old data is refused instead of panicking or answering wrong
+        let value = serde_json::to_value(&bloom).unwrap();
+        for (field, bad) in [
+            ("size", serde_json::json!(0)),
+            ("size", serde_json::json!(100_000)),
+            ("num_hashes", serde_json::json!(0)),
+            ("layout_version", serde_json::json!(2)),
+            ("layout_version", serde_json::Value::Null),
+        ] {
+            let mut value = value.clone();
+            value[field] = bad;
+            assert!(serde_json::from_value::<BloomFilter>(value.clone()).is_err());
+            assert!(serde_json::from_value::<AtomicBloomFilter>(value).is_err());
+        }
+        let mut custom = BloomFilter::new(1000, 1);
+        custom
+            .set_hash_fn(vec![Box::new(|bytes: &[u8]| bytes.len() as u64)])
+            .unwrap();
+        assert!(serde_json::to_string(&custom).is_err());
+    }
+
+    #[test]
+    fn test_bytes_round_trip() {
+        let mut bloom = BloomFilter::new(1000, 4);
+        let atomic = AtomicBloomFilter::new(1000, 4);
+        for i in 0..100 {
+            bloom.set(&i);
+            atomic.set(&i);
+        }
+        let bytes = bloom.to_bytes();
+        assert_eq!(bytes, atomic.to_bytes());
+
+        let bloom = BloomFilter::from_bytes(&bytes).unwrap();
+        let atomic = AtomicBloomFilter::from_bytes(&bytes).unwrap();
+        assert!((0..100).all(|i| bloom.test(&i) && atomic.test(&i)));
+        assert_eq!(bloom.to_bytes(), bytes);
+        assert!(BloomFilter::from_bytes(&bytes[1..]).is_err());
+    }
+
+    #[test]
+    fn test_save_and_load() {
+        let path = std::env::temp_dir().join(format!("bloomf_test_{}.bf", std::process::id()));
+        let mut bloom = BloomFilter::new(1000, 4);
+        bloom.set("foo");
+        bloom.save(&path).unwrap();
+        bloom.set("bar");
+        bloom.save(&path).unwrap();
+
+        let loaded = BloomFilter::load(&path).unwrap();
+        assert!(loaded.test("foo") && loaded.test("bar"));
+        assert_eq!(loaded.to_bytes(), bloom.to_bytes());
+
+        std::fs::write(&path, b"garbage").unwrap();
+        let err = BloomFilter::load(&path).err().unwrap();
+        assert_eq!(err.kind(), std::io::ErrorKind::InvalidData);
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_union() {
+        let mut a = BloomFilter::new(1000, 4);
+        let mut b = BloomFilter::new(1000, 4);
+        a.set("foo");
+        b.set("bar");
+        let both = a.union(&b).unwrap();
+        assert!(both.test("foo") && both.test("bar"));
+        assert!(!a.test("bar"));
+
+        assert_eq!(
+            a.union(&BloomFilter::new(2000, 4)).err(),
+            Some(MergeError::SizeMismatch {
+                left: 1000,
+                right: 2000
+            })
+        );
+        assert!(a.union(&BloomFilter::new(1000, 3)).is_err());
+    }
+
+    #[test]
+    fn test_intersect() {
+        let mut a = BloomFilter::new(10_000, 4);
+        let mut b = BloomFilter::new(10_000, 4);
+        for i in 0..200 {
+            a.set(&i);
+            b.set(&(i + 100));
+        }
+        let both = a.intersect(&b).unwrap();
+        assert!((100..200).all(|i| both.test(&i)));
+        let only_one = (0..100).chain(200..300).filter(|i| both.test(i)).count();
+        assert!(only_one < 20);
+        assert!(a.intersect(&BloomFilter::new(1000, 4)).is_err());
+    }
+
+    #[test]
+    fn test_similarity() {
+        // J = 500 / 1500
+        let mut a = BloomFilter::new(100_000, 4);
+        let mut b = BloomFilter::new(100_000, 4);
+        a.insert_all(0..1000u32);
+        b.insert_all(500..1500u32);
+        let similarity = a.similarity(&b).unwrap();
+        assert!((similarity - 1.0 / 3.0).abs() < 0.02, "J = {}", similarity);
+        assert!((a.similarity(&a).unwrap() - 1.0).abs() < 1e-9);
+
+        let mut disjoint = BloomFilter::new(100_000, 4);
+        disjoint.insert_all(5000..6000u32);
+        assert!(a.similarity(&disjoint).unwrap() < 0.02);
+        let empty = BloomFilter::new(100_000, 4);
+        assert_eq!(empty.similarity(&empty.clone()).unwrap(), 1.0);
+        assert!(a.similarity(&BloomFilter::new(1000, 4)).is_err());
+    }
+
+    #[test]
+    fn test_operators() {
+        let mut global = BloomFilter::new(1000, 4);
+        let mut shard = BloomFilter::new(1000, 4);
+        global.set("foo");
+        shard.set("bar");
+
+        let both = &global | &shard;
+        assert!(both.test("foo") && both.test("bar"));
+        assert!((&both & &shard).test("bar"));
+        global |= &shard;
+        assert!(global.test("bar"));
+        global &= &shard;
+        assert!(global.test("bar"));
+    }
+
+    #[test]
+    #[should_panic(expected = "Can't merge filters of 1000 and 2000 bits.")]
+    fn test_operator_mismatch_panics() {
+        let mut global = BloomFilter::new(1000, 4);
+        global |= &BloomFilter::new(2000, 4);
+    }
+
+    #[test]
+    fn test_params_fingerprint() {
+        use std::collections::hash_map::RandomState;
+
+        let a = BloomFilter::new(1000, 4);
+        assert_eq!(
+            a.params_fingerprint(),
+            BloomFilter::new(1000, 4).params_fingerprint()
+        );
+        assert_ne!(
+            a.params_fingerprint(),
+            BloomFilter::new(1000, 5).params_fingerprint()
+        );
+
+        let x = BloomFilter::with_hasher(1000, 4, RandomState::new());
+        let y = BloomFilter::with_hasher(1000, 4, RandomState::new());
+        assert!(matches!(
+            x.union(&y),
+            Err(MergeError::FingerprintMismatch { .. })
+        ));
+        assert!(x.union(&x).is_ok());
+    }
+
+    #[test]
+    fn test_estimated_len() {
+        let mut bloom = BloomFilter::builder()
+            .expected_items(10_000)
+            .false_positive_rate(0.01)
+            .build();
+        let atomic = AtomicBloomFilter::new(bloom.size, bloom.num_hashes);
+        assert_eq!(bloom.estimated_len(), 0);
+        for i in 0..5000 {
+            bloom.set(&i);
+            bloom.set(&i);
+            atomic.set(&i);
+        }
+        let estimate = bloom.estimated_len();
+        assert!((4900..5100).contains(&estimate), "estimate {}", estimate);
+        assert_eq!(atomic.estimated_len(), estimate);
+    }
+
+    #[test]
+    fn test_current_fpp() {
+        let mut bloom = BloomFilter::builder()
+            .expected_items(1000)
+            .false_positive_rate(0.01)
+            .build();
+        assert_eq!(bloom.current_fpp(), 0.0);
+        for i in 0..1000 {
+            bloom.set(&i);
+        }
+        let fpp = bloom.current_fpp();
+        assert!((fpp - 0.01).abs() < 0.003, "fpp {}", fpp);
+        for i in 1000..3000 {
+            bloom.set(&i);
+        }
+        assert!(bloom.current_fpp() > 0.1);
+    }
+
+    #[test]
+    fn test_fill_ratio() {
+        let mut bloom = BloomFilter::new(128, 2);
+        let atomic = AtomicBloomFilter::new(128, 2);
+        assert_eq!(bloom.fill_ratio(), 0.0);
+        bloom.set("foo");
+        atomic.set("foo");
+        assert!(bloom.fill_ratio() > 0.0 && bloom.fill_ratio() <= 2.0 / 128.0);
+        assert_eq!(bloom.fill_ratio(), atomic.fill_ratio());
+        for i in 0..1000 {
+            bloom.set(&i);
+        }
+        assert_eq!(bloom.fill_ratio(), 1.0);
+    }
+
+    #[test]
+    fn test_insert_reports_presence() {
+        let mut bloom = BloomFilter::new(1000, 3);
+        assert!(!bloom.insert("foo"));
+        assert!(bloom.insert("foo"));
+        assert!(bloom.test("foo"));
+
+        let atomic = AtomicBloomFilter::new(1000, 3);
+        assert!(!atomic.insert("foo"));
+        assert!(atomic.insert("foo"));
+
+        let thread_safe = ThreadSafeBF::new(1000, 3);
+        assert_eq!(thread_safe.insert("foo"), Ok(false));
+        assert_eq!(thread_safe.insert("foo"), Ok(true));
+    }
+
+    #[test]
+    fn test_insert_all() {
+        let items: Vec<u32> = (0..1000).collect();
+        let mut one_by_one = BloomFilter::new(10_000, 3);
+        for item in &items {
+            one_by_one.set(item);
+        }
+        let mut bulk = BloomFilter::new(10_000, 3);
+        bulk.insert_all(&items);
+        assert_eq!(bulk.to_bytes(), one_by_one.to_bytes());
+
+        let atomic = AtomicBloomFilter::new(10_000, 3);
+        atomic.insert_all(items.iter().map(|i| i.to_string()));
+        assert!(items.iter().all(|i| atomic.test(&i.to_string())));
+
+        let thread_safe = ThreadSafeBF::new(10_000, 3);
+        thread_safe.insert_all(["foo", "bar"]).unwrap();
+        assert!(thread_safe.test("foo") && thread_safe.test("bar"));
+    }
+
+    #[test]
+    fn test_clone_debug_eq() {
+        let mut bloom = BloomFilter::new(1000, 3);
+        bloom.set("foo");
+        let mut copy = bloom.clone();
+        assert_eq!(copy, bloom);
+        copy.set("bar");
+        assert_ne!(copy, bloom);
+        assert!(!bloom.test("bar"));
+        assert_ne!(BloomFilter::new(1000, 3), BloomFilter::new(1000, 4));
+
+        let debug = format!("{:?}", bloom);
+        assert!(debug.starts_with("BloomFilter { size: 1000, num_hashes: 3, fill_ratio: 0.003"));
+
+        let atomic = AtomicBloomFilter::new(1000, 3);
+        atomic.set("foo");
+        let copy = atomic.clone();
+        copy.set("bar");
+        assert!(!atomic.test("bar"));
+        assert_ne!(copy, atomic);
+        assert_eq!(atomic.clone(), atomic);
+        assert!(format!("{:?}", atomic).starts_with("AtomicBloomFilter { size: 1000"));
+    }
+
+    #[test]
+    fn test_poisoned_lock_error() {
+        let bloom = Arc::new(ThreadSafeBF::new(100, 3));
+        let poisoner = Arc::clone(&bloom);
+        let _ = thread::spawn(move || {
+            let _guard = poisoner.bf.write().unwrap();
+            panic!("poison the lock");
+        })
+        .join();
+        assert_eq!(bloom.set("foo"), Err(BloomError::PoisonedLock));
+        assert_eq!(
+            BloomFilter::new(100, 3).set_hash_fn(Vec::new()),
+            Err(BloomError::InvalidParams(
+                "Expected 3 hash functions, got 0.".into()
+            ))
+        );
+        let err = BloomFilter::from_bytes(b"nope").unwrap_err();
+        assert!(matches!(err, BloomError::SerializationError(_)));
+    }
+
+    #[test]
+    fn test_try_new() {
+        assert!(BloomFilter::try_new(1000, 3).is_ok());
+        assert!(matches!(
+            BloomFilter::try_new(0, 3),
+            Err(BloomError::InvalidParams(_))
+        ));
+        assert!(BloomFilter::try_new(1000, 0).is_err());
+        assert!(BloomFilter::try_new(1000, 65).is_err());
+        assert!(AtomicBloomFilter::try_new(0, 0).is_err());
+        assert!(ThreadSafeBF::try_new(1, 64).is_ok());
+    }
+
+    #[cfg(feature = "tracing")]
+    #[test]
+    fn test_tracing_spans() {
+        use std::sync::atomic::{AtomicUsize, Ordering};
+        use std::sync::Mutex;
+        use tracing::span::{Attributes, Id, Record};
+        use tracing::{Event, Metadata, Subscriber};
+
+        // Keeps the names of new spans and counts events
+        #[derive(Default)]
+        struct Recorder {
+            spans: Mutex<Vec<&'static str>>,
+            events: AtomicUsize,
+        }
+
+        impl Subscriber for Recorder {
+            fn enabled(&self, _: &Metadata<'_>) -> bool {
+                true
+            }
+            fn new_span(&self, span: &Attributes<'_>) -> Id {
+                let mut spans = self.spans.lock().unwrap();
+                spans.push(span.metadata().name());
+                Id::from_u64(spans.len() as u64)
+            }
+            fn record(&self, _: &Id, _: &Record<'_>) {}
+            fn record_follows_from(&self, _: &Id, _: &Id) {}
+            fn event(&self, _: &Event<'_>) {
+                self.events.fetch_add(1, Ordering::Relaxed);
+            }
+            fn enter(&self, _: &Id) {}
+            fn exit(&self, _: &Id) {}
+        }
+
+        let recorder = Arc::new(Recorder::default());
+        tracing::subscriber::with_default(recorder.clone(), || {
+            let mut bloom = BloomFilter::new(1000, 3);
+            bloom.insert_all(0..300);
+            bloom.test_many(&[1, 2]);
+            assert!(bloom.union_with(&BloomFilter::new(10, 3)).is_err());
+            BloomFilter::from_bytes(&bloom.to_bytes()).unwrap();
+        });
+        let spans = recorder.spans.lock().unwrap();
+        assert_eq!(
+            *spans,
+            [
+                "insert_all",
+                "test_many",
+                "union_with",
+                "to_bytes",
+                "from_bytes_with_hasher"
+            ]
+        );
+        // two batches and the union error
+        assert_eq!(recorder.events.load(Ordering::Relaxed), 3);
+    }
+
+    #[test]
+    fn test_memory_usage() {
+        let bloom = BloomFilter::new(64_000, 3);
+        // 1000 words and the empty list of custom hash functions
+        assert_eq!(
+            bloom.memory_usage(),
+            size_of::<BloomFilter>() + 8000 + 2 * size_of::<usize>() + size_of::<Vec<HashFn>>()
+        );
+        assert_eq!(
+            AtomicBloomFilter::new(64_000, 3).memory_usage(),
+            size_of::<AtomicBloomFilter>() + 8000
+        );
+        let thread_safe = ThreadSafeBF::new(64_000, 3).memory_usage().unwrap();
+        assert!(thread_safe > bloom.memory_usage());
+        assert_eq!(
+            CountingBloomFilter::new(64_000, 3).memory_usage(),
+            size_of::<CountingBloomFilter>() + 64_000
+        );
+        let sharded = ShardedBloomFilter::with_shards(64_000, 3, 4, Sha256BuildHasher);
+        assert!(sharded.memory_usage() >= 8000 + 4 * 64);
+        assert_eq!(
+            ConstBloomFilter::<16, 3>::new().memory_usage(),
+            16 * size_of::<u64>()
+        );
+    }
+
+    #[test]
+    fn test_fold() {
+        let mut bloom = BloomFilter::new(100_000, 4);
+        for i in 0..1000u32 {
+            bloom.set(&i);
+        }
+        let folded = bloom.fold(4).unwrap();
+        assert_eq!(folded.size(), 25_000);
+        assert!((0..1000u32).all(|i| folded.test(&i)));
+        assert!(folded.fill_ratio() > bloom.fill_ratio());
+        // same as inserting into a filter of the folded size
+        let mut small = BloomFilter::new(25_000, 4);
+        small.insert_all(0..1000u32);
+        assert_eq!(folded, small);
+        assert_eq!(bloom.halve().unwrap().size(), 50_000);
+
+        assert!(bloom.fold(3).is_err());
+        assert!(bloom.fold(0).is_err());
+    }
+
+    #[test]
+    fn test_seed() {
+        let mut unseeded = BloomFilter::new(1000, 3);
+        let mut zero = BloomFilter::new(1000, 3).with_seed(0);
+        let mut a = BloomFilter::new(1000, 3).with_seed(42);
+        let mut b = BloomFilter::new(1000, 3).with_seed(42);
+        let mut other = BloomFilter::new(1000, 3).with_seed(7);
+        let atomic = AtomicBloomFilter::new(1000, 3).with_seed(42);
+        for i in 0..20u32 {
+            unseeded.set(&i);
+            zero.set(&i);
+            a.set(&i);
+            b.set(&i);
+            other.set(&i);
+            atomic.set(&i);
+        }
+        assert_eq!(unseeded, zero);
+        assert_eq!(a, b);
+        assert_ne!(a, unseeded);
+        assert_ne!(a, other);
+        assert_eq!(a.to_bytes(), atomic.to_bytes());
+        // same bits under another seed are a different filter
+        let empty = BloomFilter::new(1000, 3);
+        assert_ne!(empty, BloomFilter::new(1000, 3).with_seed(42));
+        assert_ne!(
+            AtomicBloomFilter::new(1000, 3),
+            AtomicBloomFilter::new(1000, 3).with_seed(42)
+        );
+        assert!(matches!(
+            a.union(&other),
+            Err(MergeError::FingerprintMismatch { .. })
+        ));
+        assert!(a.union(&b).is_ok());
+
+        let decoded = BloomFilter::from_bytes(&a.to_bytes()).unwrap();
+        assert_eq!(decoded.seed(), 42);
+        assert!((0..20u32).all(|i| decoded.test(&i)));
+        assert_eq!(
+            AtomicBloomFilter::from_bytes(&a.to_bytes()).unwrap().seed(),
+            42
+        );
+        // unseeded filters keep the old encoding
+        assert_eq!(unseeded.to_bytes().len(), 24 + 16 * 8);
+        assert_eq!(BloomFilter::builder().seed(42).build().seed(), 42);
+    }
+
+    #[test]
+    fn test_bit_access() {
+        let mut bloom = BloomFilter::new(100, 3);
+        bloom.set("foo");
+        let ones: Vec<usize> = (0..bloom.bit_len())
+            .filter(|&idx| bloom.get_bit(idx))
+            .collect();
+        assert!((1..=3).contains(&ones.len()));
+        assert_eq!(bloom.iter_set_bits().collect::<Vec<_>>(), ones);
+        assert_eq!(bloom.as_raw_words().len(), 2);
+        assert_eq!(
+            &bloom.to_bytes()[24..],
+            bloom
+                .as_raw_words()
+                .iter()
+                .flat_map(|word| word.to_le_bytes())
+                .collect::<Vec<_>>()
+        );
+
+        // copying the bits by hand gives the same filter
+        let atomic = AtomicBloomFilter::new(100, 3);
+        let thread_safe = ThreadSafeBF::new(100, 3);
+        for &idx in &ones {
+            atomic.set_bit(idx);
+            thread_safe.set_bit(idx).unwrap();
+        }
+        assert!(atomic.test("foo"));
+        assert_eq!(atomic.iter_set_bits().collect::<Vec<_>>(), ones);
+        assert!(thread_safe.test("foo"));
+        assert!(thread_safe.get_bit(ones[0]));
+        assert_eq!(atomic.bit_len(), 100);
+
+        bloom.set_bit(99);
+        assert!(bloom.get_bit(99));
+        assert_eq!(
+            bloom.bit_array.count_ones(),
+            ones.len() + !ones.contains(&99) as usize
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "out of range")]
+    fn test_bit_access_out_of_range() {
+        BloomFilter::new(100, 3).set_bit(100);
+    }
+
+    #[test]
+    fn test_contains_all_any() {
+        let mut bloom = BloomFilter::new(10_000, 4);
+        bloom.insert_all(["foo", "bar"]);
+        assert!(bloom.contains_all(["foo", "bar"]));
+        assert!(!bloom.contains_all(["foo", "baz"]));
+        assert!(bloom.contains_any(["baz", "bar"]));
+        assert!(!bloom.contains_any(["baz", "qux"]));
+        assert!(bloom.contains_all(Vec::<&str>::new()));
+        assert!(!bloom.contains_any(Vec::<&str>::new()));
+
+        let atomic = AtomicBloomFilter::new(10_000, 4);
+        atomic.insert_all(0..10u32);
+        assert!(atomic.contains_all(0..10u32));
+        assert!(!atomic.contains_any(100..110u32));
+
+        let thread_safe = ThreadSafeBF::new(10_000, 4);
+        thread_safe.set("foo").unwrap();
+        assert!(thread_safe.contains_any(&["baz", "foo"]));
+        assert!(!thread_safe.contains_all(&["baz", "foo"]));
+    }
+
+    #[test]
+    fn test_u64_keys() {
+        let mut bloom = BloomFilter::new(100_000, 7);
+        let present = (0..10_000u64)
+            .filter(|key| bloom.insert_u64(key * 3))
+            .count();
+        assert!(present < 50, "{}", present);
+        assert!((0..10_000u64).all(|key| bloom.contains_u64(key * 3)));
+        assert!(bloom.insert_u64(3));
+        let false_positives = (0..30_000u64)
+            .filter(|key| key % 3 != 0 && bloom.contains_u64(*key))
+            .count();
+        assert!(false_positives < 300, "{} false positives", false_positives);
+        // a path of its own
+        assert!(!bloom.test(&3u64));
+
+        let seeded = BloomFilter::new(100_000, 7).with_seed(1);
+        let atomic = AtomicBloomFilter::new(100_000, 7).with_seed(1);
+        assert!(!atomic.insert_u64(42));
+        assert!(atomic.contains_u64(42));
+        assert!(!seeded.contains_u64(42));
+    }
+
+    #[test]
+    fn test_insert_lines() {
+        let dump = "foo\nbar\r\n\nbaz";
+        let mut bloom = BloomFilter::new(10_000, 4);
+        assert_eq!(bloom.insert_lines(dump.as_bytes()).unwrap(), 3);
+        assert!(bloom.test_bytes("foo") && bloom.test_bytes("bar") && bloom.test_key("baz"));
+        assert!(!bloom.test_bytes("bar\r"));
+        assert!(!bloom.test_bytes(""));
+
+        let reader = std::io::BufReader::new(std::io::Cursor::new(b"a\nb\n".to_vec()));
+        assert_eq!(bloom.insert_lines(reader).unwrap(), 2);
+    }
+
+    #[test]
+    fn test_from_keys_file() {
+        let path = std::env::temp_dir().join(format!("bloomf_keys_{}.txt", std::process::id()));
+        let keys: String = (0..1000).map(|i| format!("key_{}\n", i)).collect();
+        std::fs::write(&path, keys).unwrap();
+
+        let bloom = BloomFilter::from_keys_file(&path, 0.01).unwrap();
+        assert_eq!(
+            bloom.size(),
+            BloomFilter::builder().expected_items(1000).size()
+        );
+        assert!((0..1000).all(|i| bloom.test_bytes(format!("key_{}", i))));
+        let hinted = BloomFilter::from_keys_file_with_hint(&path, 0.01, 1000).unwrap();
+        assert_eq!(hinted, bloom);
+        for rate in [0.0, 1.0, f64::NAN] {
+            let err = BloomFilter::from_keys_file_with_hint(&path, rate, 1000).unwrap_err();
+            assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+        }
+        std::fs::remove_file(&path).unwrap();
+
+        assert!(BloomFilter::from_keys_file(&path, 0.01).is_err());
+    }
+
+    #[test]
+    fn test_atomic_test_and_set() {
+        let bloom = Arc::new(AtomicBloomFilter::new(100_000, 4));
+        let handles: Vec<_> = (0..4)
+            .map(|_| {
+                let bloom = Arc::clone(&bloom);
+                thread::spawn(move || {
+                    (0..1000u32)
+                        .filter(|i| !bloom.test_and_set(i))
+                        .collect::<Vec<_>>()
+                })
+            })
+            .collect();
+        let mut processed: Vec<u32> = handles
+            .into_iter()
+            .flat_map(|handle| handle.join().unwrap())
+            .collect();
+        processed.sort_unstable();
+        processed.dedup();
+        // every item by some thread, except false positives
+        assert!(processed.len() > 990);
+        assert!(bloom.test_and_set(&1u32));
+    }
+
+    #[test]
+    fn test_atomic_clear() {
+        let bloom = AtomicBloomFilter::new(10_000, 4).with_seed(2);
+        bloom.insert_all(0..100u32);
+        let previous = bloom.replace_with_empty();
+        assert!((0..100u32).all(|i| previous.test(&i)));
+        assert_eq!(previous.seed(), 2);
+        assert_eq!(bloom.fill_ratio(), 0.0);
+
+        bloom.set("foo");
+        bloom.clear();
+        assert!(!bloom.test("foo"));
+        assert_eq!(bloom.iter_set_bits().count(), 0);
+    }
+
+    #[test]
+    fn test_scoped_access() {
+        let bloom = ThreadSafeBF::new(10_000, 4);
+        let fresh = bloom
+            .with_write(|bf| (0..100u32).filter(|i| !bf.insert(i)).count())
+            .unwrap();
+        assert_eq!(fresh, 100);
+        let (found, fill) = bloom
+            .with_read(|bf| {
+                let found = (0..200u32).filter(|i| bf.test(i)).count();
+                (found, bf.fill_ratio())
+            })
+            .unwrap();
+        assert!((100..110).contains(&found));
+        assert!(fill > 0.0);
+
+        // a panic under the write lock poisons it for both
+        let panicked = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+            bloom.with_write(|_| panic!("poison")).unwrap();
+        }));
+        assert!(panicked.is_err());
+        assert_eq!(bloom.with_read(|_| ()), Err(BloomError::PoisonedLock));
+        assert_eq!(bloom.with_write(|_| ()), Err(BloomError::PoisonedLock));
+    }
+
+    #[test]
+    fn test_builder() {
+        let builder = BloomFilter::builder()
+            .expected_items(1_000_000)
+            .false_positive_rate(0.01);
+        // Well known values for n = 1M, p = 1%: ~9.59 bits per item and 7 hashes
+        assert_eq!(builder.size(), 9_585_059);
+        assert_eq!(builder.num_hashes(), 7);
+
+        let builder = BloomFilter::builder()
+            .expected_items(1000)
+            .false_positive_rate(0.01);
+        let mut bloom = builder.build();
+        let atomic = builder.build_atomic();
+        let thread_safe = builder.build_thread_safe();
+        for i in 0..1000 {
+            bloom.set(&i);
+            atomic.set(&i);
+            thread_safe.set(&i).unwrap();
+        }
+        for i in 0..1000 {
+            assert!(bloom.test(&i));
+            assert!(atomic.test(&i));
+            assert!(thread_safe.test(&i));
+        }
+
+        let false_positives = (1000..11_000).filter(|i| bloom.test(i)).count();
+        assert!(false_positives < 200, "{} false positives", false_positives);
+
+        let seeded = builder.seed(42);
+        assert_eq!(
+            seeded
+                .build_thread_safe()
+                .with_read(|bf| bf.seed())
+                .unwrap(),
+            42
+        );
+        let mut counting = seeded.build_counting();
+        let mut blocked = seeded.build_blocked();
+        let mut scalable = seeded.build_scalable();
+        for i in 0..5000 {
+            counting.insert(&i);
+            blocked.insert(&i);
+            scalable.insert(&i);
+        }
+        assert!((0..5000).all(|i| counting.contains(&i) && blocked.contains(&i)));
+        assert!((0..5000).all(|i| scalable.contains(&i)));
+        assert!(scalable.num_filters() > 1);
+        assert_eq!((blocked.seed(), scalable.seed()), (42, 42));
+        let compacted = counting.compact();
+        assert_eq!(compacted.seed(), 42);
+        assert!((0..5000).all(|i| compacted.test(&i)));
+        let mut unseeded = seeded.seed(0).build_counting();
+        (0..5000).for_each(|i| unseeded.insert(&i));
+        assert_ne!(
+            unseeded.compact().bit_array.words(),
+            compacted.bit_array.words()
+        );
+    }
+
+    #[test]
+    fn test_many_hashes() {
+        // Many hashes per item should still spread across the whole bit array
+        let mut bloom = BloomFilter::new(10_000, 16);
+        for i in 0..300 {
+            bloom.set(&i);
+        }
+        let false_positives = (300..10_300).filter(|i| bloom.test(i)).count();
+        assert!(false_positives < 100, "{} false positives", false_positives);
+    }
+
+    #[test]
+    fn test_custom_build_hasher() {
+        use std::collections::hash_map::RandomState;
+
+        let mut bloom = BloomFilter::with_hasher(1000, 3, RandomState::new());
+        bloom.set("foo");
+        bloom.set_bytes(b"bar");
+        assert!(bloom.test("foo"));
+        assert!(bloom.test_bytes(b"bar"));
+        assert!(!bloom.test("baz"));
+
+        let atomic = AtomicBloomFilter::with_hasher(1000, 3, RandomState::new());
+        atomic.set(&1u32);
+        assert!(atomic.test(&1u32));
+        assert!(!atomic.test(&2u32));
+
+        let thread_safe = ThreadSafeBF::with_hasher(1000, 3, RandomState::new());
+        thread_safe.set(&1u32).unwrap();
+        assert!(thread_safe.test(&1u32));
+    }
+
+    #[test]
+    fn test_custom_hash_fns() {
+        let mut bloom = BloomFilter::new(1000, 2);
+
+        let err = bloom.set_hash_fn(vec![Box::new(|bytes: &[u8]| bytes.len() as u64)]);
+        assert!(err.is_err());
+
+        bloom
+            .set_hash_fn(vec![
+                Box::new(|bytes: &[u8]| bytes.iter().map(|&b| b as u64).sum()),
+                Box::new(|bytes: &[u8]| bytes.iter().fold(7u64, |h, &b| h * 31 + b as u64)),
+            ])
+            .unwrap();
+
+        bloom.set("foo");
+        bloom.set_bytes(b"bar");
+        assert!(bloom.test("foo"));
+        assert!(bloom.test_bytes(b"bar"));
+        assert!(!bloom.test("baz"));
+
+        // "oof" has the same byte sum as "foo" but not the same polynomial hash
+        assert!(!bloom.test("oof"));
+
+        let thread_safe = ThreadSafeBF::new(1000, 1);
+        thread_safe
+            .set_hash_fn(vec![Box::new(|bytes: &[u8]| bytes[0] as u64)])
+            .unwrap();
+        thread_safe.set_bytes(b"a").unwrap();
+        assert!(thread_safe.test_bytes(b"abc"));
+        assert!(!thread_safe.test_bytes(b"bcd"));
+    }
+
     #[test]
     fn test_concurrent_reads_and_writes() {
         let bloom = Arc::new(ThreadSafeBF::new(1000, 5));
@@ -182,13 +2646,13 @@ mod tests {
         let bloom_clone5 = Arc::clone(&bloom);
 
         let writer1 = thread::spawn(move || {
-            bloom_clone1.set("concurrent_item_1");
-            bloom_clone1.set("concurrent_item_2");
+            bloom_clone1.set("concurrent_item_1").unwrap();
+            bloom_clone1.set("concurrent_item_2").unwrap();
         });
 
         let writer2 = thread::spawn(move || {
-            bloom_clone4.set("concurrent_item_3");
-            bloom_clone4.set("concurrent_item_4");
+            bloom_clone4.set("concurrent_item_3").unwrap();
+            bloom_clone4.set("concurrent_item_4").unwrap();
         });
 
         let reader1 = thread::spawn(move || {
@@ -0,0 +1,193 @@
+// Prometheus metrics for long-lived filters (feature `metrics`). `MeteredBloomFilter` wraps an
+// `AtomicBloomFilter` and counts what goes through it:
+//
+//   bloomf_inserts_total           items inserted
+//   bloomf_queries_total           items queried
+//   bloomf_query_positives_total   queries answered "maybe present"
+//   bloomf_fill_ratio              fraction of bits set
+//   bloomf_estimated_fpp           fill_ratio^k, the false positive rate right now
+//
+// all labelled with filter="<name>". The gauges are computed when the registry is scraped (a
+// popcount over the filter), not on every insert.
+//
+// Ex. filter.register(prometheus::default_registry())?
+
+use std::hash::{BuildHasher, Hash};
+use std::sync::Arc;
+
+use prometheus::core::{Collector, Desc};
+use prometheus::proto::MetricFamily;
+use prometheus::{Gauge, IntCounter, Opts, Registry};
+
+use crate::hash::Sha256BuildHasher;
+use crate::AtomicBloomFilter;
+
+pub struct MeteredBloomFilter<S = Sha256BuildHasher> {
+    filter: Arc<AtomicBloomFilter<S>>,
+    metrics: Metrics,
+}
+
+#[derive(Clone)]
+struct Metrics {
+    inserts: IntCounter,
+    queries: IntCounter,
+    positives: IntCounter,
+    fill_ratio: Gauge,
+    estimated_fpp: Gauge,
+}
+
+impl Metrics {
+    fn new(name: &str) -> prometheus::Result<Self> {
+        let opts = |metric: &str, help: &str| Opts::new(metric, help).const_label("filter", name);
+        Ok(Metrics {
+            inserts: IntCounter::with_opts(opts("bloomf_inserts_total", "Items inserted."))?,
+            queries: IntCounter::with_opts(opts("bloomf_queries_total", "Items queried."))?,
+            positives: IntCounter::with_opts(opts(
+                "bloomf_query_positives_total",
+                "Queries answered possibly present.",
+            ))?,
+            fill_ratio: Gauge::with_opts(opts("bloomf_fill_ratio", "Fraction of bits set."))?,
+            estimated_fpp: Gauge::with_opts(opts(
+                "bloomf_estimated_fpp",
+                "Current false positive probability, fill_ratio^k.",
+            ))?,
+        })
+    }
+
+    fn counters(&self) -> [&IntCounter; 3] {
+        [&self.inserts, &self.queries, &self.positives]
+    }
+}
+
+impl MeteredBloomFilter {
+    pub fn new(name: &str, size: usize, num_hashes: usize) -> prometheus::Result<Self> {
+        Self::from_filter(name, AtomicBloomFilter::new(size, num_hashes))
+    }
+}
+
+impl<S: BuildHasher> MeteredBloomFilter<S> {
+    pub fn from_filter(name: &str, filter: AtomicBloomFilter<S>) -> prometheus::Result<Self> {
+        Ok(MeteredBloomFilter {
+            filter: Arc::new(filter),
+            metrics: Metrics::new(name)?,
+        })
+    }
+
+    pub fn set<T: Hash + ?Sized>(&self, item: &T) {
+        self.metrics.inserts.inc();
+        self.filter.set(item);
+    }
+
+    // Returns true if the item was possibly present already
+    pub fn insert<T: Hash + ?Sized>(&self, item: &T) -> bool {
+        self.metrics.inserts.inc();
+        self.filter.insert(item)
+    }
+
+    pub fn insert_all<I>(&self, items: I)
+    where
+        I: IntoIterator,
+        I::Item: Hash,
+    {
+        let inserts = &self.metrics.inserts;
+        self.filter
+            .insert_all(items.into_iter().inspect(|_| inserts.inc()));
+    }
+
+    pub fn test<T: Hash + ?Sized>(&self, item: &T) -> bool {
+        let found = self.filter.test(item);
+        self.record_queries(1, found as u64);
+        found
+    }
+
+    pub fn test_many<T: Hash>(&self, items: &[T]) -> Vec<bool> {
+        let found = self.filter.test_many(items);
+        let positives = found.iter().filter(|&&found| found).count();
+        self.record_queries(items.len() as u64, positives as u64);
+        found
+    }
+
+    fn record_queries(&self, queries: u64, positives: u64) {
+        self.metrics.queries.inc_by(queries);
+        self.metrics.positives.inc_by(positives);
+    }
+
+    pub fn filter(&self) -> &AtomicBloomFilter<S> {
+        &self.filter
+    }
+}
+
+impl<S: BuildHasher + Send + Sync + 'static> MeteredBloomFilter<S> {
+    // Registers this filter's metrics, fails if a filter with the same name already is
+    pub fn register(&self, registry: &Registry) -> prometheus::Result<()> {
+        registry.register(Box::new(FilterCollector {
+            filter: self.filter.clone(),
+            metrics: self.metrics.clone(),
+        }))
+    }
+}
+
+struct FilterCollector<S> {
+    filter: Arc<AtomicBloomFilter<S>>,
+    metrics: Metrics,
+}
+
+impl<S: BuildHasher + Send + Sync> Collector for FilterCollector<S> {
+    fn desc(&self) -> Vec<&Desc> {
+        let gauges = [&self.metrics.fill_ratio, &self.metrics.estimated_fpp];
+        let counters = self.metrics.counters().into_iter().flat_map(|c| c.desc());
+        counters
+            .chain(gauges.into_iter().flat_map(|g| g.desc()))
+            .collect()
+    }
+
+    fn collect(&self) -> Vec<MetricFamily> {
+        self.metrics.fill_ratio.set(self.filter.fill_ratio());
+        self.metrics.estimated_fpp.set(self.filter.current_fpp());
+        let counters = self
+            .metrics
+            .counters()
+            .into_iter()
+            .flat_map(|c| c.collect());
+        counters
+            .chain(self.metrics.fill_ratio.collect())
+            .chain(self.metrics.estimated_fpp.collect())
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use prometheus::{Encoder, TextEncoder};
+
+    #[test]
+    fn test_metrics() {
+        let registry = Registry::new();
+        let filter = MeteredBloomFilter::new("users", 1000, 3).unwrap();
+        filter.register(&registry).unwrap();
+        // same name twice
+        let other = MeteredBloomFilter::new("users", 1000, 3).unwrap();
+        assert!(other.register(&registry).is_err());
+
+        filter.set("alice");
+        filter.insert_all(["bob", "carol"]);
+        assert!(filter.test("alice"));
+        assert_eq!(filter.test_many(&["bob", "dave"]), vec![true, false]);
+
+        let mut text = Vec::new();
+        TextEncoder::new()
+            .encode(&registry.gather(), &mut text)
+            .unwrap();
+        let text = String::from_utf8(text).unwrap();
+        assert!(text.contains("bloomf_inserts_total{filter=\"users\"} 3\n"));
+        assert!(text.contains("bloomf_queries_total{filter=\"users\"} 3\n"));
+        assert!(text.contains("bloomf_query_positives_total{filter=\"users\"} 2\n"));
+        let fill_ratio = filter.filter().fill_ratio();
+        assert!(fill_ratio > 0.0);
+        assert!(text.contains(&format!(
+            "bloomf_fill_ratio{{filter=\"users\"}} {}\n",
+            fill_ratio
+        )));
+    }
+}
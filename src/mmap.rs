@@ -0,0 +1,196 @@
+// Bloom filter answering queries directly against a memory mapped file, for filters too big to
+// load on the heap. The file uses the same format as `BloomFilter::save` (see `encoding.rs`),
+// bit i of the filter is bit i % 8 of byte 24 + i / 8 of the file (32 + i / 8 for seeded
+// filters).
+
+use std::fs::{File, OpenOptions};
+use std::hash::{BuildHasher, Hash};
+use std::io::{self, Write};
+use std::path::Path;
+
+use memmap2::{Mmap, MmapMut};
+
+use crate::bitset::word_count;
+use crate::encoding::{self, HEADER_LEN};
+use crate::hash::{self, Sha256BuildHasher};
+use crate::stats::FilterStats;
+
+enum Mapping {
+    ReadOnly(Mmap),
+    ReadWrite(MmapMut),
+}
+
+impl Mapping {
+    fn bytes(&self) -> &[u8] {
+        match self {
+            Mapping::ReadOnly(map) => map,
+            Mapping::ReadWrite(map) => map,
+        }
+    }
+}
+
+pub struct MmapBloomFilter<S = Sha256BuildHasher> {
+    map: Mapping,
+    num_hashes: usize,
+    size: usize,
+    hasher: S,
+    seed: u64,
+}
+
+impl MmapBloomFilter {
+    pub fn open(path: impl AsRef<Path>) -> io::Result<Self> {
+        Self::open_with_hasher(path, Sha256BuildHasher)
+    }
+
+    pub fn open_mut(path: impl AsRef<Path>) -> io::Result<Self> {
+        Self::open_mut_with_hasher(path, Sha256BuildHasher)
+    }
+
+    pub fn create(path: impl AsRef<Path>, size: usize, num_hashes: usize) -> io::Result<Self> {
+        Self::create_with_hasher(path, size, num_hashes, Sha256BuildHasher)
+    }
+}
+
+impl<S: BuildHasher> MmapBloomFilter<S> {
+    // Read-only mapping, `set` fails with `PermissionDenied`
+    pub fn open_with_hasher(path: impl AsRef<Path>, hasher: S) -> io::Result<Self> {
+        let file = File::open(path)?;
+        // SAFETY: the file must not be truncated by another process while it is mapped
+        let map = unsafe { Mmap::map(&file)? };
+        Self::from_mapping(Mapping::ReadOnly(map), hasher)
+    }
+
+    // Changes are written to the file, call `flush` to make sure they reached the disk
+    pub fn open_mut_with_hasher(path: impl AsRef<Path>, hasher: S) -> io::Result<Self> {
+        let file = OpenOptions::new().read(true).write(true).open(path)?;
+        // SAFETY: see `open_with_hasher`
+        let map = unsafe { MmapMut::map_mut(&file)? };
+        Self::from_mapping(Mapping::ReadWrite(map), hasher)
+    }
+
+    // Creates (or truncates) the file with an empty filter and maps it read-write
+    pub fn create_with_hasher(
+        path: impl AsRef<Path>,
+        size: usize,
+        num_hashes: usize,
+        hasher: S,
+    ) -> io::Result<Self> {
+        let path = path.as_ref();
+        let mut file = File::create(path)?;
+        file.write_all(&encoding::encode(size, num_hashes, 0, &[]))?;
+        // the bit array is a hole of zeros, sparse on most file systems
+        file.set_len((HEADER_LEN + word_count(size) * 8) as u64)?;
+        file.sync_all()?;
+        Self::open_mut_with_hasher(path, hasher)
+    }
+
+    fn from_mapping(map: Mapping, hasher: S) -> io::Result<Self> {
+        let (size, num_hashes, seed) = encoding::decode_header(map.bytes())
+            .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))?;
+        Ok(MmapBloomFilter {
+            map,
+            num_hashes,
+            size,
+            hasher,
+            seed,
+        })
+    }
+
+    pub fn set<T: Hash + ?Sized>(&mut self, item: &T) -> io::Result<()> {
+        let indices = hash::indices(&self.seeded_hasher(), item, self.num_hashes, self.size);
+        let bytes = match &mut self.map {
+            Mapping::ReadWrite(map) => &mut map[encoding::header_len(self.seed)..],
+            Mapping::ReadOnly(_) => {
+                return Err(io::Error::new(
+                    io::ErrorKind::PermissionDenied,
+                    "MmapBloomFilter is opened read-only.",
+                ))
+            }
+        };
+        for idx in indices {
+            bytes[idx / 8] |= 1 << (idx % 8);
+        }
+        Ok(())
+    }
+
+    pub fn test<T: Hash + ?Sized>(&self, item: &T) -> bool {
+        let bytes = &self.map.bytes()[encoding::header_len(self.seed)..];
+        hash::indices(&self.seeded_hasher(), item, self.num_hashes, self.size)
+            .all(|idx| bytes[idx / 8] & (1 << (idx % 8)) != 0)
+    }
+
+    pub fn flush(&self) -> io::Result<()> {
+        match &self.map {
+            Mapping::ReadWrite(map) => map.flush(),
+            Mapping::ReadOnly(_) => Ok(()),
+        }
+    }
+
+    pub fn is_writable(&self) -> bool {
+        matches!(self.map, Mapping::ReadWrite(_))
+    }
+
+    // The mapped file is not counted, the OS pages it in and out as needed
+    pub fn memory_usage(&self) -> usize {
+        size_of::<Self>()
+    }
+
+    // Reads the whole bit array, paging it in
+    pub fn stats(&self) -> FilterStats {
+        let set_bits = self.map.bytes()[encoding::header_len(self.seed)..]
+            .iter()
+            .map(|byte| byte.count_ones() as usize)
+            .sum();
+        FilterStats::new(self.size, self.num_hashes, set_bits, self.memory_usage())
+    }
+
+    pub fn size(&self) -> usize {
+        self.size
+    }
+
+    pub fn num_hashes(&self) -> usize {
+        self.num_hashes
+    }
+
+    pub fn seed(&self) -> u64 {
+        self.seed
+    }
+
+    fn seeded_hasher(&self) -> hash::Seeded<'_, S> {
+        hash::Seeded {
+            build_hasher: &self.hasher,
+            seed: self.seed,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::BloomFilter;
+
+    #[test]
+    fn test_mmap_matches_heap_filter() {
+        let path = std::env::temp_dir().join(format!("bloomf_mmap_{}.bf", std::process::id()));
+        let mut bloom = MmapBloomFilter::create(&path, 10_000, 5).unwrap();
+        for i in 0..500u32 {
+            bloom.set(&i).unwrap();
+        }
+        bloom.flush().unwrap();
+        drop(bloom);
+
+        let heap = BloomFilter::load(&path).unwrap();
+        let bloom = MmapBloomFilter::open(&path).unwrap();
+        assert!(!bloom.is_writable());
+        for i in 0..2000u32 {
+            assert_eq!(bloom.test(&i), heap.test(&i));
+        }
+        assert!((0..500u32).all(|i| bloom.test(&i)));
+        assert_eq!(bloom.stats().set_bits, heap.stats().set_bits);
+
+        let mut read_only = bloom;
+        let err = read_only.set("foo").unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::PermissionDenied);
+        std::fs::remove_file(&path).unwrap();
+    }
+}
@@ -0,0 +1,91 @@
+// Parallel construction with rayon (feature `rayon`). Every thread sets bits of the same
+// atomic bit array with `fetch_or`, so no per-thread filters have to be merged afterwards and the
+// result is the same as inserting the items one by one.
+
+use std::hash::{BuildHasher, Hash};
+use std::mem;
+
+use rayon::iter::{IntoParallelIterator, ParallelIterator};
+
+use crate::bitset::{AtomicBitVec, BitVec};
+use crate::hash;
+use crate::{AtomicBloomFilter, BloomFilter};
+
+impl BloomFilter {
+    // Ex. BloomFilter::from_par_iter(1 << 30, 7, keys.par_iter())
+    pub fn from_par_iter<I>(size: usize, num_hashes: usize, items: I) -> Self
+    where
+        I: IntoParallelIterator,
+        I::Item: Hash,
+    {
+        let mut bloom = BloomFilter::new(size, num_hashes);
+        bloom.par_insert_all(items);
+        bloom
+    }
+}
+
+impl<S: BuildHasher + Sync> BloomFilter<S> {
+    // Same as `insert_all`, spread over the rayon thread pool
+    pub fn par_insert_all<I>(&mut self, items: I)
+    where
+        I: IntoParallelIterator,
+        I::Item: Hash,
+    {
+        let words = mem::replace(&mut self.bit_array, BitVec::new(0)).into_words();
+        let bits = AtomicBitVec::from_words(words);
+        items.into_par_iter().for_each(|item| {
+            for idx in hash::probe(
+                &self.seeded_hasher(),
+                &self.hash_fns,
+                &item,
+                self.num_hashes,
+                self.size,
+            ) {
+                bits.set(idx);
+            }
+        });
+        self.bit_array = BitVec::from_words(bits.into_words());
+    }
+}
+
+impl AtomicBloomFilter {
+    pub fn from_par_iter<I>(size: usize, num_hashes: usize, items: I) -> Self
+    where
+        I: IntoParallelIterator,
+        I::Item: Hash,
+    {
+        let bloom = AtomicBloomFilter::new(size, num_hashes);
+        bloom.par_insert_all(items);
+        bloom
+    }
+}
+
+impl<S: BuildHasher + Sync> AtomicBloomFilter<S> {
+    // Can run next to other inserts and queries
+    pub fn par_insert_all<I>(&self, items: I)
+    where
+        I: IntoParallelIterator,
+        I::Item: Hash,
+    {
+        items.into_par_iter().for_each(|item| self.set(&item));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rayon::prelude::*;
+
+    #[test]
+    fn test_parallel_matches_sequential() {
+        let items: Vec<u64> = (0..50_000).collect();
+        let mut sequential = BloomFilter::new(200_000, 5);
+        sequential.insert_all(&items);
+
+        let parallel = BloomFilter::from_par_iter(200_000, 5, items.par_iter());
+        assert_eq!(parallel.to_bytes(), sequential.to_bytes());
+
+        let atomic = AtomicBloomFilter::from_par_iter(200_000, 5, 0..50_000u64);
+        assert_eq!(atomic.to_bytes(), sequential.to_bytes());
+    }
+}
@@ -0,0 +1,131 @@
+// Standard Bloom filter parameter math.
+// n = expected items, m = number of bits, k = number of hashes, p = false positive probability
+
+use std::f64::consts::LN_2;
+
+// m = -n * ln(p) / ln(2)^2
+pub fn optimal_bit_count(n: usize, p: f64) -> usize {
+    assert!(p > 0.0 && p < 1.0, "false positive rate must be in (0, 1)");
+    let n = n.max(1) as f64;
+    let m = -n * p.ln() / (LN_2 * LN_2);
+    (m.ceil() as usize).max(1)
+}
+
+// k = m / n * ln(2)
+pub fn optimal_num_hashes(m: usize, n: usize) -> usize {
+    let k = m as f64 / n.max(1) as f64 * LN_2;
+    (k.round() as usize).max(1)
+}
+
+// p = (1 - e^(-k * n / m))^k
+pub fn false_positive_rate(m: usize, n: usize, k: usize) -> f64 {
+    if m == 0 {
+        return 1.0;
+    }
+    let fill = 1.0 - (-(k as f64) * n as f64 / m as f64).exp();
+    fill.powi(k as i32)
+}
+
+// n = -m * ln(2)^2 / ln(p), i.e. how many items m bits can hold before exceeding p
+// (assuming k is chosen optimally)
+pub fn capacity(m: usize, p: f64) -> usize {
+    assert!(p > 0.0 && p < 1.0, "false positive rate must be in (0, 1)");
+    (-(m as f64) * LN_2 * LN_2 / p.ln()).floor() as usize
+}
+
+// Bits per item for a target p with the optimal k: -ln(p) / ln(2)^2
+pub fn bits_per_item(p: f64) -> f64 {
+    assert!(p > 0.0 && p < 1.0, "false positive rate must be in (0, 1)");
+    -p.ln() / (LN_2 * LN_2)
+}
+
+// n ~ -m / k * ln(1 - X / m) (Swamidass & Baldi 2007), the number of distinct items that
+// most likely set X of the m bits. Infinite once every bit is set.
+pub fn estimated_items(m: usize, k: usize, set_bits: usize) -> f64 {
+    if m == 0 || k == 0 {
+        return 0.0;
+    }
+    let m = m as f64;
+    -m / k as f64 * (-(set_bits as f64) / m).ln_1p()
+}
+
+// (m, k) for a filter that is sent compressed (see `BloomFilter::compress`): the pair with
+// false positive rate p that compresses smallest, with m at most max_bits_per_item * n.
+// Fewer hashes over more bits leave the filter sparser, its entropy per item drops below the
+// 1.44 * log2(1 / p) bits of the optimal filter (Mitzenmacher 2002), at the cost of memory.
+pub fn compressed_params(n: usize, p: f64, max_bits_per_item: f64) -> (usize, usize) {
+    assert!(p > 0.0 && p < 1.0, "false positive rate must be in (0, 1)");
+    let optimal_m = optimal_bit_count(n, p);
+    let optimal_k = optimal_num_hashes(optimal_m, n);
+    let n = n.max(1) as f64;
+    let mut best = (optimal_m, optimal_k);
+    let mut best_bits = optimal_m as f64 * entropy(0.5);
+    for k in 1..optimal_k {
+        // fill^k = p with fill = 1 - e^(-k * n / m)
+        let fill = p.powf(1.0 / k as f64);
+        let m = (-(k as f64) * n / (-fill).ln_1p()).ceil();
+        if m > max_bits_per_item * n {
+            continue;
+        }
+        let bits = m * entropy(fill);
+        if bits < best_bits {
+            best = (m as usize, k);
+            best_bits = bits;
+        }
+    }
+    best
+}
+
+// Bits of information per bit of a filter with the given fill ratio
+pub(crate) fn entropy(fill: f64) -> f64 {
+    if fill <= 0.0 || fill >= 1.0 {
+        return 0.0;
+    }
+    -fill * fill.log2() - (1.0 - fill) * (1.0 - fill).log2()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_optimal_parameters() {
+        let m = optimal_bit_count(1_000_000, 0.01);
+        assert_eq!(m, 9_585_059);
+        assert_eq!(optimal_num_hashes(m, 1_000_000), 7);
+
+        let p = false_positive_rate(m, 1_000_000, 7);
+        assert!((p - 0.01).abs() < 0.0005, "p = {}", p);
+
+        let n = capacity(m, 0.01);
+        assert!((999_999..=1_000_000).contains(&n), "n = {}", n);
+        assert!((bits_per_item(0.01) - 9.585).abs() < 0.001);
+    }
+
+    #[test]
+    fn test_compressed_params() {
+        // with no room to grow it's the optimal filter
+        assert_eq!(compressed_params(1000, 0.01, 9.6), (9586, 7));
+        let (m, k) = compressed_params(1000, 0.01, 16.0);
+        assert_eq!(k, 3);
+        assert!(m <= 16_000 && m > 9586);
+        assert!((false_positive_rate(m, 1000, k) - 0.01).abs() < 0.001);
+        let fill = 1.0 - (-(k as f64) * 1000.0 / m as f64).exp();
+        assert!(m as f64 * entropy(fill) < 9586.0 * 0.98);
+    }
+
+    #[test]
+    fn test_false_positive_rate_edges() {
+        assert_eq!(false_positive_rate(1000, 0, 3), 0.0);
+        assert_eq!(false_positive_rate(0, 10, 3), 1.0);
+        assert!(false_positive_rate(100, 10_000, 3) > 0.99);
+    }
+
+    #[test]
+    fn test_estimated_items() {
+        assert_eq!(estimated_items(1000, 3, 0), 0.0);
+        assert!(estimated_items(1000, 3, 1000).is_infinite());
+        // 100 items with k = 3 set about 1000 * (1 - e^(-0.3)) = 259 bits
+        assert!((estimated_items(1000, 3, 259) - 100.0).abs() < 1.0);
+    }
+}
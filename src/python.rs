@@ -0,0 +1,180 @@
+// Python bindings (feature `python`), built with maturin (see pyproject.toml):
+//
+//     import bloomf
+//     bloom = bloomf.BloomFilter(1_000_000, 7)
+//     bloom.insert_many(["foo", "bar"])
+//     open("users.bf", "wb").write(bloom.to_bytes())
+//
+// `to_bytes` is the `BloomFilter::to_bytes` encoding, so filters built in Python load in Rust
+// with `BloomFilter::from_bytes` and answer the same: `str` items are hashed like a Rust `&str`,
+// `bytes` items like `set_bytes`. The batch methods convert the items first and release the
+// GIL while hashing.
+
+use pyo3::exceptions::PyValueError;
+use pyo3::prelude::*;
+use pyo3::types::PyBytes;
+
+use crate::{BloomError, BloomFilter, CountingBloomFilter};
+
+#[derive(FromPyObject)]
+enum Item {
+    Str(String),
+    Bytes(Vec<u8>),
+}
+
+fn to_py_error(err: BloomError) -> PyErr {
+    PyValueError::new_err(err.to_string())
+}
+
+#[pyclass(name = "BloomFilter")]
+pub struct PyBloomFilter {
+    inner: BloomFilter,
+}
+
+impl PyBloomFilter {
+    fn set(&mut self, item: &Item) {
+        match item {
+            Item::Str(item) => self.inner.set(item.as_str()),
+            Item::Bytes(bytes) => self.inner.set_bytes(bytes),
+        }
+    }
+
+    fn test(&self, item: &Item) -> bool {
+        match item {
+            Item::Str(item) => self.inner.test(item.as_str()),
+            Item::Bytes(bytes) => self.inner.test_bytes(bytes),
+        }
+    }
+}
+
+#[pymethods]
+impl PyBloomFilter {
+    #[new]
+    fn new(size: usize, num_hashes: usize) -> PyResult<Self> {
+        let inner = BloomFilter::try_new(size, num_hashes).map_err(to_py_error)?;
+        Ok(PyBloomFilter { inner })
+    }
+
+    fn insert(&mut self, item: Item) {
+        self.set(&item);
+    }
+
+    fn contains(&self, item: Item) -> bool {
+        self.test(&item)
+    }
+
+    fn __contains__(&self, item: Item) -> bool {
+        self.test(&item)
+    }
+
+    fn insert_many(&mut self, py: Python<'_>, items: Vec<Item>) {
+        py.allow_threads(|| {
+            for item in &items {
+                self.set(item);
+            }
+        });
+    }
+
+    fn contains_many(&self, py: Python<'_>, items: Vec<Item>) -> Vec<bool> {
+        py.allow_threads(|| items.iter().map(|item| self.test(item)).collect())
+    }
+
+    fn to_bytes<'py>(&self, py: Python<'py>) -> Bound<'py, PyBytes> {
+        PyBytes::new(py, &self.inner.to_bytes())
+    }
+
+    #[staticmethod]
+    fn from_bytes(bytes: &[u8]) -> PyResult<Self> {
+        let inner = BloomFilter::from_bytes(bytes).map_err(to_py_error)?;
+        Ok(PyBloomFilter { inner })
+    }
+}
+
+#[pyclass(name = "CountingBloomFilter")]
+pub struct PyCountingBloomFilter {
+    inner: CountingBloomFilter,
+}
+
+#[pymethods]
+impl PyCountingBloomFilter {
+    #[new]
+    fn new(size: usize, num_hashes: usize) -> PyResult<Self> {
+        if size == 0 || num_hashes == 0 {
+            return Err(PyValueError::new_err(
+                "Size and number of hashes must not be 0.",
+            ));
+        }
+        Ok(PyCountingBloomFilter {
+            inner: CountingBloomFilter::new(size, num_hashes),
+        })
+    }
+
+    fn insert(&mut self, item: &str) {
+        self.inner.insert(item);
+    }
+
+    fn remove(&mut self, item: &str) -> bool {
+        self.inner.remove(item)
+    }
+
+    fn contains(&self, item: &str) -> bool {
+        self.inner.contains(item)
+    }
+
+    fn __contains__(&self, item: &str) -> bool {
+        self.inner.contains(item)
+    }
+
+    fn insert_many(&mut self, py: Python<'_>, items: Vec<String>) {
+        py.allow_threads(|| {
+            for item in &items {
+                self.inner.insert(item.as_str());
+            }
+        });
+    }
+
+    fn contains_many(&self, py: Python<'_>, items: Vec<String>) -> Vec<bool> {
+        py.allow_threads(|| {
+            items
+                .iter()
+                .map(|item| self.inner.contains(item.as_str()))
+                .collect()
+        })
+    }
+}
+
+#[pymodule]
+fn bloomf(module: &Bound<'_, PyModule>) -> PyResult<()> {
+    module.add_class::<PyBloomFilter>()?;
+    module.add_class::<PyCountingBloomFilter>()?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pyo3::types::PyList;
+
+    #[test]
+    fn test_python_filter_matches_rust() {
+        pyo3::prepare_freethreaded_python();
+        Python::with_gil(|py| {
+            let mut bloom = PyBloomFilter::new(1000, 3).unwrap();
+            let items = PyList::new(py, ["foo", "bar"]).unwrap();
+            bloom.insert_many(py, items.extract().unwrap());
+            bloom.insert(Item::Bytes(b"baz".to_vec()));
+            assert_eq!(
+                bloom.contains_many(py, vec![Item::Str("foo".into()), Item::Str("qux".into())]),
+                vec![true, false]
+            );
+
+            let mut rust = BloomFilter::new(1000, 3);
+            rust.set("foo");
+            rust.set("bar");
+            rust.set_bytes(b"baz");
+            let bytes = bloom.to_bytes(py);
+            assert_eq!(bytes.as_bytes(), rust.to_bytes().as_slice());
+            assert!(PyBloomFilter::from_bytes(&bytes.as_bytes()[1..]).is_err());
+        });
+    }
+}
@@ -0,0 +1,529 @@
+// Quotient filter (Bender et al. 2012): every item is reduced to a p = q + r bit fingerprint,
+// the high q bits (quotient) pick the canonical slot and the low r bits (remainder) are stored.
+// Remainders of the same quotient are kept sorted in a run of consecutive slots, and three
+// metadata bits per slot are enough to recover the quotient of every stored remainder:
+// - occupied: some fingerprint has this slot as its canonical slot
+// - continuation: this slot holds a remainder of the same run as the previous slot
+// - shifted: the remainder in this slot is not in its canonical slot
+// Since full fingerprints can be recovered, filters can be merged and resized without the keys.
+
+use std::hash::{BuildHasher, Hash};
+
+use crate::error::{BloomError, MergeError};
+use crate::hash::{self, Sha256BuildHasher};
+use crate::heap_bytes;
+
+const OCCUPIED: u64 = 1;
+const CONTINUATION: u64 = 2;
+const SHIFTED: u64 = 4;
+const METADATA: u64 = 7;
+
+fn is_occupied(slot: u64) -> bool {
+    slot & OCCUPIED != 0
+}
+
+fn is_continuation(slot: u64) -> bool {
+    slot & CONTINUATION != 0
+}
+
+fn is_shifted(slot: u64) -> bool {
+    slot & SHIFTED != 0
+}
+
+fn is_empty(slot: u64) -> bool {
+    slot & METADATA == 0
+}
+
+fn is_cluster_start(slot: u64) -> bool {
+    is_occupied(slot) && !is_continuation(slot) && !is_shifted(slot)
+}
+
+fn is_run_start(slot: u64) -> bool {
+    !is_continuation(slot) && (is_occupied(slot) || is_shifted(slot))
+}
+
+fn remainder(slot: u64) -> u64 {
+    slot >> 3
+}
+
+pub struct QuotientFilter<S = Sha256BuildHasher> {
+    // (remainder << 3) | metadata bits
+    slots: Vec<u64>,
+    qbits: u32,
+    rbits: u32,
+    len: usize,
+    hasher: S,
+}
+
+impl QuotientFilter {
+    // 2^qbits slots, each holding an rbits remainder. qbits + rbits must be at most 64.
+    // The false positive rate is about len / 2^(qbits + rbits).
+    pub fn new(qbits: u32, rbits: u32) -> Self {
+        Self::with_hasher(qbits, rbits, Sha256BuildHasher)
+    }
+}
+
+impl<S: BuildHasher> QuotientFilter<S> {
+    pub fn with_hasher(qbits: u32, rbits: u32, hasher: S) -> Self {
+        assert!(qbits > 0 && rbits > 0, "qbits and rbits must not be 0");
+        assert!(qbits + rbits <= 64, "qbits + rbits must be at most 64");
+        assert!(qbits < usize::BITS, "qbits too large");
+        QuotientFilter {
+            slots: vec![0; 1 << qbits],
+            qbits,
+            rbits,
+            len: 0,
+            hasher,
+        }
+    }
+
+    fn fingerprint<T: Hash + ?Sized>(&self, item: &T) -> u64 {
+        let hash = self.hasher.hash_one(item);
+        match self.qbits + self.rbits {
+            64 => hash,
+            bits => hash & ((1 << bits) - 1),
+        }
+    }
+
+    fn split(&self, fingerprint: u64) -> (usize, u64) {
+        let quotient = (fingerprint >> self.rbits) as usize;
+        let remainder = fingerprint & ((1 << self.rbits) - 1);
+        (quotient, remainder)
+    }
+
+    fn incr(&self, idx: usize) -> usize {
+        (idx + 1) & (self.slots.len() - 1)
+    }
+
+    fn decr(&self, idx: usize) -> usize {
+        idx.wrapping_sub(1) & (self.slots.len() - 1)
+    }
+
+    // Slot where the run of `quotient` starts (or would start)
+    fn find_run_index(&self, quotient: usize) -> usize {
+        // Walk back to the start of the cluster
+        let mut b = quotient;
+        while is_shifted(self.slots[b]) {
+            b = self.decr(b);
+        }
+        // Then forward, one run per occupied canonical slot, until reaching `quotient`
+        let mut s = b;
+        while b != quotient {
+            loop {
+                s = self.incr(s);
+                if !is_continuation(self.slots[s]) {
+                    break;
+                }
+            }
+            loop {
+                b = self.incr(b);
+                if is_occupied(self.slots[b]) {
+                    break;
+                }
+            }
+        }
+        s
+    }
+
+    // Put `slot` at `s` and shift everything right up to the next empty slot.
+    // The occupied bits belong to the positions, not to the remainders, so they stay in place.
+    fn insert_into(&mut self, mut s: usize, slot: u64) {
+        let mut curr = slot;
+        loop {
+            let mut prev = self.slots[s];
+            let empty = is_empty(prev);
+            if !empty {
+                prev |= SHIFTED;
+                if is_occupied(prev) {
+                    curr |= OCCUPIED;
+                    prev &= !OCCUPIED;
+                }
+            }
+            self.slots[s] = curr;
+            curr = prev;
+            s = self.incr(s);
+            if empty {
+                break;
+            }
+        }
+    }
+
+    fn insert_fingerprint(&mut self, fingerprint: u64) -> Result<(), BloomError> {
+        let (fq, fr) = self.split(fingerprint);
+        let canonical = self.slots[fq];
+        let mut entry = fr << 3;
+
+        if is_empty(canonical) {
+            if self.len >= self.slots.len() {
+                return Err(BloomError::CapacityExceeded);
+            }
+            self.slots[fq] = entry | OCCUPIED;
+            self.len += 1;
+            return Ok(());
+        }
+
+        let full = self.len >= self.slots.len();
+        if !is_occupied(canonical) {
+            if full {
+                return Err(BloomError::CapacityExceeded);
+            }
+            // Needs to be set before looking for the run, runs are counted by occupied slots
+            self.slots[fq] |= OCCUPIED;
+        }
+
+        let start = self.find_run_index(fq);
+        let mut s = start;
+        if is_occupied(canonical) {
+            // Find the sorted position of the remainder in the run
+            loop {
+                let rem = remainder(self.slots[s]);
+                if rem == fr {
+                    return Ok(());
+                } else if rem > fr {
+                    break;
+                }
+                s = self.incr(s);
+                if !is_continuation(self.slots[s]) {
+                    break;
+                }
+            }
+            if full {
+                return Err(BloomError::CapacityExceeded);
+            }
+            if s == start {
+                // The old start of the run becomes a continuation
+                self.slots[start] |= CONTINUATION;
+            } else {
+                entry |= CONTINUATION;
+            }
+        }
+        if s != fq {
+            entry |= SHIFTED;
+        }
+        self.insert_into(s, entry);
+        self.len += 1;
+        Ok(())
+    }
+
+    fn contains_fingerprint(&self, fingerprint: u64) -> bool {
+        let (fq, fr) = self.split(fingerprint);
+        if !is_occupied(self.slots[fq]) {
+            return false;
+        }
+        let mut s = self.find_run_index(fq);
+        loop {
+            let rem = remainder(self.slots[s]);
+            if rem == fr {
+                return true;
+            } else if rem > fr {
+                return false;
+            }
+            s = self.incr(s);
+            if !is_continuation(self.slots[s]) {
+                return false;
+            }
+        }
+    }
+
+    // Remove the slot at `s` and shift the rest of the cluster left,
+    // fixing the shifted bits of remainders that land in their canonical slot.
+    fn delete_entry(&mut self, mut s: usize, mut quotient: usize) {
+        let orig = s;
+        let mut curr = self.slots[s];
+        let mut sp = self.incr(s);
+        loop {
+            let next = self.slots[sp];
+            let curr_occupied = is_occupied(curr);
+            if is_empty(next) || is_cluster_start(next) || sp == orig {
+                self.slots[s] = 0;
+                return;
+            }
+            let mut updated_next = next;
+            if is_run_start(next) {
+                loop {
+                    quotient = self.incr(quotient);
+                    if is_occupied(self.slots[quotient]) {
+                        break;
+                    }
+                }
+                if curr_occupied && quotient == s {
+                    updated_next &= !SHIFTED;
+                }
+            }
+            self.slots[s] = if curr_occupied {
+                updated_next | OCCUPIED
+            } else {
+                updated_next & !OCCUPIED
+            };
+            s = sp;
+            sp = self.incr(sp);
+            curr = next;
+        }
+    }
+
+    fn remove_fingerprint(&mut self, fingerprint: u64) -> bool {
+        let (fq, fr) = self.split(fingerprint);
+        if !is_occupied(self.slots[fq]) || self.len == 0 {
+            return false;
+        }
+        let start = self.find_run_index(fq);
+        let mut s = start;
+        loop {
+            let rem = remainder(self.slots[s]);
+            if rem == fr {
+                break;
+            } else if rem > fr {
+                return false;
+            }
+            s = self.incr(s);
+            if !is_continuation(self.slots[s]) {
+                return false;
+            }
+        }
+
+        let kill = self.slots[s];
+        let replace_run_start = is_run_start(kill);
+        // Deleting the only remainder of the run: the canonical slot isn't occupied anymore
+        if replace_run_start && !is_continuation(self.slots[self.incr(s)]) {
+            self.slots[fq] &= !OCCUPIED;
+        }
+        self.delete_entry(s, fq);
+        if replace_run_start {
+            let next = self.slots[s];
+            let mut updated_next = next;
+            // The new start of the run is no longer a continuation
+            updated_next &= !CONTINUATION;
+            if s == fq && is_run_start(updated_next) {
+                // and it's back in its canonical slot
+                updated_next &= !SHIFTED;
+            }
+            self.slots[s] = updated_next;
+        }
+        self.len -= 1;
+        true
+    }
+
+    // Fails once all 2^qbits slots are used. Inserting an item with a fingerprint that is
+    // already stored is a no-op (the filter is a set of fingerprints).
+    pub fn insert<T: Hash + ?Sized>(&mut self, item: &T) -> Result<(), BloomError> {
+        let fingerprint = self.fingerprint(item);
+        self.insert_fingerprint(fingerprint)
+    }
+
+    pub fn contains<T: Hash + ?Sized>(&self, item: &T) -> bool {
+        self.contains_fingerprint(self.fingerprint(item))
+    }
+
+    // Removes the fingerprint of the item, which also removes any other item sharing it.
+    pub fn remove<T: Hash + ?Sized>(&mut self, item: &T) -> bool {
+        let fingerprint = self.fingerprint(item);
+        self.remove_fingerprint(fingerprint)
+    }
+
+    // Stored fingerprints in increasing order
+    pub fn fingerprints(&self) -> std::vec::IntoIter<u64> {
+        let mut fingerprints: Vec<u64> = self.table_order().collect();
+        // Walking the table from a cluster start gives the sorted fingerprints rotated by
+        // the cluster that wraps around the end of the table
+        if let Some(wrap) = fingerprints.windows(2).position(|w| w[0] > w[1]) {
+            fingerprints.rotate_left(wrap + 1);
+        }
+        fingerprints.into_iter()
+    }
+
+    // Stored fingerprints in slot order, starting at a cluster start so the quotient
+    // of every slot can be tracked
+    fn table_order(&self) -> TableOrder<'_, S> {
+        let start = (0..self.slots.len())
+            .find(|&i| is_cluster_start(self.slots[i]))
+            .unwrap_or(0);
+        TableOrder {
+            filter: self,
+            index: start,
+            quotient: start,
+            visited: 0,
+        }
+    }
+
+    // Same filter with twice the slots: one bit moves from the remainder to the quotient,
+    // fingerprints (and so the false positive rate for a given len) stay the same.
+    // Fails with `CapacityExceeded` once the remainders are down to one bit.
+    pub fn resize(&self) -> Result<QuotientFilter<S>, BloomError>
+    where
+        S: Clone,
+    {
+        if self.rbits == 1 {
+            return Err(BloomError::CapacityExceeded);
+        }
+        let mut resized =
+            QuotientFilter::with_hasher(self.qbits + 1, self.rbits - 1, self.hasher.clone());
+        for fingerprint in self.fingerprints() {
+            resized.insert_fingerprint(fingerprint)?;
+        }
+        Ok(resized)
+    }
+
+    // Union of two filters with the same fingerprint size, sized to hold both
+    pub fn merge(&self, other: &QuotientFilter<S>) -> Result<QuotientFilter<S>, MergeError>
+    where
+        S: Clone,
+    {
+        let (left, right) = (self.params_fingerprint(), other.params_fingerprint());
+        if left != right {
+            return Err(MergeError::FingerprintMismatch { left, right });
+        }
+        let bits = self.qbits + self.rbits;
+        let mut qbits = self.qbits.max(other.qbits);
+        while (1usize << qbits) < self.len + other.len {
+            qbits += 1;
+        }
+        if qbits >= bits {
+            return Err(MergeError::CapacityExceeded);
+        }
+        let mut merged = QuotientFilter::with_hasher(qbits, bits - qbits, self.hasher.clone());
+        for fingerprint in self.fingerprints().chain(other.fingerprints()) {
+            merged
+                .insert_fingerprint(fingerprint)
+                .map_err(|_| MergeError::CapacityExceeded)?;
+        }
+        Ok(merged)
+    }
+
+    // Hash of the fingerprint size (q + r), hasher and layout version. Filters can be merged
+    // when it's equal, the number of slots doesn't need to match.
+    pub fn params_fingerprint(&self) -> u64 {
+        hash::params_fingerprint(&self.hasher, &[(self.qbits + self.rbits) as u64])
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.slots.len()
+    }
+
+    pub fn memory_usage(&self) -> usize {
+        size_of::<Self>() + heap_bytes(&self.slots)
+    }
+
+    pub fn clear(&mut self) {
+        self.slots.fill(0);
+        self.len = 0;
+    }
+}
+
+struct TableOrder<'a, S> {
+    filter: &'a QuotientFilter<S>,
+    index: usize,
+    quotient: usize,
+    visited: usize,
+}
+
+impl<S: BuildHasher> Iterator for TableOrder<'_, S> {
+    type Item = u64;
+
+    fn next(&mut self) -> Option<u64> {
+        let filter = self.filter;
+        while self.visited < filter.len {
+            let slot = filter.slots[self.index];
+            if is_cluster_start(slot) {
+                self.quotient = self.index;
+            } else if is_run_start(slot) {
+                // Next run belongs to the next occupied canonical slot
+                loop {
+                    self.quotient = filter.incr(self.quotient);
+                    if is_occupied(filter.slots[self.quotient]) {
+                        break;
+                    }
+                }
+            }
+            self.index = filter.incr(self.index);
+            if !is_empty(slot) {
+                self.visited += 1;
+                return Some(((self.quotient as u64) << filter.rbits) | remainder(slot));
+            }
+        }
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::rng::Rng;
+    use std::collections::BTreeSet;
+
+    // Random inserts and removes of raw fingerprints, checked against a set
+    #[test]
+    fn test_matches_set_of_fingerprints() {
+        let mut qf = QuotientFilter::new(6, 4);
+        let mut expected = BTreeSet::new();
+        let mut rng = Rng::new(1);
+        for _ in 0..20_000 {
+            let fingerprint = rng.below(1 << 10) as u64;
+            if rng.below(3) == 0 {
+                assert_eq!(
+                    qf.remove_fingerprint(fingerprint),
+                    expected.remove(&fingerprint)
+                );
+            } else if expected.len() < 60 || expected.contains(&fingerprint) {
+                qf.insert_fingerprint(fingerprint).unwrap();
+                expected.insert(fingerprint);
+            }
+            assert_eq!(qf.len(), expected.len());
+        }
+        for fingerprint in 0..(1 << 10) {
+            assert_eq!(
+                qf.contains_fingerprint(fingerprint),
+                expected.contains(&fingerprint)
+            );
+        }
+        assert_eq!(
+            qf.fingerprints().collect::<Vec<_>>(),
+            expected.iter().copied().collect::<Vec<_>>()
+        );
+    }
+
+    #[test]
+    fn test_items_resize_and_merge() {
+        let mut a = QuotientFilter::new(8, 16);
+        let mut b = QuotientFilter::new(8, 16);
+        for i in 0..200 {
+            a.insert(&i).unwrap();
+            b.insert(&(i + 1000)).unwrap();
+        }
+        assert!(a.contains(&5));
+        assert!(!a.contains(&1005));
+        assert!(a.remove(&5));
+        assert!(!a.contains(&5));
+
+        let a = a.resize().unwrap();
+        assert_eq!(a.capacity(), 512);
+        assert_eq!(a.len(), 199);
+        assert!(a.contains(&6));
+
+        let merged = a.merge(&b).unwrap();
+        assert_eq!(merged.len(), 399);
+        for i in 6..200 {
+            assert!(merged.contains(&i));
+            assert!(merged.contains(&(i + 1000)));
+        }
+    }
+
+    #[test]
+    fn test_full() {
+        let mut qf = QuotientFilter::new(2, 8);
+        for i in 0..4u64 {
+            qf.insert_fingerprint(i).unwrap();
+        }
+        assert!(qf.insert_fingerprint(10).is_err());
+        assert!(qf.insert_fingerprint(3).is_ok());
+        qf.clear();
+        assert!(qf.is_empty());
+    }
+}
@@ -0,0 +1,160 @@
+// Set reconciliation with IBLTs (Eppstein et al. 2011): both peers put their keys into a
+// `SetSketch` sized for the difference they expect, not for their sets, send it over, and
+// `diff` subtracts the two. The keys both have cancel out, what's left lists the keys only
+// one side has, so the bytes sent grow with the symmetric difference only:
+//
+//   let sketch = SetSketch::from_keys(local_keys, 1000, 32);
+//   send(sketch.to_bytes());
+//   let remote = SetSketch::from_bytes(&received)?;
+//   let diff = sketch.diff(&remote)?; // Err(CapacityExceeded) -> retry with a larger sketch
+//
+// Keys have a fixed size, variable length keys can be sent as their 32 byte digests and
+// looked up in a digest -> key map on each side.
+
+use std::hash::BuildHasher;
+
+use crate::error::BloomError;
+use crate::hash::Sha256BuildHasher;
+use crate::iblt::Iblt;
+
+const NUM_HASHES: usize = 3;
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SetSketch<S = Sha256BuildHasher> {
+    iblt: Iblt<S>,
+}
+
+// Result of `SetSketch::diff`
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct SetDiff {
+    // keys the remote peer is missing
+    pub local_only: Vec<Vec<u8>>,
+    // keys missing here
+    pub remote_only: Vec<Vec<u8>>,
+}
+
+impl SetDiff {
+    pub fn is_empty(&self) -> bool {
+        self.local_only.is_empty() && self.remote_only.is_empty()
+    }
+
+    // Size of the symmetric difference
+    pub fn len(&self) -> usize {
+        self.local_only.len() + self.remote_only.len()
+    }
+}
+
+impl SetSketch {
+    pub fn new(max_diff: usize, key_size: usize) -> Self {
+        Self::with_hasher(max_diff, key_size, Sha256BuildHasher)
+    }
+
+    pub fn from_keys<I>(keys: I, max_diff: usize, key_size: usize) -> Self
+    where
+        I: IntoIterator,
+        I::Item: AsRef<[u8]>,
+    {
+        let mut sketch = Self::new(max_diff, key_size);
+        for key in keys {
+            sketch.insert(key.as_ref());
+        }
+        sketch
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, BloomError> {
+        Self::from_bytes_with_hasher(bytes, Sha256BuildHasher)
+    }
+}
+
+impl<S: BuildHasher + Clone> SetSketch<S> {
+    // Sketch that decodes differences of up to max_diff keys: two cells per key, plus some
+    // slack since small tables fail to peel more often. Both peers must use the same max_diff.
+    pub fn with_hasher(max_diff: usize, key_size: usize, hasher: S) -> Self {
+        SetSketch {
+            iblt: Iblt::with_hasher(2 * max_diff + 32, NUM_HASHES, key_size, 0, hasher),
+        }
+    }
+
+    // Panics if the key has the wrong size
+    pub fn insert(&mut self, key: &[u8]) {
+        self.iblt.insert(key, &[]);
+    }
+
+    pub fn remove(&mut self, key: &[u8]) {
+        self.iblt.delete(key, &[]);
+    }
+
+    // Keys only this side or only the remote side has. `IncompatibleParams` for sketches of
+    // another size or hasher, `CapacityExceeded` when the difference is too large for them.
+    pub fn diff(&self, remote: &SetSketch<S>) -> Result<SetDiff, BloomError> {
+        let difference = self
+            .iblt
+            .subtract(&remote.iblt)
+            .map_err(BloomError::IncompatibleParams)?;
+        let entries = difference.list_entries()?;
+        Ok(SetDiff {
+            local_only: entries.inserted.into_iter().map(|(key, _)| key).collect(),
+            remote_only: entries.deleted.into_iter().map(|(key, _)| key).collect(),
+        })
+    }
+
+    pub fn key_size(&self) -> usize {
+        self.iblt.key_size()
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        self.iblt.to_bytes()
+    }
+
+    pub fn from_bytes_with_hasher(bytes: &[u8], hasher: S) -> Result<Self, BloomError> {
+        let iblt = Iblt::from_bytes_with_hasher(bytes, hasher)?;
+        if iblt.value_size() != 0 {
+            return Err(BloomError::SerializationError(
+                "Encoded IBLT has values, not a set sketch.".into(),
+            ));
+        }
+        Ok(SetSketch { iblt })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn key(i: u32) -> [u8; 8] {
+        (i as u64).to_le_bytes()
+    }
+
+    #[test]
+    fn test_diff() {
+        // 10k keys each, 40 only here and 60 only on the remote side
+        let local = SetSketch::from_keys((0..10_040).map(key), 200, 8);
+        let remote = SetSketch::from_keys((40..10_100).map(key), 200, 8);
+        let received = SetSketch::from_bytes(&remote.to_bytes()).unwrap();
+        // bandwidth depends on max_diff, not on the 10k keys
+        assert!(remote.to_bytes().len() < 432 * 24 + 100);
+
+        let mut diff = local.diff(&received).unwrap();
+        diff.local_only.sort();
+        diff.remote_only.sort();
+        assert_eq!(
+            diff.local_only,
+            (0..40).map(|i| key(i).to_vec()).collect::<Vec<_>>()
+        );
+        assert_eq!(
+            diff.remote_only,
+            (10_040..10_100)
+                .map(|i| key(i).to_vec())
+                .collect::<Vec<_>>()
+        );
+        assert_eq!(diff.len(), 100);
+        assert!(local.diff(&local).unwrap().is_empty());
+
+        let far = SetSketch::from_keys((5000..20_000).map(key), 200, 8);
+        assert_eq!(local.diff(&far), Err(BloomError::CapacityExceeded));
+        assert!(matches!(
+            local.diff(&SetSketch::new(100, 8)),
+            Err(BloomError::IncompatibleParams(_))
+        ));
+    }
+}
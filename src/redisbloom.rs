@@ -0,0 +1,544 @@
+// Interop with RedisBloom scalable filters (the `BF.*` commands).
+//
+// `BF.SCANDUMP key iter` returns (iter, data) pairs: the first call (iter 0) returns the header
+// with iter 1, the following ones return raw chunks of the bit arrays, and a (0, "") pair marks
+// the end. `BF.LOADCHUNK key iter data` takes the same pairs back.
+//
+// Header, packed and little-endian:
+//   u64 total items, u32 number of filters, u32 options, u32 growth
+//   then per filter: u64 bytes, u64 bits, u64 items, f64 error, f64 bits per entry,
+//                    u32 hashes, u64 capacity, u8 log2(bits) (0 if bits is not a power of two)
+// Data chunks hold the bit arrays of the filters back to back, a chunk returned with iter `i`
+// and length `n` starts at byte i - n - 1. Bit x is bit x % 8 of byte x / 8.
+//
+// Items are hashed with MurmurHash64A: a = murmur(item, 0xc6a4a7935bd1e995), b = murmur(item, a),
+// bit i = (a + i * b) % bits (or % 2^log2 when set). Only the 64 bit hashing of RedisBloom 2.0+
+// is supported, older filters hashed with 32 bit murmur are rejected.
+//
+// `reserve` sizes filters like `BF.RESERVE` (without power of two rounding): m = capacity * bpe
+// rounded up to a multiple of 64 with bpe = -ln(error) / ln(2)^2 and k = ceil(ln(2) * bpe).
+// Once the newest filter holds `capacity` items a new one is added with `expansion` times the
+// capacity and half the error rate, unless the chain is non-scaling. No filter of the chain gets
+// more than `MAX_LINK_BITS` bits: reserving a bigger one fails, and a chain whose next filter
+// would be bigger stops growing (the newest filter keeps taking items).
+
+use crate::error::BloomError;
+use crate::heap_bytes;
+use crate::stats::FilterStats;
+
+const HEADER_LEN: usize = 20;
+const LINK_LEN: usize = 53;
+const OPT_NOROUND: u32 = 1;
+const OPT_FORCE64: u32 = 4;
+const OPT_NO_SCALING: u32 = 8;
+const HASH_SEED: u64 = 0xc6a4_a793_5bd1_e995;
+// 512 MiB, the largest Redis string
+pub const MAX_LINK_BITS: u64 = 1 << 32;
+
+#[derive(Clone, Debug, PartialEq)]
+struct Link {
+    bits: u64,
+    size: u64,
+    error: f64,
+    bpe: f64,
+    hashes: u32,
+    entries: u64,
+    n2: u8,
+    bytes: Vec<u8>,
+}
+
+impl Link {
+    fn new(entries: u64, error: f64) -> Result<Self, BloomError> {
+        let bpe = -error.ln() / (std::f64::consts::LN_2 * std::f64::consts::LN_2);
+        if entries as f64 * bpe > MAX_LINK_BITS as f64 {
+            return Err(BloomError::InvalidParams(format!(
+                "{} items at error rate {} need more than {} bits.",
+                entries, error, MAX_LINK_BITS
+            )));
+        }
+        let bits = ((entries as f64 * bpe) as u64).max(1).next_multiple_of(64);
+        Ok(Link {
+            bits,
+            size: 0,
+            error,
+            bpe,
+            hashes: (std::f64::consts::LN_2 * bpe).ceil() as u32,
+            entries,
+            n2: 0,
+            bytes: vec![0; (bits / 8) as usize],
+        })
+    }
+
+    fn probe(&self, a: u64, b: u64) -> impl Iterator<Item = u64> + '_ {
+        let modulus = if self.n2 > 0 { 1 << self.n2 } else { self.bits };
+        (0..self.hashes as u64).map(move |i| a.wrapping_add(i.wrapping_mul(b)) % modulus)
+    }
+
+    fn contains(&self, a: u64, b: u64) -> bool {
+        self.probe(a, b)
+            .all(|x| self.bytes[(x / 8) as usize] & (1 << (x % 8)) != 0)
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct RedisBloomFilter {
+    size: u64,
+    options: u32,
+    growth: u32,
+    links: Vec<Link>,
+}
+
+impl RedisBloomFilter {
+    // `BF.RESERVE key error_rate capacity`
+    pub fn reserve(error_rate: f64, capacity: u64) -> Result<Self, BloomError> {
+        Self::reserve_with_options(error_rate, capacity, 2, false)
+    }
+
+    // `BF.RESERVE key error_rate capacity EXPANSION expansion [NONSCALING]`
+    pub fn reserve_with_options(
+        error_rate: f64,
+        capacity: u64,
+        expansion: u32,
+        non_scaling: bool,
+    ) -> Result<Self, BloomError> {
+        if !(error_rate > 0.0 && error_rate < 1.0) {
+            return Err(BloomError::InvalidParams(
+                "Error rate must be in (0, 1).".into(),
+            ));
+        }
+        if capacity == 0 || expansion == 0 {
+            return Err(BloomError::InvalidParams(
+                "Capacity and expansion must not be 0.".into(),
+            ));
+        }
+        let mut options = OPT_NOROUND | OPT_FORCE64;
+        if non_scaling {
+            options |= OPT_NO_SCALING;
+        }
+        Ok(RedisBloomFilter {
+            size: 0,
+            options,
+            growth: expansion,
+            links: vec![Link::new(capacity, error_rate)?],
+        })
+    }
+
+    // Rebuilds a filter from the (iter, data) pairs `BF.SCANDUMP` returned, in order
+    pub fn from_scandump<I, D>(chunks: I) -> Result<Self, BloomError>
+    where
+        I: IntoIterator<Item = (i64, D)>,
+        D: AsRef<[u8]>,
+    {
+        Self::load_scandump(chunks).map_err(BloomError::SerializationError)
+    }
+
+    fn load_scandump<I, D>(chunks: I) -> Result<Self, String>
+    where
+        I: IntoIterator<Item = (i64, D)>,
+        D: AsRef<[u8]>,
+    {
+        let mut chunks = chunks.into_iter();
+        let (_, header) = chunks
+            .next()
+            .ok_or("BF.SCANDUMP returned no header chunk.")?;
+        let mut filter = Self::from_header(header.as_ref())?;
+        for (iter, data) in chunks {
+            let data = data.as_ref();
+            if iter == 0 {
+                break;
+            }
+            filter.load_chunk(iter, data)?;
+        }
+        Ok(filter)
+    }
+
+    fn from_header(header: &[u8]) -> Result<Self, String> {
+        let mut reader = Reader(header);
+        let size = reader.u64()?;
+        let num_links = reader.u32()? as usize;
+        let options = reader.u32()?;
+        let growth = reader.u32()?;
+        if options & OPT_FORCE64 == 0 {
+            return Err("Only RedisBloom filters with 64 bit hashes are supported.".into());
+        }
+        if header.len() != HEADER_LEN + num_links * LINK_LEN {
+            return Err(format!(
+                "Expected a header of {} bytes for {} filters, got {}.",
+                HEADER_LEN + num_links * LINK_LEN,
+                num_links,
+                header.len()
+            ));
+        }
+
+        let mut links = Vec::with_capacity(num_links);
+        for _ in 0..num_links {
+            let num_bytes = reader.u64()?;
+            let bits = reader.u64()?;
+            let link = Link {
+                bits,
+                size: reader.u64()?,
+                error: reader.f64()?,
+                bpe: reader.f64()?,
+                hashes: reader.u32()?,
+                entries: reader.u64()?,
+                n2: reader.u8()?,
+                bytes: Vec::new(),
+            };
+            let modulus = if link.n2 > 0 {
+                1u64.checked_shl(link.n2 as u32).unwrap_or(0)
+            } else {
+                bits
+            };
+            if modulus == 0 || modulus.div_ceil(8) > num_bytes {
+                return Err("RedisBloom filter has more bits than bytes.".into());
+            }
+            if num_bytes > MAX_LINK_BITS / 8 {
+                return Err(format!(
+                    "RedisBloom filter has more than {} bits.",
+                    MAX_LINK_BITS
+                ));
+            }
+            let num_bytes = usize::try_from(num_bytes)
+                .map_err(|_| "RedisBloom filter doesn't fit in memory.")?;
+            links.push(Link {
+                bytes: vec![0; num_bytes],
+                ..link
+            });
+        }
+        Ok(RedisBloomFilter {
+            size,
+            options,
+            growth,
+            links,
+        })
+    }
+
+    // Same as `BF.LOADCHUNK`, for chunks after the header
+    fn load_chunk(&mut self, iter: i64, data: &[u8]) -> Result<(), String> {
+        let end = iter
+            .checked_sub(1)
+            .and_then(|end| usize::try_from(end).ok())
+            .ok_or_else(|| format!("Bad chunk iterator {}.", iter))?;
+        let mut offset = end
+            .checked_sub(data.len())
+            .ok_or_else(|| format!("Bad chunk iterator {}.", iter))?;
+        for link in &mut self.links {
+            if offset < link.bytes.len() {
+                let dest = link
+                    .bytes
+                    .get_mut(offset..offset + data.len())
+                    .ok_or("Chunk crosses the end of a filter.")?;
+                dest.copy_from_slice(data);
+                return Ok(());
+            }
+            offset -= link.bytes.len();
+        }
+        Err(format!(
+            "Chunk iterator {} is past the end of the filter.",
+            iter
+        ))
+    }
+
+    // (iter, data) pairs for `BF.LOADCHUNK`, starting with the header,
+    // data chunks are at most `max_chunk` bytes
+    pub fn to_loadchunks(&self, max_chunk: usize) -> Vec<(i64, Vec<u8>)> {
+        assert!(max_chunk > 0, "chunk size must not be 0");
+        let mut chunks = vec![(1, self.header())];
+        let mut position = 1i64;
+        for link in &self.links {
+            for chunk in link.bytes.chunks(max_chunk) {
+                position += chunk.len() as i64;
+                chunks.push((position, chunk.to_vec()));
+            }
+        }
+        chunks
+    }
+
+    fn header(&self) -> Vec<u8> {
+        let mut header = Vec::with_capacity(HEADER_LEN + self.links.len() * LINK_LEN);
+        header.extend_from_slice(&self.size.to_le_bytes());
+        header.extend_from_slice(&(self.links.len() as u32).to_le_bytes());
+        header.extend_from_slice(&self.options.to_le_bytes());
+        header.extend_from_slice(&self.growth.to_le_bytes());
+        for link in &self.links {
+            header.extend_from_slice(&(link.bytes.len() as u64).to_le_bytes());
+            header.extend_from_slice(&link.bits.to_le_bytes());
+            header.extend_from_slice(&link.size.to_le_bytes());
+            header.extend_from_slice(&link.error.to_le_bytes());
+            header.extend_from_slice(&link.bpe.to_le_bytes());
+            header.extend_from_slice(&link.hashes.to_le_bytes());
+            header.extend_from_slice(&link.entries.to_le_bytes());
+            header.push(link.n2);
+        }
+        header
+    }
+
+    // Items are the raw bytes Redis got, e.g. `BF.ADD key foo` is `contains(b"foo")`
+    pub fn contains(&self, item: impl AsRef<[u8]>) -> bool {
+        let (a, b) = hash(item.as_ref());
+        self.links.iter().any(|link| link.contains(a, b))
+    }
+
+    // Adds the item to the newest filter of the chain, like `BF.ADD`. Returns false if it was
+    // possibly present already. A full non-scaling chain still takes the item, its error rate
+    // goes up instead (`try_insert` refuses it).
+    pub fn insert(&mut self, item: impl AsRef<[u8]>) -> bool {
+        self.add(item.as_ref(), false).unwrap_or(false)
+    }
+
+    // Same as `insert`, but fails with `CapacityExceeded` when the chain is non-scaling and full,
+    // like `BF.ADD` does
+    pub fn try_insert(&mut self, item: impl AsRef<[u8]>) -> Result<bool, BloomError> {
+        self.add(item.as_ref(), true)
+    }
+
+    fn add(&mut self, item: &[u8], strict: bool) -> Result<bool, BloomError> {
+        let (a, b) = hash(item);
+        if self.links.iter().any(|link| link.contains(a, b)) {
+            return Ok(false);
+        }
+        let Some(last) = self.links.last() else {
+            return Ok(false);
+        };
+        if last.size >= last.entries {
+            let next = (self.options & OPT_NO_SCALING == 0)
+                .then(|| {
+                    Link::new(
+                        last.entries.saturating_mul(self.growth as u64),
+                        last.error * 0.5,
+                    )
+                })
+                .and_then(Result::ok);
+            match next {
+                Some(link) => self.links.push(link),
+                None if strict => return Err(BloomError::CapacityExceeded),
+                None => {}
+            }
+        }
+        let link = self.links.last_mut().unwrap();
+        let indices: Vec<u64> = link.probe(a, b).collect();
+        for x in indices {
+            link.bytes[(x / 8) as usize] |= 1 << (x % 8);
+        }
+        link.size += 1;
+        self.size += 1;
+        Ok(true)
+    }
+
+    pub fn memory_usage(&self) -> usize {
+        let links: usize = self.links.iter().map(|link| heap_bytes(&link.bytes)).sum();
+        size_of::<Self>() + heap_bytes(&self.links) + links
+    }
+
+    // Number of items added, as `BF.INFO` reports it
+    pub fn len(&self) -> u64 {
+        self.size
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.size == 0
+    }
+
+    pub fn num_filters(&self) -> usize {
+        self.links.len()
+    }
+
+    // Sum of the capacities of all filters of the chain
+    pub fn capacity(&self) -> u64 {
+        self.links.iter().map(|link| link.entries).sum()
+    }
+
+    // Sums over the chain like `ScalableBloomFilter::stats`
+    pub fn stats(&self) -> FilterStats {
+        let links = self.links.iter().map(|link| {
+            let set_bits = link
+                .bytes
+                .iter()
+                .map(|byte| byte.count_ones() as usize)
+                .sum();
+            FilterStats::new(link.bits as usize, link.hashes as usize, set_bits, 0)
+        });
+        FilterStats::from_chain(links, self.memory_usage())
+    }
+}
+
+fn hash(item: &[u8]) -> (u64, u64) {
+    let a = murmur64a(item, HASH_SEED);
+    (a, murmur64a(item, a))
+}
+
+// MurmurHash64A by Austin Appleby, as bundled with RedisBloom
+pub(crate) fn murmur64a(key: &[u8], seed: u64) -> u64 {
+    const M: u64 = 0xc6a4_a793_5bd1_e995;
+    const R: u32 = 47;
+
+    let mut h = seed ^ (key.len() as u64).wrapping_mul(M);
+    let mut blocks = key.chunks_exact(8);
+    for block in &mut blocks {
+        let mut k = u64::from_le_bytes(block.try_into().unwrap());
+        k = k.wrapping_mul(M);
+        k ^= k >> R;
+        k = k.wrapping_mul(M);
+        h ^= k;
+        h = h.wrapping_mul(M);
+    }
+    let tail = blocks.remainder();
+    if !tail.is_empty() {
+        for (i, &byte) in tail.iter().enumerate() {
+            h ^= (byte as u64) << (8 * i);
+        }
+        h = h.wrapping_mul(M);
+    }
+    h ^= h >> R;
+    h = h.wrapping_mul(M);
+    h ^ (h >> R)
+}
+
+struct Reader<'a>(&'a [u8]);
+
+impl Reader<'_> {
+    fn take<const N: usize>(&mut self) -> Result<[u8; N], String> {
+        if self.0.len() < N {
+            return Err("RedisBloom header is truncated.".into());
+        }
+        let (head, rest) = self.0.split_at(N);
+        self.0 = rest;
+        Ok(head.try_into().unwrap())
+    }
+
+    fn u8(&mut self) -> Result<u8, String> {
+        Ok(self.take::<1>()?[0])
+    }
+
+    fn u32(&mut self) -> Result<u32, String> {
+        self.take().map(u32::from_le_bytes)
+    }
+
+    fn u64(&mut self) -> Result<u64, String> {
+        self.take().map(u64::from_le_bytes)
+    }
+
+    fn f64(&mut self) -> Result<f64, String> {
+        self.take().map(f64::from_le_bytes)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // A dump of `BF.RESERVE key 0.01 100` followed by `BF.ADD key foo`
+    fn dump() -> Vec<(i64, Vec<u8>)> {
+        let mut header = Vec::new();
+        header.extend_from_slice(&1u64.to_le_bytes());
+        header.extend_from_slice(&1u32.to_le_bytes());
+        header.extend_from_slice(&(OPT_FORCE64 | 1).to_le_bytes());
+        header.extend_from_slice(&2u32.to_le_bytes());
+        header.extend_from_slice(&128u64.to_le_bytes());
+        header.extend_from_slice(&1024u64.to_le_bytes());
+        header.extend_from_slice(&1u64.to_le_bytes());
+        header.extend_from_slice(&0.01f64.to_le_bytes());
+        header.extend_from_slice(&9.585f64.to_le_bytes());
+        header.extend_from_slice(&7u32.to_le_bytes());
+        header.extend_from_slice(&100u64.to_le_bytes());
+        header.push(0);
+
+        let mut bits = [0u8; 128];
+        let (a, b) = hash(b"foo");
+        for i in 0..7u64 {
+            let x = a.wrapping_add(i.wrapping_mul(b)) % 1024;
+            bits[(x / 8) as usize] |= 1 << (x % 8);
+        }
+        vec![
+            (1, header),
+            (65, bits[..64].to_vec()),
+            (129, bits[64..].to_vec()),
+            (0, Vec::new()),
+        ]
+    }
+
+    #[test]
+    fn test_murmur64a() {
+        assert_eq!(murmur64a(b"", 0), 0);
+        // tail handling must not depend on the block part
+        assert_ne!(murmur64a(b"12345678a", 1), murmur64a(b"12345678b", 1));
+    }
+
+    #[test]
+    fn test_scandump_round_trip() {
+        let chunks = dump();
+        let mut bloom = RedisBloomFilter::from_scandump(chunks.clone()).unwrap();
+        assert_eq!(bloom.len(), 1);
+        assert_eq!(bloom.num_filters(), 1);
+        assert_eq!(bloom.capacity(), 100);
+        assert!(bloom.contains("foo"));
+        assert!(!bloom.contains("bar"));
+
+        assert_eq!(bloom.to_loadchunks(64), chunks[..3].to_vec());
+
+        assert!(bloom.insert("bar"));
+        assert!(!bloom.insert("bar"));
+        let reloaded = RedisBloomFilter::from_scandump(bloom.to_loadchunks(50)).unwrap();
+        assert_eq!(reloaded, bloom);
+        assert!(reloaded.contains("bar"));
+    }
+
+    #[test]
+    fn test_reserve_and_grow() {
+        let mut bloom = RedisBloomFilter::reserve(0.01, 100).unwrap();
+        assert_eq!(bloom.num_filters(), 1);
+        // same sizing as the dump above, 958.5 bits rounded up to 960
+        assert_eq!(bloom.links[0].bits, 960);
+        assert_eq!(bloom.links[0].hashes, 7);
+        for i in 0..250u32 {
+            bloom.insert(i.to_string());
+        }
+        assert_eq!(bloom.num_filters(), 2);
+        assert_eq!(bloom.capacity(), 300);
+        assert!((0..250u32).all(|i| bloom.contains(i.to_string())));
+        let reloaded = RedisBloomFilter::from_scandump(bloom.to_loadchunks(1000)).unwrap();
+        assert_eq!(reloaded, bloom);
+
+        let mut fixed = RedisBloomFilter::reserve_with_options(0.01, 10, 2, true).unwrap();
+        let added = (0..20u32)
+            .map(|i| fixed.try_insert(i.to_string()))
+            .filter(|result| result.is_ok())
+            .count();
+        assert!(added <= 11);
+        assert_eq!(fixed.try_insert("x"), Err(BloomError::CapacityExceeded));
+        assert_eq!(fixed.num_filters(), 1);
+        assert!(RedisBloomFilter::reserve(0.0, 10).is_err());
+        assert!(RedisBloomFilter::reserve(0.01, u64::MAX).is_err());
+        assert!(RedisBloomFilter::reserve(1e-300, 1 << 30).is_err());
+
+        // the chain stops growing before a filter gets too big
+        let mut capped = RedisBloomFilter::reserve_with_options(0.01, 10, u32::MAX, false).unwrap();
+        for i in 0..30u32 {
+            capped.insert(i.to_string());
+        }
+        assert_eq!(capped.num_filters(), 1);
+        assert_eq!(capped.try_insert("x"), Err(BloomError::CapacityExceeded));
+    }
+
+    #[test]
+    fn test_rejects_bad_dumps() {
+        let mut chunks = dump();
+        chunks[0].1.truncate(40);
+        assert!(RedisBloomFilter::from_scandump(chunks).is_err());
+
+        let mut chunks = dump();
+        chunks[1].0 = 1000;
+        assert!(RedisBloomFilter::from_scandump(chunks).is_err());
+        for iter in [i64::MIN, -1] {
+            let mut chunks = dump();
+            chunks[1].0 = iter;
+            assert!(RedisBloomFilter::from_scandump(chunks).is_err());
+        }
+
+        // bytes and bits of the first filter claiming 1 TiB
+        let mut chunks = dump();
+        chunks[0].1[20..28].copy_from_slice(&(1u64 << 40).to_le_bytes());
+        chunks[0].1[28..36].copy_from_slice(&(1u64 << 43).to_le_bytes());
+        assert!(RedisBloomFilter::from_scandump(chunks).is_err());
+    }
+}
@@ -0,0 +1,385 @@
+// RedisBloom compatible server (feature `resp`). Speaks RESP over TCP and implements
+//
+//   BF.RESERVE key error_rate capacity [EXPANSION expansion] [NONSCALING]
+//   BF.ADD key item                 :1 if newly added, :0 if possibly present already
+//   BF.MADD key item [item ...]     an array of those
+//   BF.EXISTS key item              :0 for a missing key
+//   BF.MEXISTS key item [item ...]
+//
+// plus PING and QUIT, so redis-cli and the RedisBloom client of any language can use it as is.
+// BF.ADD / BF.MADD create missing keys with RedisBloom's defaults (error rate 0.01, capacity 100,
+// expansion 2). Keys are `RedisBloomFilter`s, hashed and sized like RedisBloom, so a filter can
+// move to a real Redis with BF.SCANDUMP / BF.LOADCHUNK (`RedisBloomFilter::to_loadchunks`).
+// Every connection gets its own thread, all of them share one lock on the keys.
+//
+// Ex. bloomf::resp::serve(std::net::TcpListener::bind("127.0.0.1:6379")?)
+
+use std::collections::HashMap;
+use std::io::{self, BufRead, BufReader, BufWriter, Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::sync::{Arc, Mutex};
+use std::thread;
+
+use crate::error::BloomError;
+use crate::redisbloom::RedisBloomFilter;
+
+type Filters = Arc<Mutex<HashMap<Vec<u8>, RedisBloomFilter>>>;
+
+const DEFAULT_ERROR_RATE: f64 = 0.01;
+const DEFAULT_CAPACITY: u64 = 100;
+const DEFAULT_EXPANSION: u32 = 2;
+// same limits as Redis: inline commands up to 64 KiB, bulk strings up to 512 MiB
+const MAX_LINE: u64 = 64 * 1024;
+const MAX_BULK_LEN: usize = 512 * 1024 * 1024;
+
+// Accepts connections until the listener fails
+pub fn serve(listener: TcpListener) -> io::Result<()> {
+    let filters = Filters::default();
+    for stream in listener.incoming() {
+        let stream = stream?;
+        let filters = filters.clone();
+        thread::spawn(move || handle(stream, &filters));
+    }
+    Ok(())
+}
+
+fn handle(stream: TcpStream, filters: &Filters) -> io::Result<()> {
+    let mut reader = BufReader::new(stream.try_clone()?);
+    let mut writer = BufWriter::new(stream);
+    loop {
+        let args = match read_command(&mut reader) {
+            Ok(Some(args)) => args,
+            Ok(None) => return writer.flush(),
+            Err(err) if err.kind() == io::ErrorKind::InvalidData => {
+                Reply::Error(format!("ERR Protocol error: {}", err)).write_to(&mut writer)?;
+                return writer.flush();
+            }
+            Err(err) => return Err(err),
+        };
+        if args.is_empty() {
+            continue;
+        }
+        let quit = args[0].eq_ignore_ascii_case(b"QUIT");
+        let reply = if quit {
+            Reply::Status("OK")
+        } else {
+            execute(filters, &args)
+        };
+        reply.write_to(&mut writer)?;
+        // pipelined commands are answered in one write
+        if quit || reader.buffer().is_empty() {
+            writer.flush()?;
+        }
+        if quit {
+            return Ok(());
+        }
+    }
+}
+
+enum Reply {
+    Status(&'static str),
+    Error(String),
+    Integer(i64),
+    Bulk(Vec<u8>),
+    Array(Vec<Reply>),
+}
+
+impl Reply {
+    fn write_to(&self, writer: &mut impl Write) -> io::Result<()> {
+        match self {
+            Reply::Status(status) => write!(writer, "+{}\r\n", status),
+            Reply::Error(message) => write!(writer, "-{}\r\n", message),
+            Reply::Integer(value) => write!(writer, ":{}\r\n", value),
+            Reply::Bulk(data) => {
+                write!(writer, "${}\r\n", data.len())?;
+                writer.write_all(data)?;
+                writer.write_all(b"\r\n")
+            }
+            Reply::Array(replies) => {
+                write!(writer, "*{}\r\n", replies.len())?;
+                replies.iter().try_for_each(|reply| reply.write_to(writer))
+            }
+        }
+    }
+}
+
+fn execute(filters: &Filters, args: &[Vec<u8>]) -> Reply {
+    let name = String::from_utf8_lossy(&args[0]).to_ascii_uppercase();
+    let args = &args[1..];
+    if name == "PING" {
+        return match args {
+            [] => Reply::Status("PONG"),
+            [message] => Reply::Bulk(message.clone()),
+            _ => wrong_arity(&name),
+        };
+    }
+    let Ok(mut filters) = filters.lock() else {
+        return Reply::Error(format!("ERR {}", BloomError::PoisonedLock));
+    };
+    match (name.as_str(), args) {
+        ("BF.RESERVE", [key, error_rate, capacity, options @ ..]) => {
+            if filters.contains_key(key) {
+                return Reply::Error("ERR item exists".into());
+            }
+            match reserve(error_rate, capacity, options) {
+                Ok(filter) => {
+                    filters.insert(key.clone(), filter);
+                    Reply::Status("OK")
+                }
+                Err(reply) => reply,
+            }
+        }
+        ("BF.ADD", [key, item]) => add(get_or_create(&mut filters, key), item),
+        ("BF.MADD", [key, items @ ..]) if !items.is_empty() => {
+            let filter = get_or_create(&mut filters, key);
+            Reply::Array(items.iter().map(|item| add(filter, item)).collect())
+        }
+        ("BF.EXISTS", [key, item]) => exists(filters.get(key), item),
+        ("BF.MEXISTS", [key, items @ ..]) if !items.is_empty() => {
+            let filter = filters.get(key);
+            Reply::Array(items.iter().map(|item| exists(filter, item)).collect())
+        }
+        ("BF.RESERVE" | "BF.ADD" | "BF.MADD" | "BF.EXISTS" | "BF.MEXISTS", _) => wrong_arity(&name),
+        _ => Reply::Error(format!(
+            "ERR unknown command '{}'",
+            name.to_ascii_lowercase()
+        )),
+    }
+}
+
+fn wrong_arity(name: &str) -> Reply {
+    Reply::Error(format!(
+        "ERR wrong number of arguments for '{}' command",
+        name.to_ascii_lowercase()
+    ))
+}
+
+fn reserve(
+    error_rate: &[u8],
+    capacity: &[u8],
+    options: &[Vec<u8>],
+) -> Result<RedisBloomFilter, Reply> {
+    let error_rate = parse::<f64>(error_rate)
+        .filter(|rate| *rate > 0.0 && *rate < 1.0)
+        .ok_or_else(|| Reply::Error("ERR bad error rate".into()))?;
+    let capacity = parse::<u64>(capacity)
+        .filter(|&capacity| capacity > 0)
+        .ok_or_else(|| Reply::Error("ERR bad capacity".into()))?;
+    let mut expansion = DEFAULT_EXPANSION;
+    let mut non_scaling = false;
+    let mut options = options.iter();
+    while let Some(option) = options.next() {
+        if option.eq_ignore_ascii_case(b"NONSCALING") {
+            non_scaling = true;
+        } else if option.eq_ignore_ascii_case(b"EXPANSION") {
+            expansion = options
+                .next()
+                .and_then(|value| parse::<u32>(value))
+                .filter(|&expansion| expansion > 0)
+                .ok_or_else(|| Reply::Error("ERR bad expansion".into()))?;
+        } else {
+            return Err(Reply::Error("ERR unknown argument received".into()));
+        }
+    }
+    RedisBloomFilter::reserve_with_options(error_rate, capacity, expansion, non_scaling)
+        .map_err(|err| Reply::Error(format!("ERR {}", err)))
+}
+
+fn get_or_create<'a>(
+    filters: &'a mut HashMap<Vec<u8>, RedisBloomFilter>,
+    key: &[u8],
+) -> &'a mut RedisBloomFilter {
+    filters.entry(key.to_vec()).or_insert_with(|| {
+        RedisBloomFilter::reserve_with_options(
+            DEFAULT_ERROR_RATE,
+            DEFAULT_CAPACITY,
+            DEFAULT_EXPANSION,
+            false,
+        )
+        .unwrap()
+    })
+}
+
+fn add(filter: &mut RedisBloomFilter, item: &[u8]) -> Reply {
+    match filter.try_insert(item) {
+        Ok(added) => Reply::Integer(added as i64),
+        Err(BloomError::CapacityExceeded) => Reply::Error("ERR non scaling filter is full".into()),
+        Err(err) => Reply::Error(format!("ERR {}", err)),
+    }
+}
+
+fn exists(filter: Option<&RedisBloomFilter>, item: &[u8]) -> Reply {
+    Reply::Integer(filter.is_some_and(|filter| filter.contains(item)) as i64)
+}
+
+fn parse<T: std::str::FromStr>(arg: &[u8]) -> Option<T> {
+    std::str::from_utf8(arg).ok()?.parse().ok()
+}
+
+// A command as an array of bulk strings, or an inline command (ex. typed into telnet).
+// None once the client hung up.
+fn read_command(reader: &mut impl BufRead) -> io::Result<Option<Vec<Vec<u8>>>> {
+    let Some(line) = read_line(reader)? else {
+        return Ok(None);
+    };
+    let Some(count) = line.strip_prefix(b"*") else {
+        let args = line
+            .split(|byte| byte.is_ascii_whitespace())
+            .filter(|arg| !arg.is_empty())
+            .map(<[u8]>::to_vec)
+            .collect();
+        return Ok(Some(args));
+    };
+    let count = parse::<usize>(count).ok_or_else(|| invalid("invalid multibulk length"))?;
+    let mut args = Vec::with_capacity(count.min(1024));
+    for _ in 0..count {
+        let line = read_line(reader)?.ok_or(io::ErrorKind::UnexpectedEof)?;
+        let len = line
+            .strip_prefix(b"$")
+            .and_then(parse::<usize>)
+            .filter(|&len| len <= MAX_BULK_LEN)
+            .ok_or_else(|| invalid("invalid bulk length"))?;
+        let mut arg = vec![0; len + 2];
+        reader.read_exact(&mut arg)?;
+        if !arg.ends_with(b"\r\n") {
+            return Err(invalid("expected '\\r\\n' after bulk string"));
+        }
+        arg.truncate(len);
+        args.push(arg);
+    }
+    Ok(Some(args))
+}
+
+fn read_line(reader: &mut impl BufRead) -> io::Result<Option<Vec<u8>>> {
+    let mut line = Vec::new();
+    reader
+        .by_ref()
+        .take(MAX_LINE)
+        .read_until(b'\n', &mut line)?;
+    if line.is_empty() {
+        return Ok(None);
+    }
+    if line.pop() != Some(b'\n') {
+        return Err(invalid("too big inline request"));
+    }
+    if line.last() == Some(&b'\r') {
+        line.pop();
+    }
+    Ok(Some(line))
+}
+
+fn invalid(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::net::SocketAddr;
+
+    fn connect() -> (SocketAddr, TcpStream) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        thread::spawn(move || serve(listener));
+        (addr, TcpStream::connect(addr).unwrap())
+    }
+
+    fn send(stream: &mut TcpStream, args: &[&str]) {
+        write!(stream, "*{}\r\n", args.len()).unwrap();
+        for arg in args {
+            write!(stream, "${}\r\n{}\r\n", arg.len(), arg).unwrap();
+        }
+    }
+
+    // One reply as its raw lines, joined with spaces
+    fn read_reply(reader: &mut impl BufRead) -> String {
+        let line = String::from_utf8(read_line(reader).unwrap().unwrap()).unwrap();
+        match line.as_bytes()[0] {
+            b'*' => {
+                let count: usize = line[1..].parse().unwrap();
+                (0..count).fold(line, |reply, _| reply + " " + &read_reply(reader))
+            }
+            b'$' => line + " " + &read_reply(reader),
+            _ => line,
+        }
+    }
+
+    fn command(stream: &mut TcpStream, reader: &mut impl BufRead, args: &[&str]) -> String {
+        send(stream, args);
+        read_reply(reader)
+    }
+
+    #[test]
+    fn test_bf_commands() {
+        let (_, mut stream) = connect();
+        let mut reader = BufReader::new(stream.try_clone().unwrap());
+        let mut cmd = |args: &[&str]| command(&mut stream, &mut reader, args);
+
+        assert_eq!(cmd(&["PING"]), "+PONG");
+        assert_eq!(cmd(&["ping", "hi"]), "$2 hi");
+        assert_eq!(cmd(&["BF.RESERVE", "users", "0.001", "1000"]), "+OK");
+        assert_eq!(
+            cmd(&["BF.RESERVE", "users", "0.01", "10"]),
+            "-ERR item exists"
+        );
+        assert_eq!(cmd(&["BF.RESERVE", "x", "2", "10"]), "-ERR bad error rate");
+        assert_eq!(
+            cmd(&["BF.RESERVE", "x", "0.1", "10", "EXPANSION", "0"]),
+            "-ERR bad expansion"
+        );
+        assert!(cmd(&["BF.RESERVE", "x", "0.01", "18446744073709551615"]).starts_with("-ERR "));
+        assert_eq!(cmd(&["BF.ADD", "users", "alice"]), ":1");
+        assert_eq!(cmd(&["bf.add", "users", "alice"]), ":0");
+        assert_eq!(cmd(&["BF.MADD", "users", "bob", "alice"]), "*2 :1 :0");
+        assert_eq!(cmd(&["BF.EXISTS", "users", "bob"]), ":1");
+        assert_eq!(cmd(&["BF.EXISTS", "users", "carol"]), ":0");
+        assert_eq!(cmd(&["BF.EXISTS", "missing", "bob"]), ":0");
+        assert_eq!(cmd(&["BF.MEXISTS", "users", "alice", "carol"]), "*2 :1 :0");
+        assert_eq!(
+            cmd(&["BF.ADD", "users"]),
+            "-ERR wrong number of arguments for 'bf.add' command"
+        );
+        assert_eq!(cmd(&["SET", "a", "b"]), "-ERR unknown command 'set'");
+
+        // BF.ADD creates the key
+        assert_eq!(cmd(&["BF.ADD", "auto", "x"]), ":1");
+        assert_eq!(cmd(&["BF.EXISTS", "auto", "x"]), ":1");
+
+        assert_eq!(
+            cmd(&["BF.RESERVE", "small", "0.01", "2", "NONSCALING"]),
+            "+OK"
+        );
+        assert_eq!(cmd(&["BF.MADD", "small", "a", "b"]), "*2 :1 :1");
+        assert_eq!(
+            cmd(&["BF.ADD", "small", "c"]),
+            "-ERR non scaling filter is full"
+        );
+        assert_eq!(cmd(&["QUIT"]), "+OK");
+    }
+
+    #[test]
+    fn test_pipelining_and_inline_commands() {
+        let (addr, mut stream) = connect();
+        let mut reader = BufReader::new(stream.try_clone().unwrap());
+        send(&mut stream, &["BF.ADD", "k", "a"]);
+        send(&mut stream, &["BF.ADD", "k", "b"]);
+        stream.write_all(b"BF.MEXISTS k a b c\r\n").unwrap();
+        assert_eq!(read_reply(&mut reader), ":1");
+        assert_eq!(read_reply(&mut reader), ":1");
+        assert_eq!(read_reply(&mut reader), "*3 :1 :1 :0");
+
+        // other connections see the same keys
+        let mut other = TcpStream::connect(addr).unwrap();
+        let mut other_reader = BufReader::new(other.try_clone().unwrap());
+        assert_eq!(
+            command(&mut other, &mut other_reader, &["BF.EXISTS", "k", "b"]),
+            ":1"
+        );
+
+        stream.write_all(b"*1\r\n$x\r\n").unwrap();
+        assert_eq!(
+            read_reply(&mut reader),
+            "-ERR Protocol error: invalid bulk length"
+        );
+    }
+}
@@ -0,0 +1,257 @@
+// Standard Ribbon filter (Dillinger & Walzer 2021) for static sets, as used by RocksDB.
+// Every key gets a start row and a 64 bit coefficient row; the filter stores a solution
+// matrix Z (one r bit row per slot) such that for every key the xor of the rows of Z selected
+// by its coefficients, starting at its start row, equals its r bit fingerprint.
+// The banded linear system is solved by incremental Gaussian elimination ("banding") and back
+// substitution. With r result bits the fpp is 2^-r and space is r * (1 + small overhead) bits
+// per key, within a few percent of the information-theoretic bound.
+//
+// The solution is stored column-major: for every result bit a bit vector over all slots,
+// so a query is r popcounts over a 64 bit window.
+
+use std::hash::{BuildHasher, Hash};
+
+use crate::error::BloomError;
+use crate::hash::{mix, Sha256BuildHasher};
+use crate::heap_bytes;
+use crate::rng::Rng;
+
+// Coefficient width
+const W: usize = 64;
+const SEEDS_PER_OVERHEAD: usize = 8;
+
+pub struct RibbonFilterBuilder<S = Sha256BuildHasher> {
+    bits_per_key: f64,
+    overhead: f64,
+    hasher: S,
+}
+
+pub struct RibbonFilter<S = Sha256BuildHasher> {
+    seed: u64,
+    num_starts: u64,
+    result_bits: u32,
+    words_per_column: usize,
+    // result_bits columns of words_per_column words
+    columns: Vec<u64>,
+    hasher: S,
+}
+
+impl Default for RibbonFilterBuilder {
+    fn default() -> Self {
+        RibbonFilterBuilder {
+            bits_per_key: 10.0,
+            overhead: 0.05,
+            hasher: Sha256BuildHasher,
+        }
+    }
+}
+
+impl RibbonFilterBuilder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+impl<S: BuildHasher> RibbonFilterBuilder<S> {
+    // Space budget, the number of result bits (and so the fpp, 2^-r) is derived from it.
+    // Ex. 10 bits per key -> r = 9 -> fpp ~0.2%
+    pub fn bits_per_key(mut self, bits_per_key: f64) -> Self {
+        assert!(bits_per_key >= 1.0, "bits per key must be at least 1");
+        self.bits_per_key = bits_per_key;
+        self
+    }
+
+    pub fn hasher<H: BuildHasher>(self, hasher: H) -> RibbonFilterBuilder<H> {
+        RibbonFilterBuilder {
+            bits_per_key: self.bits_per_key,
+            overhead: self.overhead,
+            hasher,
+        }
+    }
+
+    pub fn result_bits(&self) -> u32 {
+        ((self.bits_per_key / (1.0 + self.overhead)).floor() as u32).clamp(1, 32)
+    }
+
+    // Duplicate keys are ignored. Banding can fail for a given seed, in that case other seeds
+    // are tried and the overhead is increased if needed, so this only fails for absurd inputs.
+    pub fn build<T: Hash>(self, items: &[T]) -> Result<RibbonFilter<S>, BloomError> {
+        let mut keys: Vec<u64> = items
+            .iter()
+            .map(|item| self.hasher.hash_one(item))
+            .collect();
+        keys.sort_unstable();
+        keys.dedup();
+
+        let result_bits = self.result_bits();
+        let mut overhead = self.overhead;
+        let mut rng = Rng::new(0x726b_2b9d_438b_9d4d);
+        while overhead < 1.0 {
+            let num_starts = ((keys.len() as f64 * (1.0 + overhead)).ceil() as u64).max(1);
+            for _ in 0..SEEDS_PER_OVERHEAD {
+                let seed = rng.next_u64();
+                if let Some(columns) = solve(&keys, seed, num_starts, result_bits) {
+                    return Ok(RibbonFilter {
+                        seed,
+                        num_starts,
+                        result_bits,
+                        words_per_column: columns.len() / result_bits as usize,
+                        columns,
+                        hasher: self.hasher,
+                    });
+                }
+            }
+            overhead += 0.05;
+        }
+        Err(BloomError::ConstructionFailed(
+            "Can't build RibbonFilter: banding kept failing.".into(),
+        ))
+    }
+}
+
+impl RibbonFilter {
+    pub fn builder() -> RibbonFilterBuilder {
+        RibbonFilterBuilder::new()
+    }
+}
+
+// start row, coefficients (bit 0 always set) and fingerprint of a key
+fn key_row(key: u64, seed: u64, num_starts: u64, result_bits: u32) -> (usize, u64, u32) {
+    let hash = mix(key, seed);
+    let start = ((hash as u128 * num_starts as u128) >> 64) as usize;
+    let coefficients = mix(hash, 1) | 1;
+    let fingerprint = (mix(hash, 2) as u32) & (u32::MAX >> (32 - result_bits));
+    (start, coefficients, fingerprint)
+}
+
+// Bands the keys and back substitutes, returns the solution columns or None if the system
+// has no solution for this seed
+fn solve(keys: &[u64], seed: u64, num_starts: u64, result_bits: u32) -> Option<Vec<u64>> {
+    let num_slots = num_starts as usize + W - 1;
+    let mut coefficients = vec![0u64; num_slots];
+    let mut results = vec![0u32; num_slots];
+
+    // Banding: row i of the band either is empty or has its leading coefficient at bit 0
+    for &key in keys {
+        let (mut i, mut c, mut r) = key_row(key, seed, num_starts, result_bits);
+        loop {
+            if coefficients[i] == 0 {
+                coefficients[i] = c;
+                results[i] = r;
+                break;
+            }
+            c ^= coefficients[i];
+            r ^= results[i];
+            if c == 0 {
+                // Linearly dependent on previous keys: fine if consistent
+                if r == 0 {
+                    break;
+                }
+                return None;
+            }
+            let shift = c.trailing_zeros() as usize;
+            i += shift;
+            c >>= shift;
+        }
+    }
+
+    // Back substitution, from the last row up. `windows[j]` holds the solution bits of
+    // column j for rows i..i+64 (row i at bit 0).
+    let words_per_column = num_slots.div_ceil(64);
+    let mut columns = vec![0u64; result_bits as usize * words_per_column];
+    let mut windows = vec![0u64; result_bits as usize];
+    for i in (0..num_slots).rev() {
+        for (j, window) in windows.iter_mut().enumerate() {
+            *window <<= 1;
+            // Free variables (empty rows) are 0
+            let bit = ((*window & coefficients[i]).count_ones() as u64 & 1)
+                ^ ((results[i] >> j) & 1) as u64;
+            let bit = if coefficients[i] == 0 { 0 } else { bit };
+            *window |= bit;
+            columns[j * words_per_column + i / 64] |= bit << (i % 64);
+        }
+    }
+
+    Some(columns)
+}
+
+impl<S: BuildHasher> RibbonFilter<S> {
+    // 64 solution bits of column j starting at row `start`
+    fn window(&self, j: usize, start: usize) -> u64 {
+        let column = &self.columns[j * self.words_per_column..(j + 1) * self.words_per_column];
+        let (word, offset) = (start / 64, start % 64);
+        if offset == 0 {
+            column[word]
+        } else {
+            let high = column.get(word + 1).copied().unwrap_or(0);
+            (column[word] >> offset) | (high << (64 - offset))
+        }
+    }
+
+    pub fn contains<T: Hash + ?Sized>(&self, item: &T) -> bool {
+        let (start, coefficients, fingerprint) = key_row(
+            self.hasher.hash_one(item),
+            self.seed,
+            self.num_starts,
+            self.result_bits,
+        );
+        (0..self.result_bits as usize).all(|j| {
+            let bit = (self.window(j, start) & coefficients).count_ones() & 1;
+            bit == (fingerprint >> j) & 1
+        })
+    }
+
+    pub fn result_bits(&self) -> u32 {
+        self.result_bits
+    }
+
+    pub fn memory_usage(&self) -> usize {
+        size_of::<Self>() + heap_bytes(&self.columns)
+    }
+
+    // Size of the solution in bits
+    pub fn bit_len(&self) -> usize {
+        (self.num_starts as usize + W - 1) * self.result_bits as usize
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_ribbon() {
+        let items: Vec<u64> = (0..50_000).collect();
+        let filter = RibbonFilter::builder()
+            .bits_per_key(8.0)
+            .build(&items)
+            .unwrap();
+        assert_eq!(filter.result_bits(), 7);
+        for item in &items {
+            assert!(filter.contains(item));
+        }
+        // fpp ~ 1/128
+        let false_positives = (50_000..150_000u64).filter(|i| filter.contains(i)).count();
+        assert!(
+            false_positives < 1000,
+            "{} false positives",
+            false_positives
+        );
+        let bits_per_key = filter.bit_len() as f64 / items.len() as f64;
+        assert!(bits_per_key < 8.0, "{} bits per key", bits_per_key);
+    }
+
+    #[test]
+    fn test_small_and_duplicate_keys() {
+        let filter = RibbonFilter::builder()
+            .bits_per_key(16.0)
+            .build(&["foo", "bar", "foo"])
+            .unwrap();
+        assert!(filter.contains("foo"));
+        assert!(filter.contains("bar"));
+        assert!(!filter.contains("baz"));
+
+        let empty = RibbonFilter::builder().build::<u32>(&[]).unwrap();
+        assert!(!empty.contains(&1u32));
+    }
+}
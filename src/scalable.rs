@@ -0,0 +1,303 @@
+// Scalable Bloom filter (Almeida et al. 2007): a chain of Bloom filters where every new
+// filter is bigger (growth factor) and has a tighter error ratio (tightening ratio), so the
+// total false positive probability stays below the target no matter how many items come in.
+//
+// Slice i holds initial_capacity * growth^i items at p * (1 - r) * r^i, and
+// sum(p * (1 - r) * r^i) over all i is p.
+
+use std::hash::{BuildHasher, Hash};
+
+use crate::hash::Sha256BuildHasher;
+use crate::heap_bytes;
+use crate::stats::FilterStats;
+use crate::{params, BloomFilter};
+
+const DEFAULT_GROWTH_FACTOR: usize = 2;
+const DEFAULT_TIGHTENING_RATIO: f64 = 0.85;
+
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(
+    feature = "serde",
+    serde(
+        try_from = "ScalableRepr<S>",
+        bound(deserialize = "S: serde::Deserialize<'de>")
+    )
+)]
+pub struct ScalableBloomFilter<S = Sha256BuildHasher> {
+    filters: Vec<BloomFilter<S>>,
+    // number of items inserted in each filter, the last one is the one being filled
+    counts: Vec<usize>,
+    capacities: Vec<usize>,
+    initial_capacity: usize,
+    false_positive_rate: f64,
+    growth_factor: usize,
+    tightening_ratio: f64,
+    hasher: S,
+    seed: u64,
+}
+
+// Deserialized form, checked before it becomes a filter: the filters check themselves, the rest
+// has to match them and the options `with_options` accepts
+#[cfg(feature = "serde")]
+#[derive(serde::Deserialize)]
+struct ScalableRepr<S> {
+    filters: Vec<BloomFilter<S>>,
+    counts: Vec<usize>,
+    capacities: Vec<usize>,
+    initial_capacity: usize,
+    false_positive_rate: f64,
+    growth_factor: usize,
+    tightening_ratio: f64,
+    hasher: S,
+    #[serde(default)]
+    seed: u64,
+}
+
+#[cfg(feature = "serde")]
+impl<S> TryFrom<ScalableRepr<S>> for ScalableBloomFilter<S> {
+    type Error = String;
+
+    fn try_from(repr: ScalableRepr<S>) -> Result<Self, String> {
+        if repr.filters.is_empty()
+            || repr.counts.len() != repr.filters.len()
+            || repr.capacities.len() != repr.filters.len()
+        {
+            return Err("Scalable filter needs one count and capacity per filter.".into());
+        }
+        if repr.initial_capacity == 0
+            || repr.growth_factor == 0
+            || !(repr.false_positive_rate > 0.0 && repr.false_positive_rate < 1.0)
+            || !(repr.tightening_ratio > 0.0 && repr.tightening_ratio < 1.0)
+        {
+            return Err("Invalid scalable filter options.".into());
+        }
+        if repr.filters.iter().any(|filter| filter.seed != repr.seed) {
+            return Err("Scalable filter seed doesn't match its filters.".into());
+        }
+        Ok(ScalableBloomFilter {
+            filters: repr.filters,
+            counts: repr.counts,
+            capacities: repr.capacities,
+            initial_capacity: repr.initial_capacity,
+            false_positive_rate: repr.false_positive_rate,
+            growth_factor: repr.growth_factor,
+            tightening_ratio: repr.tightening_ratio,
+            hasher: repr.hasher,
+            seed: repr.seed,
+        })
+    }
+}
+
+impl ScalableBloomFilter {
+    pub fn new(initial_capacity: usize, false_positive_rate: f64) -> Self {
+        Self::with_hasher(initial_capacity, false_positive_rate, Sha256BuildHasher)
+    }
+}
+
+impl<S: BuildHasher + Clone> ScalableBloomFilter<S> {
+    pub fn with_hasher(initial_capacity: usize, false_positive_rate: f64, hasher: S) -> Self {
+        Self::with_options(
+            initial_capacity,
+            false_positive_rate,
+            DEFAULT_GROWTH_FACTOR,
+            DEFAULT_TIGHTENING_RATIO,
+            hasher,
+        )
+    }
+
+    // growth_factor: how much bigger every new filter is (usually 2 or 4)
+    // tightening_ratio: how much smaller the error of every new filter is, in (0, 1)
+    pub fn with_options(
+        initial_capacity: usize,
+        false_positive_rate: f64,
+        growth_factor: usize,
+        tightening_ratio: f64,
+        hasher: S,
+    ) -> Self {
+        assert!(
+            false_positive_rate > 0.0 && false_positive_rate < 1.0,
+            "false positive rate must be in (0, 1)"
+        );
+        assert!(growth_factor >= 1, "growth factor must be at least 1");
+        assert!(
+            tightening_ratio > 0.0 && tightening_ratio < 1.0,
+            "tightening ratio must be in (0, 1)"
+        );
+        let mut bloom = ScalableBloomFilter {
+            filters: Vec::new(),
+            counts: Vec::new(),
+            capacities: Vec::new(),
+            initial_capacity: initial_capacity.max(1),
+            false_positive_rate,
+            growth_factor,
+            tightening_ratio,
+            hasher,
+            seed: 0,
+        };
+        bloom.grow();
+        bloom
+    }
+
+    // Seeds every filter of the chain, see `BloomFilter::with_seed`
+    pub fn with_seed(mut self, seed: u64) -> Self {
+        self.seed = seed;
+        for filter in &mut self.filters {
+            filter.seed = seed;
+        }
+        self
+    }
+
+    pub fn seed(&self) -> u64 {
+        self.seed
+    }
+
+    fn grow(&mut self) {
+        let i = self.filters.len() as i32;
+        let capacity = self
+            .initial_capacity
+            .saturating_mul(self.growth_factor.saturating_pow(i as u32));
+        let fpp = self.false_positive_rate
+            * (1.0 - self.tightening_ratio)
+            * self.tightening_ratio.powi(i);
+        let size = params::optimal_bit_count(capacity, fpp);
+        let num_hashes = params::optimal_num_hashes(size, capacity);
+
+        self.filters.push(
+            BloomFilter::with_hasher(size, num_hashes, self.hasher.clone()).with_seed(self.seed),
+        );
+        self.counts.push(0);
+        self.capacities.push(capacity);
+    }
+
+    // Items that are possibly present already are not inserted again,
+    // so they don't count towards the capacity of the current filter.
+    pub fn insert<T: Hash + ?Sized>(&mut self, item: &T) {
+        if self.contains(item) {
+            return;
+        }
+        let last = self.filters.len() - 1;
+        if self.counts[last] >= self.capacities[last] {
+            self.grow();
+        }
+        let last = self.filters.len() - 1;
+        self.filters[last].set(item);
+        self.counts[last] += 1;
+    }
+
+    pub fn contains<T: Hash + ?Sized>(&self, item: &T) -> bool {
+        // newest filters are the biggest, most items live there
+        self.filters.iter().rev().any(|bloom| bloom.test(item))
+    }
+
+    // Number of (distinct, up to false positives) items inserted
+    pub fn len(&self) -> usize {
+        self.counts.iter().sum()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    // Items the filter can hold before it grows again
+    pub fn capacity(&self) -> usize {
+        self.capacities.iter().sum()
+    }
+
+    pub fn num_filters(&self) -> usize {
+        self.filters.len()
+    }
+
+    // Upper bound of the false positive probability of the whole chain
+    pub fn false_positive_rate(&self) -> f64 {
+        1.0 - (0..self.filters.len())
+            .map(|i| {
+                1.0 - self.false_positive_rate
+                    * (1.0 - self.tightening_ratio)
+                    * self.tightening_ratio.powi(i as i32)
+            })
+            .product::<f64>()
+    }
+
+    // Sums over the chain, num_hashes is the one of the newest filter
+    pub fn stats(&self) -> FilterStats {
+        FilterStats::from_chain(
+            self.filters.iter().map(|filter| filter.stats()),
+            self.memory_usage(),
+        )
+    }
+
+    pub fn memory_usage(&self) -> usize {
+        let filters: usize = self.filters.iter().map(|f| f.heap_bytes()).sum();
+        size_of::<Self>()
+            + heap_bytes(&self.filters)
+            + filters
+            + heap_bytes(&self.counts)
+            + heap_bytes(&self.capacities)
+    }
+
+    pub fn clear(&mut self) {
+        self.filters.clear();
+        self.counts.clear();
+        self.capacities.clear();
+        self.grow();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_grows_without_false_negatives() {
+        let mut bloom = ScalableBloomFilter::new(100, 0.01);
+        assert_eq!(bloom.num_filters(), 1);
+        assert!(bloom.is_empty());
+
+        for i in 0..5000 {
+            bloom.insert(&i);
+        }
+        assert!(bloom.num_filters() > 1);
+        assert!(bloom.capacity() >= 5000);
+        assert!(bloom.len() <= 5000 && bloom.len() > 4900);
+        for i in 0..5000 {
+            assert!(bloom.contains(&i));
+        }
+
+        assert!(bloom.false_positive_rate() < 0.01);
+        let false_positives = (5000..25_000).filter(|i| bloom.contains(i)).count();
+        assert!(false_positives < 200, "{} false positives", false_positives);
+
+        bloom.clear();
+        assert_eq!(bloom.num_filters(), 1);
+        assert!(!bloom.contains(&1));
+    }
+
+    #[test]
+    fn test_duplicates_do_not_count() {
+        let mut bloom = ScalableBloomFilter::new(10, 0.01);
+        for _ in 0..100 {
+            bloom.insert("foo");
+        }
+        assert_eq!(bloom.len(), 1);
+        assert_eq!(bloom.num_filters(), 1);
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn test_serde_round_trip() {
+        let mut bloom = ScalableBloomFilter::new(10, 0.01);
+        for i in 0..100 {
+            bloom.insert(&i);
+        }
+        let len = bloom.len();
+        let json = serde_json::to_string(&bloom).unwrap();
+        let bloom: ScalableBloomFilter = serde_json::from_str(&json).unwrap();
+        assert_eq!(bloom.len(), len);
+        assert!(bloom.num_filters() > 1);
+        assert!((0..100).all(|i| bloom.contains(&i)));
+
+        let mut value = serde_json::to_value(&bloom).unwrap();
+        value["counts"] = serde_json::json!([]);
+        assert!(serde_json::from_value::<ScalableBloomFilter>(value).is_err());
+    }
+}
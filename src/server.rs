@@ -0,0 +1,248 @@
+// HTTP service (feature `server`) keeping named filters in memory, for a shared dedup service:
+//
+//   POST /filters                       {"name": "users", "expected_items": 1000000,
+//                                        "false_positive_rate": 0.01}  (rate defaults to 0.01)
+//   POST /filters/{name}/items          {"items": ["a", "b"]}
+//   GET  /filters/{name}/contains?item=a
+//   GET  /filters/{name}/stats
+//   GET  /filters/{name}/bytes          the filter in the `BloomFilter::to_bytes` encoding
+//
+// Items are strings hashed like a Rust `&str`, so a downloaded filter loaded with
+// `BloomFilter::from_bytes` answers the same. The filters are `AtomicBloomFilter`s, inserts and
+// queries on the same filter don't wait for each other. A filter gets at most `MAX_FILTER_BITS`
+// bits, requests that would need more are refused.
+//
+// Ex. axum::serve(tokio::net::TcpListener::bind("0.0.0.0:8080").await?, bloomf::server::router())
+
+use std::collections::HashMap;
+use std::sync::{Arc, PoisonError, RwLock};
+
+use axum::extract::{Path, Query, State};
+use axum::http::{header, StatusCode};
+use axum::response::IntoResponse;
+use axum::routing::{get, post};
+use axum::{Json, Router};
+use serde::{Deserialize, Serialize};
+
+use crate::{AtomicBloomFilter, BloomError, BloomFilter};
+
+// 1 GiB
+pub const MAX_FILTER_BITS: usize = 1 << 33;
+
+type Filters = Arc<RwLock<HashMap<String, Arc<AtomicBloomFilter>>>>;
+type Error = (StatusCode, String);
+
+// A handler panicked while holding the lock
+fn poisoned<T>(_: PoisonError<T>) -> Error {
+    (
+        StatusCode::INTERNAL_SERVER_ERROR,
+        BloomError::PoisonedLock.to_string(),
+    )
+}
+
+pub fn router() -> Router {
+    Router::new()
+        .route("/filters", post(create_filter))
+        .route("/filters/{name}/items", post(insert_items))
+        .route("/filters/{name}/contains", get(contains))
+        .route("/filters/{name}/stats", get(stats))
+        .route("/filters/{name}/bytes", get(bytes))
+        .with_state(Filters::default())
+}
+
+#[derive(Deserialize)]
+struct CreateFilter {
+    name: String,
+    expected_items: usize,
+    #[serde(default = "default_false_positive_rate")]
+    false_positive_rate: f64,
+}
+
+fn default_false_positive_rate() -> f64 {
+    0.01
+}
+
+#[derive(Deserialize)]
+struct Items {
+    items: Vec<String>,
+}
+
+#[derive(Deserialize)]
+struct ContainsQuery {
+    item: String,
+}
+
+#[derive(Serialize)]
+struct Contains {
+    item: String,
+    contains: bool,
+}
+
+#[derive(Serialize)]
+struct Stats {
+    size: usize,
+    num_hashes: usize,
+    fill_ratio: f64,
+    estimated_items: usize,
+    current_fpp: f64,
+}
+
+fn lookup(filters: &Filters, name: &str) -> Result<Arc<AtomicBloomFilter>, Error> {
+    filters
+        .read()
+        .map_err(poisoned)?
+        .get(name)
+        .cloned()
+        .ok_or_else(|| (StatusCode::NOT_FOUND, format!("No filter named {}.", name)))
+}
+
+async fn create_filter(
+    State(filters): State<Filters>,
+    Json(request): Json<CreateFilter>,
+) -> Result<StatusCode, Error> {
+    let rate = request.false_positive_rate;
+    if !(rate > 0.0 && rate < 1.0) || request.expected_items == 0 {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            "expected_items must not be 0 and false_positive_rate must be in (0, 1).".into(),
+        ));
+    }
+    let builder = BloomFilter::builder()
+        .expected_items(request.expected_items)
+        .false_positive_rate(rate);
+    if builder.size() > MAX_FILTER_BITS {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            format!(
+                "Filter would need {} bits, at most {} are allowed.",
+                builder.size(),
+                MAX_FILTER_BITS
+            ),
+        ));
+    }
+    // rates far below 2^-64 need more hashes than filters take
+    crate::check_params(builder.size(), builder.num_hashes())
+        .map_err(|err| (StatusCode::BAD_REQUEST, err.to_string()))?;
+    let mut filters = filters.write().map_err(poisoned)?;
+    if filters.contains_key(&request.name) {
+        return Err((
+            StatusCode::CONFLICT,
+            format!("Filter {} already exists.", request.name),
+        ));
+    }
+    filters.insert(request.name, Arc::new(builder.build_atomic()));
+    Ok(StatusCode::CREATED)
+}
+
+async fn insert_items(
+    State(filters): State<Filters>,
+    Path(name): Path<String>,
+    Json(request): Json<Items>,
+) -> Result<StatusCode, Error> {
+    let bloom = lookup(&filters, &name)?;
+    bloom.insert_all(request.items.iter().map(String::as_str));
+    Ok(StatusCode::NO_CONTENT)
+}
+
+async fn contains(
+    State(filters): State<Filters>,
+    Path(name): Path<String>,
+    Query(query): Query<ContainsQuery>,
+) -> Result<Json<Contains>, Error> {
+    let bloom = lookup(&filters, &name)?;
+    Ok(Json(Contains {
+        contains: bloom.test(query.item.as_str()),
+        item: query.item,
+    }))
+}
+
+async fn stats(
+    State(filters): State<Filters>,
+    Path(name): Path<String>,
+) -> Result<Json<Stats>, Error> {
+    let bloom = lookup(&filters, &name)?;
+    Ok(Json(Stats {
+        size: bloom.size(),
+        num_hashes: bloom.num_hashes(),
+        fill_ratio: bloom.fill_ratio(),
+        estimated_items: bloom.estimated_len(),
+        current_fpp: bloom.current_fpp(),
+    }))
+}
+
+async fn bytes(
+    State(filters): State<Filters>,
+    Path(name): Path<String>,
+) -> Result<impl IntoResponse, Error> {
+    let bloom = lookup(&filters, &name)?;
+    Ok((
+        [(header::CONTENT_TYPE, "application/octet-stream")],
+        bloom.to_bytes(),
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::{Read, Write};
+    use std::net::{SocketAddr, TcpStream};
+
+    // Plain HTTP/1.1 over a socket, returns the status and the body
+    fn request(addr: SocketAddr, method: &str, path: &str, body: &str) -> (u16, Vec<u8>) {
+        let mut stream = TcpStream::connect(addr).unwrap();
+        write!(
+            stream,
+            "{} {} HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n\
+             Content-Type: application/json\r\nContent-Length: {}\r\n\r\n{}",
+            method,
+            path,
+            body.len(),
+            body
+        )
+        .unwrap();
+        let mut response = Vec::new();
+        stream.read_to_end(&mut response).unwrap();
+        let status = std::str::from_utf8(&response[9..12])
+            .unwrap()
+            .parse()
+            .unwrap();
+        let body_start = response.windows(4).position(|w| w == b"\r\n\r\n").unwrap() + 4;
+        (status, response[body_start..].to_vec())
+    }
+
+    #[tokio::test]
+    async fn test_http_api() {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move { axum::serve(listener, router()).await });
+
+        let (status, body) = tokio::task::spawn_blocking(move || {
+            let create = r#"{"name": "users", "expected_items": 1000}"#;
+            assert_eq!(request(addr, "POST", "/filters", create).0, 201);
+            assert_eq!(request(addr, "POST", "/filters", create).0, 409);
+            let huge = r#"{"name": "huge", "expected_items": 1000000000000}"#;
+            assert_eq!(request(addr, "POST", "/filters", huge).0, 400);
+            let tiny_rate =
+                r#"{"name": "huge", "expected_items": 1000, "false_positive_rate": 1e-300}"#;
+            assert_eq!(request(addr, "POST", "/filters", tiny_rate).0, 400);
+            assert_eq!(request(addr, "GET", "/filters/huge/stats", "").0, 404);
+            let items = r#"{"items": ["alice", "bob"]}"#;
+            assert_eq!(request(addr, "POST", "/filters/users/items", items).0, 204);
+            assert_eq!(request(addr, "POST", "/filters/nope/items", items).0, 404);
+
+            let (status, body) = request(addr, "GET", "/filters/users/contains?item=alice", "");
+            assert_eq!(status, 200);
+            assert_eq!(body, br#"{"item":"alice","contains":true}"#);
+            let (_, body) = request(addr, "GET", "/filters/users/contains?item=carol", "");
+            assert_eq!(body, br#"{"item":"carol","contains":false}"#);
+            let (_, body) = request(addr, "GET", "/filters/users/stats", "");
+            assert!(body.starts_with(br#"{"size":9586,"num_hashes":7,"#));
+            request(addr, "GET", "/filters/users/bytes", "")
+        })
+        .await
+        .unwrap();
+        assert_eq!(status, 200);
+        let bloom = BloomFilter::from_bytes(&body).unwrap();
+        assert!(bloom.test("alice") && bloom.test("bob"));
+    }
+}
@@ -0,0 +1,175 @@
+// Probabilistic set with `HashSet` naming, for when the sizing and the bits don't matter:
+//
+//   let mut seen: BloomSet<str> = BloomSet::with_capacity(1_000_000);
+//   if seen.insert(url) { crawl(url) }
+//
+// Differences to `HashSet`: `contains` can answer true for items never inserted (about
+// false_positive_rate of the time while len stays under the capacity), nothing can be removed
+// and `len` is an estimate. The filter is `BloomFilterBuilder`'s with the same capacity and
+// rate, `as_filter` / `into_filter` give access to it.
+
+use std::fmt;
+use std::hash::{BuildHasher, Hash};
+use std::marker::PhantomData;
+
+use crate::hash::Sha256BuildHasher;
+use crate::{BloomFilter, BloomFilterBuilder};
+
+pub struct BloomSet<T: ?Sized, S = Sha256BuildHasher> {
+    filter: BloomFilter<S>,
+    // not owned, only hashed
+    _items: PhantomData<fn(&T)>,
+}
+
+impl<T: Hash + ?Sized> BloomSet<T> {
+    // Room for 1000 items at 1% false positives
+    pub fn new() -> Self {
+        BloomFilterBuilder::new().build_set()
+    }
+
+    // 1% false positives up to `capacity` items
+    pub fn with_capacity(capacity: usize) -> Self {
+        BloomFilterBuilder::new()
+            .expected_items(capacity)
+            .build_set()
+    }
+
+    pub fn with_capacity_and_rate(capacity: usize, false_positive_rate: f64) -> Self {
+        BloomFilterBuilder::new()
+            .expected_items(capacity)
+            .false_positive_rate(false_positive_rate)
+            .build_set()
+    }
+}
+
+impl<T: Hash + ?Sized> Default for BloomSet<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T: Hash + ?Sized, S: BuildHasher> BloomSet<T, S> {
+    pub fn from_filter(filter: BloomFilter<S>) -> Self {
+        BloomSet {
+            filter,
+            _items: PhantomData,
+        }
+    }
+
+    // Like `HashSet::insert`: true if the item wasn't in the set. A false positive makes it
+    // return false for a new item.
+    pub fn insert(&mut self, item: &T) -> bool {
+        !self.filter.insert(item)
+    }
+
+    pub fn contains(&self, item: &T) -> bool {
+        self.filter.test(item)
+    }
+
+    // Estimated number of distinct items, usize::MAX once the filter is saturated
+    pub fn len(&self) -> usize {
+        self.filter.estimated_len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.filter.bit_array.count_ones() == 0
+    }
+
+    pub fn clear(&mut self) {
+        self.filter.reset();
+    }
+
+    // The false positive probability at the current fill
+    pub fn false_positive_rate(&self) -> f64 {
+        self.filter.current_fpp()
+    }
+
+    pub fn memory_usage(&self) -> usize {
+        self.filter.memory_usage()
+    }
+
+    pub fn as_filter(&self) -> &BloomFilter<S> {
+        &self.filter
+    }
+
+    pub fn into_filter(self) -> BloomFilter<S> {
+        self.filter
+    }
+}
+
+impl<T: Hash, S: BuildHasher> Extend<T> for BloomSet<T, S> {
+    fn extend<I: IntoIterator<Item = T>>(&mut self, items: I) {
+        for item in items {
+            self.filter.set(&item);
+        }
+    }
+}
+
+impl<'a, T: Hash + ?Sized, S: BuildHasher> Extend<&'a T> for BloomSet<T, S> {
+    fn extend<I: IntoIterator<Item = &'a T>>(&mut self, items: I) {
+        for item in items {
+            self.filter.set(item);
+        }
+    }
+}
+
+// Sized for the collected items at 1% false positives, which needs them buffered first
+impl<T: Hash> FromIterator<T> for BloomSet<T> {
+    fn from_iter<I: IntoIterator<Item = T>>(items: I) -> Self {
+        let items: Vec<T> = items.into_iter().collect();
+        let mut set = Self::with_capacity(items.len().max(1));
+        set.extend(items);
+        set
+    }
+}
+
+impl<T: ?Sized, S: Clone> Clone for BloomSet<T, S> {
+    fn clone(&self) -> Self {
+        BloomSet {
+            filter: self.filter.clone(),
+            _items: PhantomData,
+        }
+    }
+}
+
+impl<T: ?Sized, S> fmt::Debug for BloomSet<T, S> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("BloomSet")
+            .field("size", &self.filter.size)
+            .field("num_hashes", &self.filter.num_hashes)
+            .finish()
+    }
+}
+
+impl BloomFilterBuilder {
+    pub fn build_set<T: Hash + ?Sized>(&self) -> BloomSet<T> {
+        BloomSet::from_filter(self.build())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_bloom_set() {
+        let mut set: BloomSet<str> = BloomSet::with_capacity(1000);
+        assert!(set.is_empty());
+        assert!(set.insert("a"));
+        assert!(!set.insert("a"));
+        assert!(set.contains("a"));
+        assert!(!set.contains("b"));
+
+        let keys: Vec<String> = (0..999).map(|i| i.to_string()).collect();
+        set.extend(keys.iter().map(String::as_str));
+        assert!((950..1050).contains(&set.len()), "len = {}", set.len());
+        assert!(set.false_positive_rate() < 0.015);
+        set.clear();
+        assert!(set.is_empty() && !set.contains("a"));
+
+        let numbers: BloomSet<u32> = (0..5000).collect();
+        assert!((0..5000).all(|i| numbers.contains(&i)));
+        let false_positives = (5000..15_000).filter(|i| numbers.contains(i)).count();
+        assert!(false_positives < 200, "{} false positives", false_positives);
+    }
+}
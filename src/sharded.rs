@@ -0,0 +1,163 @@
+// Bloom filter split into independently locked shards. The item hash picks the shard and all
+// k bits of the item are set inside it, so writers only contend when they hit the same shard
+// and a query takes a single read lock. With the default 64 shards concurrent writers rarely
+// meet, unlike `ThreadSafeBF` where every write takes the one lock.
+//
+// Every shard gets an equal part of the bits. The item is hashed once, the shard comes from
+// the high bits of the hash and the k positions from double hashing over remixes of it.
+
+use std::hash::{BuildHasher, Hash};
+use std::sync::RwLock;
+
+use crate::bitset::{word_count, BitVec};
+use crate::error::BloomError;
+use crate::hash::{fastrange, mix, Sha256BuildHasher};
+use crate::heap_bytes;
+use crate::stats::FilterStats;
+
+const DEFAULT_SHARDS: usize = 64;
+
+// Own cache line per shard so the lock words of neighbouring shards don't bounce between cores
+#[repr(align(64))]
+struct Shard(RwLock<BitVec>);
+
+pub struct ShardedBloomFilter<S = Sha256BuildHasher> {
+    shards: Vec<Shard>,
+    shard_size: usize,
+    num_hashes: usize,
+    hasher: S,
+}
+
+impl ShardedBloomFilter {
+    pub fn new(size: usize, num_hashes: usize) -> Self {
+        Self::with_shards(size, num_hashes, DEFAULT_SHARDS, Sha256BuildHasher)
+    }
+}
+
+impl<S: BuildHasher> ShardedBloomFilter<S> {
+    pub fn with_hasher(size: usize, num_hashes: usize, hasher: S) -> Self {
+        Self::with_shards(size, num_hashes, DEFAULT_SHARDS, hasher)
+    }
+
+    // size is rounded up to a multiple of num_shards
+    pub fn with_shards(size: usize, num_hashes: usize, num_shards: usize, hasher: S) -> Self {
+        assert!(num_shards > 0, "number of shards must not be 0");
+        let shard_size = size.div_ceil(num_shards).max(1);
+        ShardedBloomFilter {
+            shards: (0..num_shards)
+                .map(|_| Shard(RwLock::new(BitVec::new(shard_size))))
+                .collect(),
+            shard_size,
+            num_hashes,
+            hasher,
+        }
+    }
+
+    // Shard index and the bit positions of the item inside it
+    fn locate<T: Hash + ?Sized>(&self, item: &T) -> (usize, impl Iterator<Item = usize>) {
+        let hash = self.hasher.hash_one(item);
+        let shard = fastrange(hash, self.shards.len() as u64) as usize;
+        let h1 = mix(hash, 1);
+        let h2 = mix(hash, 2);
+        let shard_size = self.shard_size as u64;
+        let bits = (0..self.num_hashes as u64)
+            .map(move |i| fastrange(h1.wrapping_add(i.wrapping_mul(h2)), shard_size) as usize);
+        (shard, bits)
+    }
+
+    pub fn insert<T: Hash + ?Sized>(&self, item: &T) -> Result<(), BloomError> {
+        let (shard, bits) = self.locate(item);
+        let mut shard = self.shards[shard]
+            .0
+            .write()
+            .map_err(|_| BloomError::PoisonedLock)?;
+        for idx in bits {
+            shard.set(idx);
+        }
+        Ok(())
+    }
+
+    pub fn contains<T: Hash + ?Sized>(&self, item: &T) -> bool {
+        let (shard, mut bits) = self.locate(item);
+        let shard = self.shards[shard].0.read().unwrap();
+        bits.all(|idx| shard.get(idx))
+    }
+
+    pub fn num_shards(&self) -> usize {
+        self.shards.len()
+    }
+
+    pub fn bit_len(&self) -> usize {
+        self.shards.len() * self.shard_size
+    }
+
+    pub fn memory_usage(&self) -> usize {
+        // the shards never grow, no need to lock them
+        let shard_bits = word_count(self.shard_size) * size_of::<u64>();
+        size_of::<Self>() + heap_bytes(&self.shards) + self.shards.len() * shard_bits
+    }
+
+    pub fn num_hashes(&self) -> usize {
+        self.num_hashes
+    }
+
+    // Estimates per shard, one read lock at a time
+    pub fn stats(&self) -> Result<FilterStats, BloomError> {
+        let set_bits = self
+            .shards
+            .iter()
+            .map(|shard| Ok(shard.0.read()?.count_ones()))
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(|_: std::sync::PoisonError<_>| BloomError::PoisonedLock)?;
+        Ok(FilterStats::from_parts(
+            self.shard_size,
+            self.num_hashes,
+            set_bits.into_iter(),
+            self.memory_usage(),
+        ))
+    }
+
+    // Shard by shard, inserts running at the same time may or may not survive
+    pub fn clear(&self) -> Result<(), BloomError> {
+        for shard in &self.shards {
+            shard
+                .0
+                .write()
+                .map_err(|_| BloomError::PoisonedLock)?
+                .clear();
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Arc;
+    use std::thread;
+
+    #[test]
+    fn test_sharded_concurrent_inserts() {
+        let bloom = Arc::new(ShardedBloomFilter::new(100_000, 7));
+        assert_eq!(bloom.num_shards(), 64);
+        let handles: Vec<_> = (0..4u32)
+            .map(|t| {
+                let bloom = Arc::clone(&bloom);
+                thread::spawn(move || {
+                    for i in 0..2_000u32 {
+                        bloom.insert(&(t * 2_000 + i)).unwrap();
+                    }
+                })
+            })
+            .collect();
+        for handle in handles {
+            handle.join().unwrap();
+        }
+        assert!((0..8_000u32).all(|i| bloom.contains(&i)));
+        let false_positives = (8_000..108_000u32).filter(|i| bloom.contains(i)).count();
+        assert!(false_positives < 500, "{} false positives", false_positives);
+
+        bloom.clear().unwrap();
+        assert!(!bloom.contains(&1u32));
+    }
+}
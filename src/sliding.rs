@@ -0,0 +1,150 @@
+// Bloom filter over the last `window` inserted items, ex. "seen in the last million events".
+// The count based counterpart of `ExpiringBloomFilter`: the window is split into n segments of
+// window / n items, every segment is a plain Bloom filter and new items go into the newest
+// one. Once it is full the oldest of the n + 1 segments is cleared and reused as the newest,
+// so an item is reported for at least the next `window` inserts and at most
+// `window + window / n`.
+//
+// Unlike `AgePartitionedBloomFilter` every segment is only sized for its own items, the
+// trade-off is n + 1 probes per query.
+
+use std::collections::VecDeque;
+use std::hash::{BuildHasher, Hash};
+
+use crate::hash::Sha256BuildHasher;
+use crate::{params, BloomFilter};
+
+const DEFAULT_SEGMENTS: usize = 4;
+
+pub struct SlidingWindowBloomFilter<S = Sha256BuildHasher> {
+    // oldest first
+    segments: VecDeque<BloomFilter<S>>,
+    window: usize,
+    num_segments: usize,
+    segment_len: usize,
+    // inserts into the newest segment
+    count: usize,
+    size: usize,
+    num_hashes: usize,
+    hasher: S,
+}
+
+impl SlidingWindowBloomFilter {
+    pub fn new(window: usize, false_positive_rate: f64) -> Self {
+        Self::with_hasher(window, false_positive_rate, Sha256BuildHasher)
+    }
+}
+
+impl<S: BuildHasher + Clone> SlidingWindowBloomFilter<S> {
+    pub fn with_hasher(window: usize, false_positive_rate: f64, hasher: S) -> Self {
+        Self::with_options(window, DEFAULT_SEGMENTS, false_positive_rate, hasher)
+    }
+
+    // More segments forget items closer to the window but make queries slower
+    pub fn with_options(
+        window: usize,
+        num_segments: usize,
+        false_positive_rate: f64,
+        hasher: S,
+    ) -> Self {
+        assert!(window > 0, "window must not be 0");
+        assert!(num_segments > 0, "number of segments must not be 0");
+        assert!(
+            false_positive_rate > 0.0 && false_positive_rate < 1.0,
+            "false positive rate must be in (0, 1)"
+        );
+        let segment_len = window.div_ceil(num_segments);
+        // a query probes all n + 1 segments
+        let fpp = false_positive_rate / (num_segments + 1) as f64;
+        let size = params::optimal_bit_count(segment_len, fpp);
+        let num_hashes = params::optimal_num_hashes(size, segment_len);
+        SlidingWindowBloomFilter {
+            segments: VecDeque::with_capacity(num_segments + 1),
+            window,
+            num_segments,
+            segment_len,
+            count: 0,
+            size,
+            num_hashes,
+            hasher,
+        }
+    }
+
+    pub fn insert<T: Hash + ?Sized>(&mut self, item: &T) {
+        if self.segments.is_empty() || self.count >= self.segment_len {
+            self.rotate();
+        }
+        self.segments.back_mut().unwrap().set(item);
+        self.count += 1;
+    }
+
+    pub fn contains<T: Hash + ?Sized>(&self, item: &T) -> bool {
+        self.segments.iter().rev().any(|segment| segment.test(item))
+    }
+
+    // Inserts the item and returns whether it was seen within the window
+    pub fn check_and_insert<T: Hash + ?Sized>(&mut self, item: &T) -> bool {
+        let seen = self.contains(item);
+        self.insert(item);
+        seen
+    }
+
+    // Starts a new segment, retiring the oldest one if all n + 1 are in use
+    fn rotate(&mut self) {
+        let segment = if self.segments.len() > self.num_segments {
+            let mut oldest = self.segments.pop_front().unwrap();
+            oldest.reset();
+            oldest
+        } else {
+            BloomFilter::with_hasher(self.size, self.num_hashes, self.hasher.clone())
+        };
+        self.segments.push_back(segment);
+        self.count = 0;
+    }
+
+    pub fn window(&self) -> usize {
+        self.window
+    }
+
+    // Live segments, up to n + 1
+    pub fn num_segments(&self) -> usize {
+        self.segments.len()
+    }
+
+    pub fn memory_usage(&self) -> usize {
+        let segments: usize = self.segments.iter().map(|s| s.heap_bytes()).sum();
+        size_of::<Self>() + self.segments.capacity() * size_of::<BloomFilter<S>>() + segments
+    }
+
+    pub fn clear(&mut self) {
+        self.segments.clear();
+        self.count = 0;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sliding_window() {
+        let mut bloom = SlidingWindowBloomFilter::new(1000, 0.01);
+        for i in 0..10_000u32 {
+            bloom.insert(&i);
+        }
+        assert_eq!(bloom.num_segments(), 5);
+        assert!((9000..10_000u32).all(|i| bloom.contains(&i)));
+        // forgotten after window + window / 4 inserts
+        let false_positives = (0..8750u32).filter(|i| bloom.contains(i)).count();
+        assert!(false_positives < 150, "{} false positives", false_positives);
+
+        assert!(!bloom.check_and_insert("new"));
+        assert!(bloom.check_and_insert("new"));
+        for i in 0..1250u32 {
+            bloom.insert(&(i + 20_000));
+        }
+        assert!(!bloom.contains("new"));
+        bloom.clear();
+        assert!(!bloom.contains(&19_999u32));
+    }
+}
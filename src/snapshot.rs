@@ -0,0 +1,143 @@
+// Bloom filter with lock-free reads (feature `arc-swap`). Writers update a private filter under
+// a mutex and `publish` swaps a copy of it in as the new read snapshot, an immutable
+// `Arc<BloomFilter>`. Readers load the current snapshot without locking, so a query never
+// waits for a writer, however many writes are going on.
+//
+// Inserts are only visible to queries after the next `publish`, which copies the whole bit
+// array. Publish after a batch of writes (or on a timer), not after every insert.
+
+use std::hash::{BuildHasher, Hash};
+use std::sync::{Arc, Mutex};
+
+use arc_swap::ArcSwap;
+
+use crate::error::BloomError;
+use crate::hash::Sha256BuildHasher;
+use crate::stats::FilterStats;
+use crate::BloomFilter;
+
+pub struct SnapshotBloomFilter<S = Sha256BuildHasher> {
+    writer: Mutex<BloomFilter<S>>,
+    snapshot: ArcSwap<BloomFilter<S>>,
+}
+
+impl SnapshotBloomFilter {
+    pub fn new(size: usize, num_hashes: usize) -> Self {
+        Self::with_hasher(size, num_hashes, Sha256BuildHasher)
+    }
+}
+
+impl<S: BuildHasher + Clone> SnapshotBloomFilter<S> {
+    pub fn with_hasher(size: usize, num_hashes: usize, hasher: S) -> Self {
+        let bloom = BloomFilter::with_hasher(size, num_hashes, hasher);
+        SnapshotBloomFilter {
+            snapshot: ArcSwap::from_pointee(bloom.clone()),
+            writer: Mutex::new(bloom),
+        }
+    }
+
+    // Not visible to `test` until the next `publish`
+    pub fn set<T: Hash + ?Sized>(&self, item: &T) -> Result<(), BloomError> {
+        self.writer
+            .lock()
+            .map_err(|_| BloomError::PoisonedLock)?
+            .set(item);
+        Ok(())
+    }
+
+    pub fn insert_all<I>(&self, items: I) -> Result<(), BloomError>
+    where
+        I: IntoIterator,
+        I::Item: Hash,
+    {
+        self.writer
+            .lock()
+            .map_err(|_| BloomError::PoisonedLock)?
+            .insert_all(items);
+        Ok(())
+    }
+
+    // Makes everything set so far visible to readers
+    pub fn publish(&self) -> Result<(), BloomError> {
+        let snapshot = self
+            .writer
+            .lock()
+            .map_err(|_| BloomError::PoisonedLock)?
+            .clone();
+        self.snapshot.store(Arc::new(snapshot));
+        Ok(())
+    }
+
+    // Against the last published snapshot
+    pub fn test<T: Hash + ?Sized>(&self, item: &T) -> bool {
+        self.snapshot.load().test(item)
+    }
+
+    // The writer's filter and the current snapshot, older snapshots still held by readers are
+    // not counted
+    pub fn memory_usage(&self) -> Result<usize, BloomError> {
+        let writer = self.writer.lock().map_err(|_| BloomError::PoisonedLock)?;
+        let snapshot = self.snapshot.load();
+        Ok(size_of::<Self>()
+            + writer.heap_bytes()
+            + 2 * size_of::<usize>()
+            + size_of::<BloomFilter<S>>()
+            + snapshot.heap_bytes())
+    }
+
+    // Of the last published snapshot, memory_bytes counts the writer's filter too
+    pub fn stats(&self) -> Result<FilterStats, BloomError> {
+        Ok(FilterStats {
+            memory_bytes: self.memory_usage()?,
+            ..self.snapshot.load().stats()
+        })
+    }
+
+    // The last published snapshot, for many queries against the same state
+    pub fn snapshot(&self) -> Arc<BloomFilter<S>> {
+        self.snapshot.load_full()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::thread;
+    use std::time::{Duration, Instant};
+
+    #[test]
+    fn test_reads_see_published_snapshots() {
+        let bloom = Arc::new(SnapshotBloomFilter::new(10_000, 4));
+        bloom.set("foo").unwrap();
+        assert!(!bloom.test("foo"));
+        assert_eq!(bloom.stats().unwrap().set_bits, 0);
+        let before = bloom.snapshot();
+        bloom.publish().unwrap();
+        assert!(bloom.test("foo"));
+        assert!(!before.test("foo"));
+
+        let writer = {
+            let bloom = Arc::clone(&bloom);
+            thread::spawn(move || {
+                for i in 0..1_000u32 {
+                    bloom.set(&i).unwrap();
+                    if i % 100 == 99 {
+                        bloom.publish().unwrap();
+                    }
+                }
+            })
+        };
+        // runs until the writer is done, a panic in it shows up in `join`
+        let deadline = Instant::now() + Duration::from_secs(60);
+        while !writer.is_finished() {
+            assert!(Instant::now() < deadline, "writer timed out");
+            // a published prefix is always complete
+            let snapshot = bloom.snapshot();
+            if snapshot.test(&500u32) {
+                assert!((0..500u32).all(|i| snapshot.test(&i)));
+            }
+        }
+        writer.join().unwrap();
+        assert!((0..1_000u32).all(|i| bloom.test(&i)));
+    }
+}
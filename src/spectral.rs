@@ -0,0 +1,157 @@
+// Spectral Bloom filter (Cohen & Matias 2003): a counting filter whose counters answer
+// "how many times was this item inserted", estimated as the minimum of its k counters
+// (Minimum Selection). Estimates never undercount, they overcount when all k counters are
+// shared with other items.
+
+use std::hash::{BuildHasher, Hash};
+
+use crate::error::BloomError;
+use crate::hash::{self, Sha256BuildHasher};
+use crate::heap_bytes;
+
+pub struct SpectralBloomFilter<S = Sha256BuildHasher> {
+    counters: Vec<u32>,
+    num_hashes: usize,
+    size: usize,
+    // Minimal Increase: only raise the smallest counters on insert, see `minimal_increase`
+    minimal_increase: bool,
+    hasher: S,
+}
+
+impl SpectralBloomFilter {
+    pub fn new(size: usize, num_hashes: usize) -> Self {
+        Self::with_hasher(size, num_hashes, Sha256BuildHasher)
+    }
+
+    pub fn try_new(size: usize, num_hashes: usize) -> Result<Self, BloomError> {
+        Self::try_with_hasher(size, num_hashes, Sha256BuildHasher)
+    }
+}
+
+impl<S: BuildHasher> SpectralBloomFilter<S> {
+    // Panics unless size is at least 1 and num_hashes between 1 and 64, see `try_with_hasher`
+    pub fn with_hasher(size: usize, num_hashes: usize, hasher: S) -> Self {
+        Self::try_with_hasher(size, num_hashes, hasher).unwrap_or_else(|err| panic!("{}", err))
+    }
+
+    pub fn try_with_hasher(size: usize, num_hashes: usize, hasher: S) -> Result<Self, BloomError> {
+        crate::check_params(size, num_hashes)?;
+        Ok(SpectralBloomFilter {
+            counters: vec![0; size],
+            num_hashes,
+            size,
+            minimal_increase: false,
+            hasher,
+        })
+    }
+
+    // Minimal Increase gives much better estimates for skewed streams but `remove` can't be
+    // used anymore (it would create undercounts).
+    pub fn minimal_increase(mut self, minimal_increase: bool) -> Self {
+        self.minimal_increase = minimal_increase;
+        self
+    }
+
+    pub fn insert<T: Hash + ?Sized>(&mut self, item: &T) {
+        self.insert_count(item, 1);
+    }
+
+    pub fn insert_count<T: Hash + ?Sized>(&mut self, item: &T, count: u32) {
+        if self.minimal_increase {
+            let target = (self.estimate(item) + count as u64).min(u32::MAX as u64) as u32;
+            for idx in hash::indices(&self.hasher, item, self.num_hashes, self.size) {
+                self.counters[idx] = self.counters[idx].max(target);
+            }
+        } else {
+            for idx in hash::indices(&self.hasher, item, self.num_hashes, self.size) {
+                self.counters[idx] = self.counters[idx].saturating_add(count);
+            }
+        }
+    }
+
+    // Returns false if the item is definitely not present. Fails with minimal increase on.
+    pub fn remove<T: Hash + ?Sized>(&mut self, item: &T) -> Result<bool, BloomError> {
+        if self.minimal_increase {
+            return Err(BloomError::InvalidParams(
+                "Can't remove items with minimal increase on.".into(),
+            ));
+        }
+        if !self.contains(item) {
+            return Ok(false);
+        }
+        for idx in hash::indices(&self.hasher, item, self.num_hashes, self.size) {
+            if self.counters[idx] != u32::MAX {
+                self.counters[idx] -= 1;
+            }
+        }
+        Ok(true)
+    }
+
+    // Approximate multiplicity of the item, never below the real count
+    pub fn estimate<T: Hash + ?Sized>(&self, item: &T) -> u64 {
+        hash::indices(&self.hasher, item, self.num_hashes, self.size)
+            .map(|idx| self.counters[idx] as u64)
+            .min()
+            .unwrap_or(0)
+    }
+
+    pub fn contains<T: Hash + ?Sized>(&self, item: &T) -> bool {
+        self.estimate(item) > 0
+    }
+
+    pub fn memory_usage(&self) -> usize {
+        size_of::<Self>() + heap_bytes(&self.counters)
+    }
+
+    pub fn clear(&mut self) {
+        self.counters.fill(0);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_estimates() {
+        let mut bloom = SpectralBloomFilter::new(1000, 3);
+        for _ in 0..5 {
+            bloom.insert("foo");
+        }
+        bloom.insert_count("bar", 42);
+        assert_eq!(bloom.estimate("foo"), 5);
+        assert_eq!(bloom.estimate("bar"), 42);
+        assert_eq!(bloom.estimate("baz"), 0);
+        assert!(bloom.contains("foo"));
+        assert!(!bloom.contains("baz"));
+
+        assert!(bloom.remove("foo").unwrap());
+        assert_eq!(bloom.estimate("foo"), 4);
+        assert!(!bloom.remove("baz").unwrap());
+
+        assert!(SpectralBloomFilter::try_new(0, 3).is_err());
+        assert!(SpectralBloomFilter::try_new(1000, 0).is_err());
+        assert!(std::panic::catch_unwind(|| SpectralBloomFilter::new(0, 3)).is_err());
+    }
+
+    #[test]
+    fn test_minimal_increase_never_undercounts() {
+        let mut bloom = SpectralBloomFilter::new(64, 3).minimal_increase(true);
+        for i in 0..200u32 {
+            bloom.insert_count(&(i % 20), i % 7 + 1);
+        }
+        for key in 0..20u32 {
+            let real: u64 = (0..200u32)
+                .filter(|i| i % 20 == key)
+                .map(|i| (i % 7 + 1) as u64)
+                .sum();
+            assert!(bloom.estimate(&key) >= real);
+        }
+        assert!(bloom.remove(&0u32).is_err());
+
+        // estimates saturate instead of wrapping
+        bloom.insert_count("big", u32::MAX);
+        bloom.insert_count("big", u32::MAX);
+        assert_eq!(bloom.estimate("big"), u32::MAX as u64);
+    }
+}
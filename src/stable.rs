@@ -0,0 +1,138 @@
+// Stable Bloom filter (Deng & Rafiei 2006) for deduplicating unbounded streams.
+// Every cell is a small counter: inserting an item first decrements `decrement` random cells
+// and then sets its k cells to the max value, so old items slowly fade out and the fraction of
+// zero cells converges to a stable point instead of the filter filling up.
+// Unlike a plain Bloom filter it can have false negatives (for items that faded out).
+
+use std::hash::{BuildHasher, Hash};
+
+use crate::hash::{self, Sha256BuildHasher};
+use crate::heap_bytes;
+use crate::rng::Rng;
+use crate::stats::FilterStats;
+
+pub struct StableBloomFilter<S = Sha256BuildHasher> {
+    cells: Vec<u8>,
+    max: u8,
+    num_hashes: usize,
+    decrement: usize,
+    size: usize,
+    hasher: S,
+    rng: Rng,
+}
+
+impl StableBloomFilter {
+    // size: number of cells
+    // cell_width: bits per cell, 1 to 8, cells count down from 2^cell_width - 1
+    // decrement: cells decremented on every insert
+    pub fn new(size: usize, cell_width: u8, num_hashes: usize, decrement: usize) -> Self {
+        Self::with_hasher(size, cell_width, num_hashes, decrement, Sha256BuildHasher)
+    }
+}
+
+impl<S: BuildHasher> StableBloomFilter<S> {
+    pub fn with_hasher(
+        size: usize,
+        cell_width: u8,
+        num_hashes: usize,
+        decrement: usize,
+        hasher: S,
+    ) -> Self {
+        assert!(
+            (1..=8).contains(&cell_width),
+            "cell width must be between 1 and 8 bits"
+        );
+        StableBloomFilter {
+            cells: vec![0; size],
+            max: (((1u16) << cell_width) - 1) as u8,
+            num_hashes,
+            decrement,
+            size,
+            hasher,
+            rng: Rng::from_entropy(),
+        }
+    }
+
+    // Fixes the random decrements, two filters with the same seed and inputs end up identical
+    pub fn rng_seed(mut self, seed: u64) -> Self {
+        self.rng = Rng::new(seed);
+        self
+    }
+
+    pub fn insert<T: Hash + ?Sized>(&mut self, item: &T) {
+        // Decrement `decrement` consecutive cells starting at a random one, as in the paper
+        let start = self.rng.below(self.size);
+        for i in 0..self.decrement.min(self.size) {
+            let cell = &mut self.cells[(start + i) % self.size];
+            *cell = cell.saturating_sub(1);
+        }
+        for idx in hash::indices(&self.hasher, item, self.num_hashes, self.size) {
+            self.cells[idx] = self.max;
+        }
+    }
+
+    pub fn contains<T: Hash + ?Sized>(&self, item: &T) -> bool {
+        hash::indices(&self.hasher, item, self.num_hashes, self.size).all(|idx| self.cells[idx] > 0)
+    }
+
+    // Insert and report whether the item was (possibly) seen before, the usual stream dedup call
+    pub fn test_and_insert<T: Hash + ?Sized>(&mut self, item: &T) -> bool {
+        let seen = self.contains(item);
+        self.insert(item);
+        seen
+    }
+
+    // False positive rate once the filter reached its stable point:
+    // (1 - (1 / (1 + 1 / (P * (1/k - 1/m))))^Max)^k
+    pub fn stable_false_positive_rate(&self) -> f64 {
+        let k = self.num_hashes as f64;
+        let m = self.size as f64;
+        let p = self.decrement as f64;
+        let zero_fraction = (1.0 / (1.0 + 1.0 / (p * (1.0 / k - 1.0 / m)))).powi(self.max as i32);
+        (1.0 - zero_fraction).powi(self.num_hashes as i32)
+    }
+
+    // Nonzero cells count as set bits, so estimated_items are the items that haven't faded
+    // out yet and estimated_fpp the current rate, not the stable one
+    pub fn stats(&self) -> FilterStats {
+        let set_cells = self.cells.iter().filter(|&&cell| cell > 0).count();
+        FilterStats::new(self.size, self.num_hashes, set_cells, self.memory_usage())
+    }
+
+    pub fn memory_usage(&self) -> usize {
+        size_of::<Self>() + heap_bytes(&self.cells)
+    }
+
+    pub fn clear(&mut self) {
+        self.cells.fill(0);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_recent_items_are_remembered() {
+        let mut bloom = StableBloomFilter::new(10_000, 3, 3, 10).rng_seed(7);
+        assert!(!bloom.test_and_insert("foo"));
+        assert!(bloom.test_and_insert("foo"));
+        assert!(bloom.contains("foo"));
+        assert!(!bloom.contains("bar"));
+    }
+
+    #[test]
+    fn test_old_items_fade_out() {
+        let mut bloom = StableBloomFilter::new(1000, 1, 3, 50).rng_seed(7);
+        bloom.insert("old");
+        for i in 0..10_000 {
+            bloom.insert(&i);
+        }
+        assert!(!bloom.contains("old"));
+
+        // the stream never saturates the filter
+        let zero_cells = bloom.cells.iter().filter(|&&c| c == 0).count();
+        assert!(zero_cells > 0);
+        assert!(bloom.stable_false_positive_rate() < 1.0);
+    }
+}
@@ -0,0 +1,217 @@
+// Point-in-time report on a filter for capacity monitoring, from `stats()` on the Bloom filter
+// types. Logs as one line through `Display`, serializes with the `serde` feature.
+//
+// The estimates assume a standard filter: estimated_items from the number of set bits
+// (Swamidass & Baldi), estimated_fpp = fill_ratio^k. Types that don't spread an item over the
+// whole array (blocked, sharded) sum and average those per block or shard.
+
+use std::fmt;
+
+use crate::params;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct FilterStats {
+    // bits, or counters for counting filters
+    pub size_bits: usize,
+    pub num_hashes: usize,
+    // set bits (nonzero counters)
+    pub set_bits: usize,
+    pub fill_ratio: f64,
+    // distinct items inserted, usize::MAX once every bit is set
+    pub estimated_items: usize,
+    pub estimated_fpp: f64,
+    pub memory_bytes: usize,
+}
+
+impl FilterStats {
+    // Stats of one standard filter
+    pub(crate) fn new(
+        size_bits: usize,
+        num_hashes: usize,
+        set_bits: usize,
+        memory_bytes: usize,
+    ) -> Self {
+        let fill_ratio = crate::fill_ratio(set_bits, size_bits);
+        FilterStats {
+            size_bits,
+            num_hashes,
+            set_bits,
+            fill_ratio,
+            estimated_items: params::estimated_items(size_bits, num_hashes, set_bits).round()
+                as usize,
+            estimated_fpp: fill_ratio.powi(num_hashes as i32),
+            memory_bytes,
+        }
+    }
+
+    // Stats of equally sized parts of which a query probes one (blocks, shards): the items
+    // add up, the false positive probability is the average over the parts
+    pub(crate) fn from_parts(
+        part_bits: usize,
+        num_hashes: usize,
+        set_bits: impl ExactSizeIterator<Item = usize>,
+        memory_bytes: usize,
+    ) -> Self {
+        let parts = set_bits.len();
+        let mut stats = FilterStats::new(part_bits * parts, num_hashes, 0, memory_bytes);
+        stats.estimated_items = 0;
+        stats.estimated_fpp = 0.0;
+        for ones in set_bits {
+            let part = FilterStats::new(part_bits, num_hashes, ones, 0);
+            stats.set_bits += ones;
+            stats.estimated_items = stats.estimated_items.saturating_add(part.estimated_items);
+            stats.estimated_fpp += part.estimated_fpp / parts as f64;
+        }
+        stats.fill_ratio = crate::fill_ratio(stats.set_bits, stats.size_bits);
+        stats
+    }
+
+    // Stats of filters that are all queried (scalable chains, generations): the sizes and items
+    // add up, num_hashes is the one of the last filter and the false positive probability the
+    // chance that any of them answers wrong
+    pub(crate) fn from_chain(parts: impl IntoIterator<Item = Self>, memory_bytes: usize) -> Self {
+        let mut stats = FilterStats {
+            estimated_fpp: 1.0,
+            memory_bytes,
+            ..FilterStats::new(0, 0, 0, 0)
+        };
+        for part in parts {
+            stats.size_bits += part.size_bits;
+            stats.num_hashes = part.num_hashes;
+            stats.set_bits += part.set_bits;
+            stats.estimated_items = stats.estimated_items.saturating_add(part.estimated_items);
+            stats.estimated_fpp *= 1.0 - part.estimated_fpp;
+        }
+        stats.fill_ratio = crate::fill_ratio(stats.set_bits, stats.size_bits);
+        stats.estimated_fpp = 1.0 - stats.estimated_fpp;
+        stats
+    }
+}
+
+impl fmt::Display for FilterStats {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} bits, k = {}, {} set ({:.1}% full), ~",
+            self.size_bits,
+            self.num_hashes,
+            self.set_bits,
+            self.fill_ratio * 100.0
+        )?;
+        if self.estimated_items == usize::MAX {
+            f.write_str("? items")?;
+        } else {
+            write!(f, "{} items", self.estimated_items)?;
+        }
+        write!(
+            f,
+            ", fpp {:.2e}, {} bytes",
+            self.estimated_fpp, self.memory_bytes
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use crate::{
+        AgePartitionedBloomFilter, BlockedBloomFilter, BloomFilter, ConstBloomFilter,
+        CountingBloomFilter, DeltaBloomFilter, ExpiringBloomFilter, GuavaBloomFilter,
+        RedisBloomFilter, ScalableBloomFilter, ShardedBloomFilter, StableBloomFilter,
+    };
+
+    #[test]
+    fn test_stats() {
+        let mut bloom = BloomFilter::builder().expected_items(1000).build();
+        bloom.insert_all(0..1000u32);
+        let stats = bloom.stats();
+        assert_eq!(stats.size_bits, 9586);
+        assert_eq!(stats.num_hashes, 7);
+        assert!((stats.fill_ratio - 0.5).abs() < 0.02);
+        assert!((950..1050).contains(&stats.estimated_items));
+        assert!((stats.estimated_fpp - 0.01).abs() < 0.002);
+        assert_eq!(stats.memory_bytes, bloom.memory_usage());
+        let line = stats.to_string();
+        assert!(line.starts_with("9586 bits, k = 7, "), "{}", line);
+        assert!(line.contains("% full), ~"), "{}", line);
+
+        let mut blocked = BlockedBloomFilter::new(9586, 7);
+        let mut counting = CountingBloomFilter::new(9586, 7);
+        let sharded = ShardedBloomFilter::new(9586, 7);
+        let mut scalable = ScalableBloomFilter::new(500, 0.01);
+        for i in 0..1000u32 {
+            blocked.insert(&i);
+            counting.insert(&i);
+            sharded.insert(&i).unwrap();
+            scalable.insert(&i);
+        }
+        for stats in [
+            blocked.stats(),
+            counting.stats(),
+            sharded.stats().unwrap(),
+            scalable.stats(),
+        ] {
+            assert!((850..1150).contains(&stats.estimated_items), "{}", stats);
+            assert!(
+                stats.estimated_fpp > 0.001 && stats.estimated_fpp < 0.05,
+                "{}",
+                stats
+            );
+        }
+
+        let mut full = BloomFilter::new(64, 3);
+        full.insert_all(0..1000u32);
+        assert!(full.stats().to_string().contains("~? items"));
+    }
+
+    #[test]
+    fn test_stats_of_wrappers() {
+        let mut guava = GuavaBloomFilter::new(1000, 0.01);
+        let mut delta = DeltaBloomFilter::new(9586, 7);
+        let mut stable = StableBloomFilter::new(9586, 1, 7, 0);
+        let mut expiring = ExpiringBloomFilter::new(Duration::from_secs(60), 1000, 0.01);
+        let mut redis = RedisBloomFilter::reserve(0.01, 1000).unwrap();
+        let mut inline = ConstBloomFilter::<150, 7>::new();
+        for i in 0..1000u32 {
+            guava.insert(i.to_le_bytes());
+            delta.set(&i);
+            stable.insert(&i);
+            expiring.insert(&i);
+            redis.insert(i.to_le_bytes());
+            inline.insert(&i);
+        }
+        for stats in [
+            guava.stats(),
+            delta.stats(),
+            stable.stats(),
+            expiring.stats(),
+            redis.stats(),
+            inline.stats(),
+        ] {
+            assert!((900..1100).contains(&stats.estimated_items), "{}", stats);
+            assert!(
+                stats.estimated_fpp > 0.001 && stats.estimated_fpp < 0.02,
+                "{}",
+                stats
+            );
+            assert!(stats.memory_bytes > 0, "{}", stats);
+        }
+        assert_eq!(inline.stats().memory_bytes, 150 * 8);
+
+        // k = 7 newest slices answer, ~0.1% for l = 7 once the window is full
+        let mut aged = AgePartitionedBloomFilter::new(7, 7, 1000);
+        for i in 0..5000u32 {
+            aged.insert(&i);
+        }
+        let stats = aged.stats();
+        let measured = (5000..105_000u32).filter(|i| aged.contains(i)).count() as f64 / 1e5;
+        assert!(
+            (stats.estimated_fpp - measured).abs() < 0.002,
+            "{} measured {}",
+            stats,
+            measured
+        );
+    }
+}
@@ -0,0 +1,120 @@
+// Probabilistic dedup for async streams (feature `futures`), the `Stream` counterpart of
+// `BloomDedupExt`:
+//
+//   use bloomf::BloomDedupStreamExt;
+//   let events = consumer.stream().bloom_dedup(10_000_000, 0.001);
+//
+// The filter is an `AtomicBloomFilter` behind an `Arc`, so `bloom_dedup_shared` can hand the
+// same one to several streams, ex. one per Kafka partition or websocket connection, to dedup
+// across them. The bits are set one at a time, so an item arriving on two streams at
+// the same moment can pass through both: at least one of them, rarely more. Same trade-off as
+// the iterator adapter: about false_positive_rate of the new items are dropped as duplicates.
+
+use std::hash::{BuildHasher, Hash};
+use std::pin::Pin;
+use std::sync::Arc;
+use std::task::{Context, Poll};
+
+use futures_core::stream::{FusedStream, Stream};
+use pin_project_lite::pin_project;
+
+use crate::hash::Sha256BuildHasher;
+use crate::{AtomicBloomFilter, BloomFilter};
+
+pub trait BloomDedupStreamExt: Stream + Sized {
+    // Filter sized for `capacity` distinct items at `false_positive_rate`
+    fn bloom_dedup(self, capacity: usize, false_positive_rate: f64) -> BloomDedupStream<Self>
+    where
+        Self::Item: Hash,
+    {
+        let filter = BloomFilter::builder()
+            .expected_items(capacity)
+            .false_positive_rate(false_positive_rate)
+            .build_atomic();
+        self.bloom_dedup_shared(Arc::new(filter))
+    }
+
+    fn bloom_dedup_shared<S: BuildHasher>(
+        self,
+        filter: Arc<AtomicBloomFilter<S>>,
+    ) -> BloomDedupStream<Self, S>
+    where
+        Self::Item: Hash,
+    {
+        BloomDedupStream {
+            stream: self,
+            filter,
+        }
+    }
+}
+
+impl<St: Stream> BloomDedupStreamExt for St {}
+
+pin_project! {
+    pub struct BloomDedupStream<St, S = Sha256BuildHasher> {
+        #[pin]
+        stream: St,
+        filter: Arc<AtomicBloomFilter<S>>,
+    }
+}
+
+impl<St, S> BloomDedupStream<St, S> {
+    pub fn filter(&self) -> &Arc<AtomicBloomFilter<S>> {
+        &self.filter
+    }
+}
+
+impl<St, S> Stream for BloomDedupStream<St, S>
+where
+    St: Stream,
+    St::Item: Hash,
+    S: BuildHasher,
+{
+    type Item = St::Item;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<St::Item>> {
+        let mut this = self.project();
+        loop {
+            match this.stream.as_mut().poll_next(cx) {
+                Poll::Ready(Some(item)) if this.filter.insert(&item) => continue,
+                poll => return poll,
+            }
+        }
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (0, self.stream.size_hint().1)
+    }
+}
+
+impl<St, S> FusedStream for BloomDedupStream<St, S>
+where
+    St: FusedStream,
+    St::Item: Hash,
+    S: BuildHasher,
+{
+    fn is_terminated(&self) -> bool {
+        self.stream.is_terminated()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use futures::stream::{self, StreamExt};
+
+    #[tokio::test]
+    async fn test_stream_dedup() {
+        let items = stream::iter((0..1000u32).chain(500..1500));
+        let unique: Vec<u32> = items.bloom_dedup(1500, 0.001).collect().await;
+        assert!(unique.len() > 1490 && unique.len() <= 1500);
+
+        // polled one after the other, an item passes through one of the streams sharing the filter
+        let filter = Arc::new(AtomicBloomFilter::new(10_000, 7));
+        let left = stream::iter(["a", "b"]).bloom_dedup_shared(filter.clone());
+        let right = stream::iter(["b", "c", "a"]).bloom_dedup_shared(filter);
+        let mut seen: Vec<&str> = left.chain(right).collect().await;
+        seen.sort();
+        assert_eq!(seen, ["a", "b", "c"]);
+    }
+}
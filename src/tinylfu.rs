@@ -0,0 +1,173 @@
+// Frequency sketch of W-TinyLFU cache admission (Einziger, Friedman & Manes 2017). Access
+// counts are kept in a Count-Min sketch of 4 rows of 4 bit counters (16 to a word), updated
+// conservatively: only the counters at the minimum are incremented. Every `sample_size`
+// recorded accesses all counters are halved, so old popularity fades and counters saturating
+// at 15 is enough.
+//
+// A `Doorkeeper` sits in front of the counters: the first access of an item in a sample only
+// sets its bits, the counters start counting from the second one. One-hit wonders, most of the
+// keys in a typical trace, never take up counter space. Both are reset together.
+//
+//   let mut sketch = FrequencySketch::new(cache_capacity);
+//   sketch.increment(&key);                        // on every access
+//   if sketch.admit(&candidate, &victim) { ... }   // on eviction from the window cache
+
+use std::hash::{BuildHasher, Hash};
+
+use crate::doorkeeper::Doorkeeper;
+use crate::hash::{self, Sha256BuildHasher};
+use crate::heap_bytes;
+
+const DEPTH: usize = 4;
+const MAX_COUNT: u64 = 15;
+const COUNTERS_PER_WORD: usize = 16;
+// halves every counter of a word
+const HALF_MASK: u64 = 0x7777_7777_7777_7777;
+const DOORKEEPER_FPP: f64 = 0.01;
+// samples of ten times the cache capacity, as in the paper
+const SAMPLE_FACTOR: usize = 10;
+
+pub struct FrequencySketch<S = Sha256BuildHasher> {
+    counters: Vec<u64>,
+    // counters per row
+    width: usize,
+    doorkeeper: Doorkeeper<S>,
+    sample_size: usize,
+    // accesses recorded since the last reset, halved with the counters
+    additions: usize,
+    hasher: S,
+}
+
+impl FrequencySketch {
+    pub fn new(capacity: usize) -> Self {
+        Self::with_hasher(capacity, Sha256BuildHasher)
+    }
+}
+
+impl<S: BuildHasher + Clone> FrequencySketch<S> {
+    // capacity: number of entries of the cache
+    pub fn with_hasher(capacity: usize, hasher: S) -> Self {
+        let capacity = capacity.max(1);
+        let width = capacity.next_multiple_of(COUNTERS_PER_WORD);
+        let sample_size = capacity.saturating_mul(SAMPLE_FACTOR);
+        FrequencySketch {
+            counters: vec![0; DEPTH * width / COUNTERS_PER_WORD],
+            width,
+            doorkeeper: Doorkeeper::with_hasher(sample_size, DOORKEEPER_FPP, hasher.clone()),
+            sample_size,
+            additions: 0,
+            hasher,
+        }
+    }
+}
+
+impl<S: BuildHasher> FrequencySketch<S> {
+    // Records an access
+    pub fn increment<T: Hash + ?Sized>(&mut self, item: &T) {
+        // the doorkeeper never reaches its own reset period, reset() below clears it first
+        if self.doorkeeper.admit(item) {
+            let slots = self.slots(item);
+            let min = slots.iter().map(|&slot| self.counter(slot)).min().unwrap();
+            if min < MAX_COUNT {
+                for slot in slots {
+                    if self.counter(slot) == min {
+                        self.counters[slot / COUNTERS_PER_WORD] +=
+                            1 << (slot % COUNTERS_PER_WORD * 4);
+                    }
+                }
+            }
+        }
+        self.additions += 1;
+        if self.additions >= self.sample_size {
+            self.reset();
+        }
+    }
+
+    // Estimated accesses in the recent samples, up to 16
+    pub fn frequency<T: Hash + ?Sized>(&self, item: &T) -> u64 {
+        let seen = self.doorkeeper.contains(item) as u64;
+        let count = self
+            .slots(item)
+            .iter()
+            .map(|&slot| self.counter(slot))
+            .min()
+            .unwrap();
+        count + seen
+    }
+
+    // TinyLFU admission: whether the candidate is worth evicting the victim for
+    pub fn admit<T: Hash + ?Sized, U: Hash + ?Sized>(&self, candidate: &T, victim: &U) -> bool {
+        self.frequency(candidate) > self.frequency(victim)
+    }
+
+    // Ages all counts: halves the counters and clears the doorkeeper. Runs automatically every
+    // sample_size accesses.
+    pub fn reset(&mut self) {
+        for word in &mut self.counters {
+            *word = (*word >> 1) & HALF_MASK;
+        }
+        self.doorkeeper.reset();
+        self.additions /= 2;
+    }
+
+    // Counter of every row, as indices into the packed counters
+    fn slots<T: Hash + ?Sized>(&self, item: &T) -> [usize; DEPTH] {
+        let mut slots = [0; DEPTH];
+        for (row, column) in hash::indices(&self.hasher, item, DEPTH, self.width).enumerate() {
+            slots[row] = row * self.width + column;
+        }
+        slots
+    }
+
+    fn counter(&self, slot: usize) -> u64 {
+        (self.counters[slot / COUNTERS_PER_WORD] >> (slot % COUNTERS_PER_WORD * 4)) & MAX_COUNT
+    }
+
+    pub fn sample_size(&self) -> usize {
+        self.sample_size
+    }
+
+    pub fn memory_usage(&self) -> usize {
+        size_of::<Self>() - size_of::<Doorkeeper<S>>()
+            + heap_bytes(&self.counters)
+            + self.doorkeeper.memory_usage()
+    }
+
+    pub fn clear(&mut self) {
+        self.counters.fill(0);
+        self.doorkeeper.reset();
+        self.additions = 0;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_frequency() {
+        let mut sketch = FrequencySketch::new(1000);
+        sketch.increment("once");
+        assert_eq!(sketch.frequency("once"), 1);
+        for _ in 0..5 {
+            sketch.increment("hot");
+        }
+        assert_eq!(sketch.frequency("hot"), 5);
+        for _ in 0..100 {
+            sketch.increment("hot");
+        }
+        assert_eq!(sketch.frequency("hot"), 16);
+        assert_eq!(sketch.frequency("never"), 0);
+        assert!(sketch.admit("hot", "once"));
+        assert!(!sketch.admit("once", "hot"));
+
+        // a sample of one-hit wonders ages the hot item
+        for i in 0..sketch.sample_size() as u32 {
+            sketch.increment(&i);
+        }
+        assert_eq!(sketch.frequency("hot"), 7);
+        assert_eq!(sketch.frequency("once"), 0);
+        sketch.clear();
+        assert_eq!(sketch.frequency("hot"), 0);
+    }
+}
@@ -0,0 +1,181 @@
+// Space-Saving (Metwally, Agrawal & El Abbadi 2005) for the k most frequent items of a stream.
+// Keeps k monitored items with counters; an unmonitored item replaces the item with the smallest
+// counter and inherits that counter (+1), recording it as the overestimation error. Any item with a
+// true frequency above total / k is guaranteed to be monitored.
+
+use std::borrow::Borrow;
+use std::collections::{BTreeSet, HashMap};
+use std::hash::Hash;
+
+use crate::heap_bytes;
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct TopKEntry<T> {
+    pub item: T,
+    // upper bound of the true count
+    pub count: u64,
+    // count - error is a lower bound of the true count
+    pub error: u64,
+}
+
+pub struct TopK<T> {
+    entries: Vec<TopKEntry<T>>,
+    slots: HashMap<T, usize>,
+    // (count, slot) ordered so the first element is the eviction candidate
+    order: BTreeSet<(u64, usize)>,
+    k: usize,
+    total: u64,
+}
+
+impl<T: Hash + Eq + Clone> TopK<T> {
+    pub fn new(k: usize) -> Self {
+        assert!(k > 0, "k must not be 0");
+        TopK {
+            entries: Vec::with_capacity(k),
+            slots: HashMap::with_capacity(k),
+            order: BTreeSet::new(),
+            k,
+            total: 0,
+        }
+    }
+
+    pub fn insert(&mut self, item: T) -> Option<T> {
+        self.insert_by(item, 1)
+    }
+
+    // Returns the item evicted to make room, if any
+    pub fn insert_by(&mut self, item: T, count: u64) -> Option<T> {
+        self.total = self.total.saturating_add(count);
+
+        if let Some(&slot) = self.slots.get(&item) {
+            self.bump(slot, count);
+            return None;
+        }
+
+        if self.entries.len() < self.k {
+            let slot = self.entries.len();
+            self.entries.push(TopKEntry {
+                item: item.clone(),
+                count: 0,
+                error: 0,
+            });
+            self.slots.insert(item, slot);
+            self.order.insert((0, slot));
+            self.bump(slot, count);
+            return None;
+        }
+
+        let &(min, slot) = self.order.first().expect("TopK is full");
+        let evicted = std::mem::replace(&mut self.entries[slot].item, item.clone());
+        self.entries[slot].error = min;
+        self.slots.remove(&evicted);
+        self.slots.insert(item, slot);
+        self.bump(slot, count);
+        Some(evicted)
+    }
+
+    fn bump(&mut self, slot: usize, count: u64) {
+        let entry = &mut self.entries[slot];
+        self.order.remove(&(entry.count, slot));
+        entry.count = entry.count.saturating_add(count);
+        self.order.insert((entry.count, slot));
+    }
+
+    // Estimated count of a monitored item
+    pub fn count<Q>(&self, item: &Q) -> Option<u64>
+    where
+        T: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.slots.get(item).map(|&slot| self.entries[slot].count)
+    }
+
+    pub fn contains<Q>(&self, item: &Q) -> bool
+    where
+        T: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.slots.contains_key(item)
+    }
+
+    // Monitored items, most frequent first
+    pub fn top(&self) -> Vec<TopKEntry<T>> {
+        self.order
+            .iter()
+            .rev()
+            .map(|&(_, slot)| self.entries[slot].clone())
+            .collect()
+    }
+
+    pub fn k(&self) -> usize {
+        self.k
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    // Sum of all inserted counts
+    pub fn total(&self) -> u64 {
+        self.total
+    }
+
+    // Approximate: the map counts one control byte per bucket, the ordered set only its
+    // elements, and heap memory owned by the items themselves (ex. String contents) is not seen
+    pub fn memory_usage(&self) -> usize {
+        size_of::<Self>()
+            + heap_bytes(&self.entries)
+            + self.slots.capacity() * (size_of::<(T, usize)>() + 1)
+            + self.order.len() * size_of::<(u64, usize)>()
+    }
+
+    pub fn clear(&mut self) {
+        self.entries.clear();
+        self.slots.clear();
+        self.order.clear();
+        self.total = 0;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_heavy_hitters() {
+        let mut topk = TopK::new(20);
+        for i in 0..10_000u32 {
+            // 0..5 are heavy hitters, everything else appears once
+            let item = if i % 2 == 0 { i % 10 / 2 } else { 100 + i };
+            topk.insert(item);
+        }
+        assert_eq!(topk.len(), 20);
+        assert_eq!(topk.total(), 10_000);
+        assert!(topk.memory_usage() > 20 * size_of::<TopKEntry<u32>>());
+
+        let top = topk.top();
+        let mut heavy: Vec<u32> = top[..5].iter().map(|e| e.item).collect();
+        heavy.sort();
+        assert_eq!(heavy, vec![0, 1, 2, 3, 4]);
+        for entry in &top[..5] {
+            assert!(entry.count >= 1000);
+            assert!(entry.count - entry.error <= 1000);
+        }
+        assert!(top.windows(2).all(|w| w[0].count >= w[1].count));
+    }
+
+    #[test]
+    fn test_eviction() {
+        let mut topk = TopK::new(2);
+        assert_eq!(topk.insert_by("a".to_string(), 5), None);
+        assert_eq!(topk.insert("b".to_string()), None);
+        assert_eq!(topk.insert("c".to_string()), Some("b".to_string()));
+        assert_eq!(topk.count("c"), Some(2));
+        assert_eq!(topk.top()[1].error, 1);
+        assert!(!topk.contains("b"));
+    }
+}
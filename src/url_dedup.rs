@@ -0,0 +1,247 @@
+// URL frontier dedup for crawlers. Links to one page come in many spellings, and a filter that
+// sees `HTTP://Example.com:80/a/?b=2&a=1#top` and `http://example.com/a?a=1&b=2` as different
+// keys crawls the page twice. `UrlDeduper` normalizes URLs before they reach the filter:
+//
+//   scheme and host     lowercased (not the path, paths are case sensitive)
+//   default port        :80 for http / ws, :443 for https / wss, :21 for ftp dropped
+//   empty path          becomes "/"
+//   trailing slash      dropped from non-root paths
+//   query               parameters sorted, empty ones and a lone "?" dropped
+//   fragment            dropped, it never reaches the server
+//
+// Every rule can be turned off in `UrlRules`. Strings without "scheme://" only get the query
+// and fragment rules. The filter is a `ScalableBloomFilter`, so the frontier can grow past the
+// initial capacity without the false positive rate (pages never crawled) going up.
+
+use std::hash::BuildHasher;
+
+use crate::hash::Sha256BuildHasher;
+use crate::ScalableBloomFilter;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct UrlRules {
+    // scheme and host
+    pub lowercase_host: bool,
+    pub strip_default_port: bool,
+    pub strip_trailing_slash: bool,
+    pub sort_query: bool,
+    pub strip_fragment: bool,
+}
+
+impl Default for UrlRules {
+    fn default() -> Self {
+        UrlRules {
+            lowercase_host: true,
+            strip_default_port: true,
+            strip_trailing_slash: true,
+            sort_query: true,
+            strip_fragment: true,
+        }
+    }
+}
+
+impl UrlRules {
+    pub fn normalize(&self, url: &str) -> String {
+        let url = url.trim();
+        let (url, fragment) = match url.split_once('#') {
+            Some((url, fragment)) => (url, Some(fragment)),
+            None => (url, None),
+        };
+        let (url, query) = match url.split_once('?') {
+            Some((url, query)) => (url, Some(query)),
+            None => (url, None),
+        };
+
+        let mut normalized = String::with_capacity(url.len() + 1);
+        match url.split_once("://") {
+            Some((scheme, rest)) => {
+                let (authority, path) = rest.split_at(rest.find('/').unwrap_or(rest.len()));
+                let scheme = if self.lowercase_host {
+                    scheme.to_ascii_lowercase()
+                } else {
+                    scheme.to_string()
+                };
+                normalized.push_str(&scheme);
+                normalized.push_str("://");
+                self.push_authority(&mut normalized, &scheme, authority);
+                match path {
+                    "" => normalized.push('/'),
+                    path if self.strip_trailing_slash => match path.trim_end_matches('/') {
+                        "" => normalized.push('/'),
+                        path => normalized.push_str(path),
+                    },
+                    path => normalized.push_str(path),
+                }
+            }
+            None => normalized.push_str(url),
+        }
+
+        if let Some(query) = query {
+            if self.sort_query {
+                let mut params: Vec<&str> = query.split('&').filter(|p| !p.is_empty()).collect();
+                params.sort_unstable();
+                if !params.is_empty() {
+                    normalized.push('?');
+                    normalized.push_str(&params.join("&"));
+                }
+            } else {
+                normalized.push('?');
+                normalized.push_str(query);
+            }
+        }
+        if let Some(fragment) = fragment.filter(|_| !self.strip_fragment) {
+            normalized.push('#');
+            normalized.push_str(fragment);
+        }
+        normalized
+    }
+
+    // [user@]host[:port]
+    fn push_authority(&self, normalized: &mut String, scheme: &str, authority: &str) {
+        let (user, host_port) = match authority.rsplit_once('@') {
+            Some((user, host_port)) => (Some(user), host_port),
+            None => (None, authority),
+        };
+        if let Some(user) = user {
+            normalized.push_str(user);
+            normalized.push('@');
+        }
+        // the port follows the last ':' unless it's inside an IPv6 literal
+        let (host, port) = match host_port.rfind(':') {
+            Some(i) if !host_port[i..].contains(']') => {
+                (&host_port[..i], Some(&host_port[i + 1..]))
+            }
+            _ => (host_port, None),
+        };
+        if self.lowercase_host {
+            normalized.push_str(&host.to_ascii_lowercase());
+        } else {
+            normalized.push_str(host);
+        }
+        let default_port = match scheme.to_ascii_lowercase().as_str() {
+            "http" | "ws" => Some("80"),
+            "https" | "wss" => Some("443"),
+            "ftp" => Some("21"),
+            _ => None,
+        };
+        match port {
+            Some(port)
+                if self.strip_default_port && (port.is_empty() || Some(port) == default_port) => {}
+            Some(port) => {
+                normalized.push(':');
+                normalized.push_str(port);
+            }
+            None => {}
+        }
+    }
+}
+
+pub struct UrlDeduper<S = Sha256BuildHasher> {
+    seen: ScalableBloomFilter<S>,
+    rules: UrlRules,
+}
+
+impl UrlDeduper {
+    pub fn new(initial_capacity: usize, false_positive_rate: f64) -> Self {
+        Self::with_rules(initial_capacity, false_positive_rate, UrlRules::default())
+    }
+
+    pub fn with_rules(initial_capacity: usize, false_positive_rate: f64, rules: UrlRules) -> Self {
+        Self::from_filter(
+            ScalableBloomFilter::new(initial_capacity, false_positive_rate),
+            rules,
+        )
+    }
+}
+
+impl<S: BuildHasher + Clone> UrlDeduper<S> {
+    pub fn from_filter(seen: ScalableBloomFilter<S>, rules: UrlRules) -> Self {
+        UrlDeduper { seen, rules }
+    }
+
+    // True if no spelling of the URL was inserted before, i.e. it should be crawled. A false
+    // positive makes it return false for a new URL.
+    pub fn insert(&mut self, url: &str) -> bool {
+        let url = self.rules.normalize(url);
+        if self.seen.contains(&url) {
+            return false;
+        }
+        self.seen.insert(&url);
+        true
+    }
+
+    pub fn contains(&self, url: &str) -> bool {
+        self.seen.contains(&self.rules.normalize(url))
+    }
+
+    pub fn rules(&self) -> &UrlRules {
+        &self.rules
+    }
+
+    // Number of distinct normalized URLs inserted
+    pub fn len(&self) -> usize {
+        self.seen.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.seen.is_empty()
+    }
+
+    pub fn memory_usage(&self) -> usize {
+        size_of::<Self>() - size_of::<ScalableBloomFilter<S>>() + self.seen.memory_usage()
+    }
+
+    pub fn clear(&mut self) {
+        self.seen.clear();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_normalize() {
+        let rules = UrlRules::default();
+        for (url, expected) in [
+            (
+                "HTTP://Example.COM:80/a/?b=2&a=1#top",
+                "http://example.com/a?a=1&b=2",
+            ),
+            ("https://example.com:443", "https://example.com/"),
+            ("https://example.com:8443/", "https://example.com:8443/"),
+            ("http://User@Host.com//", "http://User@host.com/"),
+            ("http://[::1]:80/Path/?", "http://[::1]/Path"),
+            ("http://[::1]/x", "http://[::1]/x"),
+            ("mailto:a@b.c#x", "mailto:a@b.c"),
+        ] {
+            assert_eq!(rules.normalize(url), expected, "{}", url);
+        }
+        let keep_fragment = UrlRules {
+            strip_fragment: false,
+            sort_query: false,
+            ..UrlRules::default()
+        };
+        assert_eq!(
+            keep_fragment.normalize("http://a.com/?b&a#Top"),
+            "http://a.com/?b&a#Top"
+        );
+    }
+
+    #[test]
+    fn test_url_deduper() {
+        let mut frontier = UrlDeduper::new(100, 0.001);
+        assert!(frontier.insert("http://example.com/page?id=1&lang=en"));
+        assert!(!frontier.insert("http://EXAMPLE.com:80/page/?lang=en&id=1#reviews"));
+        assert!(frontier.contains("http://example.com/page?id=1&lang=en"));
+        assert!(!frontier.contains("http://example.com/Page?id=1&lang=en"));
+
+        let new = (0..1000)
+            .filter(|i| frontier.insert(&format!("https://example.com/item/{}", i)))
+            .count();
+        assert!(new > 990, "{} new", new);
+        assert_eq!(frontier.len(), new + 1);
+        frontier.clear();
+        assert!(frontier.is_empty());
+    }
+}
@@ -0,0 +1,173 @@
+// Read-only view of a filter's bit array that lives somewhere else, ex. inside an mmap'd
+// database page or a received network buffer, answering queries without copying the bits.
+// The words are the ones of `BloomFilter::as_raw_words`, with the same size, number of
+// hashes, seed and hasher the view answers exactly like the filter:
+//
+//   let view = BloomFilterRef::new(words, size, num_hashes, seed)?;
+//   view.contains(&item)
+
+use std::hash::{BuildHasher, Hash};
+
+use crate::bitset::word_count;
+use crate::error::BloomError;
+use crate::hash::{self, Sha256BuildHasher};
+use crate::{check_params, BloomFilter};
+
+#[derive(Clone, Copy, Debug)]
+pub struct BloomFilterRef<'a, S = Sha256BuildHasher> {
+    words: &'a [u64],
+    size: usize,
+    num_hashes: usize,
+    seed: u64,
+    hasher: S,
+}
+
+impl<'a> BloomFilterRef<'a> {
+    pub fn new(
+        words: &'a [u64],
+        size: usize,
+        num_hashes: usize,
+        seed: u64,
+    ) -> Result<Self, BloomError> {
+        Self::with_hasher(words, size, num_hashes, seed, Sha256BuildHasher)
+    }
+}
+
+impl<'a, S: BuildHasher> BloomFilterRef<'a, S> {
+    // `words` must hold exactly the ceil(size / 64) words of the bit array
+    pub fn with_hasher(
+        words: &'a [u64],
+        size: usize,
+        num_hashes: usize,
+        seed: u64,
+        hasher: S,
+    ) -> Result<Self, BloomError> {
+        check_params(size, num_hashes)?;
+        if words.len() != word_count(size) {
+            return Err(BloomError::InvalidParams(format!(
+                "A filter of {} bits needs {} words, got {}.",
+                size,
+                word_count(size),
+                words.len()
+            )));
+        }
+        Ok(BloomFilterRef {
+            words,
+            size,
+            num_hashes,
+            seed,
+            hasher,
+        })
+    }
+
+    pub fn contains<T: Hash + ?Sized>(&self, item: &T) -> bool {
+        let indices = hash::indices(&self.seeded_hasher(), item, self.num_hashes, self.size);
+        self.all_set(indices)
+    }
+
+    // Same as `BloomFilter::test_bytes`
+    pub fn contains_bytes(&self, bytes: impl AsRef<[u8]>) -> bool {
+        let indices = hash::bytes_indices(
+            &self.seeded_hasher(),
+            bytes.as_ref(),
+            self.num_hashes,
+            self.size,
+        );
+        self.all_set(indices)
+    }
+
+    fn all_set(&self, mut indices: impl Iterator<Item = usize>) -> bool {
+        indices.all(|idx| self.words[idx / 64] & (1 << (idx % 64)) != 0)
+    }
+
+    fn seeded_hasher(&self) -> hash::Seeded<'_, S> {
+        hash::Seeded {
+            build_hasher: &self.hasher,
+            seed: self.seed,
+        }
+    }
+
+    pub fn size(&self) -> usize {
+        self.size
+    }
+
+    pub fn num_hashes(&self) -> usize {
+        self.num_hashes
+    }
+
+    pub fn seed(&self) -> u64 {
+        self.seed
+    }
+
+    pub fn as_raw_words(&self) -> &'a [u64] {
+        self.words
+    }
+
+    // Owned copy of the viewed filter
+    pub fn to_filter(&self) -> BloomFilter<S>
+    where
+        S: Clone,
+    {
+        BloomFilter::from_parts(
+            self.size,
+            self.num_hashes,
+            self.words.to_vec(),
+            self.hasher.clone(),
+        )
+        .with_seed(self.seed)
+    }
+}
+
+impl<S: BuildHasher + Clone> BloomFilter<S> {
+    // View of this filter. Views hash with the hasher only, filters with custom hash functions
+    // are refused.
+    pub fn as_filter_ref(&self) -> Result<BloomFilterRef<'_, S>, BloomError> {
+        if !self.hash_fns.is_empty() {
+            return Err(BloomError::InvalidParams(
+                "Filters with custom hash functions have no view.".into(),
+            ));
+        }
+        Ok(BloomFilterRef {
+            words: self.bit_array.words(),
+            size: self.size,
+            num_hashes: self.num_hashes,
+            seed: self.seed,
+            hasher: self.hasher.clone(),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_borrowed_view() {
+        let mut bloom = BloomFilter::new(1000, 4).with_seed(9);
+        for i in 0..50u32 {
+            bloom.set(&i);
+        }
+        bloom.set_bytes(b"raw");
+        // ex. words that came in a network buffer
+        let words = bloom.as_raw_words().to_vec();
+        let view = BloomFilterRef::new(&words, 1000, 4, 9).unwrap();
+        for i in 0..200u32 {
+            assert_eq!(view.contains(&i), bloom.test(&i));
+        }
+        assert!(view.contains_bytes(b"raw"));
+        assert_eq!(view.to_filter(), bloom);
+        assert!(bloom.as_filter_ref().unwrap().contains(&7u32));
+        let mut custom = BloomFilter::new(1000, 1);
+        custom
+            .set_hash_fn(vec![Box::new(|bytes| bytes.len() as u64)])
+            .unwrap();
+        assert!(custom.as_filter_ref().is_err());
+
+        // wrong seed, other bits
+        let unseeded = BloomFilterRef::new(&words, 1000, 4, 0).unwrap();
+        assert!(!(0..50u32).all(|i| unseeded.contains(&i)));
+
+        assert!(BloomFilterRef::new(&words[1..], 1000, 4, 9).is_err());
+        assert!(BloomFilterRef::new(&words, 1000, 0, 9).is_err());
+    }
+}
@@ -0,0 +1,390 @@
+// Durable Bloom filter, a snapshot plus a write-ahead log in one directory:
+//
+//   dir/snapshot.bloom   the filter as of the last checkpoint, in the `BloomFilter::save` format
+//   dir/wal.log          bit indices of the inserts since then
+//
+// An insert that sets new bits appends its k bit indices to the log before touching the
+// filter, so `recover` (load the snapshot, replay the log) gets back every insert that
+// returned, even after the process crashed. Log writes go straight to the OS, `sync` fsyncs
+// them to survive power loss as well. `checkpoint` saves a new snapshot and empties the log,
+// `set_checkpoint_interval` does that every n log records. A crash between the two only
+// replays records that are in the snapshot already. An automatic checkpoint that fails doesn't
+// fail the insert, which is logged and applied by then: it is retried on the next insert and
+// the error kept for `take_checkpoint_error`.
+//
+// Log layout, little-endian: magic b"BLWL", u32 k, then one record per insert of k u64 indices
+// followed by a FNV-1a checksum (u32) of them. A record torn by a crash fails the checksum,
+// `recover` drops it and cuts the log there.
+//
+// Replay doesn't need the items or the hasher, but the filter has to be reopened with the
+// hasher it was created with to answer queries right.
+
+use std::fs::{self, File, OpenOptions};
+use std::hash::{BuildHasher, Hash};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+
+use crate::encoding;
+use crate::hash::{self, Sha256BuildHasher};
+use crate::stats::FilterStats;
+use crate::BloomFilter;
+
+const SNAPSHOT_FILE: &str = "snapshot.bloom";
+const WAL_FILE: &str = "wal.log";
+const WAL_MAGIC: [u8; 4] = *b"BLWL";
+const WAL_HEADER_LEN: usize = 8;
+
+pub struct DurableBloomFilter<S = Sha256BuildHasher> {
+    filter: BloomFilter<S>,
+    dir: PathBuf,
+    wal: File,
+    // bytes of complete records (and header) in the log
+    wal_len: u64,
+    // records in the log since the last checkpoint
+    wal_records: usize,
+    checkpoint_interval: Option<usize>,
+    // last failed automatic checkpoint
+    checkpoint_error: Option<io::Error>,
+}
+
+impl DurableBloomFilter {
+    pub fn create(dir: impl AsRef<Path>, size: usize, num_hashes: usize) -> io::Result<Self> {
+        Self::create_with_hasher(dir, size, num_hashes, Sha256BuildHasher)
+    }
+
+    pub fn recover(dir: impl AsRef<Path>) -> io::Result<Self> {
+        Self::recover_with_hasher(dir, Sha256BuildHasher)
+    }
+}
+
+impl<S: BuildHasher> DurableBloomFilter<S> {
+    // Creates `dir` if needed, fails with `AlreadyExists` if it holds a filter already
+    pub fn create_with_hasher(
+        dir: impl AsRef<Path>,
+        size: usize,
+        num_hashes: usize,
+        hasher: S,
+    ) -> io::Result<Self> {
+        let filter = BloomFilter::try_with_hasher(size, num_hashes, hasher)
+            .map_err(|err| io::Error::new(io::ErrorKind::InvalidInput, err))?;
+        let dir = dir.as_ref().to_path_buf();
+        fs::create_dir_all(&dir)?;
+        let snapshot = dir.join(SNAPSHOT_FILE);
+        if snapshot.exists() {
+            return Err(io::Error::new(
+                io::ErrorKind::AlreadyExists,
+                format!("{} already exists.", snapshot.display()),
+            ));
+        }
+        filter.save(&snapshot)?;
+        let wal = reset_wal(&dir, num_hashes)?;
+        Ok(DurableBloomFilter {
+            filter,
+            dir,
+            wal,
+            wal_len: WAL_HEADER_LEN as u64,
+            wal_records: 0,
+            checkpoint_interval: None,
+            checkpoint_error: None,
+        })
+    }
+
+    // Loads the snapshot and replays the log. A torn record at the end of the log is dropped,
+    // anything else that doesn't fit the snapshot fails with `InvalidData`.
+    pub fn recover_with_hasher(dir: impl AsRef<Path>, hasher: S) -> io::Result<Self> {
+        let dir = dir.as_ref().to_path_buf();
+        let mut filter = BloomFilter::load_with_hasher(dir.join(SNAPSHOT_FILE), hasher)?;
+        let wal_path = dir.join(WAL_FILE);
+        let bytes = match fs::read(&wal_path) {
+            Ok(bytes) => bytes,
+            // crashed in `create` between the snapshot and the log
+            Err(err) if err.kind() == io::ErrorKind::NotFound => Vec::new(),
+            Err(err) => return Err(err),
+        };
+        if bytes.is_empty() {
+            let wal = reset_wal(&dir, filter.num_hashes)?;
+            return Ok(Self::from_parts(filter, dir, wal, WAL_HEADER_LEN, 0));
+        }
+        if bytes.len() < WAL_HEADER_LEN
+            || bytes[..4] != WAL_MAGIC
+            || u32::from_le_bytes(bytes[4..8].try_into().unwrap()) as usize != filter.num_hashes
+        {
+            return Err(invalid_data(
+                "Write-ahead log doesn't belong to the snapshot.",
+            ));
+        }
+
+        let record_len = filter.num_hashes * 8 + 4;
+        let mut wal_len = WAL_HEADER_LEN;
+        let mut wal_records = 0;
+        for record in bytes[WAL_HEADER_LEN..].chunks_exact(record_len) {
+            let (indices, checksum) = record.split_at(record_len - 4);
+            if fnv1a(indices) != u32::from_le_bytes(checksum.try_into().unwrap()) {
+                break;
+            }
+            for idx in indices.chunks_exact(8) {
+                let idx = u64::from_le_bytes(idx.try_into().unwrap());
+                if idx >= filter.size as u64 {
+                    return Err(invalid_data("Write-ahead log index is out of range."));
+                }
+                filter.bit_array.set(idx as usize);
+            }
+            wal_len += record_len;
+            wal_records += 1;
+        }
+        let wal = OpenOptions::new().append(true).open(&wal_path)?;
+        if wal_len < bytes.len() {
+            wal.set_len(wal_len as u64)?;
+        }
+        Ok(Self::from_parts(filter, dir, wal, wal_len, wal_records))
+    }
+
+    fn from_parts(
+        filter: BloomFilter<S>,
+        dir: PathBuf,
+        wal: File,
+        wal_len: usize,
+        wal_records: usize,
+    ) -> Self {
+        DurableBloomFilter {
+            filter,
+            dir,
+            wal,
+            wal_len: wal_len as u64,
+            wal_records,
+            checkpoint_interval: None,
+            checkpoint_error: None,
+        }
+    }
+
+    pub fn set<T: Hash + ?Sized>(&mut self, item: &T) -> io::Result<()> {
+        self.insert(item).map(|_| ())
+    }
+
+    // Returns whether the item was (possibly) already present, nothing is logged then.
+    // If the log write fails the filter is left unchanged.
+    // Only a failed log write is an error, see `take_checkpoint_error`.
+    pub fn insert<T: Hash + ?Sized>(&mut self, item: &T) -> io::Result<bool> {
+        let filter = &mut self.filter;
+        let indices: Vec<usize> = hash::probe(
+            &filter.seeded_hasher(),
+            &filter.hash_fns,
+            item,
+            filter.num_hashes,
+            filter.size,
+        )
+        .collect();
+        if indices.iter().all(|&idx| filter.bit_array.get(idx)) {
+            return Ok(true);
+        }
+
+        let mut record = Vec::with_capacity(indices.len() * 8 + 4);
+        for &idx in &indices {
+            record.extend_from_slice(&(idx as u64).to_le_bytes());
+        }
+        let checksum = fnv1a(&record);
+        record.extend_from_slice(&checksum.to_le_bytes());
+        if let Err(err) = self.wal.write_all(&record) {
+            // don't leave half a record for the next one to be appended to
+            let _ = self.wal.set_len(self.wal_len);
+            return Err(err);
+        }
+        self.wal_len += record.len() as u64;
+        self.wal_records += 1;
+        for idx in indices {
+            filter.bit_array.set(idx);
+        }
+
+        if self
+            .checkpoint_interval
+            .is_some_and(|interval| self.wal_records >= interval)
+        {
+            // the log stays over the interval, so the next insert tries again
+            if let Err(err) = self.checkpoint() {
+                self.checkpoint_error = Some(err);
+            }
+        }
+        Ok(false)
+    }
+
+    pub fn test<T: Hash + ?Sized>(&self, item: &T) -> bool {
+        self.filter.test(item)
+    }
+
+    // Saves the filter as the new snapshot and empties the log
+    pub fn checkpoint(&mut self) -> io::Result<()> {
+        self.filter.save(self.dir.join(SNAPSHOT_FILE))?;
+        self.wal = reset_wal(&self.dir, self.filter.num_hashes)?;
+        self.wal_len = WAL_HEADER_LEN as u64;
+        self.wal_records = 0;
+        self.checkpoint_error = None;
+        Ok(())
+    }
+
+    // Error of the last automatic checkpoint, if it failed and none succeeded since
+    pub fn take_checkpoint_error(&mut self) -> Option<io::Error> {
+        self.checkpoint_error.take()
+    }
+
+    // Checkpoint automatically once the log holds `records` inserts
+    pub fn set_checkpoint_interval(&mut self, records: usize) {
+        self.checkpoint_interval = Some(records.max(1));
+    }
+
+    // Flushes the log to disk
+    pub fn sync(&self) -> io::Result<()> {
+        self.wal.sync_data()
+    }
+
+    // Inserts logged since the last checkpoint
+    pub fn pending_records(&self) -> usize {
+        self.wal_records
+    }
+
+    // The filter, the log is on disk
+    pub fn memory_usage(&self) -> usize {
+        size_of::<Self>() + self.filter.heap_bytes() + self.dir.capacity()
+    }
+
+    pub fn stats(&self) -> FilterStats {
+        FilterStats {
+            memory_bytes: self.memory_usage(),
+            ..self.filter.stats()
+        }
+    }
+
+    pub fn filter(&self) -> &BloomFilter<S> {
+        &self.filter
+    }
+}
+
+// Replaces the log with an empty one (just the header) and opens it for appending
+fn reset_wal(dir: &Path, num_hashes: usize) -> io::Result<File> {
+    let path = dir.join(WAL_FILE);
+    let mut header = WAL_MAGIC.to_vec();
+    header.extend_from_slice(&(num_hashes as u32).to_le_bytes());
+    encoding::write_atomic(&path, &header)?;
+    OpenOptions::new().append(true).open(path)
+}
+
+fn fnv1a(bytes: &[u8]) -> u32 {
+    bytes.iter().fold(0x811c_9dc5, |hash, &byte| {
+        (hash ^ byte as u32).wrapping_mul(0x0100_0193)
+    })
+}
+
+fn invalid_data(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("bloomf_{}_{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        dir
+    }
+
+    #[test]
+    fn test_recover() {
+        let dir = temp_dir("wal_recover");
+        let mut bloom = DurableBloomFilter::create(&dir, 10_000, 4).unwrap();
+        assert!(DurableBloomFilter::create(&dir, 10_000, 4).is_err());
+        for i in 0..100u32 {
+            assert!(!bloom.insert(&i).unwrap());
+        }
+        assert!(bloom.insert(&5u32).unwrap());
+        assert_eq!(bloom.pending_records(), 100);
+        let expected = bloom.filter().clone();
+        drop(bloom);
+
+        let mut recovered = DurableBloomFilter::recover(&dir).unwrap();
+        assert_eq!(recovered.filter(), &expected);
+        assert_eq!(recovered.pending_records(), 100);
+        assert_eq!(recovered.stats().set_bits, expected.stats().set_bits);
+
+        // torn last record
+        recovered.set(&1000u32).unwrap();
+        drop(recovered);
+        let wal = dir.join(WAL_FILE);
+        let len = fs::metadata(&wal).unwrap().len();
+        OpenOptions::new()
+            .write(true)
+            .open(&wal)
+            .unwrap()
+            .set_len(len - 3)
+            .unwrap();
+        let mut recovered = DurableBloomFilter::recover(&dir).unwrap();
+        assert_eq!(recovered.filter(), &expected);
+        assert_eq!(fs::metadata(&wal).unwrap().len(), len - 36);
+
+        // appends after the cut are replayed
+        recovered.set(&1001u32).unwrap();
+        drop(recovered);
+        let recovered = DurableBloomFilter::recover(&dir).unwrap();
+        assert!(recovered.test(&1001u32));
+        assert_eq!(recovered.pending_records(), 101);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_checkpoint() {
+        let dir = temp_dir("wal_checkpoint");
+        let mut bloom = DurableBloomFilter::create(&dir, 10_000, 4).unwrap();
+        bloom.set_checkpoint_interval(30);
+        for i in 0..100u32 {
+            bloom.set(&i).unwrap();
+        }
+        assert_eq!(bloom.pending_records(), 10);
+        assert_eq!(
+            fs::metadata(dir.join(WAL_FILE)).unwrap().len(),
+            (WAL_HEADER_LEN + 10 * 36) as u64
+        );
+        bloom.sync().unwrap();
+        let expected = bloom.filter().clone();
+        drop(bloom);
+
+        let recovered = DurableBloomFilter::recover(&dir).unwrap();
+        assert_eq!(recovered.filter(), &expected);
+        assert!((0..100u32).all(|i| recovered.test(&i)));
+
+        // a log from another filter
+        fs::write(dir.join(WAL_FILE), b"BLWL\x07\x00\x00\x00").unwrap();
+        let err = DurableBloomFilter::recover(&dir).err().unwrap();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_failed_checkpoint() {
+        let dir = temp_dir("wal_failed_checkpoint");
+        let mut bloom = DurableBloomFilter::create(&dir, 10_000, 4).unwrap();
+        bloom.set_checkpoint_interval(1);
+        // a directory in the way of the snapshot makes the rename fail, even for root
+        let snapshot = dir.join(SNAPSHOT_FILE);
+        fs::remove_file(&snapshot).unwrap();
+        fs::create_dir_all(snapshot.join("blocker")).unwrap();
+
+        assert!(!bloom.insert(&1u32).unwrap());
+        assert!(bloom.test(&1u32));
+        assert_eq!(bloom.pending_records(), 1);
+        assert!(bloom.take_checkpoint_error().is_some());
+        assert!(bloom.take_checkpoint_error().is_none());
+        assert!(!bloom.insert(&2u32).unwrap());
+        assert_eq!(bloom.pending_records(), 2);
+
+        fs::remove_dir_all(&snapshot).unwrap();
+        assert!(!bloom.insert(&3u32).unwrap());
+        assert_eq!(bloom.pending_records(), 0);
+        assert!(bloom.take_checkpoint_error().is_none());
+        let expected = bloom.filter().clone();
+        drop(bloom);
+        assert_eq!(
+            DurableBloomFilter::recover(&dir).unwrap().filter(),
+            &expected
+        );
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
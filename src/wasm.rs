@@ -0,0 +1,76 @@
+// JavaScript bindings (feature `wasm`), built with `wasm-pack build bindings --features wasm`.
+// Exported as `BloomFilter` with the same encoding as `BloomFilter::to_bytes`, so a filter
+// built and saved in Rust can be loaded in the browser and answers the same. Strings are hashed
+// like a Rust `&str` and byte arrays like `set_bytes`.
+
+use wasm_bindgen::prelude::*;
+
+use crate::BloomFilter;
+
+#[wasm_bindgen(js_name = BloomFilter)]
+pub struct WasmBloomFilter {
+    inner: BloomFilter,
+}
+
+#[wasm_bindgen(js_class = BloomFilter)]
+impl WasmBloomFilter {
+    #[wasm_bindgen(constructor)]
+    pub fn new(size: usize, num_hashes: usize) -> Result<WasmBloomFilter, JsError> {
+        let inner = BloomFilter::try_new(size, num_hashes).map_err(to_js_error)?;
+        Ok(WasmBloomFilter { inner })
+    }
+
+    pub fn insert(&mut self, item: &str) {
+        self.inner.set(item);
+    }
+
+    pub fn contains(&self, item: &str) -> bool {
+        self.inner.test(item)
+    }
+
+    #[wasm_bindgen(js_name = insertBytes)]
+    pub fn insert_bytes(&mut self, bytes: &[u8]) {
+        self.inner.set_bytes(bytes);
+    }
+
+    #[wasm_bindgen(js_name = containsBytes)]
+    pub fn contains_bytes(&self, bytes: &[u8]) -> bool {
+        self.inner.test_bytes(bytes)
+    }
+
+    #[wasm_bindgen(js_name = toBytes)]
+    pub fn to_bytes(&self) -> Vec<u8> {
+        self.inner.to_bytes()
+    }
+
+    #[wasm_bindgen(js_name = fromBytes)]
+    pub fn from_bytes(bytes: &[u8]) -> Result<WasmBloomFilter, JsError> {
+        let inner = BloomFilter::from_bytes(bytes).map_err(to_js_error)?;
+        Ok(WasmBloomFilter { inner })
+    }
+}
+
+fn to_js_error(err: crate::BloomError) -> JsError {
+    JsError::new(&err.to_string())
+}
+
+// Only the paths that don't create JS values can run outside of wasm
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_same_bytes_as_bloom_filter() {
+        let mut filter = WasmBloomFilter::new(1000, 3).ok().unwrap();
+        filter.insert("foo");
+        filter.insert_bytes(b"bar");
+        let mut bloom = BloomFilter::new(1000, 3);
+        bloom.set("foo");
+        bloom.set_bytes(b"bar");
+        assert_eq!(filter.to_bytes(), bloom.to_bytes());
+
+        let filter = WasmBloomFilter::from_bytes(&bloom.to_bytes()).ok().unwrap();
+        assert!(filter.contains("foo") && filter.contains_bytes(b"bar"));
+        assert!(!filter.contains("bar"));
+    }
+}
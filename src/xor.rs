@@ -0,0 +1,185 @@
+// Xor filter (Graf & Lemire 2019) for static sets: built once from the full key list, then
+// read-only. Every key maps to three slots (one per block) and the stored fingerprints are
+// chosen so that the xor of the three slots is the key's fingerprint.
+// Uses ~1.23 * bits-per-fingerprint bits per key, fpp is 2^-8 for Xor8 and 2^-16 for Xor16.
+
+use std::hash::{BuildHasher, Hash};
+use std::ops::BitXor;
+
+use crate::hash::{mix, Sha256BuildHasher};
+use crate::heap_bytes;
+use crate::rng::Rng;
+
+// Fingerprint types a static filter can store (u8 and u16)
+pub trait Fingerprint: Copy + Default + Eq + BitXor<Output = Self> {
+    fn from_hash(hash: u64) -> Self;
+}
+
+impl Fingerprint for u8 {
+    fn from_hash(hash: u64) -> Self {
+        (hash ^ (hash >> 32)) as u8
+    }
+}
+
+impl Fingerprint for u16 {
+    fn from_hash(hash: u64) -> Self {
+        (hash ^ (hash >> 32)) as u16
+    }
+}
+
+// Maps a 32 bit value to [0, n) without a division
+pub(crate) fn reduce(hash: u32, n: u32) -> u32 {
+    ((hash as u64 * n as u64) >> 32) as u32
+}
+
+pub struct XorFilter<F = u8, S = Sha256BuildHasher> {
+    seed: u64,
+    block_length: u32,
+    fingerprints: Vec<F>,
+    num_keys: usize,
+    hasher: S,
+}
+
+pub type Xor8<S = Sha256BuildHasher> = XorFilter<u8, S>;
+pub type Xor16<S = Sha256BuildHasher> = XorFilter<u16, S>;
+
+impl<F: Fingerprint> XorFilter<F> {
+    pub fn from_items<T: Hash>(items: &[T]) -> Self {
+        Self::from_items_with_hasher(items, Sha256BuildHasher)
+    }
+}
+
+impl<F: Fingerprint, S: BuildHasher> XorFilter<F, S> {
+    pub fn from_items_with_hasher<T: Hash>(items: &[T], hasher: S) -> Self {
+        let mut keys: Vec<u64> = items.iter().map(|item| hasher.hash_one(item)).collect();
+        // Duplicate keys can never be peeled
+        keys.sort_unstable();
+        keys.dedup();
+
+        let capacity = 32 + (1.23 * keys.len() as f64).ceil() as u32;
+        let block_length = capacity / 3;
+        let size = block_length as usize * 3;
+        let mut rng = Rng::new(0x726b_2b9d_438b_9d4d);
+
+        loop {
+            let seed = rng.next_u64();
+            if let Some(fingerprints) = Self::try_build(&keys, seed, block_length, size) {
+                return XorFilter {
+                    seed,
+                    block_length,
+                    fingerprints,
+                    num_keys: keys.len(),
+                    hasher,
+                };
+            }
+        }
+    }
+
+    fn slots(hash: u64, block_length: u32) -> [usize; 3] {
+        [
+            reduce(hash as u32, block_length) as usize,
+            (reduce(hash.rotate_left(21) as u32, block_length) + block_length) as usize,
+            (reduce(hash.rotate_left(42) as u32, block_length) + 2 * block_length) as usize,
+        ]
+    }
+
+    // Peel the 3-hypergraph of keys, None if it has a cycle for this seed
+    fn try_build(keys: &[u64], seed: u64, block_length: u32, size: usize) -> Option<Vec<F>> {
+        let mut counts = vec![0u32; size];
+        let mut xors = vec![0u64; size];
+        for &key in keys {
+            let hash = mix(key, seed);
+            for slot in Self::slots(hash, block_length) {
+                counts[slot] += 1;
+                xors[slot] ^= hash;
+            }
+        }
+
+        let mut queue: Vec<usize> = (0..size).filter(|&slot| counts[slot] == 1).collect();
+        let mut stack: Vec<(u64, usize)> = Vec::with_capacity(keys.len());
+        while let Some(slot) = queue.pop() {
+            if counts[slot] != 1 {
+                continue;
+            }
+            let hash = xors[slot];
+            stack.push((hash, slot));
+            for other in Self::slots(hash, block_length) {
+                counts[other] -= 1;
+                xors[other] ^= hash;
+                if counts[other] == 1 {
+                    queue.push(other);
+                }
+            }
+        }
+        if stack.len() != keys.len() {
+            return None;
+        }
+
+        // Assign in reverse peeling order, the slot of every key is free when it's its turn
+        let mut fingerprints = vec![F::default(); size];
+        for &(hash, slot) in stack.iter().rev() {
+            let [a, b, c] = Self::slots(hash, block_length);
+            fingerprints[slot] =
+                F::from_hash(hash) ^ fingerprints[a] ^ fingerprints[b] ^ fingerprints[c];
+        }
+        Some(fingerprints)
+    }
+
+    pub fn contains<T: Hash + ?Sized>(&self, item: &T) -> bool {
+        let hash = mix(self.hasher.hash_one(item), self.seed);
+        let [a, b, c] = Self::slots(hash, self.block_length);
+        F::from_hash(hash) == self.fingerprints[a] ^ self.fingerprints[b] ^ self.fingerprints[c]
+    }
+
+    // Number of distinct keys the filter was built from
+    pub fn len(&self) -> usize {
+        self.num_keys
+    }
+
+    pub fn num_slots(&self) -> usize {
+        self.fingerprints.len()
+    }
+
+    pub fn memory_usage(&self) -> usize {
+        size_of::<Self>() + heap_bytes(&self.fingerprints)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.num_keys == 0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_xor8() {
+        let items: Vec<u64> = (0..10_000).collect();
+        let filter = Xor8::from_items(&items);
+        for item in &items {
+            assert!(filter.contains(item));
+        }
+        // fpp ~ 1/256
+        let false_positives = (10_000..110_000u64).filter(|i| filter.contains(i)).count();
+        assert!(false_positives < 600, "{} false positives", false_positives);
+        assert_eq!(filter.len(), 10_000);
+        assert!(filter.num_slots() < 12_400);
+    }
+
+    #[test]
+    fn test_xor16_with_duplicates() {
+        let items = ["foo", "bar", "foo", "baz", "bar"];
+        let filter = Xor16::from_items(&items);
+        for item in &items {
+            assert!(filter.contains(item));
+        }
+        assert!(!filter.contains("qux"));
+        assert_eq!(filter.len(), 3);
+
+        let empty = Xor16::from_items::<u32>(&[]);
+        assert!(!empty.contains(&1u32));
+        assert!(empty.is_empty());
+        assert!(empty.num_slots() > 0);
+    }
+}